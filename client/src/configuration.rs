@@ -69,12 +69,17 @@ impl Configuration {
         public_key: String::from_utf8(keys.public_key_to_pem().expect("Failed to encoding public key")).expect("OpenSSL generate invalid output"),
       }
     });
-    serde_json::to_writer(std::fs::OpenOptions::new().write(true).open(&path).expect("Failed to write client configuration."), &data)
-      .expect("Failed to encode client configuration");
+    std::fs::create_dir_all(dirs.config_dir()).expect("Failed to create configuration directory.");
+    serde_json::to_writer(
+      std::fs::OpenOptions::new().write(true).create(true).truncate(true).open(&path).expect("Failed to write client configuration."),
+      &data,
+    )
+    .expect("Failed to encode client configuration");
     Configuration { data, path }
   }
   pub fn process_connection_string(&mut self, connection_string: &str) -> Result<ServerConfiguration, ConnectionStringError> {
-    let result = if std::fs::metadata(connection_string)?.file_type().is_file() {
+    use std::os::unix::fs::FileTypeExt;
+    let result = if std::fs::metadata(connection_string).map(|m| m.file_type().is_socket()).unwrap_or(false) {
       Ok(ServerConfiguration::Socket(connection_string.to_string()))
     } else {
       match connection_string.parse()? {
@@ -84,11 +89,18 @@ impl Configuration {
     }?;
     if !self.data.accounts.contains(&result) {
       self.data.accounts.push(result.clone());
-      serde_json::to_writer(std::fs::OpenOptions::new().write(true).open(&self.path).expect("Failed to write client configuration."), &self.data)
-        .expect("Failed to encode client configuration");
+      serde_json::to_writer(
+        std::fs::OpenOptions::new().write(true).truncate(true).open(&self.path).expect("Failed to write client configuration."),
+        &self.data,
+      )
+      .expect("Failed to encode client configuration");
     }
     Ok(result)
   }
+  /// The key this client uses to authenticate with servers that support public key login
+  pub fn private_key(&self) -> Result<openssl::pkey::PKey<openssl::pkey::Private>, openssl::error::ErrorStack> {
+    openssl::pkey::PKey::from_ec_key(openssl::ec::EcKey::private_key_from_pem(self.data.private_key.as_bytes())?)
+  }
   pub fn remove(&mut self, item: usize) {
     if item < self.data.accounts.len() {
       self.data.accounts.swap_remove(item);
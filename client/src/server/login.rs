@@ -0,0 +1,191 @@
+use crate::server::active_connection::ActiveConnection;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{http, StatusCode};
+use hyper_util::rt::TokioIo;
+use openssl::pkey::{PKey, Private};
+use spadina_core::net::mixed_connection::MixedConnection;
+use spadina_core::net::server::auth::{
+  challenge_message, compute_fingerprint, AuthMethod, AuthPublicKey, AuthScheme, PasswordRequest, PublicKeyChallenge, PublicKeyChallengeResponse,
+};
+use spadina_core::net::server::{AUTH_METHOD_PATH, CLIENT_KEY_PATH, CLIENT_KEY_RESPONSE_PATH, CLIENT_V1_PATH, PASSWORD_AUTH_PATH};
+use tokio::net::{TcpStream, UnixStream};
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+
+/// The secret a player uses to prove their identity to a server
+pub enum Credentials<'a> {
+  /// A password for a server that uses password authentication, and a one-time code if the player has enrolled a second factor
+  Password { password: &'a str, otp: Option<&'a str> },
+  /// The client's private key; the matching public key must have been previously registered with the server
+  PublicKey(&'a PKey<Private>),
+}
+
+#[derive(Debug)]
+pub enum LoginError {
+  BadResponse,
+  BadServerName(http::Error),
+  Crypto(openssl::error::ErrorStack),
+  Http(hyper::Error),
+  Io(std::io::Error),
+  Json(serde_json::Error),
+  Rejected(StatusCode, String),
+  Tls(native_tls::Error),
+  UnsupportedScheme(Option<AuthScheme>),
+  WebSocket(tokio_tungstenite::tungstenite::Error),
+}
+
+/// Obtain an authentication token from a server
+pub async fn login(server: &str, player: &str, credentials: Credentials<'_>) -> Result<String, LoginError> {
+  match credentials {
+    Credentials::Password { password, otp } => {
+      let (status, body) = send(server, hyper::Request::get(uri(server, AUTH_METHOD_PATH)?), Bytes::new()).await?;
+      if status != StatusCode::OK {
+        return Err(LoginError::Rejected(status, String::from_utf8_lossy(&body).into_owned()));
+      }
+      let methods = serde_json::from_slice::<Vec<AuthMethod<String>>>(&body)?;
+      match methods.into_iter().find(|method| method.accounts.local_name(player).is_some()) {
+        Some(AuthMethod { scheme: AuthScheme::Password, .. }) => (),
+        Some(AuthMethod { scheme, .. }) => return Err(LoginError::UnsupportedScheme(Some(scheme))),
+        None => return Err(LoginError::UnsupportedScheme(None)),
+      }
+      let request = serde_json::to_vec(&PasswordRequest { username: player, password, otp })?;
+      let (status, body) = send(server, hyper::Request::post(uri(server, PASSWORD_AUTH_PATH)?), request.into()).await?;
+      if status != StatusCode::OK {
+        return Err(LoginError::Rejected(status, String::from_utf8_lossy(&body).into_owned()));
+      }
+      Ok(serde_json::from_slice(&body)?)
+    }
+    Credentials::PublicKey(key) => {
+      let fingerprint = compute_fingerprint(&key.public_key_to_der()?);
+      let request = serde_json::to_vec(&AuthPublicKey { player, fingerprint: fingerprint.as_str() })?;
+      let (status, body) = send(server, hyper::Request::post(uri(server, CLIENT_KEY_PATH)?), request.into()).await?;
+      if status != StatusCode::OK {
+        return Err(LoginError::Rejected(status, String::from_utf8_lossy(&body).into_owned()));
+      }
+      let challenge = serde_json::from_slice::<PublicKeyChallenge<String>>(&body)?;
+      // Only sign challenges for the server being logged in to, so another server cannot relay its challenge to us
+      if uri(server, "/")?.host() != Some(challenge.server.as_str()) {
+        return Err(LoginError::BadResponse);
+      }
+      let mut signer = match key.id() {
+        openssl::pkey::Id::ED25519 | openssl::pkey::Id::ED448 => openssl::sign::Signer::new_without_digest(key)?,
+        _ => openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), key)?,
+      };
+      let signature = signer.sign_oneshot_to_vec(&challenge_message(&challenge.server, player, &fingerprint, &challenge.nonce))?;
+      let request =
+        serde_json::to_vec(&PublicKeyChallengeResponse { player, fingerprint: fingerprint.as_str(), nonce: &challenge.nonce, signature })?;
+      let (status, body) = send(server, hyper::Request::post(uri(server, CLIENT_KEY_RESPONSE_PATH)?), request.into()).await?;
+      if status != StatusCode::OK {
+        return Err(LoginError::Rejected(status, String::from_utf8_lossy(&body).into_owned()));
+      }
+      Ok(serde_json::from_slice(&body)?)
+    }
+  }
+}
+
+/// Open a client connection to a server using a token obtained from [login]
+pub async fn open(server: &str, token: &str) -> Result<ActiveConnection, LoginError> {
+  let request = hyper::Request::get(uri(server, CLIENT_V1_PATH)?)
+    .header(http::header::HOST, server)
+    .header(http::header::CONNECTION, "upgrade")
+    .header(http::header::SEC_WEBSOCKET_VERSION, "13")
+    .header(http::header::UPGRADE, "websocket")
+    .header(http::header::SEC_WEBSOCKET_KEY, tokio_tungstenite::tungstenite::handshake::client::generate_key())
+    .header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+  let response = connect(server).await?.send_request(request.body(Full::new(Bytes::new())).map_err(LoginError::BadServerName)?).await?;
+  if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+    let status = response.status();
+    let body = response.into_body().collect().await?.to_bytes();
+    return Err(LoginError::Rejected(status, String::from_utf8_lossy(&body).into_owned()));
+  }
+  let upgraded = hyper::upgrade::on(response).await?;
+  Ok(ActiveConnection::Active(WebSocketStream::from_raw_socket(MixedConnection::from(upgraded), Role::Client, None).await))
+}
+
+/// Open a client connection to a server running on the same machine through its UNIX socket
+///
+/// The server trusts the operating system's permissions on the socket, so no credentials are required.
+pub async fn open_socket(path: &str, player: &str) -> Result<ActiveConnection, LoginError> {
+  let stream = UnixStream::connect(path).await?;
+  let (connection, _) = tokio_tungstenite::client_async(format!("ws://localhost/{}", player), MixedConnection::from(stream)).await?;
+  Ok(ActiveConnection::Active(connection))
+}
+
+fn uri(server: &str, path: &str) -> Result<hyper::Uri, LoginError> {
+  hyper::Uri::builder().scheme(http::uri::Scheme::HTTPS).authority(server).path_and_query(path).build().map_err(LoginError::BadServerName)
+}
+
+async fn connect(server: &str) -> Result<hyper::client::conn::http1::SendRequest<Full<Bytes>>, LoginError> {
+  let uri = uri(server, "/")?;
+  let tls = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+  let host = uri.host().ok_or(LoginError::BadResponse)?;
+  let stream = tls.connect(host, TcpStream::connect((host, uri.port_u16().unwrap_or(443))).await?).await?;
+  let (sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+  tokio::spawn(async move {
+    if let Err(e) = connection.with_upgrades().await {
+      eprintln!("Failed to communicate with server: {}", e);
+    }
+  });
+  Ok(sender)
+}
+
+async fn send(server: &str, builder: http::request::Builder, body: Bytes) -> Result<(StatusCode, Bytes), LoginError> {
+  let request = builder
+    .header(http::header::HOST, server)
+    .header(http::header::CONTENT_TYPE, "application/json")
+    .body(Full::new(body))
+    .map_err(LoginError::BadServerName)?;
+  let response = connect(server).await?.send_request(request).await?;
+  let status = response.status();
+  Ok((status, response.into_body().collect().await?.to_bytes()))
+}
+
+impl From<openssl::error::ErrorStack> for LoginError {
+  fn from(value: openssl::error::ErrorStack) -> Self {
+    LoginError::Crypto(value)
+  }
+}
+impl From<hyper::Error> for LoginError {
+  fn from(value: hyper::Error) -> Self {
+    LoginError::Http(value)
+  }
+}
+impl From<std::io::Error> for LoginError {
+  fn from(value: std::io::Error) -> Self {
+    LoginError::Io(value)
+  }
+}
+impl From<serde_json::Error> for LoginError {
+  fn from(value: serde_json::Error) -> Self {
+    LoginError::Json(value)
+  }
+}
+impl From<native_tls::Error> for LoginError {
+  fn from(value: native_tls::Error) -> Self {
+    LoginError::Tls(value)
+  }
+}
+impl From<tokio_tungstenite::tungstenite::Error> for LoginError {
+  fn from(value: tokio_tungstenite::tungstenite::Error) -> Self {
+    LoginError::WebSocket(value)
+  }
+}
+impl std::fmt::Display for LoginError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      LoginError::BadResponse => f.write_str("server sent an invalid response"),
+      LoginError::BadServerName(e) => write!(f, "invalid server name: {}", e),
+      LoginError::Crypto(e) => write!(f, "cryptographic failure: {}", e),
+      LoginError::Http(e) => write!(f, "HTTP error: {}", e),
+      LoginError::Io(e) => write!(f, "connection failed: {}", e),
+      LoginError::Json(e) => write!(f, "cannot decode server response: {}", e),
+      LoginError::Rejected(status, message) => write!(f, "server rejected request ({}): {}", status, message),
+      LoginError::Tls(e) => write!(f, "TLS error: {}", e),
+      LoginError::UnsupportedScheme(Some(scheme)) => write!(f, "server requires unsupported authentication method {:?}", scheme),
+      LoginError::UnsupportedScheme(None) => write!(f, "server has no authentication method for this player"),
+      LoginError::WebSocket(e) => write!(f, "cannot establish connection: {}", e),
+    }
+  }
+}
+impl std::error::Error for LoginError {}
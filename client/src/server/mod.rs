@@ -35,10 +35,11 @@ pub mod active_connection;
 pub mod cache;
 pub mod direct_message;
 pub mod exports;
+pub mod login;
 pub mod peer;
 pub mod updates;
 
-trait EventKind: 'static + Sized + Send {
+pub trait EventKind: 'static + Sized + Send {
   type AccessChange: 'static
     + Update<AccessSetting<String, Privilege>>
     + Update<AccessSetting<String, OnlineAccess>>
@@ -54,8 +55,10 @@ trait EventKind: 'static + Sized + Send {
   type CalendarLocation: 'static + Update<BTreeSet<LocalTarget<String>>> + Send;
   type LocationSearch: Clone + 'static + Send;
   type LocationVisibility: 'static + Send;
+  type PlayerData: 'static + Send;
   type PlayerReset: 'static + Send;
   type PublicKey: 'static + Update<BTreeMap<String, PublicKey>> + Send;
+  type SecondFactor: 'static + Send;
 
   fn access_change(context: Self::AccessChange, result: UpdateResult) -> Option<Self>;
   fn access_location_default_updated() -> Option<Self>;
@@ -86,9 +89,13 @@ trait EventKind: 'static + Sized + Send {
   fn location_visibility_changed(context: Self::LocationVisibility, result: UpdateResult) -> Option<Self>;
   fn peers_updated() -> Option<Self>;
   fn player_online_state_updated() -> Option<Self>;
+  fn player_data(context: Self::PlayerData, data: Option<Vec<u8>>) -> Option<Self>;
   fn player_reset_changed(context: Self::PlayerReset, result: UpdateResult) -> Option<Self>;
   fn public_keys_changed(context: Self::PublicKey, result: UpdateResult) -> Option<Self>;
   fn public_keys_updated() -> Option<Self>;
+  fn second_factor_changed(context: Self::SecondFactor, result: UpdateResult) -> Option<Self>;
+  fn second_factor_enrolment(context: Self::SecondFactor, secret: String, url: String) -> Option<Self>;
+  fn second_factor_recovery_codes(context: Self::SecondFactor, codes: Vec<String>) -> Option<Self>;
 }
 
 enum Task<Event: EventKind> {
@@ -129,9 +136,11 @@ pub struct Server<Event: EventKind, Store: AssetStore> {
   peers: cache::Cache<BTreeSet<Peer>>,
   player_location: HashMap<PlayerIdentifier<String>, (DateTime<Utc>, OnlineState<String>)>,
   player_location_updates: TrackingMap<PlayerIdentifier<String>>,
+  player_data: TrackingMap<Event::PlayerData>,
   player_reset: TrackingMap<Event::PlayerReset>,
   public_key_updates: TrackingMap<Event::PublicKey>,
   public_keys: cache::Cache<BTreeMap<String, PublicKey>>,
+  second_factor_updates: TrackingMap<Event::SecondFactor>,
   tasks: SelectAll<BoxStream<'static, Task<Event>>>,
 }
 pub enum ServerEvent<T> {
@@ -142,6 +151,48 @@ pub enum ServerEvent<T> {
 }
 
 impl<Event: EventKind, Store: AssetStore + 'static> Server<Event, Store> {
+  /// Create a new server state around a connection established by [login::open] or [login::open_socket]
+  pub fn new(name: String, jwt: String, connection: ActiveConnection, asset_store: Arc<Store>) -> Self {
+    Server {
+      access_default: Default::default(),
+      access_message: Default::default(),
+      access_online: Default::default(),
+      access_updates: Default::default(),
+      activity_updates: Default::default(),
+      announcement_updates: Default::default(),
+      announcements: Default::default(),
+      asset_download: Default::default(),
+      asset_store,
+      asset_upload: Default::default(),
+      avatar: Default::default(),
+      avatar_updates: Default::default(),
+      banned_peers: Default::default(),
+      banned_peers_updates: Default::default(),
+      bookmark_updates: Default::default(),
+      bookmarks: Default::default(),
+      calendar_id: Default::default(),
+      calendar_location_updates: Default::default(),
+      calendar_locations: Default::default(),
+      connection,
+      direct_message_stats: Default::default(),
+      direct_messages: Default::default(),
+      direct_messages_outstanding: Default::default(),
+      jwt,
+      last_login: None,
+      location_searches: Default::default(),
+      location_visibility_updates: Default::default(),
+      name,
+      peers: Default::default(),
+      player_location: Default::default(),
+      player_location_updates: Default::default(),
+      player_data: Default::default(),
+      player_reset: Default::default(),
+      public_key_updates: Default::default(),
+      public_keys: Default::default(),
+      second_factor_updates: Default::default(),
+      tasks: SelectAll::new(),
+    }
+  }
   pub async fn check_activity(&mut self, player: PlayerIdentifier<&str>, check: Event::ActivityCheck) -> active_connection::SendResult<()> {
     let message = self.activity_updates.add(check, |id, _| ClientRequest::<_, &[u8]>::Activity { id, player }.into());
     self.connection.send(message).await
@@ -323,6 +374,10 @@ impl<Event: EventKind, Store: AssetStore + 'static> Server<Event, Store> {
         }
         Event::banned_peers_changed(callback, result).map(ServerEvent::Result)
       }
+      ClientResponse::PlayerData { id, data } => {
+        let callback = self.player_data.finish(id)?;
+        Event::player_data(callback, data).map(ServerEvent::Result)
+      }
       ClientResponse::PlayerReset { id, result } => {
         let callback = self.player_reset.finish(id)?;
         Event::player_reset_changed(callback, result).map(ServerEvent::Result)
@@ -338,6 +393,18 @@ impl<Event: EventKind, Store: AssetStore + 'static> Server<Event, Store> {
         }
         Event::public_keys_changed(callback, result).map(ServerEvent::Result)
       }
+      ClientResponse::SecondFactorEnrolment { id, secret, url } => {
+        let callback = self.second_factor_updates.finish(id)?;
+        Event::second_factor_enrolment(callback, secret, url).map(ServerEvent::Result)
+      }
+      ClientResponse::SecondFactorRecoveryCodes { id, codes } => {
+        let callback = self.second_factor_updates.finish(id)?;
+        Event::second_factor_recovery_codes(callback, codes).map(ServerEvent::Result)
+      }
+      ClientResponse::SecondFactorUpdate { id, result } => {
+        let callback = self.second_factor_updates.finish(id)?;
+        Event::second_factor_changed(callback, result).map(ServerEvent::Result)
+      }
       ClientResponse::LocationVisibility { id, result } => {
         let callback = self.location_visibility_updates.finish(id)?;
         Event::location_visibility_changed(callback, result).map(ServerEvent::Result)
@@ -388,10 +455,35 @@ impl<Event: EventKind, Store: AssetStore + 'static> Server<Event, Store> {
     );
   }
 
+  /// Request a JSON document with everything the server stores about this player
+  pub async fn export_player_data(&mut self, export: Event::PlayerData) -> active_connection::SendResult<()> {
+    let message = self.player_data.add(export, |id, _| ClientRequest::<&str, &[u8]>::PlayerDataExport { id }.into());
+    self.connection.send(message).await
+  }
   pub async fn reset_player(&mut self, player: &str, reset: Event::PlayerReset) -> active_connection::SendResult<()> {
     let message = self.player_reset.add(reset, |id, _| ClientRequest::<_, &[u8]>::PlayerReset { id, player }.into());
     self.connection.send(message).await
   }
+  /// Confirm the second factor being enrolled using a code from the authenticator; recovery codes are provided on success
+  pub async fn second_factor_confirm(&mut self, code: &str, update: Event::SecondFactor) -> active_connection::SendResult<()> {
+    let message = self.second_factor_updates.add(update, |id, _| ClientRequest::<_, &[u8]>::SecondFactorConfirm { id, code }.into());
+    self.connection.send(message).await
+  }
+  /// Remove the second factor using a code from the authenticator or a recovery code
+  pub async fn second_factor_disable(&mut self, code: &str, update: Event::SecondFactor) -> active_connection::SendResult<()> {
+    let message = self.second_factor_updates.add(update, |id, _| ClientRequest::<_, &[u8]>::SecondFactorDisable { id, code }.into());
+    self.connection.send(message).await
+  }
+  /// Start enrolling a second factor; it must be confirmed before it is required to log in
+  pub async fn second_factor_enrol(&mut self, update: Event::SecondFactor) -> active_connection::SendResult<()> {
+    let message = self.second_factor_updates.add(update, |id, _| ClientRequest::<&str, &[u8]>::SecondFactorEnrol { id }.into());
+    self.connection.send(message).await
+  }
+  /// Replace the recovery codes using a code from the authenticator or a recovery code
+  pub async fn second_factor_recovery_reset(&mut self, code: &str, update: Event::SecondFactor) -> active_connection::SendResult<()> {
+    let message = self.second_factor_updates.add(update, |id, _| ClientRequest::<_, &[u8]>::SecondFactorRecoveryReset { id, code }.into());
+    self.connection.send(message).await
+  }
   pub async fn search_locations(
     &mut self,
     source: Search<&str>,
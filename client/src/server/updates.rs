@@ -71,21 +71,24 @@ impl<S: AsRef<str>> Update<BTreeMap<String, PublicKey>> for Remove<S> {
   }
 }
 
-impl Update<BTreeMap<String, PublicKey>> for Add<&[u8]> {
+impl<S: AsRef<str>> Update<BTreeMap<String, PublicKey>> for Add<(S, &[u8])> {
   fn update(&self, id: u32, keys: Option<&mut BTreeMap<String, PublicKey>>) -> Option<Message> {
+    let (name, der) = &self.0;
     if let Some(keys) = keys {
-      let fingerprint = compute_fingerprint(self.0);
-      if keys.contains_key(fingerprint.as_str()) {
+      let fingerprint = compute_fingerprint(der);
+      if keys.get(name.as_ref()).map(|key| key.fingerprint == fingerprint).unwrap_or(false) {
         return None;
       }
-      keys.insert(fingerprint, PublicKey { created: Utc::now(), last_used: None });
+      keys.retain(|_, key| key.fingerprint != fingerprint);
+      keys.insert(name.as_ref().to_string(), PublicKey { created: Utc::now(), fingerprint, last_used: None });
     }
-    Some(ClientRequest::<&str, _>::PublicKeyAdd { id, der: self.0 }.into())
+    Some(ClientRequest::PublicKeyAdd { id, name: name.as_ref(), der: *der }.into())
   }
 }
-impl Update<BTreeMap<String, PublicKey>> for Add<&PKey<Public>> {
+impl<S: AsRef<str>> Update<BTreeMap<String, PublicKey>> for Add<(S, &PKey<Public>)> {
   fn update(&self, id: u32, keys: Option<&mut BTreeMap<String, PublicKey>>) -> Option<Message> {
-    Add(self.0.public_key_to_der().expect("Failed to encode public key").as_slice()).update(id, keys)
+    let (name, key) = &self.0;
+    Add((name.as_ref(), key.public_key_to_der().expect("Failed to encode public key").as_slice())).update(id, keys)
   }
 }
 
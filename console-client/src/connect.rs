@@ -0,0 +1,722 @@
+use chrono::{Duration, Utc};
+use spadina_client::configuration::{Configuration, ServerConfiguration};
+use spadina_client::server::direct_message::MessageFailure;
+use spadina_client::server::exports::Export;
+use spadina_client::server::login::{self, Credentials};
+use spadina_client::server::updates::{Add, Clear, Remove, Set, Update};
+use spadina_client::server::{EventKind, Server, ServerEvent};
+use spadina_core::access::{AccessControl, AccessSetting, BannedPeer, OnlineAccess, Privilege, SimpleAccess};
+use spadina_core::asset::Asset;
+use spadina_core::asset_store::file_system_asset_store::FileSystemAssetStore;
+use spadina_core::avatar::Avatar;
+use spadina_core::communication::{Announcement, DirectMessage, MessageBody};
+use spadina_core::location::change::LocationChangeResponse;
+use spadina_core::location::directory::{Activity, DirectoryEntry, Search, SearchCriteria, Visibility};
+use spadina_core::location::protocol::LocationResponse;
+use spadina_core::location::target::{AbsoluteTarget, LocalTarget, UnresolvedTarget};
+use spadina_core::net::server::hosting::HostEvent;
+use spadina_core::net::server::AssetError;
+use spadina_core::player::PlayerIdentifier;
+use spadina_core::resource::Resource;
+use spadina_core::UpdateResult;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio_tungstenite::tungstenite::Message;
+
+type ConsoleServer = Server<ConsoleEvent, FileSystemAssetStore<String>>;
+
+const HELP: &str = "Commands:
+  access <dm|location|online>                            Show an access control list
+  access <dm|location|online> default <value>            Change the default access
+  access <dm|location|online> player <player> <value>    Add a rule for a player
+  access <dm|location|online> server <server> <value>    Add a rule for all players on a server
+  access <dm|location|online> domain <domain> <value>    Add a rule for all servers in a domain
+  access <dm|location|online> local <value>              Add a rule for all players on this server
+  access <dm|location|online> clear                      Remove all rules, keeping the default
+  announcements                                          Show the server's announcements
+  announcements clear                                    Remove all announcements (administrators only)
+  bookmarks                                              Show bookmarks
+  bookmark add <resource>                                Add a bookmark
+  bookmark remove <resource>                             Remove a bookmark
+  dm <player> <message>                                  Send a direct message
+  export <file>                                          Save everything the server stores about you to a file
+  otp enrol                                              Start requiring a one-time code to log in
+  otp confirm <code>                                     Finish enrolling using a code from the authenticator
+  otp disable <code>                                     Stop requiring a one-time code to log in
+  otp recovery <code>                                    Replace the recovery codes
+  read <player> [days]                                   Show direct messages from the last few days (default 1)
+  search <bookmarks|calendar|mine|local|remote <server>|name <text>>
+                                                         Search for locations
+  help                                                   Show this message
+  quit                                                   Disconnect
+
+Values for dm are allow or deny; for location: access, admin, or deny; for online: location, online, or deny";
+
+pub enum ConsoleEvent {
+  Access(AccessKind),
+  Announcements,
+  Bookmarks,
+  DirectMessage(PlayerIdentifier<String>),
+  Notice(String),
+}
+#[derive(Copy, Clone)]
+pub enum AccessKind {
+  DirectMessage,
+  Location,
+  Online,
+}
+pub enum AccessChange {
+  DirectMessage(AccessSetting<String, SimpleAccess>),
+  Location(AccessSetting<String, Privilege>),
+  Online(AccessSetting<String, OnlineAccess>),
+}
+pub enum BookmarkChange {
+  Add(Resource<String>),
+  Remove(Resource<String>),
+}
+/// A request type for features the console does not support
+pub enum Unsupported {}
+
+/// Prints a cached value to the console or indicates that it is being fetched
+struct Print;
+
+trait AccessValue: Copy + std::fmt::Debug + Sized {
+  fn parse(value: &str) -> Option<Self>;
+}
+
+impl EventKind for ConsoleEvent {
+  type AccessChange = AccessChange;
+  type ActivityCheck = ();
+  type Announcement = Clear;
+  type AssetDownload = ();
+  type AssetUpload = ();
+  type Avatar = Set<Avatar>;
+  type BannedPeers = Unsupported;
+  type Bookmark = BookmarkChange;
+  type CalendarLocation = Add<LocalTarget<String>>;
+  type LocationSearch = ();
+  type LocationVisibility = ();
+  type PlayerData = String;
+  type PlayerReset = ();
+  type PublicKey = Clear;
+  type SecondFactor = ();
+
+  fn access_change(_context: Self::AccessChange, result: UpdateResult) -> Option<Self> {
+    Some(ConsoleEvent::Notice(format!("Access change: {:?}", result)))
+  }
+
+  fn access_location_default_updated() -> Option<Self> {
+    Some(ConsoleEvent::Access(AccessKind::Location))
+  }
+
+  fn access_message_updated() -> Option<Self> {
+    Some(ConsoleEvent::Access(AccessKind::DirectMessage))
+  }
+
+  fn access_online_updated() -> Option<Self> {
+    Some(ConsoleEvent::Access(AccessKind::Online))
+  }
+
+  fn activity_event(_context: Self::ActivityCheck, _activity: Activity) -> Option<Self> {
+    None
+  }
+
+  fn announcement_change(_context: Self::Announcement, result: UpdateResult) -> Option<Self> {
+    Some(ConsoleEvent::Notice(format!("Announcement change: {:?}", result)))
+  }
+
+  fn announcements_updated() -> Option<Self> {
+    Some(ConsoleEvent::Announcements)
+  }
+
+  fn asset_available(_context: Self::AssetDownload, _asset: Asset<String, Vec<u8>>) -> Option<Self> {
+    None
+  }
+
+  fn asset_unavailable(_context: Self::AssetDownload) -> Option<Self> {
+    None
+  }
+
+  fn asset_uploaded(_context: Self::AssetUpload, _result: Result<(), AssetError>) -> Option<Self> {
+    None
+  }
+
+  fn avatar_update(_context: Self::Avatar, _result: UpdateResult) -> Option<Self> {
+    None
+  }
+
+  fn avatar_updated() -> Option<Self> {
+    None
+  }
+
+  fn banned_peers_changed(context: Self::BannedPeers, _result: UpdateResult) -> Option<Self> {
+    match context {}
+  }
+
+  fn banned_peers_updated() -> Option<Self> {
+    None
+  }
+
+  fn bookmark_update(_context: Self::Bookmark, success: bool) -> Option<Self> {
+    if success {
+      None
+    } else {
+      Some(ConsoleEvent::Notice("Failed to update bookmarks".to_string()))
+    }
+  }
+
+  fn bookmarks_updated() -> Option<Self> {
+    Some(ConsoleEvent::Bookmarks)
+  }
+
+  fn calendar_updated() -> Option<Self> {
+    None
+  }
+
+  fn calendar_location_changed(_context: Self::CalendarLocation, _success: bool) -> Option<Self> {
+    None
+  }
+
+  fn calendar_location_updated() -> Option<Self> {
+    None
+  }
+
+  fn direct_message(sender: PlayerIdentifier<String>) -> Option<Self> {
+    Some(ConsoleEvent::DirectMessage(sender))
+  }
+
+  fn direct_message_failed(player: PlayerIdentifier<String>, failure: MessageFailure, _body: MessageBody<String>) -> Option<Self> {
+    Some(ConsoleEvent::Notice(format!(
+      "Failed to send message to {}: {}",
+      player,
+      match failure {
+        MessageFailure::UnknownRecipient => "unknown recipient",
+        MessageFailure::Forbidden => "not allowed",
+        MessageFailure::InternalError => "internal error",
+      }
+    )))
+  }
+
+  fn direct_message_stats_updated() -> Option<Self> {
+    None
+  }
+
+  fn hosting(_event: HostEvent<String, Vec<u8>>) -> Option<Self> {
+    None
+  }
+
+  fn in_location(_response: LocationResponse<String, Vec<u8>>) -> Option<Self> {
+    None
+  }
+
+  fn location_change(_response: LocationChangeResponse<String>) -> Option<Self> {
+    None
+  }
+
+  fn location_search(_context: Self::LocationSearch, result: Result<Vec<DirectoryEntry<String>>, Option<String>>) -> Option<Self> {
+    Some(ConsoleEvent::Notice(match result {
+      Ok(entries) if entries.is_empty() => "No locations found".to_string(),
+      Ok(entries) => entries
+        .into_iter()
+        .map(|entry| {
+          let description = format!("{} (owner: {} on {}, {:?})", &entry.name, &entry.owner, &entry.server, entry.activity);
+          let resource =
+            Resource::Location(UnresolvedTarget::Absolute(AbsoluteTarget { descriptor: entry.descriptor, owner: entry.owner, server: entry.server }));
+          format!("{}\n  {}", description, resource)
+        })
+        .collect::<Vec<_>>()
+        .join("\n"),
+      Err(Some(server)) => format!("Search failed on {}", server),
+      Err(None) => "Search failed".to_string(),
+    }))
+  }
+
+  fn location_visibility_changed(_context: Self::LocationVisibility, _result: UpdateResult) -> Option<Self> {
+    None
+  }
+
+  fn peers_updated() -> Option<Self> {
+    None
+  }
+
+  fn player_online_state_updated() -> Option<Self> {
+    None
+  }
+
+  fn player_data(context: Self::PlayerData, data: Option<Vec<u8>>) -> Option<Self> {
+    Some(ConsoleEvent::Notice(match data {
+      None => "Server could not export your data".to_string(),
+      Some(data) => match std::fs::write(&context, data) {
+        Ok(()) => format!("Saved your data to {}", context),
+        Err(e) => format!("Failed to save your data to {}: {}", context, e),
+      },
+    }))
+  }
+
+  fn player_reset_changed(_context: Self::PlayerReset, _result: UpdateResult) -> Option<Self> {
+    None
+  }
+
+  fn public_keys_changed(_context: Self::PublicKey, _result: UpdateResult) -> Option<Self> {
+    None
+  }
+
+  fn public_keys_updated() -> Option<Self> {
+    None
+  }
+
+  fn second_factor_changed(_context: Self::SecondFactor, result: UpdateResult) -> Option<Self> {
+    Some(ConsoleEvent::Notice(format!("One-time password change: {:?}", result)))
+  }
+
+  fn second_factor_enrolment(_context: Self::SecondFactor, secret: String, url: String) -> Option<Self> {
+    Some(ConsoleEvent::Notice(format!(
+      "Add this secret to an authenticator app: {}\n  or use: {}\nThen use `otp confirm <code>` with a code from the app to finish enrolling",
+      secret, url
+    )))
+  }
+
+  fn second_factor_recovery_codes(_context: Self::SecondFactor, codes: Vec<String>) -> Option<Self> {
+    Some(ConsoleEvent::Notice(format!(
+      "Store these recovery codes somewhere safe; each can be used once in place of a one-time code:\n  {}",
+      codes.join("\n  ")
+    )))
+  }
+}
+
+impl Update<AccessSetting<String, SimpleAccess>> for AccessChange {
+  fn update(&self, id: u32, entry: Option<&mut AccessSetting<String, SimpleAccess>>) -> Option<Message> {
+    match self {
+      AccessChange::DirectMessage(setting) => Set(setting).update(id, entry),
+      _ => None,
+    }
+  }
+}
+impl Update<AccessSetting<String, Privilege>> for AccessChange {
+  fn update(&self, id: u32, entry: Option<&mut AccessSetting<String, Privilege>>) -> Option<Message> {
+    match self {
+      AccessChange::Location(setting) => Set(setting).update(id, entry),
+      _ => None,
+    }
+  }
+}
+impl Update<AccessSetting<String, OnlineAccess>> for AccessChange {
+  fn update(&self, id: u32, entry: Option<&mut AccessSetting<String, OnlineAccess>>) -> Option<Message> {
+    match self {
+      AccessChange::Online(setting) => Set(setting).update(id, entry),
+      _ => None,
+    }
+  }
+}
+impl Update<HashSet<Resource<String>>> for BookmarkChange {
+  fn update(&self, id: u32, entry: Option<&mut HashSet<Resource<String>>>) -> Option<Message> {
+    match self {
+      BookmarkChange::Add(resource) => Add(resource).update(id, entry),
+      BookmarkChange::Remove(resource) => Remove(resource).update(id, entry),
+    }
+  }
+}
+impl Update<HashSet<BannedPeer<String>>> for Unsupported {
+  fn update(&self, _id: u32, _entry: Option<&mut HashSet<BannedPeer<String>>>) -> Option<Message> {
+    match *self {}
+  }
+}
+
+impl<T: AccessValue> Export<AccessSetting<String, T>> for Print {
+  type Output<'a>
+    = Option<AccessSetting<String, T>>
+  where
+    T: 'a;
+
+  fn export<'a>(self, value: Option<&'a AccessSetting<String, T>>) -> Self::Output<'a> {
+    match value {
+      None => println!("Fetching access control list..."),
+      Some(setting) => {
+        println!("Default: {:?}", setting.default);
+        for rule in &setting.rules {
+          match rule {
+            AccessControl::Player(player, expiry, access) => println!("Player {}: {:?}{}", player, access, until(expiry)),
+            AccessControl::Server(server, expiry, access) => println!("Server {}: {:?}{}", server, access, until(expiry)),
+            AccessControl::Domain(domain, expiry, access) => println!("Domain {}: {:?}{}", domain, access, until(expiry)),
+            AccessControl::Local(expiry, access) => println!("Local players: {:?}{}", access, until(expiry)),
+          }
+        }
+      }
+    }
+    value.cloned()
+  }
+}
+impl Export<Vec<Announcement<String>>> for Print {
+  type Output<'a> = ();
+
+  fn export<'a>(self, value: Option<&'a Vec<Announcement<String>>>) -> Self::Output<'a> {
+    match value {
+      None => println!("Fetching announcements..."),
+      Some(announcements) if announcements.is_empty() => println!("No announcements"),
+      Some(announcements) => {
+        for announcement in announcements {
+          println!("{}\n  {}", &announcement.title, &announcement.body);
+        }
+      }
+    }
+  }
+}
+impl Export<HashSet<Resource<String>>> for Print {
+  type Output<'a> = ();
+
+  fn export<'a>(self, value: Option<&'a HashSet<Resource<String>>>) -> Self::Output<'a> {
+    match value {
+      None => println!("Fetching bookmarks..."),
+      Some(bookmarks) if bookmarks.is_empty() => println!("No bookmarks"),
+      Some(bookmarks) => {
+        for bookmark in bookmarks {
+          println!("{}", bookmark);
+        }
+      }
+    }
+  }
+}
+impl Export<[DirectMessage<String>]> for Print {
+  type Output<'a> = ();
+
+  fn export<'a>(self, value: Option<&'a [DirectMessage<String>]>) -> Self::Output<'a> {
+    match value {
+      None => println!("Fetching messages..."),
+      Some(messages) if messages.is_empty() => println!("No messages"),
+      Some(messages) => {
+        for message in messages {
+          let body = match &message.body {
+            MessageBody::Text(text) => text.clone(),
+            MessageBody::Announcement(announcement) => format!("[announcement] {}", &announcement.title),
+            MessageBody::Reply(_, text) => format!("[reply] {}", text),
+            MessageBody::Resource(resource) => format!("[resource] {}", resource),
+            MessageBody::Read => continue,
+            MessageBody::Typing => continue,
+            _ => "[unsupported message]".to_string(),
+          };
+          println!("{} {} {}", message.timestamp.format("%Y-%m-%d %H:%M"), if message.inbound { "<" } else { ">" }, body);
+        }
+      }
+    }
+  }
+}
+
+impl AccessValue for SimpleAccess {
+  fn parse(value: &str) -> Option<Self> {
+    match value {
+      "allow" => Some(SimpleAccess::Allow),
+      "deny" => Some(SimpleAccess::Deny),
+      _ => None,
+    }
+  }
+}
+impl AccessValue for Privilege {
+  fn parse(value: &str) -> Option<Self> {
+    match value {
+      "access" => Some(Privilege::Access),
+      "admin" => Some(Privilege::Admin),
+      "deny" => Some(Privilege::Deny),
+      _ => None,
+    }
+  }
+}
+impl AccessValue for OnlineAccess {
+  fn parse(value: &str) -> Option<Self> {
+    match value {
+      "location" => Some(OnlineAccess::Location),
+      "online" => Some(OnlineAccess::OnlineOnly),
+      "deny" => Some(OnlineAccess::Deny),
+      _ => None,
+    }
+  }
+}
+
+fn until(expiry: &Option<chrono::DateTime<Utc>>) -> String {
+  match expiry {
+    Some(expiry) => format!(" until {}", expiry),
+    None => String::new(),
+  }
+}
+
+/// Apply an access command to an access control list, returning the updated list
+fn edit_access<T: AccessValue>(mut setting: AccessSetting<String, T>, arguments: &[&str]) -> Result<AccessSetting<String, T>, &'static str> {
+  let value = |value: &str| T::parse(value).ok_or("Invalid access value");
+  match arguments {
+    ["default", access] => setting.default = value(access)?,
+    ["player", player, access] => {
+      let player = player.parse::<PlayerIdentifier<String>>().map_err(|_| "Invalid player name")?;
+      setting.rules.push(AccessControl::Player(player, None, value(access)?))
+    }
+    ["server", server, access] => setting.rules.push(AccessControl::Server(server.to_string(), None, value(access)?)),
+    ["domain", domain, access] => setting.rules.push(AccessControl::Domain(domain.to_string(), None, value(access)?)),
+    ["local", access] => setting.rules.push(AccessControl::Local(None, value(access)?)),
+    ["clear"] => setting.rules.clear(),
+    _ => return Err("Unknown access command; try help"),
+  }
+  Ok(setting)
+}
+
+/// Process a single line of input; returns false if the session should end
+async fn command(server: &mut ConsoleServer, line: &str) -> Result<bool, tokio_tungstenite::tungstenite::Error> {
+  let words: Vec<_> = line.split_whitespace().collect();
+  match words.as_slice() {
+    [] => (),
+    ["quit"] | ["exit"] => return Ok(false),
+    ["help"] => println!("{}", HELP),
+    ["access", "dm"] => {
+      server.access_direct_message(Print).await?;
+    }
+    ["access", "location"] => {
+      server.access_location_default(Print).await?;
+    }
+    ["access", "online"] => {
+      server.access_online_status(Print).await?;
+    }
+    ["access", "dm", arguments @ ..] => match server.access_direct_message(Print).await? {
+      None => println!("Access control list is not available yet; try again"),
+      Some(setting) => match edit_access(setting, arguments) {
+        Ok(setting) => server.access_direct_message_request(AccessChange::DirectMessage(setting)).await?,
+        Err(message) => println!("{}", message),
+      },
+    },
+    ["access", "location", arguments @ ..] => match server.access_location_default(Print).await? {
+      None => println!("Access control list is not available yet; try again"),
+      Some(setting) => match edit_access(setting, arguments) {
+        Ok(setting) => server.access_location_default_request(AccessChange::Location(setting)).await?,
+        Err(message) => println!("{}", message),
+      },
+    },
+    ["access", "online", arguments @ ..] => match server.access_online_status(Print).await? {
+      None => println!("Access control list is not available yet; try again"),
+      Some(setting) => match edit_access(setting, arguments) {
+        Ok(setting) => server.access_online_request(AccessChange::Online(setting)).await?,
+        Err(message) => println!("{}", message),
+      },
+    },
+    ["announcements"] => server.announcements(Print).await?,
+    ["announcements", "clear"] => server.announcements_request(Clear).await?,
+    ["bookmarks"] => server.bookmarks(Print).await?,
+    ["bookmark", action @ ("add" | "remove"), resource] => match Resource::try_from(*resource) {
+      Ok(resource) => {
+        server.bookmarks_request(if *action == "add" { BookmarkChange::Add(resource) } else { BookmarkChange::Remove(resource) }).await?
+      }
+      Err(e) => println!("Invalid resource: {:?}", e),
+    },
+    ["dm", player, message @ ..] if !message.is_empty() => match player.parse::<PlayerIdentifier<String>>() {
+      Ok(player) => server.direct_message_send(player, MessageBody::Text(message.join(" "))).await?,
+      Err(_) => println!("Invalid player name"),
+    },
+    ["export", file] => server.export_player_data(file.to_string()).await?,
+    ["otp", "enrol"] => server.second_factor_enrol(()).await?,
+    ["otp", "confirm", code] => server.second_factor_confirm(code, ()).await?,
+    ["otp", "disable", code] => server.second_factor_disable(code, ()).await?,
+    ["otp", "recovery", code] => server.second_factor_recovery_reset(code, ()).await?,
+    ["read", player, days @ ..] => {
+      let days = match days {
+        [] => Some(1),
+        [days] => days.parse::<i64>().ok(),
+        _ => None,
+      };
+      match (player.parse::<PlayerIdentifier<String>>(), days) {
+        (Ok(player), Some(days)) => {
+          let now = Utc::now();
+          server.direct_message_read(player, now - Duration::days(days), now, Print).await?;
+        }
+        (Err(_), _) => println!("Invalid player name"),
+        (_, None) => println!("Invalid number of days"),
+      }
+    }
+    ["search", "bookmarks"] => server.search_locations(Search::Bookmarks, Duration::seconds(30), ()).await?,
+    ["search", "calendar"] => server.search_locations(Search::Calendar, Duration::seconds(30), ()).await?,
+    ["search", "mine"] => server.search_locations(Search::Personal(vec![Visibility::Public, Visibility::Private]), Duration::seconds(30), ()).await?,
+    ["search", "local"] => server.search_locations(Search::PublicLocal, Duration::seconds(30), ()).await?,
+    ["search", "remote", remote] => server.search_locations(Search::PublicRemote(*remote), Duration::seconds(30), ()).await?,
+    ["search", "name", text @ ..] if !text.is_empty() => {
+      let text = text.join(" ");
+      server
+        .search_locations(
+          Search::PublicSearch { query: SearchCriteria::NameContains { text: text.as_str(), case_sensitive: false }, server: None },
+          Duration::seconds(30),
+          (),
+        )
+        .await?
+    }
+    _ => println!("Unknown command; try help"),
+  }
+  Ok(true)
+}
+
+async fn handle(server: &mut ConsoleServer, event: ConsoleEvent) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+  match event {
+    ConsoleEvent::Access(AccessKind::DirectMessage) => {
+      server.access_direct_message(Print).await?;
+    }
+    ConsoleEvent::Access(AccessKind::Location) => {
+      server.access_location_default(Print).await?;
+    }
+    ConsoleEvent::Access(AccessKind::Online) => {
+      server.access_online_status(Print).await?;
+    }
+    ConsoleEvent::Announcements => server.announcements(Print).await?,
+    ConsoleEvent::Bookmarks => server.bookmarks(Print).await?,
+    ConsoleEvent::DirectMessage(player) => println!("New messages from {}; use `read {}` to see them", &player, &player),
+    ConsoleEvent::Notice(message) => println!("{}", message),
+  }
+  Ok(())
+}
+
+async fn prompt(message: &str, input: &mut tokio::io::Lines<BufReader<tokio::io::Stdin>>) -> Option<String> {
+  let mut stdout = tokio::io::stdout();
+  stdout.write_all(message.as_bytes()).await.ok()?;
+  stdout.flush().await.ok()?;
+  input.next_line().await.ok().flatten()
+}
+
+pub fn run(args: Vec<String>) {
+  let mut directory = String::new();
+  let mut account = String::new();
+  let mut use_key = false;
+  let mut use_otp = false;
+  {
+    let mut ap = argparse::ArgumentParser::new();
+    ap.set_description("Connect to a server and start an interactive session");
+    ap.refer(&mut directory).add_option(&["-d", "--directory"], argparse::Store, "The directory to store assets in").required();
+    ap.refer(&mut use_key).add_option(&["-k", "--key"], argparse::StoreTrue, "Log in using this client's public key instead of a password");
+    ap.refer(&mut use_otp).add_option(&["-o", "--otp"], argparse::StoreTrue, "Prompt for a one-time code after the password");
+    ap.refer(&mut account).add_argument(
+      "account",
+      argparse::Store,
+      "The number of a saved account, a player@server login, or the path to a server's UNIX socket; if omitted, saved accounts are listed",
+    );
+    match ap.parse(args, &mut std::io::stdout(), &mut std::io::stderr()) {
+      Ok(()) => (),
+      Err(x) => {
+        std::process::exit(x);
+      }
+    }
+  }
+  let mut configuration = Configuration::load();
+  let account = if account.is_empty() {
+    for (index, account) in configuration.connections() {
+      match account {
+        ServerConfiguration::Remote { player, server } => println!("{}: {}@{}", index, player, server),
+        ServerConfiguration::Socket(path) => println!("{}: {}", index, path),
+      }
+    }
+    return;
+  } else if let Ok(index) = account.parse::<usize>() {
+    match configuration.connections().find(|(i, _)| *i == index) {
+      Some((_, account)) => account.clone(),
+      None => {
+        eprintln!("No saved account {}", index);
+        std::process::exit(1);
+      }
+    }
+  } else {
+    match configuration.process_connection_string(&account) {
+      Ok(account) => account,
+      Err(e) => {
+        eprintln!("Invalid account: {}", e);
+        std::process::exit(1);
+      }
+    }
+  };
+  let asset_store = Arc::new(FileSystemAssetStore::new(directory, [4, 4, 8].iter().cloned()));
+  let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+  runtime.block_on(async move {
+    let mut input = BufReader::new(tokio::io::stdin()).lines();
+    let (name, token, connection) = match account {
+      ServerConfiguration::Remote { player, server } => {
+        let token = if use_key {
+          let key = match configuration.private_key() {
+            Ok(key) => key,
+            Err(e) => {
+              eprintln!("Failed to load client key: {}", e);
+              return;
+            }
+          };
+          login::login(&server, &player, Credentials::PublicKey(&key)).await
+        } else {
+          let password = match tokio::task::spawn_blocking(|| rpassword::prompt_password("Password: ")).await {
+            Ok(Ok(password)) => password,
+            Ok(Err(e)) => {
+              eprintln!("Failed to read password: {}", e);
+              return;
+            }
+            Err(e) => {
+              eprintln!("Failed to read password: {}", e);
+              return;
+            }
+          };
+          let otp = if use_otp {
+            let Some(otp) = prompt("One-time code: ", &mut input).await else {
+              return;
+            };
+            Some(otp)
+          } else {
+            None
+          };
+          login::login(&server, &player, Credentials::Password { password: &password, otp: otp.as_deref() }).await
+        };
+        let token = match token {
+          Ok(token) => token,
+          Err(e) => {
+            eprintln!("Failed to log in to {}: {}", &server, e);
+            return;
+          }
+        };
+        match login::open(&server, &token).await {
+          Ok(connection) => (server, token, connection),
+          Err(e) => {
+            eprintln!("Failed to connect to {}: {}", &server, e);
+            return;
+          }
+        }
+      }
+      ServerConfiguration::Socket(path) => {
+        let player = std::env::var("USER").unwrap_or_default();
+        match login::open_socket(&path, &player).await {
+          Ok(connection) => (path, String::new(), connection),
+          Err(e) => {
+            eprintln!("Failed to connect to {}: {}", &path, e);
+            return;
+          }
+        }
+      }
+    };
+    println!("Connected to {}. Type help for a list of commands.", &name);
+    let mut server: ConsoleServer = Server::new(name, token, connection, asset_store);
+    loop {
+      let result = tokio::select! {
+        event = server.next() => match event {
+          ServerEvent::Result(event) => handle(&mut server, event).await,
+          ServerEvent::Disconnected => {
+            println!("Disconnected from server");
+            break;
+          }
+          ServerEvent::Reconnected => {
+            println!("Reconnected to server");
+            Ok(())
+          }
+          ServerEvent::BadMessage => Ok(()),
+        },
+        line = input.next_line() => match line {
+          Ok(Some(line)) => match command(&mut server, &line).await {
+            Ok(true) => Ok(()),
+            Ok(false) => break,
+            Err(e) => Err(e),
+          },
+          Ok(None) => break,
+          Err(e) => {
+            eprintln!("Failed to read input: {}", e);
+            break;
+          }
+        },
+      };
+      if let Err(e) = result {
+        eprintln!("Failed to send request to server: {}", e);
+      }
+    }
+  });
+}
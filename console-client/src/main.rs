@@ -4,10 +4,14 @@ use spadina_core::asset_store::AssetStore;
 use spadina_core::reference_converter::ForPacket;
 use std::sync::Arc;
 
+mod connect;
+mod simulate;
+
 #[derive(Debug)]
 enum Command {
   Install,
   Connect,
+  Simulate,
 }
 
 impl std::str::FromStr for Command {
@@ -16,6 +20,7 @@ impl std::str::FromStr for Command {
     return match src {
       "install" => Ok(Command::Install),
       "connect" => Ok(Command::Connect),
+      "simulate" => Ok(Command::Simulate),
       _ => Err(()),
     };
   }
@@ -26,7 +31,7 @@ fn main() {
   let mut args = vec![];
   {
     let mut ap = argparse::ArgumentParser::new();
-    ap.set_description("Spadina On-Disk Asset Tool");
+    ap.set_description("Spadina Console Client");
     ap.refer(&mut subcommand).required().add_argument("command", argparse::Store, "Command to run");
     ap.refer(&mut args).add_argument("arguments", argparse::List, "Arguments for command");
     ap.stop_on_first_argument(true);
@@ -62,8 +67,7 @@ fn main() {
         runtime.spawn(async move { asset_store.push(&asset.principal_hash(), &asset.reference(ForPacket)).await });
       }
     }
-    Command::Connect => {
-      todo!()
-    }
+    Command::Connect => connect::run(args),
+    Command::Simulate => simulate::run(args),
   }
 }
@@ -0,0 +1,125 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use spadina_core::controller::puzzle::state_machine::simulator::{Simulation, SimulationStep, TraceEvent};
+use spadina_core::controller::puzzle::state_machine::StateMachinePuzzleTemplate;
+use spadina_core::controller::puzzle::MultiStateValue;
+use std::fmt::Display;
+
+/// Identifiers are kept as raw values so templates using either numeric or named identifiers can be simulated
+type Identifier = serde_json::Value;
+
+fn print_event(now: DateTime<Utc>, timezone: &Tz, event: TraceEvent<'_, Identifier>) {
+  let time = now.with_timezone(timezone).format("%Y-%m-%d %H:%M:%S");
+  match event {
+    TraceEvent::Counter { machine, counter, old, new } => println!("[{}]   machine {}: counter {} {} → {}", time, machine, counter, old, new),
+    TraceEvent::Output { machine, output, value } => println!("[{}]   machine {}: output {} = {}", time, machine, output, format_value(value)),
+    TraceEvent::Timer { machine, expires: Some(expires) } => {
+      println!("[{}]   machine {}: timer expires {}", time, machine, expires.with_timezone(timezone).format("%Y-%m-%d %H:%M:%S"))
+    }
+    TraceEvent::Timer { machine, expires: None } => println!("[{}]   machine {}: timer cleared", time, machine),
+    TraceEvent::Transition { machine, from, to } => println!("[{}]   machine {}: state {} → {}", time, machine, from, to),
+    TraceEvent::Triggers(triggers) => {
+      println!("[{}]   internal events {}", time, triggers.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", "))
+    }
+  }
+}
+
+fn format_value(value: &MultiStateValue) -> String {
+  fn multi<T: Display>(default: &T, values: &[(impl std::fmt::Debug, T)]) -> String {
+    let mut result = default.to_string();
+    for (condition, value) in values {
+      result.push_str(&format!(", {} if {:?}", value, condition));
+    }
+    result
+  }
+  match value {
+    MultiStateValue::Bool(value) => value.to_string(),
+    MultiStateValue::Num(value) => value.to_string(),
+    MultiStateValue::MultiBool { default, values } => multi(default, values.as_slice()),
+    MultiStateValue::MultiNum { default, values } => multi(default, values.as_slice()),
+  }
+}
+
+fn load_template(path: &str) -> Result<StateMachinePuzzleTemplate<Identifier, Identifier, Identifier>, String> {
+  let file = std::fs::File::open(path).map_err(|e| format!("Cannot open template: {}", e))?;
+  if path.ends_with(".json") {
+    serde_json::from_reader(file).map_err(|e| format!("Cannot parse template: {}", e))
+  } else {
+    rmp_serde::from_read(file).map_err(|e| format!("Cannot parse template: {}", e))
+  }
+}
+
+fn load_script(path: &str) -> Result<Vec<SimulationStep<Identifier, Identifier>>, String> {
+  let file = std::fs::File::open(path).map_err(|e| format!("Cannot open script: {}", e))?;
+  serde_json::from_reader(file).map_err(|e| format!("Cannot parse script: {}", e))
+}
+
+pub fn run(args: Vec<String>) {
+  let mut template_path = String::new();
+  let mut script_path = String::new();
+  let mut seed = 0_u32;
+  let mut timezone = Tz::UTC;
+  let mut start = "2000-01-01T00:00:00Z".to_string();
+  {
+    let mut ap = argparse::ArgumentParser::new();
+    ap.set_description("Runs a scripted sequence of events through a puzzle and prints everything that happens");
+    ap.refer(&mut template_path)
+      .add_option(&["-t", "--template"], argparse::Store, "The puzzle template, as JSON (.json) or MessagePack (any other extension)")
+      .required();
+    ap.refer(&mut script_path).add_option(&["-s", "--script"], argparse::Store, "A JSON file containing the list of steps to perform").required();
+    ap.refer(&mut seed).add_option(&["--seed"], argparse::Store, "The random seed for the puzzle");
+    ap.refer(&mut timezone).add_option(&["-z", "--timezone"], argparse::Store, "The timezone the puzzle is in");
+    ap.refer(&mut start).add_option(&["--start"], argparse::Store, "The simulated time when the puzzle is created (RFC 3339)");
+    match ap.parse(args, &mut std::io::stdout(), &mut std::io::stderr()) {
+      Ok(()) => (),
+      Err(x) => {
+        std::process::exit(x);
+      }
+    }
+  }
+  let template = load_template(&template_path).unwrap_or_else(|e| {
+    eprintln!("{}", e);
+    std::process::exit(1);
+  });
+  let steps = load_script(&script_path).unwrap_or_else(|e| {
+    eprintln!("{}", e);
+    std::process::exit(1);
+  });
+  let start = match DateTime::parse_from_rfc3339(&start) {
+    Ok(start) => start.with_timezone(&timezone),
+    Err(e) => {
+      eprintln!("Invalid start time: {}", e);
+      std::process::exit(1);
+    }
+  };
+
+  let mut simulation = match Simulation::new(&template, seed, start) {
+    Ok(simulation) => simulation,
+    Err(e) => {
+      eprintln!("Failed to create puzzle: {}", e);
+      std::process::exit(1);
+    }
+  };
+  println!("[{}] initial outputs", start.format("%Y-%m-%d %H:%M:%S"));
+  for (output, value) in simulation.outputs() {
+    println!("[{}]   output {} = {}", start.format("%Y-%m-%d %H:%M:%S"), output, format_value(value));
+  }
+  for step in steps {
+    let time = simulation.now().with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S");
+    match &step {
+      SimulationStep::Advance { seconds } => println!("[{}] advance {} seconds", time, seconds),
+      SimulationStep::Area { area, players } if players.is_empty() => println!("[{}] area {} is now empty", time, area),
+      SimulationStep::Area { area, players } => println!(
+        "[{}] area {} now contains players with states {}",
+        time,
+        area,
+        players.iter().map(|p| format!("{:#x}", p)).collect::<Vec<_>>().join(", ")
+      ),
+      SimulationStep::Click { input, state } => println!("[{}] click {} by player with state {:#x}", time, input, state),
+    }
+    if let Err(e) = simulation.run(step, |now, event| print_event(now, &timezone, event)) {
+      eprintln!("Puzzle failed: {}", e);
+      std::process::exit(1);
+    }
+  }
+}
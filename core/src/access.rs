@@ -285,6 +285,14 @@ impl<S: AsRef<str> + Eq + Ord> LocalAccessSetting<S> {
       self.exceptions.insert(player)
     }
   }
+  /// The access for players that are not exceptions
+  pub fn default_access(&self) -> SimpleAccess {
+    self.default
+  }
+  /// The players that have the opposite of the default access
+  pub fn exceptions(&self) -> impl Iterator<Item = &S> {
+    self.exceptions.iter()
+  }
   pub fn check(&self, player: &str) -> SimpleAccess
   where
     S: Borrow<str>,
@@ -1,10 +1,15 @@
 use crate::asset::extraction::ExtractChildren;
 use crate::asset::Asset;
+use crate::controller::boxed_template::BoxedTemplate;
+use crate::controller::puzzle::PuzzleRealmTemplate;
+use crate::controller::serde_controller::SerdeControllerTemplate;
 use crate::controller::GenericControllerTemplate;
+use crate::realm::room_positions::RoomPositions;
 use crate::realm::room_world;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum AllSupportedAssets<S: AsRef<str> + Ord> {
@@ -15,15 +20,43 @@ impl<S: AsRef<str> + Ord> AllSupportedAssets<S> {
   pub fn create_realm_template<B: AsRef<[u8]>>(
     &self,
     children: &BTreeMap<String, impl AsRef<Asset<S, B>>>,
-  ) -> Result<GenericControllerTemplate, Option<Vec<S>>> {
-    todo!()
+  ) -> Result<GenericControllerTemplate, Option<Vec<S>>>
+  where
+    S: Clone + Send + Sync + 'static,
+  {
+    let mut required = BTreeSet::new();
+    self.extract_children(&mut required);
+    let missing: Vec<_> = required.into_iter().filter(|child| !children.contains_key(child.as_ref())).collect();
+    if !missing.is_empty() {
+      return Err(Some(missing));
+    }
+    if let Err(e) = self.validate() {
+      eprintln!("Realm failed validation: {}", e);
+      return Err(None);
+    }
+    Ok(match self {
+      AllSupportedAssets::RoomWorld(world) => {
+        let actions = world.resolve_actions().into();
+        let world = Arc::new(world.clone());
+        Arc::new(BoxedTemplate(SerdeControllerTemplate(PuzzleRealmTemplate::<_, RoomPositions<u16, u16, u16, S>> {
+          actions,
+          // Room worlds do not use any optional capabilities
+          capabilities: BTreeSet::new(),
+          players: world.clone(),
+          puzzle: world,
+          timezone: chrono_tz::Tz::UTC,
+        })))
+      }
+    })
   }
   pub fn validate(&self) -> Result<(), Cow<'static, str>> {
-    todo!()
+    match self {
+      AllSupportedAssets::RoomWorld(world) => world.validate(),
+    }
   }
 }
 
-impl<S: AsRef<str> + Ord> ExtractChildren<S> for AllSupportedAssets<S> {
+impl<S: AsRef<str> + Ord + Clone> ExtractChildren<S> for AllSupportedAssets<S> {
   fn extract_children(&self, assets: &mut BTreeSet<S>) {
     match self {
       AllSupportedAssets::RoomWorld(a) => a.extract_children(assets),
@@ -1,4 +1,4 @@
-use crate::controller::{Controller, ControllerTemplate, LoadError, MessagePackDeserializer};
+use crate::controller::{Controller, ControllerTemplate, GenericError, LoadError, MessagePackDeserializer};
 use serde_json::Value;
 use std::borrow::Cow;
 use std::error::Error;
@@ -7,22 +7,22 @@ pub struct BoxedTemplate<CT: ControllerTemplate>(pub CT);
 
 impl<CT: ControllerTemplate> ControllerTemplate for BoxedTemplate<CT>
 where
-  CT::Error: Error,
-  CT::Controller: 'static,
+  CT::Error: Error + Send + Sync + 'static,
+  CT::Controller: Send + Sync + 'static,
 {
-  type Error = CT::Error;
-  type Controller = Box<dyn Controller<Input = <CT::Controller as Controller>::Input, Output = <CT::Controller as Controller>::Output>>;
+  type Error = GenericError;
+  type Controller = Box<dyn Controller<Input = <CT::Controller as Controller>::Input, Output = <CT::Controller as Controller>::Output> + Send + Sync>;
 
-  fn blank(&self) -> Self::Controller {
-    Box::new(self.0.blank())
+  fn blank(&self) -> Result<Self::Controller, Self::Error> {
+    Ok(Box::new(self.0.blank().map_err(|e| GenericError(Box::new(e)))?))
   }
 
   fn load_json(&self, value: Value) -> Result<Self::Controller, LoadError<serde_json::Error, Self::Error>> {
-    Ok(Box::new(self.0.load_json(value)?))
+    Ok(Box::new(self.0.load_json(value).map_err(|e| e.boxed())?))
   }
 
   fn load_message_pack(&self, de: MessagePackDeserializer) -> Result<Self::Controller, LoadError<rmp_serde::decode::Error, Self::Error>> {
-    Ok(Box::new(self.0.load_message_pack(de)?))
+    Ok(Box::new(self.0.load_message_pack(de).map_err(|e| e.boxed())?))
   }
   fn name(&self, owner: &str) -> Cow<'static, str> {
     self.0.name(owner)
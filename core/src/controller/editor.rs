@@ -1,69 +1,440 @@
+use crate::asset::extraction::ExtractChildren;
+use crate::asset::{Asset, Compression, Licence};
+use crate::controller::puzzle::state_machine::StateMachinePuzzleTemplate;
 use crate::controller::{
-  Controller, ControllerInput, ControllerOutput, ControllerTemplate, LoadError, MessagePackDeserializer, MessagePackSerializer,
+  Controller, ControllerInput, ControllerOutput, ControllerTemplate, LoadError, MessagePackDeserializer, MessagePackSerializer, PlayerKind,
 };
-use serde::Serializer;
+use crate::player::PlayerIdentifier;
+use crate::realm::room_world::{Edge, Room, Tile, World};
+use crate::realm::RealmSetting;
+use crate::scene::value::GlobalValue;
+use crate::scene::Color;
+use chrono::Utc;
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::Value;
 use std::borrow::Cow;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
 use std::time::Duration;
 
-pub struct Editor;
+/// The maximum number of rooms a draft realm may contain; rooms are addressed by `u8` in edges
+const MAX_ROOMS: usize = u8::MAX as usize + 1;
+
+pub struct Editor {
+  /// The server that will forge any assets published from this editor
+  pub server_name: Arc<str>,
+}
 #[derive(Debug)]
 pub enum EditorError {}
+
+/// A change to the draft realm
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum EditOperation<S: AsRef<str> + Ord> {
+  /// Add a new empty room at the end of the room list
+  AddRoom {
+    size: (u16, u16),
+  },
+  /// Remove a room; any edges to it or to rooms after it are adjusted
+  RemoveRoom {
+    room: u8,
+  },
+  /// Change the size of a room; tiles and edges outside the new boundary are removed
+  ResizeRoom {
+    room: u8,
+    size: (u16, u16),
+  },
+  SetBackground {
+    room: u8,
+    background: GlobalValue<Color, u16, S>,
+  },
+  /// Connect an edge of a room to an edge of another room or remove the connection
+  SetEdge {
+    room: u8,
+    edge: Edge,
+    target: Option<(u8, Edge)>,
+  },
+  SetPuzzle {
+    puzzle: StateMachinePuzzleTemplate<u16, u16, u16>,
+  },
+  SetSetting {
+    name: S,
+    value: Option<RealmSetting<S>>,
+  },
+  /// Place a tile in a room or clear the position
+  SetTile {
+    room: u8,
+    x: u16,
+    y: u16,
+    tile: Option<Tile<u16, u16, u16, S>>,
+  },
+}
+
+/// The outcome of a batch of edit operations
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EditResult {
+  /// All the operations were applied
+  Applied,
+  /// The operation at the provided index is not valid for the current draft; no operations were applied
+  Invalid(u32),
+  /// The player is not allowed to modify the draft
+  NotAllowed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum EditorRequest<S: AsRef<str> + Ord> {
+  /// Apply a batch of edit operations atomically
+  Edit { id: i32, operations: Vec<EditOperation<S>> },
+  /// Validate the draft and create a realm asset from it
+  ///
+  /// The asset is only sent back to the owner; it is not stored anywhere, so the owner's client must upload it to the asset store.
+  Publish { id: i32, name: S, tags: Vec<S>, licence: Licence },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum EditorResponse<S: AsRef<str> + Ord> {
+  /// The current contents of the draft
+  Draft {
+    world: World<u16, u16, u16, S>,
+  },
+  EditResult {
+    id: i32,
+    result: EditResult,
+  },
+  /// The draft was published; the asset can now be uploaded to the asset store
+  Published {
+    id: i32,
+    asset: Asset<S, Vec<u8>>,
+  },
+  /// The draft could not be published
+  PublishFailed {
+    id: i32,
+    reason: S,
+  },
+}
+
+pub struct EditorController {
+  capabilities: BTreeSet<&'static str>,
+  draft: World<u16, u16, u16, String>,
+  server_name: Arc<str>,
+}
+
 impl ControllerTemplate for Editor {
   type Error = EditorError;
   type Controller = EditorController;
 
-  fn blank(&self) -> Self::Controller {
-    EditorController
+  fn blank(&self) -> Result<Self::Controller, Self::Error> {
+    Ok(self.create(World {
+      actions: Vec::new(),
+      rooms: vec![Room { size: (16, 16), background: GlobalValue::Fixed(Color::Rgb(0, 0, 0)), tiles: BTreeMap::new(), edge: BTreeMap::new() }],
+      puzzle: StateMachinePuzzleTemplate { machines: Vec::new(), variables: Vec::new() },
+      settings: BTreeMap::new(),
+    }))
   }
 
   fn load_json(&self, value: Value) -> Result<Self::Controller, LoadError<serde_json::Error, Self::Error>> {
-    todo!()
+    Ok(self.create(serde_json::from_value(value).map_err(LoadError::Deserialization)?))
   }
 
   fn load_message_pack(&self, de: MessagePackDeserializer) -> Result<Self::Controller, LoadError<rmp_serde::decode::Error, Self::Error>> {
-    todo!()
+    Ok(self.create(World::deserialize(de)?))
   }
 
   fn name(&self, _owner: &str) -> Cow<'static, str> {
     Cow::Borrowed("New Editor")
   }
 }
-pub struct EditorController;
+impl Editor {
+  fn create(&self, draft: World<u16, u16, u16, String>) -> EditorController {
+    EditorController { capabilities: BTreeSet::new(), draft, server_name: self.server_name.clone() }
+  }
+}
+
+impl EditorController {
+  fn edit(&mut self, operations: Vec<EditOperation<String>>) -> EditResult {
+    let mut draft = self.draft.clone();
+    for (index, operation) in operations.into_iter().enumerate() {
+      if !operation.apply(&mut draft) {
+        return EditResult::Invalid(index as u32);
+      }
+    }
+    self.draft = draft;
+    EditResult::Applied
+  }
+  fn publish(&self, player: PlayerIdentifier<&str>, name: String, tags: Vec<String>, licence: Licence) -> Result<Asset<String, Vec<u8>>, String> {
+    self.draft.validate().map_err(|e| e.into_owned())?;
+    let (author, server) = match player {
+      PlayerIdentifier::Local(player) => (player.to_string(), self.server_name.to_string()),
+      PlayerIdentifier::Remote { server, player } => (player.to_string(), server.to_string()),
+    };
+    let compression = Compression::ZstdMessagePack;
+    let data = compression.compress(&self.draft).map_err(|_| "Failed to encode realm".to_string())?;
+    let mut children = BTreeSet::new();
+    self.draft.extract_children(&mut children);
+    Ok(Asset {
+      asset_type: "RoomWorld".to_string(),
+      author,
+      server,
+      capabilities: Vec::new(),
+      children: children.into_iter().collect(),
+      compression,
+      data,
+      licence,
+      name,
+      tags,
+      created: Utc::now(),
+    })
+  }
+}
+
 impl Controller for EditorController {
-  type Input = ();
-  type Output = ();
+  type Input = EditorRequest<String>;
+  type Output = EditorResponse<String>;
 
   fn capabilities(&self) -> &BTreeSet<&'static str> {
-    todo!()
+    &self.capabilities
   }
 
   fn next_timer(&self) -> Option<Duration> {
-    todo!()
+    None
   }
 
   fn process(&mut self, input: ControllerInput<Self::Input, &str>) -> Vec<ControllerOutput<Self::Output>> {
-    todo!()
+    match input {
+      ControllerInput::Add { player_id, .. } => {
+        vec![ControllerOutput::Response { player: player_id, response: EditorResponse::Draft { world: self.draft.clone() } }]
+      }
+      ControllerInput::Input { player_id, player_kind, request: EditorRequest::Edit { id, operations }, .. } => {
+        let result = if player_kind == PlayerKind::Regular { EditResult::NotAllowed } else { self.edit(operations) };
+        let mut output = vec![ControllerOutput::Response { player: player_id, response: EditorResponse::EditResult { id, result } }];
+        if result == EditResult::Applied {
+          output.push(ControllerOutput::Broadcast { response: EditorResponse::Draft { world: self.draft.clone() } });
+        }
+        output
+      }
+      ControllerInput::Input { player, player_id, player_kind, request: EditorRequest::Publish { id, name, tags, licence }, .. } => {
+        let response = if player_kind == PlayerKind::Owner {
+          match self.publish(player, name, tags, licence) {
+            Ok(asset) => EditorResponse::Published { id, asset },
+            Err(reason) => EditorResponse::PublishFailed { id, reason },
+          }
+        } else {
+          EditorResponse::PublishFailed { id, reason: "Only the owner can publish this realm".to_string() }
+        };
+        vec![ControllerOutput::Response { player: player_id, response }]
+      }
+      ControllerInput::Remove { .. } | ControllerInput::Timer => Vec::new(),
+    }
   }
 
   fn serialize_message_pack(
     &self,
     serializer: MessagePackSerializer,
   ) -> Result<<MessagePackSerializer as Serializer>::Ok, <MessagePackSerializer as Serializer>::Error> {
-    todo!()
+    self.draft.serialize(serializer)
   }
 
   fn to_json(&self) -> Result<Value, serde_json::Error> {
-    todo!()
+    serde_json::to_value(&self.draft)
+  }
+}
+
+impl<S: AsRef<str> + Ord> EditOperation<S> {
+  /// Apply this operation to a draft, returning false if the operation is not valid
+  pub fn apply(self, draft: &mut World<u16, u16, u16, S>) -> bool {
+    match self {
+      EditOperation::AddRoom { size } => {
+        if draft.rooms.len() >= MAX_ROOMS {
+          return false;
+        }
+        draft.rooms.push(Room { size, background: GlobalValue::Fixed(Color::Rgb(0, 0, 0)), tiles: BTreeMap::new(), edge: BTreeMap::new() });
+        true
+      }
+      EditOperation::RemoveRoom { room } => {
+        if room as usize >= draft.rooms.len() || draft.rooms.len() == 1 {
+          return false;
+        }
+        draft.rooms.remove(room as usize);
+        for other in &mut draft.rooms {
+          other.edge.retain(|_, (target, _)| *target != room);
+          for (target, _) in other.edge.values_mut() {
+            if *target > room {
+              *target -= 1;
+            }
+          }
+        }
+        true
+      }
+      EditOperation::ResizeRoom { room, size } => match draft.rooms.get_mut(room as usize) {
+        None => false,
+        Some(current) => {
+          current.size = size;
+          current.tiles.retain(|&(x, y), _| x < size.0 && y < size.1);
+          current.edge.retain(|edge, _| edge.in_bounds(&size));
+          for other in &mut draft.rooms {
+            other.edge.retain(|_, (target, target_edge)| *target != room || target_edge.in_bounds(&size));
+          }
+          true
+        }
+      },
+      EditOperation::SetBackground { room, background } => match draft.rooms.get_mut(room as usize) {
+        None => false,
+        Some(room) => {
+          room.background = background;
+          true
+        }
+      },
+      EditOperation::SetEdge { room, edge, target } => {
+        if let Some((target, target_edge)) = &target {
+          if !draft.rooms.get(*target as usize).map(|r| target_edge.in_bounds(&r.size)).unwrap_or(false) {
+            return false;
+          }
+        }
+        match draft.rooms.get_mut(room as usize) {
+          Some(room) if edge.in_bounds(&room.size) => {
+            match target {
+              Some(target) => room.edge.insert(edge, target),
+              None => room.edge.remove(&edge),
+            };
+            true
+          }
+          _ => false,
+        }
+      }
+      EditOperation::SetPuzzle { puzzle } => {
+        draft.puzzle = puzzle;
+        true
+      }
+      EditOperation::SetSetting { name, value } => {
+        match value {
+          Some(value) => draft.settings.insert(name, value),
+          None => draft.settings.remove(&name),
+        };
+        true
+      }
+      EditOperation::SetTile { room, x, y, tile } => match draft.rooms.get_mut(room as usize) {
+        Some(room) if x < room.size.0 && y < room.size.1 => {
+          match tile {
+            Some(tile) => room.tiles.insert((x, y), tile),
+            None => room.tiles.remove(&(x, y)),
+          };
+          true
+        }
+        _ => false,
+      },
+    }
   }
 }
 
 impl Display for EditorError {
-  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-    todo!()
+  fn fmt(&self, _: &mut Formatter<'_>) -> std::fmt::Result {
+    match *self {}
   }
 }
 impl Error for EditorError {}
+
+#[cfg(test)]
+mod tests {
+  use super::{EditOperation, EditResult, Editor, EditorController, EditorRequest, EditorResponse};
+  use crate::asset::Licence;
+  use crate::controller::{Controller, ControllerInput, ControllerOutput, ControllerTemplate, PlayerKind};
+  use crate::player::PlayerIdentifier;
+  use crate::realm::room_world::{Edge, Tile};
+  use crate::scene::value::{GlobalValue, LocalDiscreteValue};
+  use crate::scene::Color;
+  use std::sync::Arc;
+
+  fn editor() -> EditorController {
+    Editor { server_name: Arc::from("localhost") }.blank().unwrap()
+  }
+
+  fn send(editor: &mut EditorController, player_kind: PlayerKind, request: EditorRequest<String>) -> Vec<ControllerOutput<EditorResponse<String>>> {
+    editor.process(ControllerInput::Input { request_id: 0, player: PlayerIdentifier::Local("owner"), player_id: 0, player_kind, request })
+  }
+
+  fn edit(editor: &mut EditorController, player_kind: PlayerKind, operations: Vec<EditOperation<String>>) -> (EditResult, bool) {
+    let output = send(editor, player_kind, EditorRequest::Edit { id: 1, operations });
+    let result = match output.first() {
+      Some(ControllerOutput::Response { player: 0, response: EditorResponse::EditResult { id: 1, result } }) => *result,
+      other => panic!("Unexpected response: {:?}", other),
+    };
+    let broadcast = output.iter().any(|output| matches!(output, ControllerOutput::Broadcast { response: EditorResponse::Draft { .. } }));
+    (result, broadcast)
+  }
+
+  fn publish(editor: &mut EditorController, player_kind: PlayerKind) -> EditorResponse<String> {
+    let mut output =
+      send(editor, player_kind, EditorRequest::Publish { id: 2, name: "Draft".to_string(), tags: Vec::new(), licence: Licence::PubDom });
+    assert_eq!(output.len(), 1);
+    match output.pop() {
+      Some(ControllerOutput::Response { player: 0, response }) => response,
+      other => panic!("Unexpected output: {:?}", other),
+    }
+  }
+
+  fn solid() -> Tile<u16, u16, u16, String> {
+    Tile::Solid(LocalDiscreteValue::Global(GlobalValue::Fixed(Color::Rgb(255, 255, 255))))
+  }
+
+  #[test]
+  fn batches_are_atomic() {
+    let mut editor = editor();
+    let (result, broadcast) = edit(
+      &mut editor,
+      PlayerKind::Owner,
+      vec![EditOperation::AddRoom { size: (4, 4) }, EditOperation::SetTile { room: 1, x: 4, y: 0, tile: Some(solid()) }],
+    );
+    assert_eq!(result, EditResult::Invalid(1));
+    assert!(!broadcast);
+    assert_eq!(editor.draft.rooms.len(), 1);
+
+    let (result, broadcast) = edit(
+      &mut editor,
+      PlayerKind::Owner,
+      vec![
+        EditOperation::AddRoom { size: (4, 4) },
+        EditOperation::SetTile { room: 1, x: 3, y: 3, tile: Some(solid()) },
+        EditOperation::SetEdge { room: 0, edge: Edge::XM(0), target: Some((1, Edge::X0(0))) },
+      ],
+    );
+    assert_eq!(result, EditResult::Applied);
+    assert!(broadcast);
+    assert_eq!(editor.draft.rooms.len(), 2);
+    assert_eq!(editor.draft.rooms[1].tiles.len(), 1);
+    assert_eq!(editor.draft.rooms[0].edge.get(&Edge::XM(0)), Some(&(1, Edge::X0(0))));
+  }
+
+  #[test]
+  fn regular_players_cannot_edit() {
+    let mut editor = editor();
+    assert_eq!(edit(&mut editor, PlayerKind::Regular, vec![EditOperation::AddRoom { size: (4, 4) }]), (EditResult::NotAllowed, false));
+    assert_eq!(editor.draft.rooms.len(), 1);
+    assert_eq!(edit(&mut editor, PlayerKind::Admin, vec![EditOperation::AddRoom { size: (4, 4) }]), (EditResult::Applied, true));
+    assert_eq!(editor.draft.rooms.len(), 2);
+  }
+
+  #[test]
+  fn only_owner_publishes() {
+    let mut editor = editor();
+    assert!(matches!(publish(&mut editor, PlayerKind::Regular), EditorResponse::PublishFailed { id: 2, .. }));
+    assert!(matches!(publish(&mut editor, PlayerKind::Admin), EditorResponse::PublishFailed { id: 2, .. }));
+    match publish(&mut editor, PlayerKind::Owner) {
+      EditorResponse::Published { id: 2, asset } => {
+        assert_eq!(asset.asset_type, "RoomWorld");
+        assert_eq!(asset.author, "owner");
+        assert_eq!(asset.server, "localhost");
+        assert_eq!(asset.name, "Draft");
+        assert!(asset.children.is_empty());
+      }
+      other => panic!("Unexpected response: {:?}", other),
+    }
+  }
+
+  #[test]
+  fn invalid_drafts_are_not_published() {
+    let mut editor = editor();
+    editor.draft.rooms[0].edge.insert(Edge::XM(0), (5, Edge::X0(0)));
+    assert!(matches!(publish(&mut editor, PlayerKind::Owner), EditorResponse::PublishFailed { id: 2, .. }));
+  }
+}
@@ -54,7 +54,7 @@ pub enum ControllerOutput<Response> {
 pub trait ControllerTemplate: Send + Sync + 'static {
   type Error: Error + 'static;
   type Controller: Controller;
-  fn blank(&self) -> Self::Controller;
+  fn blank(&self) -> Result<Self::Controller, Self::Error>;
   fn load_json(&self, value: Value) -> Result<Self::Controller, LoadError<serde_json::Error, Self::Error>>;
   fn load_message_pack(&self, de: MessagePackDeserializer) -> Result<Self::Controller, LoadError<rmp_serde::decode::Error, Self::Error>>;
   fn name(&self, owner: &str) -> Cow<'static, str>;
@@ -139,7 +139,7 @@ impl<CT: ControllerTemplate + ?Sized> ControllerTemplate for Arc<CT> {
   type Error = CT::Error;
   type Controller = CT::Controller;
 
-  fn blank(&self) -> Self::Controller {
+  fn blank(&self) -> Result<Self::Controller, Self::Error> {
     self.as_ref().blank()
   }
 
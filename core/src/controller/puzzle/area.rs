@@ -2,7 +2,7 @@ pub trait AreaCounter {
   fn count_players(&self, filter: &PlayerStateCondition) -> u32;
 }
 pub struct CountCollection<C>(pub C);
-#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum PlayerStateCondition {
   All(Vec<PlayerStateCondition>),
   Always,
@@ -47,3 +47,20 @@ pub enum PlayerStateModification {
   SetBit(u8),
   Set { offset: u8, length: u8, value: u8 },
 }
+
+impl PlayerStateModification {
+  pub fn apply(&self, value: u32) -> u32 {
+    match self {
+      PlayerStateModification::ClearAll => 0,
+      PlayerStateModification::ClearBit(b) => 1_u32.checked_shl(*b as u32).map(|v| value & !v).unwrap_or(value),
+      PlayerStateModification::SetAll => u32::MAX,
+      PlayerStateModification::SetBit(b) => 1_u32.checked_shl(*b as u32).map(|v| value | v).unwrap_or(value),
+      PlayerStateModification::Set { offset, length, value: bits } => {
+        match 1_u32.checked_shl(*length as u32).unwrap_or(0).wrapping_sub(1).checked_shl(*offset as u32) {
+          Some(mask) => (value & !mask) | ((*bits as u32).checked_shl(*offset as u32).unwrap_or(0) & mask),
+          None => value,
+        }
+      }
+    }
+  }
+}
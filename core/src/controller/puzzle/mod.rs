@@ -3,16 +3,27 @@ pub mod area;
 pub mod platforms;
 pub mod state_machine;
 
+use crate::controller::puzzle::action::{Action, LinkTarget};
+use crate::controller::puzzle::area::CountCollection;
 use crate::controller::puzzle::ProcessingResult::Updated;
-use crate::controller::{ControllerInput, ControllerOutput, MessagePackSerializer};
+use crate::controller::{
+  ControllerInput, ControllerOutput, ControllerTemplate, LoadError, MessagePackDeserializer, MessagePackSerializer, PlayerKind,
+};
+use crate::location::target::UnresolvedTarget;
+use crate::player::PlayerIdentifier;
 use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
 use serde::{Deserializer, Serializer};
+use std::borrow::Cow;
 use std::collections::BTreeSet;
 use std::error::Error;
 use std::ops::BitOr;
+use std::sync::Arc;
 use std::time::Duration;
 
+/// The most times player position updates and puzzle changes are exchanged while processing a single input
+const MAX_UPDATE_ROUNDS: usize = 8;
+
 pub trait PuzzleTemplate {
   type Error: Error + 'static;
   type Puzzle: Puzzle + 'static;
@@ -32,22 +43,45 @@ pub trait Puzzle: serde::Serialize + Send + 'static {
     now: DateTime<Utc>,
   ) -> Result<ProcessingResult, Self::Error>;
   fn process_timer(&mut self, now: DateTime<Utc>) -> Result<ProcessingResult, Self::Error>;
+  /// The random seed the puzzle was created with, which is saved as part of its state
+  fn seed(&self) -> u32;
 }
 
 pub trait PlayerPositions: Send + 'static {
   type Area: 'static;
   type Error: Error + 'static;
+  type InputIdentifier: 'static;
   type OutputIdentifier: 'static;
-  fn create(now: DateTime<Utc>, seed: u32) -> Self;
-  fn add(&mut self, player_id: u32) -> Result<(), Self::Error>;
+  type Request: Send + 'static;
+  type Response: Send + 'static;
+  type Template: Send + Sync + 'static;
+  fn create(template: &Self::Template, now: DateTime<Utc>, seed: u32) -> Self;
+  fn add(&mut self, player_id: u32, player: PlayerIdentifier<&str>, now: DateTime<Utc>)
+    -> Result<Vec<ControllerOutput<Self::Response>>, Self::Error>;
+  fn area_states(&self, area: &Self::Area) -> Vec<u32>;
+  fn events(&mut self) -> Vec<PositionEvent<Self::InputIdentifier, Self::Area>>;
+  fn leave(&mut self, player_id: u32, now: DateTime<Utc>) -> Result<Vec<ControllerOutput<Self::Response>>, Self::Error>;
   fn modify(&mut self, area: &Self::Area, condition: &area::PlayerStateCondition, modification: &area::PlayerStateModification);
   fn next_timer(&self) -> Option<Duration>;
+  fn perform(
+    &mut self,
+    player_id: u32,
+    player_kind: PlayerKind,
+    request: Self::Request,
+    now: DateTime<Utc>,
+  ) -> Result<Vec<ControllerOutput<Self::Response>>, Self::Error>;
   fn remove(&mut self, area: &Self::Area, condition: &area::PlayerStateCondition) -> Vec<u32>;
-  fn set_states(&mut self, inputs: Box<dyn Iterator<Item = (&Self::OutputIdentifier, &MultiStateValue)>>) -> Result<(), Self::Error>;
-  fn transfer(&mut self, source: &Self::Area, condition: &area::PlayerStateCondition, target: &Self::Area) -> Result<(), Self::Error>;
-  fn update(&mut self) -> Result<(), Self::Error>;
+  fn set_states<'a>(&mut self, outputs: Box<dyn Iterator<Item = (&'a Self::OutputIdentifier, &'a MultiStateValue)> + 'a>) -> Result<(), Self::Error>;
+  fn transfer(
+    &mut self,
+    source: &Self::Area,
+    condition: &area::PlayerStateCondition,
+    target: &Self::Area,
+    now: DateTime<Utc>,
+  ) -> Result<(), Self::Error>;
+  fn update(&mut self, now: DateTime<Utc>) -> Result<Vec<ControllerOutput<Self::Response>>, Self::Error>;
 }
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum MultiStateValue {
   Bool(bool),
   Num(u32),
@@ -55,6 +89,15 @@ pub enum MultiStateValue {
   MultiNum { default: u32, values: Vec<(area::PlayerStateCondition, u32)> },
 }
 
+/// A change in the player positions that the puzzle must be informed about
+#[derive(Clone, Debug)]
+pub enum PositionEvent<InputIdentifier, Area> {
+  /// The occupants of an area have changed (either by moving or by having their state changed)
+  Area(Area),
+  /// A player has clicked on an input; the player's state bits are included
+  Click(InputIdentifier, u32),
+}
+
 #[derive(Clone, Debug)]
 pub enum PuzzleInput<InputIdentifier, Area, AreaCounter> {
   Click(InputIdentifier, u32),
@@ -73,6 +116,29 @@ pub enum Value {
   Num(u32),
 }
 
+impl MultiStateValue {
+  /// Determine if the value is true for actions, which apply to all players, so per-player values use their default
+  fn is_active(&self) -> bool {
+    match self {
+      MultiStateValue::Bool(value) | MultiStateValue::MultiBool { default: value, .. } => *value,
+      MultiStateValue::Num(value) | MultiStateValue::MultiNum { default: value, .. } => *value != 0,
+    }
+  }
+  /// Determine the value a player with the provided state bits will see
+  pub fn for_player(&self, state: u32) -> Value {
+    match self {
+      MultiStateValue::Bool(value) => Value::Bool(*value),
+      MultiStateValue::Num(value) => Value::Num(*value),
+      MultiStateValue::MultiBool { default, values } => {
+        Value::Bool(values.iter().find(|(condition, _)| condition.check(state)).map(|(_, value)| *value).unwrap_or(*default))
+      }
+      MultiStateValue::MultiNum { default, values } => {
+        Value::Num(values.iter().find(|(condition, _)| condition.check(state)).map(|(_, value)| *value).unwrap_or(*default))
+      }
+    }
+  }
+}
+
 impl ProcessingResult {
   pub fn update(&mut self) {
     *self = Updated;
@@ -91,20 +157,160 @@ impl BitOr for ProcessingResult {
   }
 }
 
-pub struct PuzzleRealm<PuzzleState: Puzzle, PlayerState: PlayerPositions<Area = PuzzleState::Area, OutputIdentifier = PuzzleState::OutputIdentifier>>
-{
+/// An action to perform on the players when a puzzle output becomes true; links go to realms rather than settings
+pub type RealmAction<OutputIdentifier, Area> = (OutputIdentifier, Action<Area, UnresolvedTarget<Arc<str>>>);
+
+pub struct PuzzleRealm<PuzzleState: Puzzle, PlayerState: PlayerPositions> {
+  actions: Arc<[RealmAction<PlayerState::OutputIdentifier, PlayerState::Area>]>,
+  /// Whether the output for each action was true when last checked, so an action only happens when its output changes to true
+  active: Vec<bool>,
+  capabilities: BTreeSet<&'static str>,
+  /// Players that have been sent elsewhere by an action and are no longer in the player positions
+  ejected: BTreeSet<u32>,
   puzzle: PuzzleState,
   players: PlayerState,
 }
 
-impl<PuzzleState: Puzzle, PlayerState: PlayerPositions<Area = PuzzleState::Area, OutputIdentifier = PuzzleState::OutputIdentifier>> super::Controller
-  for PuzzleRealm<PuzzleState, PlayerState>
+/// A template that can create a realm from a puzzle and a way to track where players are
+pub struct PuzzleRealmTemplate<Template: PuzzleTemplate, PlayerState: PlayerPositions> {
+  pub actions: Arc<[RealmAction<PlayerState::OutputIdentifier, PlayerState::Area>]>,
+  pub capabilities: BTreeSet<&'static str>,
+  pub players: PlayerState::Template,
+  pub puzzle: Template,
+  pub timezone: Tz,
+}
+
+impl<
+    PuzzleState: Puzzle,
+    PlayerState: PlayerPositions<Area = PuzzleState::Area, InputIdentifier = PuzzleState::InputIdentifier, OutputIdentifier = PuzzleState::OutputIdentifier>,
+  > PuzzleRealm<PuzzleState, PlayerState>
+where
+  PuzzleState::OutputIdentifier: PartialEq,
 {
-  type Input = ();
-  type Output = ();
+  fn new(
+    actions: Arc<[RealmAction<PlayerState::OutputIdentifier, PlayerState::Area>]>,
+    capabilities: BTreeSet<&'static str>,
+    puzzle: PuzzleState,
+    mut players: PlayerState,
+  ) -> Self {
+    // Actions only happen when an output changes, so any that are already true when the realm starts are left alone
+    let active = actions.iter().map(|(output, _)| is_output_active(&puzzle, output)).collect();
+    if let Err(e) = players.set_states(puzzle.outputs()) {
+      eprintln!("Failed to update puzzle outputs: {}", e);
+    }
+    PuzzleRealm { actions, active, capabilities, ejected: BTreeSet::new(), puzzle, players }
+  }
+  fn process_at(&mut self, input: ControllerInput<PlayerState::Request, &str>, now: DateTime<Utc>) -> Vec<ControllerOutput<PlayerState::Response>> {
+    let mut output = Vec::new();
+    let result = match input {
+      ControllerInput::Add { player, player_id, .. } => self.players.add(player_id, player, now),
+      ControllerInput::Input { player_id, player_kind, request, .. } => self.players.perform(player_id, player_kind, request, now),
+      ControllerInput::Remove { player_id, .. } => {
+        if self.ejected.remove(&player_id) {
+          Ok(Vec::new())
+        } else {
+          self.players.leave(player_id, now)
+        }
+      }
+      ControllerInput::Timer => {
+        match self.puzzle.process_timer(now) {
+          Ok(result) => self.update_outputs(result, now, &mut output),
+          Err(e) => eprintln!("Failed to process puzzle timer: {}", e),
+        }
+        Ok(Vec::new())
+      }
+    };
+    match result {
+      Ok(result) => output.extend(result),
+      Err(e) => eprintln!("Failed to update player positions: {}", e),
+    }
+    self.propagate(now, &mut output);
+    // Players reach areas during the update, which can change the puzzle and so what players see; stop early if the two keep feeding each other
+    for _ in 0..MAX_UPDATE_ROUNDS {
+      match self.players.update(now) {
+        Ok(updates) => output.extend(updates),
+        Err(e) => eprintln!("Failed to update player positions: {}", e),
+      }
+      if !self.propagate(now, &mut output) {
+        break;
+      }
+    }
+    output
+  }
+  /// Feed any changes in player positions into the puzzle and then update the player positions with any changes to the puzzle's outputs
+  ///
+  /// Returns false if there were no changes in player positions
+  fn propagate(&mut self, now: DateTime<Utc>, output: &mut Vec<ControllerOutput<PlayerState::Response>>) -> bool {
+    let events = self.players.events();
+    if events.is_empty() {
+      return false;
+    }
+    let mut result = ProcessingResult::Unchanged;
+    for event in events {
+      let processed = match event {
+        PositionEvent::Area(area) => {
+          let counter = CountCollection(self.players.area_states(&area));
+          self.puzzle.process(PuzzleInput::Area(area, counter), now)
+        }
+        PositionEvent::Click(input, state) => self.puzzle.process(PuzzleInput::<_, _, CountCollection<Vec<u32>>>::Click(input, state), now),
+      };
+      match processed {
+        Ok(processed) => result = result | processed,
+        Err(e) => eprintln!("Failed to process puzzle input: {}", e),
+      }
+    }
+    self.update_outputs(result, now, output);
+    true
+  }
+  /// Send the puzzle's outputs to the player positions and perform the actions for any outputs that have become true
+  ///
+  /// Players that actions send to other realms are added to the output
+  fn update_outputs(&mut self, result: ProcessingResult, now: DateTime<Utc>, output: &mut Vec<ControllerOutput<PlayerState::Response>>) {
+    if result == Updated {
+      if let Err(e) = self.players.set_states(self.puzzle.outputs()) {
+        eprintln!("Failed to update puzzle outputs: {}", e);
+      }
+      for ((identifier, action), active) in self.actions.iter().zip(self.active.iter_mut()) {
+        let now_active = is_output_active(&self.puzzle, identifier);
+        if std::mem::replace(active, now_active) || !now_active {
+          continue;
+        }
+        match action {
+          Action::Link { from, matches, target } => {
+            let target = match target {
+              LinkTarget::Setting(target) => target.clone(),
+              // Realms have no way to know where the player's home or the next realm is, so these links go nowhere
+              LinkTarget::Home | LinkTarget::Next => UnresolvedTarget::NoWhere,
+            };
+            for player in self.players.remove(from, matches) {
+              self.ejected.insert(player);
+              output.push(ControllerOutput::Move { player, target: target.clone() });
+            }
+          }
+          Action::Move { from, to, matches } => {
+            if let Err(e) = self.players.transfer(from, matches, to, now) {
+              eprintln!("Failed to move players: {}", e);
+            }
+          }
+          Action::Mark { location, matches, modification } => self.players.modify(location, matches, modification),
+        }
+      }
+    }
+  }
+}
+
+impl<
+    PuzzleState: Puzzle,
+    PlayerState: PlayerPositions<Area = PuzzleState::Area, InputIdentifier = PuzzleState::InputIdentifier, OutputIdentifier = PuzzleState::OutputIdentifier>,
+  > super::Controller for PuzzleRealm<PuzzleState, PlayerState>
+where
+  PuzzleState::OutputIdentifier: PartialEq,
+{
+  type Input = PlayerState::Request;
+  type Output = PlayerState::Response;
 
   fn capabilities(&self) -> &BTreeSet<&'static str> {
-    todo!()
+    &self.capabilities
   }
 
   fn next_timer(&self) -> Option<Duration> {
@@ -116,12 +322,7 @@ impl<PuzzleState: Puzzle, PlayerState: PlayerPositions<Area = PuzzleState::Area,
   }
 
   fn process(&mut self, input: ControllerInput<Self::Input, &str>) -> Vec<ControllerOutput<Self::Output>> {
-    match input {
-      ControllerInput::Add { .. } => todo!(),
-      ControllerInput::Input { .. } => todo!(),
-      ControllerInput::Remove { .. } => todo!(),
-      ControllerInput::Timer => todo!(),
-    }
+    self.process_at(input, Utc::now())
   }
 
   fn serialize_message_pack(
@@ -135,6 +336,51 @@ impl<PuzzleState: Puzzle, PlayerState: PlayerPositions<Area = PuzzleState::Area,
     serde_json::to_value(&self.puzzle)
   }
 }
+
+impl<Template: PuzzleTemplate + Send + Sync + 'static, PlayerState: PlayerPositions> ControllerTemplate for PuzzleRealmTemplate<Template, PlayerState>
+where
+  PlayerState: PlayerPositions<
+    Area = <Template::Puzzle as Puzzle>::Area,
+    InputIdentifier = <Template::Puzzle as Puzzle>::InputIdentifier,
+    OutputIdentifier = <Template::Puzzle as Puzzle>::OutputIdentifier,
+  >,
+  <Template::Puzzle as Puzzle>::OutputIdentifier: PartialEq,
+{
+  type Error = Template::Error;
+  type Controller = PuzzleRealm<Template::Puzzle, PlayerState>;
+
+  fn blank(&self) -> Result<Self::Controller, Self::Error> {
+    let now = Utc::now();
+    let seed = rand::random();
+    let puzzle = self.puzzle.blank(now.with_timezone(&self.timezone), seed)?;
+    Ok(PuzzleRealm::new(self.actions.clone(), self.capabilities.clone(), puzzle, PlayerState::create(&self.players, now, seed)))
+  }
+
+  fn load_json(&self, value: serde_json::Value) -> Result<Self::Controller, LoadError<serde_json::Error, Self::Error>> {
+    let now = Utc::now();
+    let puzzle = self.puzzle.load(value, now)?;
+    let players = PlayerState::create(&self.players, now, puzzle.seed());
+    Ok(PuzzleRealm::new(self.actions.clone(), self.capabilities.clone(), puzzle, players))
+  }
+
+  fn load_message_pack(&self, de: MessagePackDeserializer) -> Result<Self::Controller, LoadError<rmp_serde::decode::Error, Self::Error>> {
+    let now = Utc::now();
+    let puzzle = self.puzzle.load(de, now)?;
+    let players = PlayerState::create(&self.players, now, puzzle.seed());
+    Ok(PuzzleRealm::new(self.actions.clone(), self.capabilities.clone(), puzzle, players))
+  }
+
+  fn name(&self, owner: &str) -> Cow<'static, str> {
+    Cow::Owned(format!("{}'s Realm", owner))
+  }
+}
+fn is_output_active<P: Puzzle>(puzzle: &P, output: &P::OutputIdentifier) -> bool
+where
+  P::OutputIdentifier: PartialEq,
+{
+  puzzle.outputs().any(|(identifier, value)| identifier == output && value.is_active())
+}
+
 impl From<Value> for bool {
   fn from(value: Value) -> Self {
     match value {
@@ -157,3 +403,163 @@ impl From<Value> for u32 {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::action::{Action, LinkTarget};
+  use super::area::{PlayerStateCondition, PlayerStateModification};
+  use super::{MultiStateValue, PlayerPositions, Puzzle, PuzzleRealm, PuzzleRealmTemplate};
+  use crate::controller::puzzle::state_machine::text::parse;
+  use crate::controller::puzzle::state_machine::StateMachinePuzzle;
+  use crate::controller::{ControllerInput, ControllerOutput, ControllerTemplate, PlayerKind};
+  use crate::location::target::UnresolvedTarget;
+  use crate::player::PlayerIdentifier;
+  use crate::realm::room_positions::RoomPositions;
+  use crate::realm::room_world::{Edge, Room, Tile, World};
+  use crate::realm::{Direction, RealmRequest, RealmSetting};
+  use crate::scene::value::{GlobalValue, LocalDiscreteValue};
+  use crate::scene::Color;
+  use chrono::{DateTime, Duration, Utc};
+  use std::collections::BTreeMap;
+  use std::sync::Arc;
+
+  type TestWorld = Arc<World<String, String, String, String>>;
+  type TestRealm = PuzzleRealm<StateMachinePuzzle<TestWorld>, RoomPositions<String, String, String, String>>;
+
+  const FLOOR: Color = Color::Rgb(0, 0, 0);
+
+  /// Players enter a single tile room and can walk east into a room with a hall followed by a yard; the puzzle tracks if the hall is occupied
+  fn world(action: Action<String, String>) -> TestWorld {
+    let puzzle = parse(
+      r#"
+machine hall {
+  outputs occupied;
+
+  state empty {
+    output occupied = false;
+    on count(hall) if players(always) > 0 -> full;
+  }
+  state full {
+    output occupied = true;
+    on count(hall) if players(always) == 0 -> empty;
+  }
+}
+"#,
+    )
+    .unwrap();
+    let area = |name: &str| Tile::Area(LocalDiscreteValue::Global(GlobalValue::Fixed(FLOOR)), name.to_string());
+    Arc::new(World {
+      actions: vec![("occupied".to_string(), action)],
+      rooms: vec![
+        Room { size: (1, 1), background: GlobalValue::Fixed(FLOOR), tiles: BTreeMap::new(), edge: BTreeMap::from([(Edge::XM(0), (1, Edge::X0(0)))]) },
+        Room {
+          size: (2, 1),
+          background: GlobalValue::Fixed(FLOOR),
+          tiles: BTreeMap::from([((0, 0), area("hall")), ((1, 0), area("yard"))]),
+          edge: BTreeMap::new(),
+        },
+      ],
+      puzzle,
+      settings: BTreeMap::from([("next".to_string(), RealmSetting::Realm(UnresolvedTarget::Personal { asset: "next-realm".to_string() }))]),
+    })
+  }
+
+  fn realm(world: TestWorld) -> TestRealm {
+    let template = PuzzleRealmTemplate::<_, RoomPositions<String, String, String, String>> {
+      actions: world.resolve_actions().into(),
+      capabilities: Default::default(),
+      players: world.clone(),
+      puzzle: world,
+      timezone: chrono_tz::Tz::UTC,
+    };
+    template.blank().unwrap()
+  }
+
+  fn occupied(realm: &TestRealm) -> bool {
+    realm.puzzle.outputs().any(|(output, value)| output == "occupied" && value == &MultiStateValue::Bool(true))
+  }
+
+  /// Add a player and walk them into the hall, returning the time when they will have arrived
+  fn enter_hall(realm: &mut TestRealm, player_id: u32, now: DateTime<Utc>) -> DateTime<Utc> {
+    let player = PlayerIdentifier::Local("alice");
+    realm.process_at(ControllerInput::Add { player: player.clone(), player_id, player_kind: PlayerKind::Regular }, now);
+    realm.process_at(
+      ControllerInput::Input {
+        request_id: 1,
+        player,
+        player_id,
+        player_kind: PlayerKind::Regular,
+        request: RealmRequest::Perform(vec![crate::realm::Action::Rotate { direction: Direction::XPos }, crate::realm::Action::Move { length: 1 }]),
+      },
+      now,
+    );
+    assert!(realm.players.next_timer().is_some());
+    now + Duration::seconds(3)
+  }
+
+  #[test]
+  fn mark_players_on_arrival() {
+    let mut realm = realm(world(Action::Mark {
+      location: "hall".to_string(),
+      matches: PlayerStateCondition::Always,
+      modification: PlayerStateModification::SetBit(2),
+    }));
+    let now = enter_hall(&mut realm, 1, Utc::now());
+    // Still moving, so the puzzle has not seen them yet
+    assert!(!occupied(&realm));
+    let output = realm.process_at(ControllerInput::Timer, now);
+    assert!(occupied(&realm));
+    assert_eq!(realm.players.area_states(&"hall".to_string()), vec![4]);
+    assert!(output.iter().any(|output| matches!(output, ControllerOutput::Response { player: 1, .. })));
+  }
+
+  #[test]
+  fn move_players_to_another_area() {
+    let mut realm = realm(world(Action::Move { from: "hall".to_string(), to: "yard".to_string(), matches: PlayerStateCondition::Always }));
+    let now = enter_hall(&mut realm, 1, Utc::now());
+    realm.process_at(ControllerInput::Timer, now);
+    assert!(occupied(&realm));
+    assert_eq!(realm.players.area_states(&"hall".to_string()), vec![0]);
+
+    realm.process_at(ControllerInput::Timer, now + Duration::seconds(1));
+    assert!(!occupied(&realm));
+    assert!(realm.players.area_states(&"hall".to_string()).is_empty());
+    assert_eq!(realm.players.area_states(&"yard".to_string()), vec![0]);
+  }
+
+  #[test]
+  fn link_players_to_setting() {
+    let mut realm =
+      realm(world(Action::Link { from: "hall".to_string(), matches: PlayerStateCondition::Always, target: LinkTarget::Setting("next".to_string()) }));
+    let now = enter_hall(&mut realm, 1, Utc::now());
+    let output = realm.process_at(ControllerInput::Timer, now);
+    assert!(output.iter().any(|output| matches!(
+      output,
+      ControllerOutput::Move { player: 1, target: UnresolvedTarget::Personal { asset } } if &**asset == "next-realm"
+    )));
+    assert!(realm.players.area_states(&"hall".to_string()).is_empty());
+    assert!(!occupied(&realm));
+
+    // The server removes the player once they have gone, but they are already gone from the realm
+    realm.process_at(ControllerInput::Remove { player: PlayerIdentifier::Local("alice"), player_id: 1, player_kind: PlayerKind::Regular }, now);
+    assert!(realm.ejected.is_empty());
+  }
+
+  #[test]
+  fn actions_only_happen_when_output_becomes_true() {
+    let mut realm = realm(world(Action::Mark {
+      location: "hall".to_string(),
+      matches: PlayerStateCondition::Always,
+      modification: PlayerStateModification::SetBit(2),
+    }));
+    let now = enter_hall(&mut realm, 1, Utc::now());
+    realm.process_at(ControllerInput::Timer, now);
+    realm.players.modify(&"hall".to_string(), &PlayerStateCondition::Always, &PlayerStateModification::ClearAll);
+    // The hall stays occupied when a second player arrives, so the first player is not marked again
+    let now = enter_hall(&mut realm, 2, now);
+    realm.process_at(ControllerInput::Timer, now);
+    let mut states = realm.players.area_states(&"hall".to_string());
+    states.sort();
+    assert_eq!(states, vec![0, 0]);
+  }
+}
@@ -0,0 +1,477 @@
+use crate::controller::puzzle::state_machine::edge_action::EdgeAction;
+use crate::controller::puzzle::state_machine::state::{LocalVariableName, StateMachine, StateVariableName};
+use crate::controller::puzzle::state_machine::timer_transition::{TimerDuration, TimerTransition};
+use crate::controller::puzzle::state_machine::transition::{AreaCount, InputTrigger};
+use crate::controller::puzzle::state_machine::{CounterId, InternalEventId, StateId, StateMachinePuzzleTemplate};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+/// A problem found in a state machine puzzle template
+///
+/// Issues with invalid references will cause the puzzle to fail at run time and are errors; the others are likely mistakes, but the puzzle will
+/// still run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AnalysisIssue {
+  /// A state refers to a counter that the machine does not have
+  InvalidCounter { machine: usize, state: StateId, counter: CounterId },
+  /// A state refers to a global variable that does not exist
+  InvalidGlobal { machine: usize, state: StateId, global: u8 },
+  /// A state refers to a local variable that it does not define
+  InvalidLocal { machine: usize, state: StateId, local: u8 },
+  /// A user transition goes to a state that does not exist
+  InvalidNextState { machine: usize, state: StateId, next: StateId },
+  /// A timer transition goes to a state that does not exist
+  InvalidTimerState { machine: usize, state: StateId, next: StateId },
+  /// A machine has no states, so it cannot be started
+  NoStates { machine: usize },
+  /// A state produces a different number of output values than the machine has outputs
+  OutputMismatch { machine: usize, state: StateId, expected: usize, actual: usize },
+  /// A machine has too many states to be addressed
+  TooManyStates { machine: usize },
+  /// A chain of internal events would trigger itself again; the loop is only broken because each machine can transition once per input
+  TriggerCycle(Vec<TriggerLink>),
+  /// A transition listens for an internal event that nothing raises
+  UnraisedTrigger { machine: usize, state: StateId, trigger: InternalEventId },
+  /// A state cannot be reached from the initial state
+  UnreachableState { machine: usize, state: StateId },
+  /// A transition raises an internal event that no transition listens for
+  UnheardTrigger { machine: usize, state: StateId, trigger: InternalEventId },
+}
+
+/// One step in an internal trigger cycle: the transition in a machine's state that listens for one event and raises another
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TriggerLink {
+  pub machine: usize,
+  pub state: StateId,
+  pub listens: InternalEventId,
+  pub raises: InternalEventId,
+}
+
+struct StateChecker<'a> {
+  counters: u8,
+  globals: usize,
+  issues: &'a mut Vec<AnalysisIssue>,
+  locals: usize,
+  machine: usize,
+  state: StateId,
+}
+
+impl AnalysisIssue {
+  /// Whether this issue would cause the puzzle to fail while running
+  pub fn is_error(&self) -> bool {
+    match self {
+      AnalysisIssue::InvalidCounter { .. }
+      | AnalysisIssue::InvalidGlobal { .. }
+      | AnalysisIssue::InvalidLocal { .. }
+      | AnalysisIssue::InvalidNextState { .. }
+      | AnalysisIssue::InvalidTimerState { .. }
+      | AnalysisIssue::NoStates { .. }
+      | AnalysisIssue::OutputMismatch { .. }
+      | AnalysisIssue::TooManyStates { .. } => true,
+      AnalysisIssue::TriggerCycle(_)
+      | AnalysisIssue::UnraisedTrigger { .. }
+      | AnalysisIssue::UnreachableState { .. }
+      | AnalysisIssue::UnheardTrigger { .. } => false,
+    }
+  }
+}
+
+impl<InputIdentifier, OutputIdentifier, Area> StateMachinePuzzleTemplate<InputIdentifier, OutputIdentifier, Area> {
+  /// Check the internal consistency of the state machines
+  ///
+  /// This does not check the names of inputs, outputs, or areas since those depend on the world the puzzle is used in.
+  pub fn analyse(&self) -> Vec<AnalysisIssue> {
+    let mut issues = Vec::new();
+    // Maps each event to the transitions that raise it and the transitions that listen for it
+    let mut raised: BTreeMap<InternalEventId, Vec<(usize, StateId)>> = BTreeMap::new();
+    let mut listeners: BTreeMap<InternalEventId, Vec<(usize, StateId)>> = BTreeMap::new();
+    let mut links: BTreeMap<InternalEventId, Vec<TriggerLink>> = BTreeMap::new();
+    for (machine, definition) in self.machines.iter().enumerate() {
+      if definition.states.is_empty() {
+        issues.push(AnalysisIssue::NoStates { machine });
+        continue;
+      }
+      if definition.states.len() > StateId::MAX as usize + 1 {
+        issues.push(AnalysisIssue::TooManyStates { machine });
+        continue;
+      }
+      for (state, state_definition) in definition.states.iter().enumerate() {
+        let state = state as StateId;
+        let mut checker = StateChecker {
+          counters: definition.counters,
+          globals: self.variables.len(),
+          issues: &mut issues,
+          locals: state_definition.variables.len(),
+          machine,
+          state,
+        };
+        for variable in &state_definition.variables {
+          variable.visit_variables(&mut |name| checker.check_state(name));
+        }
+        for output in &state_definition.outputs {
+          output.visit_variables(&mut |name| checker.check_local(name));
+        }
+        if state_definition.outputs.len() != definition.outputs.len() {
+          checker.issues.push(AnalysisIssue::OutputMismatch {
+            machine,
+            state,
+            expected: definition.outputs.len(),
+            actual: state_definition.outputs.len(),
+          });
+        }
+        for transition in &state_definition.user_transitions {
+          if transition.next as usize >= definition.states.len() {
+            checker.issues.push(AnalysisIssue::InvalidNextState { machine, state, next: transition.next });
+          }
+          let listens = match &transition.input {
+            InputTrigger::Click { condition, .. } => {
+              condition.visit_variables(&mut |name| checker.check_local(name));
+              None
+            }
+            InputTrigger::Count { condition, .. } => {
+              condition.visit_variables(&mut |name| {
+                if let AreaCount::Variable(name) = name {
+                  checker.check_local(name)
+                }
+              });
+              None
+            }
+            InputTrigger::Internal { source, condition } => {
+              condition.visit_variables(&mut |name| checker.check_local(name));
+              listeners.entry(*source).or_default().push((machine, state));
+              Some(*source)
+            }
+          };
+          for action in &transition.actions {
+            if let Some(target) = checker.check_action(action, |checker, name| checker.check_local(name)) {
+              raised.entry(target).or_default().push((machine, state));
+              if let Some(listens) = listens {
+                links.entry(listens).or_default().push(TriggerLink { machine, state, listens, raises: target });
+              }
+            }
+          }
+        }
+        match &state_definition.timer_transition {
+          TimerTransition::None => (),
+          TimerTransition::Reset { duration, next, actions } => {
+            duration.visit_variables(&mut |name| checker.check_local(name));
+            checker.check_timer(*next, definition, actions, &mut raised);
+          }
+          TimerTransition::Rollover { duration, next, actions } => {
+            duration.visit_variables(&mut |name| checker.check_timer_variable(name));
+            checker.check_timer(*next, definition, actions, &mut raised);
+          }
+        }
+      }
+      for state in definition.unreachable_states() {
+        issues.push(AnalysisIssue::UnreachableState { machine, state });
+      }
+    }
+    for (trigger, sources) in &raised {
+      if !listeners.contains_key(trigger) {
+        for &(machine, state) in sources {
+          issues.push(AnalysisIssue::UnheardTrigger { machine, state, trigger: *trigger });
+        }
+      }
+    }
+    for (trigger, targets) in &listeners {
+      if !raised.contains_key(trigger) {
+        for &(machine, state) in targets {
+          issues.push(AnalysisIssue::UnraisedTrigger { machine, state, trigger: *trigger });
+        }
+      }
+    }
+    find_cycles(&links, &mut issues);
+    issues
+  }
+}
+
+impl<InputIdentifier, OutputIdentifier, Area> StateMachine<InputIdentifier, OutputIdentifier, Area> {
+  fn unreachable_states(&self) -> impl Iterator<Item = StateId> {
+    let mut reachable = BTreeSet::new();
+    let mut queue = vec![0 as StateId];
+    while let Some(state) = queue.pop() {
+      let Some(definition) = self.states.get(state as usize) else {
+        continue;
+      };
+      if !reachable.insert(state) {
+        continue;
+      }
+      queue.extend(definition.user_transitions.iter().map(|transition| transition.next));
+      match &definition.timer_transition {
+        TimerTransition::None => (),
+        TimerTransition::Reset { next, .. } | TimerTransition::Rollover { next, .. } => queue.push(*next),
+      }
+    }
+    (0..self.states.len()).map(|state| state as StateId).filter(move |state| !reachable.contains(state))
+  }
+}
+
+impl StateChecker<'_> {
+  fn check_action<Variable>(&mut self, action: &EdgeAction<Variable>, mut check: impl FnMut(&mut Self, &Variable)) -> Option<InternalEventId> {
+    match action {
+      EdgeAction::Set { counter, condition, value } => {
+        if *counter >= self.counters {
+          self.issues.push(AnalysisIssue::InvalidCounter { machine: self.machine, state: self.state, counter: *counter });
+        }
+        condition.visit_variables(&mut |name| check(self, name));
+        value.visit_variables(&mut |name| check(self, name));
+        None
+      }
+      EdgeAction::Trigger { target, condition } => {
+        condition.visit_variables(&mut |name| check(self, name));
+        Some(*target)
+      }
+    }
+  }
+  fn check_local(&mut self, name: &LocalVariableName) {
+    match name {
+      LocalVariableName::Local(local) => {
+        if *local as usize >= self.locals {
+          self.issues.push(AnalysisIssue::InvalidLocal { machine: self.machine, state: self.state, local: *local });
+        }
+      }
+      LocalVariableName::Other(name) => self.check_state(name),
+    }
+  }
+  fn check_state(&mut self, name: &StateVariableName) {
+    match name {
+      StateVariableName::Counter(counter) => {
+        if *counter >= self.counters {
+          self.issues.push(AnalysisIssue::InvalidCounter { machine: self.machine, state: self.state, counter: *counter });
+        }
+      }
+      StateVariableName::Global(global) => {
+        if *global as usize >= self.globals {
+          self.issues.push(AnalysisIssue::InvalidGlobal { machine: self.machine, state: self.state, global: *global });
+        }
+      }
+    }
+  }
+  fn check_timer<InputIdentifier, OutputIdentifier, Area>(
+    &mut self,
+    next: StateId,
+    definition: &StateMachine<InputIdentifier, OutputIdentifier, Area>,
+    actions: &[EdgeAction<TimerDuration<LocalVariableName>>],
+    raised: &mut BTreeMap<InternalEventId, Vec<(usize, StateId)>>,
+  ) {
+    if next as usize >= definition.states.len() {
+      self.issues.push(AnalysisIssue::InvalidTimerState { machine: self.machine, state: self.state, next });
+    }
+    for action in actions {
+      if let Some(target) = self.check_action(action, |checker, name| checker.check_timer_variable(name)) {
+        raised.entry(target).or_default().push((self.machine, self.state));
+      }
+    }
+  }
+  fn check_timer_variable(&mut self, name: &TimerDuration<LocalVariableName>) {
+    if let TimerDuration::Variable(name) = name {
+      self.check_local(name);
+    }
+  }
+}
+
+/// Find loops in the graph of internal events, where each edge is a transition that listens for one event and raises another
+fn find_cycles(links: &BTreeMap<InternalEventId, Vec<TriggerLink>>, issues: &mut Vec<AnalysisIssue>) {
+  fn visit(
+    event: InternalEventId,
+    links: &BTreeMap<InternalEventId, Vec<TriggerLink>>,
+    finished: &mut BTreeSet<InternalEventId>,
+    path: &mut Vec<TriggerLink>,
+    issues: &mut Vec<AnalysisIssue>,
+  ) {
+    for link in links.get(&event).into_iter().flatten() {
+      if let Some(start) = path.iter().position(|step| step.listens == link.raises) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(link.clone());
+        issues.push(AnalysisIssue::TriggerCycle(cycle));
+      } else if link.raises == link.listens {
+        issues.push(AnalysisIssue::TriggerCycle(vec![link.clone()]));
+      } else if !finished.contains(&link.raises) {
+        path.push(link.clone());
+        visit(link.raises, links, finished, path, issues);
+        path.pop();
+      }
+    }
+    finished.insert(event);
+  }
+  let mut finished = BTreeSet::new();
+  for &event in links.keys() {
+    if !finished.contains(&event) {
+      visit(event, links, &mut finished, &mut Vec::new(), issues);
+    }
+  }
+}
+
+impl Display for AnalysisIssue {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AnalysisIssue::InvalidCounter { machine, state, counter } => {
+        write!(f, "In machine {}, state {} uses counter {}, but this does not exist", machine, state, counter)
+      }
+      AnalysisIssue::InvalidGlobal { machine, state, global } => {
+        write!(f, "In machine {}, state {} uses global variable {}, but this does not exist", machine, state, global)
+      }
+      AnalysisIssue::InvalidLocal { machine, state, local } => {
+        write!(f, "In machine {}, state {} uses local variable {}, but this does not exist", machine, state, local)
+      }
+      AnalysisIssue::InvalidNextState { machine, state, next } => {
+        write!(f, "In machine {}, state {} has a transition to state {}, but this does not exist", machine, state, next)
+      }
+      AnalysisIssue::InvalidTimerState { machine, state, next } => {
+        write!(f, "In machine {}, state {} has a timer transition to state {}, but this does not exist", machine, state, next)
+      }
+      AnalysisIssue::NoStates { machine } => write!(f, "Machine {} has no states", machine),
+      AnalysisIssue::OutputMismatch { machine, state, expected, actual } => {
+        write!(f, "In machine {}, state {} has {} outputs, but the machine has {}", machine, state, actual, expected)
+      }
+      AnalysisIssue::TooManyStates { machine } => write!(f, "Machine {} has more than {} states", machine, StateId::MAX as usize + 1),
+      AnalysisIssue::TriggerCycle(links) => {
+        f.write_str("Internal events form a loop:")?;
+        for link in links {
+          write!(f, " machine {} state {} hears {} and raises {};", link.machine, link.state, link.listens, link.raises)?;
+        }
+        Ok(())
+      }
+      AnalysisIssue::UnraisedTrigger { machine, state, trigger } => {
+        write!(f, "In machine {}, state {} listens for internal event {}, but nothing raises it", machine, state, trigger)
+      }
+      AnalysisIssue::UnreachableState { machine, state } => write!(f, "In machine {}, state {} can never be reached", machine, state),
+      AnalysisIssue::UnheardTrigger { machine, state, trigger } => {
+        write!(f, "In machine {}, state {} raises internal event {}, but nothing listens for it", machine, state, trigger)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{AnalysisIssue, TriggerLink};
+  use crate::controller::puzzle::state_machine::text::parse;
+  use crate::controller::puzzle::state_machine::timer_transition::TimerTransition;
+  use crate::controller::puzzle::state_machine::StateMachinePuzzleTemplate;
+
+  fn template(source: &str) -> StateMachinePuzzleTemplate<String, String, String> {
+    parse(source).unwrap_or_else(|e| panic!("Failed to parse source at {:?}: {}", e.line_and_column(source), e))
+  }
+
+  fn errors(template: &StateMachinePuzzleTemplate<String, String, String>) -> Vec<AnalysisIssue> {
+    template.analyse().into_iter().filter(|issue| issue.is_error()).collect()
+  }
+
+  #[test]
+  fn consistent_template() {
+    let template = template(
+      r#"
+global0 = seed % 4;
+
+machine door {
+  counters 1;
+  outputs open;
+
+  state closed {
+    local0 = counter0 + global0;
+    output open = false;
+    on click(lever) if local0 > 2 -> open;
+    on click(lever) -> closed { counter0 = counter0 + 1; }
+  }
+  state open {
+    output open = true;
+    timer reset 30 -> closed { counter0 = 0; }
+  }
+}
+"#,
+    );
+    assert_eq!(template.analyse(), vec![]);
+  }
+
+  #[test]
+  fn invalid_counter() {
+    let template = template("machine m { counters 1; state a { local0 = counter2 + 1; on click(x) -> a { counter1 = 0; } } }");
+    assert_eq!(
+      errors(&template),
+      vec![AnalysisIssue::InvalidCounter { machine: 0, state: 0, counter: 2 }, AnalysisIssue::InvalidCounter { machine: 0, state: 0, counter: 1 }]
+    );
+  }
+
+  #[test]
+  fn invalid_global() {
+    let template = template("global0 = seed; machine m { state a { local0 = global0 + global3; } }");
+    assert_eq!(errors(&template), vec![AnalysisIssue::InvalidGlobal { machine: 0, state: 0, global: 3 }]);
+  }
+
+  #[test]
+  fn invalid_local() {
+    let template = template("machine m { state a { local0 = 1 + 1; on click(x) if local0 > local1 -> a; timer rollover local2 + duration -> a; } }");
+    assert_eq!(
+      errors(&template),
+      vec![AnalysisIssue::InvalidLocal { machine: 0, state: 0, local: 1 }, AnalysisIssue::InvalidLocal { machine: 0, state: 0, local: 2 }]
+    );
+  }
+
+  #[test]
+  fn invalid_next_states() {
+    let mut template = template("machine m { state a { on click(x) -> b; } state b { timer reset 5 -> a; } }");
+    template.machines[0].states[0].user_transitions[0].next = 4;
+    match &mut template.machines[0].states[1].timer_transition {
+      TimerTransition::Reset { next, .. } => *next = 7,
+      _ => panic!("Expected a reset timer"),
+    }
+    assert_eq!(
+      errors(&template),
+      vec![AnalysisIssue::InvalidNextState { machine: 0, state: 0, next: 4 }, AnalysisIssue::InvalidTimerState { machine: 0, state: 1, next: 7 }]
+    );
+  }
+
+  #[test]
+  fn no_states() {
+    let template = template("machine m { } machine n { state a { } }");
+    assert_eq!(template.analyse(), vec![AnalysisIssue::NoStates { machine: 0 }]);
+  }
+
+  #[test]
+  fn output_mismatch() {
+    let mut template = template("machine m { outputs open; state a { output open = true; } }");
+    template.machines[0].states[0].outputs.clear();
+    assert_eq!(errors(&template), vec![AnalysisIssue::OutputMismatch { machine: 0, state: 0, expected: 1, actual: 0 }]);
+  }
+
+  #[test]
+  fn too_many_states() {
+    let mut template = template("machine m { state a { on click(x) -> a; } }");
+    let state = template.machines[0].states[0].clone();
+    template.machines[0].states.resize(257, state);
+    assert_eq!(template.analyse(), vec![AnalysisIssue::TooManyStates { machine: 0 }]);
+  }
+
+  #[test]
+  fn warnings_are_not_errors() {
+    let template = template(
+      r#"
+machine m {
+  state a {
+    on trigger(1) -> a { trigger 2; }
+    on trigger(2) -> a { trigger 1; }
+    on trigger(3) -> a;
+    on click(x) -> a { trigger 4; }
+  }
+  state b { }
+}
+"#,
+    );
+    let issues = template.analyse();
+    assert_eq!(
+      issues,
+      vec![
+        AnalysisIssue::UnreachableState { machine: 0, state: 1 },
+        AnalysisIssue::UnheardTrigger { machine: 0, state: 0, trigger: 4 },
+        AnalysisIssue::UnraisedTrigger { machine: 0, state: 0, trigger: 3 },
+        AnalysisIssue::TriggerCycle(vec![
+          TriggerLink { machine: 0, state: 0, listens: 1, raises: 2 },
+          TriggerLink { machine: 0, state: 0, listens: 2, raises: 1 }
+        ]),
+      ]
+    );
+    assert!(issues.iter().all(|issue| !issue.is_error()));
+  }
+}
@@ -1,5 +1,24 @@
+use crate::controller::puzzle::state_machine::expression::compiled::{CompiledExpression, Constants, FoldableVariable};
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum EdgeAction<Variable> {
   Set { counter: super::CounterId, condition: super::expression::BoolExpression<Variable>, value: super::expression::NumberExpression<Variable> },
   Trigger { target: super::InternalEventId, condition: super::expression::BoolExpression<Variable> },
 }
+
+#[derive(Clone, Debug)]
+pub enum CompiledEdgeAction<Variable> {
+  Set { counter: super::CounterId, condition: CompiledExpression<Variable>, value: CompiledExpression<Variable> },
+  Trigger { target: super::InternalEventId, condition: CompiledExpression<Variable> },
+}
+
+impl<Variable: FoldableVariable> EdgeAction<Variable> {
+  pub fn compile(&self, constants: &Constants) -> CompiledEdgeAction<Variable> {
+    match self {
+      EdgeAction::Set { counter, condition, value } => {
+        CompiledEdgeAction::Set { counter: *counter, condition: condition.compile(constants), value: value.compile(constants) }
+      }
+      EdgeAction::Trigger { target, condition } => CompiledEdgeAction::Trigger { target: *target, condition: condition.compile(constants) },
+    }
+  }
+}
@@ -0,0 +1,634 @@
+use crate::controller::puzzle::area::PlayerStateCondition;
+use crate::controller::puzzle::state_machine::error::StateMachineError;
+use crate::controller::puzzle::state_machine::expression::holiday::Holiday;
+use crate::controller::puzzle::state_machine::expression::{
+  mask, permutation, BoolExpression, ExpressionData, MultiStateVariableExpression, NumberExpression, VariableExpression,
+};
+use crate::controller::puzzle::{MultiStateValue, Value};
+use chrono::NaiveDate;
+
+/// Values that are fixed for the lifetime of a puzzle and can be substituted into expressions before they are evaluated
+pub struct Constants<'a> {
+  pub globals: &'a [Value],
+  pub seed: u32,
+}
+
+/// A variable that might refer to a global variable, which can be replaced by its value
+pub trait FoldableVariable: Clone {
+  fn global(&self) -> Option<u8>;
+}
+
+/// An expression flattened into instructions for a stack machine, with any constant parts already evaluated
+///
+/// True/false values are stored as 0 and 1. Evaluation order and short-circuiting match the tree-walking evaluator, so the results and
+/// errors will be identical.
+#[derive(Clone, Debug)]
+pub struct CompiledExpression<Variable> {
+  instructions: Vec<Instruction<Variable>>,
+  stack_size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum CompiledVariableExpression<Variable> {
+  Bool(CompiledExpression<Variable>),
+  Num(CompiledExpression<Variable>),
+}
+
+#[derive(Clone, Debug)]
+pub enum CompiledMultiStateVariableExpression<Variable> {
+  Bool(CompiledExpression<Variable>),
+  Num(CompiledExpression<Variable>),
+  MultiBool { default: CompiledExpression<Variable>, expressions: Vec<(PlayerStateCondition, CompiledExpression<Variable>)> },
+  MultiNum { default: CompiledExpression<Variable>, expressions: Vec<(PlayerStateCondition, CompiledExpression<Variable>)> },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operation {
+  Add,
+  BitAnd,
+  BitOr,
+  Equal,
+  GreaterThan,
+  GreaterThanOrEqual,
+  LessThan,
+  LessThanOrEqual,
+  Max,
+  Min,
+  Multiply,
+  NotEqual,
+  Subtract,
+  XOr,
+}
+
+#[derive(Clone, Debug)]
+enum Instruction<Variable> {
+  /// If the top of the stack is false, leave it and jump; otherwise, discard it
+  AndThen(usize),
+  /// Pop the right and then left operands and push the result
+  Binary(Operation),
+  /// Pop an index and jump to the matching case or to the default
+  Case(Vec<usize>, usize),
+  Constant(u32),
+  /// Pop the dividend and then the divisor and push the quotient
+  Divide,
+  /// If the divisor on the top of the stack is zero, leave it as the result and jump; otherwise, continue to push the dividend
+  DivisorCheck(usize),
+  IsHoliday(Vec<Holiday>),
+  Jump(usize),
+  /// Pop a condition and jump if it is false
+  JumpIfFalse(usize),
+  /// Pop the dividend and then the divisor and push the remainder
+  Modulo,
+  Not,
+  /// If the top of the stack is true, leave it and jump; otherwise, discard it
+  OrElse(usize),
+  /// Pop the length, index, and seed and push the permuted value
+  Permutation,
+  Variable {
+    name: Variable,
+    bool: bool,
+  },
+}
+
+struct Compiler<'a, Variable> {
+  constants: &'a Constants<'a>,
+  instructions: Vec<Instruction<Variable>>,
+}
+
+/// Data for evaluating constant instructions, which never need a date or variables
+struct NoData;
+
+impl<Variable> ExpressionData<Variable> for NoData {
+  fn date(&self) -> NaiveDate {
+    NaiveDate::default()
+  }
+
+  fn seed(&self) -> u32 {
+    0
+  }
+
+  fn variable(&self, _: &Variable) -> Result<Value, StateMachineError> {
+    Err(StateMachineError::VariableInGlobal)
+  }
+}
+
+impl<Variable: FoldableVariable> BoolExpression<Variable> {
+  /// Convert this expression into a form that is faster to evaluate, replacing anything that will not change during the puzzle's lifetime
+  pub fn compile(&self, constants: &Constants) -> CompiledExpression<Variable> {
+    let mut compiler = Compiler { constants, instructions: Vec::new() };
+    compiler.bool_expression(self);
+    compiler.finish()
+  }
+}
+impl<Variable: FoldableVariable> NumberExpression<Variable> {
+  /// Convert this expression into a form that is faster to evaluate, replacing anything that will not change during the puzzle's lifetime
+  pub fn compile(&self, constants: &Constants) -> CompiledExpression<Variable> {
+    let mut compiler = Compiler { constants, instructions: Vec::new() };
+    compiler.number_expression(self);
+    compiler.finish()
+  }
+}
+impl<Variable: FoldableVariable> VariableExpression<Variable> {
+  pub fn compile(&self, constants: &Constants) -> CompiledVariableExpression<Variable> {
+    match self {
+      VariableExpression::Bool(e) => CompiledVariableExpression::Bool(e.compile(constants)),
+      VariableExpression::Num(e) => CompiledVariableExpression::Num(e.compile(constants)),
+    }
+  }
+}
+impl<Variable: FoldableVariable> MultiStateVariableExpression<Variable> {
+  pub fn compile(&self, constants: &Constants) -> CompiledMultiStateVariableExpression<Variable> {
+    match self {
+      MultiStateVariableExpression::Bool(e) => CompiledMultiStateVariableExpression::Bool(e.compile(constants)),
+      MultiStateVariableExpression::Num(e) => CompiledMultiStateVariableExpression::Num(e.compile(constants)),
+      MultiStateVariableExpression::MultiBool { default, expressions } => CompiledMultiStateVariableExpression::MultiBool {
+        default: default.compile(constants),
+        expressions: expressions.iter().map(|(condition, e)| (condition.clone(), e.compile(constants))).collect(),
+      },
+      MultiStateVariableExpression::MultiNum { default, expressions } => CompiledMultiStateVariableExpression::MultiNum {
+        default: default.compile(constants),
+        expressions: expressions.iter().map(|(condition, e)| (condition.clone(), e.compile(constants))).collect(),
+      },
+    }
+  }
+}
+
+impl<Variable: FoldableVariable> Compiler<'_, Variable> {
+  /// Emit instructions for a true/false expression and return whether the result is constant
+  fn bool_expression(&mut self, expression: &BoolExpression<Variable>) -> bool {
+    let start = self.instructions.len();
+    let constant = match expression {
+      BoolExpression::And(left, right) => {
+        if self.bool_expression(left) {
+          return self.short_circuit(start, false, right);
+        }
+        let jump = self.placeholder();
+        self.bool_expression(right);
+        self.instructions[jump] = Instruction::AndThen(self.instructions.len());
+        false
+      }
+      BoolExpression::Constant(value) => {
+        self.instructions.push(Instruction::Constant(*value as u32));
+        true
+      }
+      BoolExpression::Equal(left, right) => self.binary(Operation::Equal, left, right),
+      BoolExpression::GreaterThan(left, right) => self.binary(Operation::GreaterThan, left, right),
+      BoolExpression::GreaterThanOrEqual(left, right) => self.binary(Operation::GreaterThanOrEqual, left, right),
+      BoolExpression::If(condition, when_true, when_false) => {
+        if self.bool_expression(condition) {
+          let value = self.take_constant(start);
+          return self.bool_expression(if value != 0 { when_true } else { when_false });
+        }
+        let jump = self.placeholder();
+        self.bool_expression(when_true);
+        let end = self.placeholder();
+        self.instructions[jump] = Instruction::JumpIfFalse(self.instructions.len());
+        self.bool_expression(when_false);
+        self.instructions[end] = Instruction::Jump(self.instructions.len());
+        false
+      }
+      BoolExpression::IsHoliday(holidays) => {
+        self.instructions.push(Instruction::IsHoliday(holidays.clone()));
+        false
+      }
+      BoolExpression::LessThan(left, right) => self.binary(Operation::LessThan, left, right),
+      BoolExpression::LessThanOrEqual(left, right) => self.binary(Operation::LessThanOrEqual, left, right),
+      BoolExpression::Not(expression) => {
+        let constant = self.bool_expression(expression);
+        self.instructions.push(Instruction::Not);
+        constant
+      }
+      BoolExpression::NotEqual(left, right) => self.binary(Operation::NotEqual, left, right),
+      BoolExpression::Or(left, right) => {
+        if self.bool_expression(left) {
+          return self.short_circuit(start, true, right);
+        }
+        let jump = self.placeholder();
+        self.bool_expression(right);
+        self.instructions[jump] = Instruction::OrElse(self.instructions.len());
+        false
+      }
+      BoolExpression::Variable(name) => self.variable(name, true),
+    };
+    self.fold(start, constant)
+  }
+  /// Emit instructions for a numeric expression and return whether the result is constant
+  fn number_expression(&mut self, expression: &NumberExpression<Variable>) -> bool {
+    let start = self.instructions.len();
+    let constant = match expression {
+      NumberExpression::Add(left, right) => self.binary(Operation::Add, left, right),
+      NumberExpression::And(left, right) => self.binary(Operation::BitAnd, left, right),
+      NumberExpression::Case(index, cases, default) => {
+        if self.number_expression(index) {
+          let index = self.take_constant(start);
+          return self.number_expression(cases.get(index as usize).unwrap_or(default));
+        }
+        let jump = self.placeholder();
+        let mut targets = Vec::with_capacity(cases.len());
+        let mut ends = Vec::with_capacity(cases.len());
+        for case in cases {
+          targets.push(self.instructions.len());
+          self.number_expression(case);
+          ends.push(self.placeholder());
+        }
+        self.instructions[jump] = Instruction::Case(targets, self.instructions.len());
+        self.number_expression(default);
+        for end in ends {
+          self.instructions[end] = Instruction::Jump(self.instructions.len());
+        }
+        false
+      }
+      NumberExpression::Constant(value) => {
+        self.instructions.push(Instruction::Constant(*value));
+        true
+      }
+      NumberExpression::Divide(left, right) | NumberExpression::Modulo(left, right) => {
+        let right_constant = self.number_expression(right);
+        let check = self.placeholder();
+        let left_constant = self.number_expression(left);
+        self.instructions.push(if matches!(expression, NumberExpression::Divide(_, _)) { Instruction::Divide } else { Instruction::Modulo });
+        self.instructions[check] = Instruction::DivisorCheck(self.instructions.len());
+        right_constant && left_constant
+      }
+      NumberExpression::If(condition, when_true, when_false) => {
+        if self.bool_expression(condition) {
+          let value = self.take_constant(start);
+          return self.number_expression(if value != 0 { when_true } else { when_false });
+        }
+        let jump = self.placeholder();
+        self.number_expression(when_true);
+        let end = self.placeholder();
+        self.instructions[jump] = Instruction::JumpIfFalse(self.instructions.len());
+        self.number_expression(when_false);
+        self.instructions[end] = Instruction::Jump(self.instructions.len());
+        false
+      }
+      NumberExpression::Mask { offset, length } => {
+        self.instructions.push(Instruction::Constant(mask(*offset, *length)));
+        true
+      }
+      NumberExpression::Max(left, right) => self.binary(Operation::Max, left, right),
+      NumberExpression::Min(left, right) => self.binary(Operation::Min, left, right),
+      NumberExpression::Multiply(left, right) => self.binary(Operation::Multiply, left, right),
+      NumberExpression::Or(left, right) => self.binary(Operation::BitOr, left, right),
+      NumberExpression::Permutation { seed, index, length } => {
+        let seed = self.number_expression(seed);
+        let index = self.number_expression(index);
+        let length = self.number_expression(length);
+        self.instructions.push(Instruction::Permutation);
+        seed && index && length
+      }
+      NumberExpression::Seed => {
+        self.instructions.push(Instruction::Constant(self.constants.seed));
+        true
+      }
+      NumberExpression::Subtract(left, right) => self.binary(Operation::Subtract, left, right),
+      NumberExpression::Variable(name) => self.variable(name, false),
+      NumberExpression::XOr(left, right) => self.binary(Operation::XOr, left, right),
+    };
+    self.fold(start, constant)
+  }
+  fn binary(&mut self, operation: Operation, left: &NumberExpression<Variable>, right: &NumberExpression<Variable>) -> bool {
+    let left = self.number_expression(left);
+    let right = self.number_expression(right);
+    self.instructions.push(Instruction::Binary(operation));
+    left && right
+  }
+  fn variable(&mut self, name: &Variable, bool: bool) -> bool {
+    match name.global().and_then(|global| self.constants.globals.get(global as usize)) {
+      Some(&value) => {
+        self.instructions.push(Instruction::Constant(if bool { bool::from(value) as u32 } else { value.into() }));
+        true
+      }
+      None => {
+        self.instructions.push(Instruction::Variable { name: name.clone(), bool });
+        false
+      }
+    }
+  }
+  /// Handle the right side of an and/or operation where the left side is constant; `stop` is the left value that skips the right side
+  fn short_circuit(&mut self, start: usize, stop: bool, right: &BoolExpression<Variable>) -> bool {
+    if (self.take_constant(start) != 0) == stop {
+      self.instructions.push(Instruction::Constant(stop as u32));
+      true
+    } else {
+      self.bool_expression(right)
+    }
+  }
+  fn placeholder(&mut self) -> usize {
+    self.instructions.push(Instruction::Jump(0));
+    self.instructions.len() - 1
+  }
+  /// Remove a constant that was just emitted and return its value
+  fn take_constant(&mut self, start: usize) -> u32 {
+    match self.instructions.drain(start..).next() {
+      Some(Instruction::Constant(value)) => value,
+      _ => unreachable!("Constant expression was not folded"),
+    }
+  }
+  /// If the instructions since the start are constant, evaluate them and replace them with their value
+  fn fold(&mut self, start: usize, constant: bool) -> bool {
+    if constant && self.instructions.len() - start > 1 {
+      if let Ok(value) = run(&self.instructions, start, &NoData) {
+        self.instructions.truncate(start);
+        self.instructions.push(Instruction::Constant(value));
+      }
+    }
+    constant
+  }
+  fn finish(self) -> CompiledExpression<Variable> {
+    // Each instruction pushes at most one value, so this is the worst case
+    let stack_size = self.instructions.len();
+    CompiledExpression { instructions: self.instructions, stack_size }
+  }
+}
+
+impl<Variable> CompiledExpression<Variable> {
+  pub fn evaluate_bool<Data: ExpressionData<Variable>>(&self, data: &Data) -> Result<bool, StateMachineError> {
+    Ok(self.evaluate_number(data)? != 0)
+  }
+  pub fn evaluate_number<Data: ExpressionData<Variable>>(&self, data: &Data) -> Result<u32, StateMachineError> {
+    match self.instructions.as_slice() {
+      [Instruction::Constant(value)] => Ok(*value),
+      instructions => {
+        let mut stack = Vec::with_capacity(self.stack_size);
+        execute(instructions, 0, data, &mut stack)
+      }
+    }
+  }
+}
+impl<Variable> CompiledVariableExpression<Variable> {
+  pub fn evaluate<D: ExpressionData<Variable>>(&self, data: &D) -> Result<Value, StateMachineError> {
+    Ok(match self {
+      CompiledVariableExpression::Bool(e) => Value::Bool(e.evaluate_bool(data)?),
+      CompiledVariableExpression::Num(e) => Value::Num(e.evaluate_number(data)?),
+    })
+  }
+}
+impl<Variable> CompiledMultiStateVariableExpression<Variable> {
+  pub fn evaluate<D: ExpressionData<Variable>>(&self, data: &D) -> Result<MultiStateValue, StateMachineError> {
+    Ok(match self {
+      CompiledMultiStateVariableExpression::Bool(e) => MultiStateValue::Bool(e.evaluate_bool(data)?),
+      CompiledMultiStateVariableExpression::Num(e) => MultiStateValue::Num(e.evaluate_number(data)?),
+      CompiledMultiStateVariableExpression::MultiBool { default, expressions } => MultiStateValue::MultiBool {
+        default: default.evaluate_bool(data)?,
+        values: expressions.iter().map(|(condition, e)| Ok((condition.clone(), e.evaluate_bool(data)?))).collect::<Result<_, _>>()?,
+      },
+      CompiledMultiStateVariableExpression::MultiNum { default, expressions } => MultiStateValue::MultiNum {
+        default: default.evaluate_number(data)?,
+        values: expressions.iter().map(|(condition, e)| Ok((condition.clone(), e.evaluate_number(data)?))).collect::<Result<_, _>>()?,
+      },
+    })
+  }
+}
+
+fn run<Variable, Data: ExpressionData<Variable>>(
+  instructions: &[Instruction<Variable>],
+  start: usize,
+  data: &Data,
+) -> Result<u32, StateMachineError> {
+  execute(instructions, start, data, &mut Vec::new())
+}
+
+fn execute<Variable, Data: ExpressionData<Variable>>(
+  instructions: &[Instruction<Variable>],
+  start: usize,
+  data: &Data,
+  stack: &mut Vec<u32>,
+) -> Result<u32, StateMachineError> {
+  let mut position = start;
+  while let Some(instruction) = instructions.get(position) {
+    position += 1;
+    match instruction {
+      Instruction::AndThen(target) => {
+        if stack.last() == Some(&0) {
+          position = *target;
+        } else {
+          stack.pop();
+        }
+      }
+      Instruction::Binary(operation) => {
+        let right = stack.pop().unwrap_or(0);
+        let left = stack.pop().unwrap_or(0);
+        // These must produce the same results as BoolExpression::evaluate and NumberExpression::evaluate
+        stack.push(match operation {
+          Operation::Add => left.saturating_add(right),
+          Operation::BitAnd => left & right,
+          Operation::BitOr => left | right,
+          Operation::Equal => (left == right) as u32,
+          Operation::GreaterThan => (left > right) as u32,
+          Operation::GreaterThanOrEqual => (left >= right) as u32,
+          Operation::LessThan => (left < right) as u32,
+          Operation::LessThanOrEqual => (left <= right) as u32,
+          Operation::Max => left.max(right),
+          Operation::Min => left.min(right),
+          Operation::Multiply => left.saturating_mul(right),
+          Operation::NotEqual => (left != right) as u32,
+          Operation::Subtract => left.saturating_sub(right),
+          Operation::XOr => left ^ right,
+        });
+      }
+      Instruction::Case(targets, default) => {
+        let index = stack.pop().unwrap_or(0);
+        position = targets.get(index as usize).copied().unwrap_or(*default);
+      }
+      Instruction::Constant(value) => stack.push(*value),
+      Instruction::Divide => {
+        let left = stack.pop().unwrap_or(0);
+        let right = stack.pop().unwrap_or(0);
+        stack.push(left.saturating_div(right));
+      }
+      Instruction::DivisorCheck(target) => {
+        if stack.last() == Some(&0) {
+          position = *target;
+        }
+      }
+      Instruction::IsHoliday(holidays) => {
+        let date = data.date();
+        stack.push(holidays.iter().any(|h| h.is_holiday(&date)) as u32);
+      }
+      Instruction::Jump(target) => position = *target,
+      Instruction::JumpIfFalse(target) => {
+        if stack.pop() == Some(0) {
+          position = *target;
+        }
+      }
+      Instruction::Modulo => {
+        let left = stack.pop().unwrap_or(0);
+        let right = stack.pop().unwrap_or(0);
+        stack.push(left % right);
+      }
+      Instruction::Not => {
+        let value = stack.pop().unwrap_or(0);
+        stack.push((value == 0) as u32);
+      }
+      Instruction::OrElse(target) => {
+        if stack.last().map(|&v| v != 0).unwrap_or(false) {
+          position = *target;
+        } else {
+          stack.pop();
+        }
+      }
+      Instruction::Permutation => {
+        let length = stack.pop().unwrap_or(0);
+        let index = stack.pop().unwrap_or(0);
+        let seed = stack.pop().unwrap_or(0);
+        stack.push(permutation(seed, index, length));
+      }
+      Instruction::Variable { name, bool } => {
+        let value = data.variable(name)?;
+        stack.push(if *bool { bool::from(value) as u32 } else { value.into() });
+      }
+    }
+  }
+  Ok(stack.pop().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Constants, FoldableVariable};
+  use crate::controller::puzzle::state_machine::error::StateMachineError;
+  use crate::controller::puzzle::state_machine::expression::{BoolExpression, ExpressionData, NumberExpression};
+  use crate::controller::puzzle::Value;
+  use chrono::NaiveDate;
+
+  const VALUES: [u32; 7] = [0, 1, 2, 6, 10, 0xF0F0_F0F0, u32::MAX];
+
+  /// A variable that is either a global, which will be folded, or one that is read from the data, which will not
+  #[derive(Clone, Copy, Debug)]
+  enum TestVariable {
+    Global(u8),
+    Local(u8),
+  }
+
+  impl FoldableVariable for TestVariable {
+    fn global(&self) -> Option<u8> {
+      match self {
+        TestVariable::Global(index) => Some(*index),
+        TestVariable::Local(_) => None,
+      }
+    }
+  }
+
+  struct TestData([Value; 2]);
+
+  impl ExpressionData<TestVariable> for TestData {
+    fn date(&self) -> NaiveDate {
+      NaiveDate::default()
+    }
+
+    fn seed(&self) -> u32 {
+      42
+    }
+
+    fn variable(&self, name: &TestVariable) -> Result<Value, StateMachineError> {
+      match name {
+        TestVariable::Global(index) | TestVariable::Local(index) => Ok(self.0[*index as usize]),
+      }
+    }
+  }
+
+  type Operator = fn(Box<NumberExpression<TestVariable>>, Box<NumberExpression<TestVariable>>) -> NumberExpression<TestVariable>;
+  type Comparison = fn(NumberExpression<TestVariable>, NumberExpression<TestVariable>) -> BoolExpression<TestVariable>;
+
+  const OPERATORS: [(&str, Operator); 10] = [
+    ("Add", NumberExpression::Add),
+    ("And", NumberExpression::And),
+    ("Divide", NumberExpression::Divide),
+    ("Max", NumberExpression::Max),
+    ("Min", NumberExpression::Min),
+    ("Modulo", NumberExpression::Modulo),
+    ("Multiply", NumberExpression::Multiply),
+    ("Or", NumberExpression::Or),
+    ("Subtract", NumberExpression::Subtract),
+    ("XOr", NumberExpression::XOr),
+  ];
+  const COMPARISONS: [(&str, Comparison); 6] = [
+    ("Equal", BoolExpression::Equal),
+    ("GreaterThan", BoolExpression::GreaterThan),
+    ("GreaterThanOrEqual", BoolExpression::GreaterThanOrEqual),
+    ("LessThan", BoolExpression::LessThan),
+    ("LessThanOrEqual", BoolExpression::LessThanOrEqual),
+    ("NotEqual", BoolExpression::NotEqual),
+  ];
+
+  fn variables(global: bool) -> (NumberExpression<TestVariable>, NumberExpression<TestVariable>) {
+    if global {
+      (NumberExpression::Variable(TestVariable::Global(0)), NumberExpression::Variable(TestVariable::Global(1)))
+    } else {
+      (NumberExpression::Variable(TestVariable::Local(0)), NumberExpression::Variable(TestVariable::Local(1)))
+    }
+  }
+
+  #[test]
+  fn operators_match_tree_walker() {
+    for (name, operator) in OPERATORS {
+      for left in VALUES {
+        for right in VALUES {
+          let data = TestData([Value::Num(left), Value::Num(right)]);
+          let constants = Constants { globals: &data.0, seed: data.seed() };
+          for global in [false, true] {
+            let (left_variable, right_variable) = variables(global);
+            let expression = operator(Box::new(left_variable), Box::new(right_variable));
+            assert_eq!(
+              expression.compile(&constants).evaluate_number(&data).unwrap(),
+              expression.evaluate(&data).unwrap(),
+              "{} of {} and {} (folded: {})",
+              name,
+              left,
+              right,
+              global
+            );
+          }
+          let expression = operator(Box::new(NumberExpression::Constant(left)), Box::new(NumberExpression::Constant(right)));
+          assert_eq!(
+            expression.compile(&constants).evaluate_number(&data).unwrap(),
+            expression.evaluate(&data).unwrap(),
+            "{} of constants {} and {}",
+            name,
+            left,
+            right
+          );
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn comparisons_match_tree_walker() {
+    for (name, comparison) in COMPARISONS {
+      for left in VALUES {
+        for right in VALUES {
+          let data = TestData([Value::Num(left), Value::Num(right)]);
+          let constants = Constants { globals: &data.0, seed: data.seed() };
+          for global in [false, true] {
+            let (left_variable, right_variable) = variables(global);
+            let expression = comparison(left_variable, right_variable);
+            assert_eq!(
+              expression.compile(&constants).evaluate_bool(&data).unwrap(),
+              expression.evaluate(&data).unwrap(),
+              "{} of {} and {} (folded: {})",
+              name,
+              left,
+              right,
+              global
+            );
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn bitwise_or_and_multiply() {
+    let data = TestData([Value::Num(6), Value::Num(10)]);
+    let constants = Constants { globals: &data.0, seed: data.seed() };
+    let (left, right) = variables(false);
+    let or = NumberExpression::Or(Box::new(left.clone()), Box::new(right.clone()));
+    assert_eq!(or.evaluate(&data).unwrap(), 14);
+    assert_eq!(or.compile(&constants).evaluate_number(&data).unwrap(), 14);
+    let multiply = NumberExpression::Multiply(Box::new(left), Box::new(right));
+    assert_eq!(multiply.evaluate(&data).unwrap(), 60);
+    assert_eq!(multiply.compile(&constants).evaluate_number(&data).unwrap(), 60);
+  }
+}
@@ -2,6 +2,7 @@ use crate::controller::puzzle::area::PlayerStateCondition;
 use crate::controller::puzzle::state_machine::error::StateMachineError;
 use crate::controller::puzzle::{MultiStateValue, Value};
 
+pub mod compiled;
 pub mod holiday;
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -86,6 +87,32 @@ impl<Variable> BoolExpression<Variable> {
       BoolExpression::Variable(name) => Ok(data.variable(name)?.into()),
     }
   }
+  /// Call a function on every variable referenced in this expression
+  pub fn visit_variables<F: FnMut(&Variable)>(&self, visitor: &mut F) {
+    match self {
+      BoolExpression::And(left, right) | BoolExpression::Or(left, right) => {
+        left.visit_variables(visitor);
+        right.visit_variables(visitor);
+      }
+      BoolExpression::Constant(_) | BoolExpression::IsHoliday(_) => (),
+      BoolExpression::Equal(left, right)
+      | BoolExpression::GreaterThan(left, right)
+      | BoolExpression::GreaterThanOrEqual(left, right)
+      | BoolExpression::LessThan(left, right)
+      | BoolExpression::LessThanOrEqual(left, right)
+      | BoolExpression::NotEqual(left, right) => {
+        left.visit_variables(visitor);
+        right.visit_variables(visitor);
+      }
+      BoolExpression::If(condition, when_true, when_false) => {
+        condition.visit_variables(visitor);
+        when_true.visit_variables(visitor);
+        when_false.visit_variables(visitor);
+      }
+      BoolExpression::Not(expression) => expression.visit_variables(visitor),
+      BoolExpression::Variable(name) => visitor(name),
+    }
+  }
 }
 impl<Variable> NumberExpression<Variable> {
   pub fn evaluate<Data: ExpressionData<Variable>>(&self, data: &Data) -> Result<u32, StateMachineError> {
@@ -105,37 +132,59 @@ impl<Variable> NumberExpression<Variable> {
           when_false.evaluate(data)
         }
       }
-      NumberExpression::Mask { offset, length } => {
-        let mask = 1_u32.checked_shl(*length as u32).map(|v| v - 1).unwrap_or(0);
-
-        Ok(mask.checked_shl(*offset as u32).unwrap_or(0))
-      }
+      NumberExpression::Mask { offset, length } => Ok(mask(*offset, *length)),
       NumberExpression::Max(left, right) => Ok(left.evaluate(data)?.max(right.evaluate(data)?)),
       NumberExpression::Min(left, right) => Ok(left.evaluate(data)?.min(right.evaluate(data)?)),
       NumberExpression::Modulo(left, right) => {
         let right = right.evaluate(data)?;
         Ok(if right == 0 { 0 } else { left.evaluate(data)? % right })
       }
-      NumberExpression::Multiply(left, right) => Ok(left.evaluate(data)?.saturating_add(right.evaluate(data)?)),
-      NumberExpression::Or(left, right) => Ok(left.evaluate(data)? & right.evaluate(data)?),
-      NumberExpression::Permutation { seed, index, length } => {
-        let seed = seed.evaluate(data)? as u64;
-        let index = index.evaluate(data)? as usize;
-        let length = length.evaluate(data)? as usize;
-        Ok(if length == 0 || index >= length {
-          0
-        } else {
-          use rand::SeedableRng;
-          let mut rng = rand::rngs::SmallRng::seed_from_u64(seed);
-          rand::seq::index::sample(&mut rng, length, length).index(index).try_into().unwrap_or(0)
-        })
-      }
+      NumberExpression::Multiply(left, right) => Ok(left.evaluate(data)?.saturating_mul(right.evaluate(data)?)),
+      NumberExpression::Or(left, right) => Ok(left.evaluate(data)? | right.evaluate(data)?),
+      NumberExpression::Permutation { seed, index, length } => Ok(permutation(seed.evaluate(data)?, index.evaluate(data)?, length.evaluate(data)?)),
       NumberExpression::Seed => Ok(data.seed()),
       NumberExpression::Subtract(left, right) => Ok(left.evaluate(data)?.saturating_sub(right.evaluate(data)?)),
       NumberExpression::Variable(name) => Ok(data.variable(name)?.into()),
       NumberExpression::XOr(left, right) => Ok(left.evaluate(data)? ^ right.evaluate(data)?),
     }
   }
+  /// Call a function on every variable referenced in this expression
+  pub fn visit_variables<F: FnMut(&Variable)>(&self, visitor: &mut F) {
+    match self {
+      NumberExpression::Add(left, right)
+      | NumberExpression::And(left, right)
+      | NumberExpression::Divide(left, right)
+      | NumberExpression::Max(left, right)
+      | NumberExpression::Min(left, right)
+      | NumberExpression::Modulo(left, right)
+      | NumberExpression::Multiply(left, right)
+      | NumberExpression::Or(left, right)
+      | NumberExpression::Subtract(left, right)
+      | NumberExpression::XOr(left, right) => {
+        left.visit_variables(visitor);
+        right.visit_variables(visitor);
+      }
+      NumberExpression::Case(index, cases, default) => {
+        index.visit_variables(visitor);
+        for case in cases {
+          case.visit_variables(visitor);
+        }
+        default.visit_variables(visitor);
+      }
+      NumberExpression::Constant(_) | NumberExpression::Mask { .. } | NumberExpression::Seed => (),
+      NumberExpression::If(condition, when_true, when_false) => {
+        condition.visit_variables(visitor);
+        when_true.visit_variables(visitor);
+        when_false.visit_variables(visitor);
+      }
+      NumberExpression::Permutation { seed, index, length } => {
+        seed.visit_variables(visitor);
+        index.visit_variables(visitor);
+        length.visit_variables(visitor);
+      }
+      NumberExpression::Variable(name) => visitor(name),
+    }
+  }
 }
 impl<Variable> VariableExpression<Variable> {
   pub fn evaluate<D: ExpressionData<Variable>>(&self, data: &D) -> Result<Value, StateMachineError> {
@@ -144,6 +193,13 @@ impl<Variable> VariableExpression<Variable> {
       VariableExpression::Num(e) => Value::Num(e.evaluate(data)?),
     })
   }
+  /// Call a function on every variable referenced in this expression
+  pub fn visit_variables<F: FnMut(&Variable)>(&self, visitor: &mut F) {
+    match self {
+      VariableExpression::Bool(e) => e.visit_variables(visitor),
+      VariableExpression::Num(e) => e.visit_variables(visitor),
+    }
+  }
 }
 impl<Variable> MultiStateVariableExpression<Variable> {
   pub fn evaluate<D: ExpressionData<Variable>>(&self, data: &D) -> Result<MultiStateValue, StateMachineError> {
@@ -160,4 +216,41 @@ impl<Variable> MultiStateVariableExpression<Variable> {
       },
     })
   }
+  /// Call a function on every variable referenced in this expression
+  pub fn visit_variables<F: FnMut(&Variable)>(&self, visitor: &mut F) {
+    match self {
+      MultiStateVariableExpression::Bool(e) => e.visit_variables(visitor),
+      MultiStateVariableExpression::Num(e) => e.visit_variables(visitor),
+      MultiStateVariableExpression::MultiBool { default, expressions } => {
+        default.visit_variables(visitor);
+        for (_, e) in expressions {
+          e.visit_variables(visitor);
+        }
+      }
+      MultiStateVariableExpression::MultiNum { default, expressions } => {
+        default.visit_variables(visitor);
+        for (_, e) in expressions {
+          e.visit_variables(visitor);
+        }
+      }
+    }
+  }
+}
+
+fn mask(offset: u8, length: u8) -> u32 {
+  let mask = 1_u32.checked_shl(length as u32).map(|v| v - 1).unwrap_or(0);
+
+  mask.checked_shl(offset as u32).unwrap_or(0)
+}
+
+fn permutation(seed: u32, index: u32, length: u32) -> u32 {
+  let index = index as usize;
+  let length = length as usize;
+  if length == 0 || index >= length {
+    0
+  } else {
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(seed as u64);
+    rand::seq::index::sample(&mut rng, length, length).index(index).try_into().unwrap_or(0)
+  }
 }
@@ -6,17 +6,21 @@ use crate::controller::{puzzle, LoadError};
 use chrono::{DateTime, NaiveDate, Utc};
 use chrono_tz::Tz;
 use error::StateMachineError;
+use expression::compiled::Constants;
 use serde::{Deserializer, Serializer};
-use state::{State, StateMachine};
+use state::{CompiledMachine, State, StateMachine};
 use std::borrow::Cow;
 use std::collections::BTreeSet;
 use std::fmt::{Debug, Display};
 use std::time::Duration;
 
+pub mod analysis;
 mod edge_action;
 pub mod error;
 pub mod expression;
+pub mod simulator;
 pub mod state;
+pub mod text;
 pub mod timer_transition;
 pub mod transition;
 
@@ -54,7 +58,16 @@ pub trait MachineIdentifier {
   fn machine(&self) -> usize;
 }
 
+/// Receives notifications about the internal behaviour of a puzzle as it processes input
+pub trait PuzzleObserver {
+  /// A machine has taken a transition; the source and destination may be the same state
+  fn transition(&mut self, machine: usize, from: StateId, to: StateId);
+  /// Internal events have been raised and will be offered to any machines that have not yet transitioned
+  fn triggers(&mut self, triggers: &BTreeSet<InternalEventId>);
+}
+
 pub struct StateMachinePuzzle<Template> {
+  compiled: Vec<CompiledMachine>,
   pub state: StateMachinePuzzleState,
   pub template: Template,
 }
@@ -94,6 +107,12 @@ impl expression::ExpressionData<()> for RootData {
   }
 }
 
+impl PuzzleObserver for () {
+  fn transition(&mut self, _machine: usize, _from: StateId, _to: StateId) {}
+
+  fn triggers(&mut self, _triggers: &BTreeSet<InternalEventId>) {}
+}
+
 impl<T: StateMachinePuzzleTemplateSource + Clone> super::PuzzleTemplate for T
 where
   T::Area: Eq,
@@ -108,14 +127,17 @@ where
     let now = now.with_timezone(&Utc);
     let root_data = RootData { seed, date };
     let global_values = self.template().variables.iter().map(|v| v.evaluate(&root_data)).collect::<Result<Vec<_>, _>>()?;
-    let states = self
-      .template()
-      .machines
+    let compiled = self.template().compile(seed, &global_values);
+    let states = compiled
       .iter()
       .enumerate()
       .map(|(id, s)| s.blank(id, now, GlobalData { id, root: root_data.clone(), globals: &global_values }))
       .collect::<Result<Vec<_>, _>>()?;
-    Ok(StateMachinePuzzle { state: StateMachinePuzzleState { global_values, last_time: now, seed, states, timezone }, template: self.clone() })
+    Ok(StateMachinePuzzle {
+      compiled,
+      state: StateMachinePuzzleState { global_values, last_time: now, seed, states, timezone },
+      template: self.clone(),
+    })
   }
 
   fn load<'de, D: Deserializer<'de>>(&self, de: D, _: DateTime<Utc>) -> Result<Self::Puzzle, LoadError<D::Error, Self::Error>> {
@@ -131,7 +153,7 @@ where
         return Err(LoadError::DeserializationMismatch);
       }
     }
-    Ok(StateMachinePuzzle { state, template: self.clone() })
+    Ok(StateMachinePuzzle { compiled: self.template().compile(state.seed, &state.global_values), state, template: self.clone() })
   }
 }
 
@@ -162,37 +184,52 @@ impl<InputIdentifier: Send + 'static, OutputIdentifier: Send + 'static, Area: Se
     self
   }
 }
+impl<T: StateMachinePuzzleTemplateSource + Sync> StateMachinePuzzleTemplateSource for std::sync::Arc<T> {
+  type InputIdentifier = T::InputIdentifier;
+  type OutputIdentifier = T::OutputIdentifier;
+  type Area = T::Area;
+
+  fn template(&self) -> &StateMachinePuzzleTemplate<Self::InputIdentifier, Self::OutputIdentifier, Self::Area> {
+    self.as_ref().template()
+  }
+}
 impl<Template: StateMachinePuzzleTemplateSource> StateMachinePuzzle<Template>
 where
   Template::InputIdentifier: Eq,
   Template::Area: Eq,
 {
-  fn process_triggers(
+  fn process_triggers<Observer: PuzzleObserver>(
     &mut self,
     now: DateTime<Utc>,
     mut triggers: BTreeSet<InternalEventId>,
     mut serviced: BTreeSet<StateId>,
+    observer: &mut Observer,
   ) -> Result<(), StateMachineError> {
     let root_data = self.state.get_root_variable_data(now);
     while !triggers.is_empty() {
+      observer.triggers(&triggers);
       let mut new_triggers = BTreeSet::new();
       let new_serviced = self
         .template
         .template()
         .machines
         .iter()
+        .zip(&self.compiled)
         .zip(&mut self.state.states)
         .enumerate()
         .filter(|(machine, _)| !serviced.contains(&(*machine as u8)))
-        .flat_map(|(machine, (definition, state))| {
-          let Some(state_definition) = definition.states.get(state.current as usize) else {
+        .flat_map(|(machine, ((definition, compiled), state))| {
+          let Some((state_definition, compiled_state)) =
+            definition.states.get(state.current as usize).zip(compiled.states.get(state.current as usize))
+          else {
             return Some(Err(StateMachineError::InvalidState { machine, state: state.current }));
           };
           let local_data = state.get_local_variable_data(GlobalData { id: machine, root: root_data.clone(), globals: &self.state.global_values });
           let transition = match state_definition
             .user_transitions
             .iter()
-            .filter_map(|transition| transition.should_trigger_internal(&triggers, &local_data))
+            .zip(&compiled_state.user_transitions)
+            .filter_map(|(transition, compiled)| transition.should_trigger_internal(compiled, &triggers, &local_data))
             .next()
             .transpose()
           {
@@ -200,16 +237,18 @@ where
             Err(e) => return Some(Err(e)),
           };
           if let Some(transition) = transition {
+            let from = state.current;
             match state.perform_transition(
               machine,
               transition.next,
               &transition.actions,
               now,
-              definition,
+              compiled,
               GlobalData { id: machine, root: root_data.clone(), globals: &self.state.global_values },
               &(),
             ) {
               Ok(triggers) => {
+                observer.transition(machine, from, transition.next);
                 new_triggers.extend(triggers);
                 Some(Ok(machine as StateId))
               }
@@ -226,32 +265,13 @@ where
     }
     Ok(())
   }
-}
-impl<Template: StateMachinePuzzleTemplateSource> super::Puzzle for StateMachinePuzzle<Template>
-where
-  Template::InputIdentifier: Eq,
-  Template::Area: Eq,
-{
-  type Area = Template::Area;
-  type Error = StateMachineError;
-  type InputIdentifier = Template::InputIdentifier;
-  type OutputIdentifier = Template::OutputIdentifier;
-
-  fn next_timer(&self) -> Option<Duration> {
-    self.state.states.iter().flat_map(|s| s.timer.as_ref()).min().map(|&t| (t - Utc::now()).to_std().ok()).flatten()
-  }
-
-  fn outputs(&self) -> Box<dyn Iterator<Item = (&Self::OutputIdentifier, &puzzle::MultiStateValue)> + '_> {
-    Box::new(
-      self.state.states.iter().zip(&self.template.template().machines).flat_map(|(state, definition)| definition.outputs.iter().zip(&state.outputs)),
-    )
-  }
-
-  fn process<Counter: AreaCounter>(
+  /// Process a player input while reporting the internal behaviour of the puzzle to an observer
+  pub fn process_observed<Counter: AreaCounter, Observer: PuzzleObserver>(
     &mut self,
-    input: puzzle::PuzzleInput<Self::InputIdentifier, Self::Area, Counter>,
+    input: puzzle::PuzzleInput<Template::InputIdentifier, Template::Area, Counter>,
     now: DateTime<Utc>,
-  ) -> Result<super::ProcessingResult, Self::Error> {
+    observer: &mut Observer,
+  ) -> Result<super::ProcessingResult, StateMachineError> {
     let mut triggers = BTreeSet::new();
     let root_data = self.state.get_root_variable_data(now);
     let serviced = self
@@ -259,29 +279,39 @@ where
       .template()
       .machines
       .iter()
+      .zip(&self.compiled)
       .zip(&mut self.state.states)
       .enumerate()
-      .flat_map(|(machine, (definition, state))| {
-        let Some(state_definition) = definition.states.get(state.current as usize) else {
+      .flat_map(|(machine, ((definition, compiled), state))| {
+        let Some((state_definition, compiled_state)) = definition.states.get(state.current as usize).zip(compiled.states.get(state.current as usize))
+        else {
           return Some(Err(StateMachineError::InvalidState { machine, state: state.current }));
         };
         let local_data = state.get_local_variable_data(GlobalData { id: machine, root: root_data.clone(), globals: &self.state.global_values });
-        let transition =
-          match state_definition.user_transitions.iter().filter_map(|transition| transition.should_trigger(&input, &local_data)).next().transpose() {
-            Ok(v) => v,
-            Err(e) => return Some(Err(e)),
-          };
+        let transition = match state_definition
+          .user_transitions
+          .iter()
+          .zip(&compiled_state.user_transitions)
+          .filter_map(|(transition, compiled)| transition.should_trigger(compiled, &input, &local_data))
+          .next()
+          .transpose()
+        {
+          Ok(v) => v,
+          Err(e) => return Some(Err(e)),
+        };
         if let Some(transition) = transition {
+          let from = state.current;
           match state.perform_transition(
             machine,
             transition.next,
             &transition.actions,
             now,
-            definition,
+            compiled,
             GlobalData { id: machine, root: root_data.clone(), globals: &self.state.global_values },
             &(),
           ) {
             Ok(t) => {
+              observer.transition(machine, from, transition.next);
               triggers.extend(t);
               Some(Ok(machine as StateId))
             }
@@ -293,40 +323,44 @@ where
       })
       .collect::<Result<BTreeSet<_>, _>>()?;
     let output = if serviced.is_empty() { super::ProcessingResult::Unchanged } else { super::ProcessingResult::Updated };
-    self.process_triggers(now, triggers, serviced)?;
+    self.process_triggers(now, triggers, serviced, observer)?;
     Ok(output)
   }
-
-  fn process_timer(&mut self, now: DateTime<Utc>) -> Result<super::ProcessingResult, Self::Error> {
+  /// Process any expired timers while reporting the internal behaviour of the puzzle to an observer
+  pub fn process_timer_observed<Observer: PuzzleObserver>(
+    &mut self,
+    now: DateTime<Utc>,
+    observer: &mut Observer,
+  ) -> Result<super::ProcessingResult, StateMachineError> {
     let mut triggers = BTreeSet::new();
     let root_data = self.state.get_root_variable_data(now);
     let serviced = self
-      .template
-      .template()
-      .machines
+      .compiled
       .iter()
       .zip(&mut self.state.states)
       .enumerate()
-      .flat_map(|(machine, (definition, state))| {
+      .flat_map(|(machine, (compiled, state))| {
         if state.timer.map(|t| t < now).unwrap_or(false) {
-          let Some(state_definition) = definition.states.get(state.current as usize) else {
+          let Some(compiled_state) = compiled.states.get(state.current as usize) else {
             return Some(Err(StateMachineError::InvalidState { machine, state: state.current }));
           };
-          let (next, actions) = match &state_definition.timer_transition {
-            timer_transition::TimerTransition::Reset { next, actions, .. } => (next, actions),
-            timer_transition::TimerTransition::Rollover { next, actions, .. } => (next, actions),
-            timer_transition::TimerTransition::None => return None,
+          let (next, actions) = match &compiled_state.timer_transition {
+            timer_transition::CompiledTimerTransition::Reset { next, actions, .. } => (next, actions),
+            timer_transition::CompiledTimerTransition::Rollover { next, actions, .. } => (next, actions),
+            timer_transition::CompiledTimerTransition::None => return None,
           };
+          let from = state.current;
           match state.perform_transition(
             machine,
             *next,
             actions,
             now,
-            definition,
+            compiled,
             GlobalData { id: machine, root: root_data.clone(), globals: &self.state.global_values },
             &TimerDurationDataGenerator(state.timer.clone().map(|t| u32::try_from((t - now).num_seconds()).unwrap_or(0)).unwrap_or(0)),
           ) {
             Ok(new_triggers) => {
+              observer.transition(machine, from, *next);
               triggers.extend(new_triggers);
               Some(Ok(machine as StateId))
             }
@@ -339,10 +373,46 @@ where
       .collect::<Result<BTreeSet<_>, _>>()?;
 
     let output = if serviced.is_empty() { super::ProcessingResult::Unchanged } else { super::ProcessingResult::Updated };
-    self.process_triggers(now, triggers, serviced)?;
+    self.process_triggers(now, triggers, serviced, observer)?;
     Ok(output)
   }
 }
+impl<Template: StateMachinePuzzleTemplateSource> super::Puzzle for StateMachinePuzzle<Template>
+where
+  Template::InputIdentifier: Eq,
+  Template::Area: Eq,
+{
+  type Area = Template::Area;
+  type Error = StateMachineError;
+  type InputIdentifier = Template::InputIdentifier;
+  type OutputIdentifier = Template::OutputIdentifier;
+
+  fn next_timer(&self) -> Option<Duration> {
+    self.state.states.iter().flat_map(|s| s.timer.as_ref()).min().map(|&t| (t - Utc::now()).to_std().ok()).flatten()
+  }
+
+  fn outputs(&self) -> Box<dyn Iterator<Item = (&Self::OutputIdentifier, &puzzle::MultiStateValue)> + '_> {
+    Box::new(
+      self.state.states.iter().zip(&self.template.template().machines).flat_map(|(state, definition)| definition.outputs.iter().zip(&state.outputs)),
+    )
+  }
+
+  fn process<Counter: AreaCounter>(
+    &mut self,
+    input: puzzle::PuzzleInput<Self::InputIdentifier, Self::Area, Counter>,
+    now: DateTime<Utc>,
+  ) -> Result<super::ProcessingResult, Self::Error> {
+    self.process_observed(input, now, &mut ())
+  }
+
+  fn process_timer(&mut self, now: DateTime<Utc>) -> Result<super::ProcessingResult, Self::Error> {
+    self.process_timer_observed(now, &mut ())
+  }
+
+  fn seed(&self) -> u32 {
+    self.state.seed
+  }
+}
 
 impl StateMachinePuzzleState {
   pub fn get_root_variable_data(&self, now: DateTime<Utc>) -> RootData {
@@ -353,6 +423,15 @@ impl StateMachinePuzzleState {
     GlobalData { id, root: self.get_root_variable_data(now), globals: &self.global_values }
   }
 }
+impl<InputIdentifier, OutputIdentifier, Area> StateMachinePuzzleTemplate<InputIdentifier, OutputIdentifier, Area> {
+  /// Compile the expressions in every machine for a puzzle with the provided seed and global variable values
+  ///
+  /// Any parts of the expressions that depend only on these values are evaluated now rather than on every transition.
+  fn compile(&self, seed: u32, globals: &[Value]) -> Vec<CompiledMachine> {
+    let constants = Constants { globals, seed };
+    self.machines.iter().map(|machine| machine.compile(&constants)).collect()
+  }
+}
 impl<InputIdentifier: Ord + Display, OutputIdentifier: Ord + Display, Area: Ord + Display>
   StateMachinePuzzleTemplate<InputIdentifier, OutputIdentifier, Area>
 {
@@ -399,6 +478,9 @@ impl<InputIdentifier: Ord + Display, OutputIdentifier: Ord + Display, Area: Ord
         return Err(Cow::Owned(format!("World uses output {}, but no machine produces it", output)));
       }
     }
+    if let Some(issue) = self.analyse().into_iter().find(|issue| issue.is_error()) {
+      return Err(Cow::Owned(issue.to_string()));
+    }
 
     Ok(())
   }
@@ -0,0 +1,294 @@
+use crate::controller::puzzle::area::CountCollection;
+use crate::controller::puzzle::state_machine::error::StateMachineError;
+use crate::controller::puzzle::state_machine::state::State;
+use crate::controller::puzzle::state_machine::{
+  CounterId, InternalEventId, PuzzleObserver, StateId, StateMachinePuzzle, StateMachinePuzzleTemplateSource,
+};
+use crate::controller::puzzle::{MultiStateValue, Puzzle, PuzzleInput, PuzzleTemplate};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+/// The maximum number of timer rounds that can fire during a single clock advance; this catches machines with zero-length timers
+const MAX_TIMER_ROUNDS: usize = 10_000;
+
+/// A single event in a scripted simulation
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SimulationStep<InputIdentifier, Area> {
+  /// Advance the simulated clock, firing any timers that expire along the way
+  Advance { seconds: u32 },
+  /// The occupants of an area have changed; the state bits of every player now in the area are provided
+  Area { area: Area, players: Vec<u32> },
+  /// A player with the provided state bits clicks on an input
+  Click { input: InputIdentifier, state: u32 },
+}
+
+#[derive(Debug)]
+pub enum SimulationError {
+  /// The puzzle failed while processing a step
+  Puzzle(StateMachineError),
+  /// Timers kept firing without the clock advancing
+  TimerLoop,
+}
+
+/// Something that happened inside the puzzle while processing a simulation step
+#[derive(Clone, Debug)]
+pub enum TraceEvent<'a, OutputIdentifier> {
+  /// A counter in a machine has changed value
+  Counter { machine: usize, counter: CounterId, old: u32, new: u32 },
+  /// An output has changed value
+  Output { machine: usize, output: &'a OutputIdentifier, value: &'a MultiStateValue },
+  /// A machine has set or cleared its timer
+  Timer { machine: usize, expires: Option<DateTime<Utc>> },
+  /// A machine has taken a transition
+  Transition { machine: usize, from: StateId, to: StateId },
+  /// Internal events have been raised
+  Triggers(&'a BTreeSet<InternalEventId>),
+}
+
+/// A puzzle running against a simulated clock, suitable for testing a template without a server
+///
+/// Given the same template, seed, timezone, start time, and steps, a simulation will always produce the same trace.
+pub struct Simulation<Template: StateMachinePuzzleTemplateSource> {
+  now: DateTime<Utc>,
+  puzzle: StateMachinePuzzle<Template>,
+}
+
+enum Recorded {
+  Transition { machine: usize, from: StateId, to: StateId },
+  Triggers(BTreeSet<InternalEventId>),
+}
+
+#[derive(Default)]
+struct Recorder(Vec<Recorded>);
+
+impl<Template: StateMachinePuzzleTemplateSource + Clone> Simulation<Template>
+where
+  Template::InputIdentifier: Eq,
+  Template::Area: Eq,
+{
+  /// Create a new simulation with the puzzle in its initial state
+  pub fn new(template: &Template, seed: u32, start: DateTime<Tz>) -> Result<Self, StateMachineError> {
+    let now = start.with_timezone(&Utc);
+    Ok(Simulation { now, puzzle: template.blank(start, seed)? })
+  }
+  /// The current simulated time
+  pub fn now(&self) -> DateTime<Utc> {
+    self.now
+  }
+  /// The current outputs of the puzzle
+  pub fn outputs(&self) -> impl Iterator<Item = (&Template::OutputIdentifier, &MultiStateValue)> + '_ {
+    self.puzzle.outputs()
+  }
+  /// The puzzle being simulated
+  pub fn puzzle(&self) -> &StateMachinePuzzle<Template> {
+    &self.puzzle
+  }
+  /// Process one step of a script, reporting everything that changes inside the puzzle
+  pub fn run(
+    &mut self,
+    step: SimulationStep<Template::InputIdentifier, Template::Area>,
+    mut trace: impl FnMut(DateTime<Utc>, TraceEvent<'_, Template::OutputIdentifier>),
+  ) -> Result<(), SimulationError> {
+    match step {
+      SimulationStep::Advance { seconds } => {
+        let end = self.now + chrono::Duration::seconds(seconds as i64);
+        for _ in 0..MAX_TIMER_ROUNDS {
+          let Some(expires) = self.puzzle.state.states.iter().flat_map(|state| state.timer).min().filter(|&expires| expires < end) else {
+            self.now = end;
+            return Ok(());
+          };
+          // Timers only fire once the time has passed their expiry
+          self.now = expires.max(self.now) + chrono::Duration::nanoseconds(1);
+          let previous = self.puzzle.state.states.clone();
+          let mut recorder = Recorder::default();
+          self.puzzle.process_timer_observed(self.now, &mut recorder)?;
+          self.report(&previous, recorder, &mut trace);
+        }
+        Err(SimulationError::TimerLoop)
+      }
+      SimulationStep::Area { area, players } => {
+        let previous = self.puzzle.state.states.clone();
+        let mut recorder = Recorder::default();
+        self.puzzle.process_observed(PuzzleInput::Area(area, CountCollection(players)), self.now, &mut recorder)?;
+        self.report(&previous, recorder, &mut trace);
+        Ok(())
+      }
+      SimulationStep::Click { input, state } => {
+        let previous = self.puzzle.state.states.clone();
+        let mut recorder = Recorder::default();
+        self.puzzle.process_observed(PuzzleInput::<_, _, CountCollection<Vec<u32>>>::Click(input, state), self.now, &mut recorder)?;
+        self.report(&previous, recorder, &mut trace);
+        Ok(())
+      }
+    }
+  }
+  fn report(&self, previous: &[State], recorder: Recorder, trace: &mut impl FnMut(DateTime<Utc>, TraceEvent<'_, Template::OutputIdentifier>)) {
+    for recorded in &recorder.0 {
+      match recorded {
+        Recorded::Transition { machine, from, to } => trace(self.now, TraceEvent::Transition { machine: *machine, from: *from, to: *to }),
+        Recorded::Triggers(triggers) => trace(self.now, TraceEvent::Triggers(triggers)),
+      }
+    }
+    for (machine, ((old, new), definition)) in
+      previous.iter().zip(&self.puzzle.state.states).zip(&self.puzzle.template.template().machines).enumerate()
+    {
+      for (counter, (&before, &after)) in old.counters.iter().zip(&new.counters).enumerate() {
+        if before != after {
+          trace(self.now, TraceEvent::Counter { machine, counter: counter as CounterId, old: before, new: after });
+        }
+      }
+      for ((output, before), value) in definition.outputs.iter().zip(&old.outputs).zip(&new.outputs) {
+        if before != value {
+          trace(self.now, TraceEvent::Output { machine, output, value });
+        }
+      }
+      if old.timer != new.timer {
+        trace(self.now, TraceEvent::Timer { machine, expires: new.timer });
+      }
+    }
+  }
+}
+
+impl PuzzleObserver for Recorder {
+  fn transition(&mut self, machine: usize, from: StateId, to: StateId) {
+    self.0.push(Recorded::Transition { machine, from, to });
+  }
+
+  fn triggers(&mut self, triggers: &BTreeSet<InternalEventId>) {
+    self.0.push(Recorded::Triggers(triggers.clone()));
+  }
+}
+
+impl From<StateMachineError> for SimulationError {
+  fn from(value: StateMachineError) -> Self {
+    SimulationError::Puzzle(value)
+  }
+}
+impl Display for SimulationError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SimulationError::Puzzle(e) => Display::fmt(e, f),
+      SimulationError::TimerLoop => write!(f, "Timers fired more than {} times during a single clock advance", MAX_TIMER_ROUNDS),
+    }
+  }
+}
+impl std::error::Error for SimulationError {}
+
+#[cfg(test)]
+mod tests {
+  use super::{Simulation, SimulationError, SimulationStep, TraceEvent};
+  use crate::controller::puzzle::state_machine::text::parse;
+  use crate::controller::puzzle::state_machine::StateMachinePuzzleTemplate;
+  use chrono::{Duration, TimeZone};
+
+  type TestSimulation = Simulation<StateMachinePuzzleTemplate<String, String, String>>;
+
+  fn simulation(source: &str) -> TestSimulation {
+    let template = parse(source).unwrap_or_else(|e| panic!("Failed to parse source at {:?}: {}", e.line_and_column(source), e));
+    Simulation::new(&template, 0, chrono_tz::Tz::UTC.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()).unwrap()
+  }
+
+  /// Run a step and write out the trace in a compact form
+  fn run(simulation: &mut TestSimulation, step: SimulationStep<String, String>) -> Vec<String> {
+    let mut trace = Vec::new();
+    simulation
+      .run(step, |_, event| {
+        trace.push(match event {
+          TraceEvent::Counter { machine, counter, old, new } => format!("counter {} {} {} -> {}", machine, counter, old, new),
+          TraceEvent::Output { machine, output, value } => format!("output {} {} {:?}", machine, output, value),
+          TraceEvent::Timer { machine, expires } => format!("timer {} {}", machine, if expires.is_some() { "set" } else { "cleared" }),
+          TraceEvent::Transition { machine, from, to } => format!("transition {} {} -> {}", machine, from, to),
+          TraceEvent::Triggers(triggers) => format!("triggers {:?}", triggers),
+        })
+      })
+      .unwrap();
+    trace
+  }
+
+  fn click() -> SimulationStep<String, String> {
+    SimulationStep::Click { input: "lever".to_string(), state: 0 }
+  }
+
+  #[test]
+  fn clicks_and_timers() {
+    let mut simulation = simulation(
+      r#"
+machine door {
+  counters 1;
+  outputs open;
+
+  state closed {
+    output open = false;
+    on click(lever) if counter0 > 1 -> open;
+    on click(lever) -> closed { counter0 = counter0 + 1; }
+  }
+  state open {
+    output open = true;
+    timer reset 30 -> closed { counter0 = 0; trigger 1; }
+  }
+}
+
+machine bell {
+  state quiet {
+    on trigger(1) -> quiet;
+  }
+}
+"#,
+    );
+    let start = simulation.now();
+    assert_eq!(run(&mut simulation, click()), vec!["transition 0 0 -> 0", "counter 0 0 0 -> 1"]);
+    assert_eq!(run(&mut simulation, click()), vec!["transition 0 0 -> 0", "counter 0 0 1 -> 2"]);
+    assert_eq!(run(&mut simulation, click()), vec!["transition 0 0 -> 1", "output 0 open Bool(true)", "timer 0 set"]);
+    assert!(run(&mut simulation, SimulationStep::Advance { seconds: 20 }).is_empty());
+    assert_eq!(
+      run(&mut simulation, SimulationStep::Advance { seconds: 20 }),
+      vec!["transition 0 1 -> 0", "triggers {1}", "transition 1 0 -> 0", "counter 0 0 2 -> 0", "output 0 open Bool(false)", "timer 0 cleared"]
+    );
+    assert_eq!(simulation.now(), start + Duration::seconds(40));
+  }
+
+  #[test]
+  fn area_counts() {
+    let mut simulation = simulation(
+      r#"
+machine hall {
+  outputs occupied;
+
+  state empty {
+    output occupied = false;
+    on count(hall) if players(bit(0)) > 1 -> full;
+  }
+  state full {
+    output occupied = true;
+    on count(hall) if players(always) == 0 -> empty;
+  }
+}
+"#,
+    );
+    let area = |players: Vec<u32>| SimulationStep::Area { area: "hall".to_string(), players };
+    assert!(run(&mut simulation, area(vec![1, 2])).is_empty());
+    assert_eq!(run(&mut simulation, area(vec![1, 3])), vec!["transition 0 0 -> 1", "output 0 occupied Bool(true)"]);
+    assert!(run(&mut simulation, area(vec![1])).is_empty());
+    assert_eq!(run(&mut simulation, area(vec![])), vec!["transition 0 1 -> 0", "output 0 occupied Bool(false)"]);
+  }
+
+  #[test]
+  fn repeating_timers_fire_within_an_advance() {
+    let mut simulation = simulation("machine clock { counters 1; state tick { timer reset 1 -> tick { counter0 = counter0 + 1; } } }");
+    let trace = run(&mut simulation, SimulationStep::Advance { seconds: 100 });
+    // Each timer fires just after it expires, so the last one is pushed slightly past the end of the advance
+    assert_eq!(trace.iter().filter(|event| event.starts_with("transition")).count(), 99);
+    assert_eq!(simulation.puzzle().state.states[0].counters, vec![99]);
+  }
+
+  #[test]
+  fn zero_length_timers_stop() {
+    let mut simulation = simulation("machine spin { state a { timer reset 0 -> a; } }");
+    let start = simulation.now();
+    assert!(matches!(simulation.run(SimulationStep::Advance { seconds: 1 }, |_, _| ()), Err(SimulationError::TimerLoop)));
+    assert!(simulation.now() < start + Duration::seconds(1));
+  }
+}
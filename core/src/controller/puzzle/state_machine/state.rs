@@ -1,12 +1,30 @@
 use crate::controller::puzzle;
-use crate::controller::puzzle::state_machine::edge_action::EdgeAction;
+use crate::controller::puzzle::state_machine::edge_action::CompiledEdgeAction;
 use crate::controller::puzzle::state_machine::error::StateMachineError;
+use crate::controller::puzzle::state_machine::expression::compiled::{
+  CompiledMultiStateVariableExpression, CompiledVariableExpression, Constants, FoldableVariable,
+};
 use crate::controller::puzzle::state_machine::expression::ExpressionData;
 use crate::controller::puzzle::state_machine::{expression, timer_transition, transition, GlobalData, MachineIdentifier, StateId};
 use crate::controller::puzzle::Value;
 use chrono::{DateTime, NaiveDate, Utc};
 use std::collections::BTreeSet;
 
+/// A state machine with all of its expressions compiled for a particular puzzle
+#[derive(Clone, Debug)]
+pub struct CompiledMachine {
+  pub counters: u8,
+  pub states: Vec<CompiledState>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompiledState {
+  pub outputs: Vec<CompiledMultiStateVariableExpression<LocalVariableName>>,
+  pub user_transitions: Vec<transition::CompiledTransition<LocalVariableName>>,
+  pub timer_transition: timer_transition::CompiledTimerTransition<LocalVariableName>,
+  pub variables: Vec<CompiledVariableExpression<StateVariableName>>,
+}
+
 #[derive(Clone)]
 pub struct LocalData<'a> {
   state_data: StateData<'a>,
@@ -90,13 +108,13 @@ impl State {
   pub fn get_local_variable_data<'a>(&'a self, global_data: GlobalData<'a>) -> LocalData<'a> {
     LocalData { state_data: self.get_state_variable_data(global_data), locals: &self.local_values }
   }
-  pub fn perform_transition<InputIdentifier, OutputIdentifier, Area, Generator: TransitionVariableGenerator>(
+  pub fn perform_transition<Generator: TransitionVariableGenerator>(
     &mut self,
     machine: usize,
     next: u8,
-    actions: &[EdgeAction<Generator::Variable>],
+    actions: &[CompiledEdgeAction<Generator::Variable>],
     now: DateTime<Utc>,
-    definition: &StateMachine<InputIdentifier, OutputIdentifier, Area>,
+    definition: &CompiledMachine,
     global_data: GlobalData,
     transition_data: &Generator,
   ) -> Result<BTreeSet<super::InternalEventId>, StateMachineError> {
@@ -106,15 +124,16 @@ impl State {
     let mut triggers = BTreeSet::new();
     for action in actions {
       match action {
-        EdgeAction::Set { counter, condition, value } => {
+        CompiledEdgeAction::Set { counter, condition, value } => {
           let data = transition_data.data_for(local_data.clone());
-          if condition.evaluate(&data)? {
-            *new_counters.get_mut(*counter as usize).ok_or(StateMachineError::NoCounter { machine, name: *counter })? = value.evaluate(&data)?;
+          if condition.evaluate_bool(&data)? {
+            *new_counters.get_mut(*counter as usize).ok_or(StateMachineError::NoCounter { machine, name: *counter })? =
+              value.evaluate_number(&data)?;
           }
         }
-        EdgeAction::Trigger { target, condition } => {
+        CompiledEdgeAction::Trigger { target, condition } => {
           let data = transition_data.data_for(local_data.clone());
-          if condition.evaluate(&data)? {
+          if condition.evaluate_bool(&data)? {
             triggers.insert(*target);
           }
         }
@@ -158,7 +177,7 @@ impl MachineIdentifier for StateData<'_> {
   }
 }
 
-impl<InputIdentifier, OutputIdentifier, Area> StateMachine<InputIdentifier, OutputIdentifier, Area> {
+impl CompiledMachine {
   pub fn blank(&self, id: usize, now: DateTime<Utc>, global_data: GlobalData) -> Result<State, StateMachineError> {
     let counters = vec![0; self.counters as usize];
     let state_data = StateData { global_data, counters: &counters };
@@ -169,6 +188,25 @@ impl<InputIdentifier, OutputIdentifier, Area> StateMachine<InputIdentifier, Outp
     let timer = self.states[0].timer_transition.prepare(now, None, local_data)?;
     Ok(State { counters, current: 0, local_values, outputs, timer })
   }
+}
+
+impl<InputIdentifier, OutputIdentifier, Area> StateMachine<InputIdentifier, OutputIdentifier, Area> {
+  /// Compile all the expressions in this machine, replacing the seed and global variables with their values
+  pub fn compile(&self, constants: &Constants) -> CompiledMachine {
+    CompiledMachine {
+      counters: self.counters,
+      states: self
+        .states
+        .iter()
+        .map(|state| CompiledState {
+          outputs: state.outputs.iter().map(|o| o.compile(constants)).collect(),
+          user_transitions: state.user_transitions.iter().map(|t| t.compile(constants)).collect(),
+          timer_transition: state.timer_transition.compile(constants),
+          variables: state.variables.iter().map(|v| v.compile(constants)).collect(),
+        })
+        .collect(),
+    }
+  }
   pub fn matches_definition(&self, state: &State) -> bool {
     state.counters.len() == self.counters as usize
   }
@@ -180,6 +218,23 @@ impl<InputIdentifier, Area> StateDefinition<InputIdentifier, Area> {
   }
 }
 
+impl FoldableVariable for LocalVariableName {
+  fn global(&self) -> Option<u8> {
+    match self {
+      LocalVariableName::Local(_) => None,
+      LocalVariableName::Other(name) => name.global(),
+    }
+  }
+}
+impl FoldableVariable for StateVariableName {
+  fn global(&self) -> Option<u8> {
+    match self {
+      StateVariableName::Counter(_) => None,
+      StateVariableName::Global(name) => Some(*name),
+    }
+  }
+}
+
 impl TransitionVariableGenerator for () {
   type Data<'a> = LocalData<'a>;
   type Variable = LocalVariableName;
@@ -0,0 +1,82 @@
+use crate::controller::puzzle::state_machine::text::ParseError;
+use std::ops::Range;
+
+/// Punctuation, with longer symbols first so they are matched in preference to their prefixes
+const PUNCTUATION: &[&str] = &[
+  "->", "==", "!=", "<=", ">=", "&&", "||", "{", "}", "(", ")", "[", "]", ",", ";", ":", "=", "<", ">", "&", "|", "^", "+", "-", "*", "/", "%", "!",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token<'a> {
+  Identifier(&'a str),
+  Number(u32),
+  Punctuation(&'static str),
+  String(String),
+}
+
+impl Token<'_> {
+  pub fn describe(&self) -> String {
+    match self {
+      Token::Identifier(name) => format!("“{}”", name),
+      Token::Number(value) => format!("number {}", value),
+      Token::Punctuation(symbol) => format!("“{}”", symbol),
+      Token::String(value) => format!("string {:?}", value),
+    }
+  }
+}
+
+/// Check if a name can be written without quotation marks
+pub fn is_identifier(name: &str) -> bool {
+  let mut chars = name.chars();
+  chars.next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false) && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<(Token<'_>, Range<usize>)>, ParseError> {
+  let mut tokens = Vec::new();
+  let mut position = 0;
+  while position < source.len() {
+    let rest = &source[position..];
+    let c = rest.chars().next().unwrap();
+    if c.is_whitespace() {
+      position += c.len_utf8();
+    } else if rest.starts_with("//") {
+      position += rest.find('\n').unwrap_or(rest.len());
+    } else if c.is_ascii_alphabetic() || c == '_' {
+      let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+      tokens.push((Token::Identifier(&rest[..length]), position..position + length));
+      position += length;
+    } else if c.is_ascii_digit() {
+      let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+      let text = &rest[..length];
+      let value = match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+      }
+      .map_err(|_| ParseError::new(position..position + length, format!("“{}” is not a valid number", text)))?;
+      tokens.push((Token::Number(value), position..position + length));
+      position += length;
+    } else if c == '"' {
+      let mut value = String::new();
+      let mut chars = rest.char_indices().skip(1);
+      let length = loop {
+        match chars.next() {
+          None => return Err(ParseError::new(position..source.len(), "String is missing closing quotation mark")),
+          Some((index, '"')) => break index + 1,
+          Some((index, '\\')) => match chars.next() {
+            Some((_, c @ ('"' | '\\'))) => value.push(c),
+            _ => return Err(ParseError::new(position + index..position + index + 1, "Only \\\" and \\\\ are allowed as escapes in strings")),
+          },
+          Some((_, c)) => value.push(c),
+        }
+      };
+      tokens.push((Token::String(value), position..position + length));
+      position += length;
+    } else if let Some(symbol) = PUNCTUATION.iter().copied().find(|symbol| rest.starts_with(symbol)) {
+      tokens.push((Token::Punctuation(symbol), position..position + symbol.len()));
+      position += symbol.len();
+    } else {
+      return Err(ParseError::new(position..position + c.len_utf8(), format!("Unexpected character “{}”", c)));
+    }
+  }
+  Ok(tokens)
+}
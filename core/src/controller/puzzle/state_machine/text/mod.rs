@@ -0,0 +1,177 @@
+use crate::controller::puzzle::state_machine::StateMachinePuzzleTemplate;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+mod lexer;
+mod parser;
+mod printer;
+
+/// A problem in the text of a puzzle
+#[derive(Clone, Debug)]
+pub struct ParseError {
+  /// The location of the problem as a range of bytes in the source text
+  pub span: Range<usize>,
+  pub message: Cow<'static, str>,
+}
+
+impl ParseError {
+  fn new(span: Range<usize>, message: impl Into<Cow<'static, str>>) -> Self {
+    ParseError { span, message: message.into() }
+  }
+  /// Find the line and column (both starting from 1) where the problem starts in the source text
+  pub fn line_and_column(&self, source: &str) -> (usize, usize) {
+    let before = &source[..self.span.start.min(source.len())];
+    let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+  }
+}
+
+impl Display for ParseError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+impl std::error::Error for ParseError {}
+
+/// Compile the text of a puzzle into a template
+///
+/// A puzzle is a list of global variables and machines:
+///
+/// ```text
+/// global0 = seed % 4;
+///
+/// machine door {
+///   counters 1;
+///   outputs open;
+///
+///   state closed {
+///     output open = false;
+///     on click(lever) if counter0 > 2 -> open;
+///     on click(lever) -> closed { counter0 = counter0 + 1; }
+///   }
+///   state open {
+///     output open = true;
+///     timer reset 30 -> closed { counter0 = 0; trigger 1; }
+///   }
+/// }
+/// ```
+///
+/// The first state in a machine is its initial state. Global, local, and counter variables are referred to as `global0`, `local0`, and
+/// `counter0`. Variables must be declared in order and can be annotated with `: bool` or `: num` if the type cannot be determined from the
+/// expression.
+pub fn parse(source: &str) -> Result<StateMachinePuzzleTemplate<String, String, String>, ParseError> {
+  parser::Parser::new(source)?.template()
+}
+
+/// Convert a template into text that can be parsed back into an equivalent template
+///
+/// Machines and states do not have names in templates, so they are numbered. A global variable that refers to another variable cannot be
+/// written, since the text has no way to express it, so such templates are rejected.
+pub fn print<InputIdentifier: Display, OutputIdentifier: Display, Area: Display>(
+  template: &StateMachinePuzzleTemplate<InputIdentifier, OutputIdentifier, Area>,
+) -> Result<String, Cow<'static, str>> {
+  for (index, variable) in template.variables.iter().enumerate() {
+    let mut uses_variable = false;
+    variable.visit_variables(&mut |_| uses_variable = true);
+    if uses_variable {
+      return Err(Cow::Owned(format!("global{} refers to another variable, which global variables cannot do", index)));
+    }
+  }
+  Ok(printer::template(template))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{parse, print};
+  use crate::controller::puzzle::state_machine::expression::{BoolExpression, NumberExpression, VariableExpression};
+  use crate::controller::puzzle::state_machine::StateMachinePuzzleTemplate;
+
+  /// Check that printing a parsed template and parsing it again produces the same template, and that printing is then stable
+  fn round_trip(source: &str) {
+    let template = parse(source).unwrap_or_else(|e| panic!("Failed to parse source at {:?}: {}", e.line_and_column(source), e));
+    let printed = print(&template).expect("Failed to print template");
+    let reparsed =
+      parse(&printed).unwrap_or_else(|e| panic!("Failed to parse printed template at {:?}: {}\n{}", e.line_and_column(&printed), e, printed));
+    assert_eq!(serde_json::to_value(&template).unwrap(), serde_json::to_value(&reparsed).unwrap(), "Template changed:\n{}", printed);
+    assert_eq!(printed, print(&reparsed).unwrap());
+  }
+
+  #[test]
+  fn documented_example() {
+    round_trip(
+      r#"
+global0 = seed % 4;
+
+machine door {
+  counters 1;
+  outputs open;
+
+  state closed {
+    output open = false;
+    on click(lever) if counter0 > 2 -> open;
+    on click(lever) -> closed { counter0 = counter0 + 1; }
+  }
+  state open {
+    output open = true;
+    timer reset 30 -> closed { counter0 = 0; trigger 1; }
+  }
+}
+"#,
+    );
+  }
+
+  #[test]
+  fn every_construct() {
+    round_trip(
+      r#"
+global0 = permutation(seed, 3, 8);
+global1 = holiday(date(December, 25, observed), !weekdays(Sat) && months(January, February), easter(-2), nth_last(1, Mon, May), nth(2, Fri, March), days(1, 15), weeks(1, 52), !(date(July, 1) && !days(2)));
+global2 = case(seed & 3, [1, 2, 4 | 8], max(seed, 7) - min(seed / 2, 3) * 5);
+global3 = if seed > 10 && !(seed == 12) || seed <= 3 then mask(2, 3) ^ seed else 4294967295;
+global4: bool = if seed != 1 then seed < 4 else seed >= 9;
+
+machine door {
+  counters 2;
+  outputs open, "light level", "say \"hi\"\\";
+
+  state closed {
+    local0 = counter0 + global2 - (counter1 - 2);
+    local1: bool = global1;
+    output open = false { bit(3): local1, all(any_bits(0, 4), not_bit(7)): !local1 && local0 == 2 };
+    output "light level" = 10 - (counter1 - 2) { no_bits(1, 2): if local1 then 1 else 2 };
+    output "say \"hi\"\\": num = global0;
+    on click(lever) when any(no_bits(4, 2), always) if local0 >= 3 -> open { counter0 = 0; trigger 1 if local1; }
+    on click("big button") -> closed { counter0 = (counter0 + 1) * 2 if counter0 < 5; }
+    on count(hall) if players(bit(0)) > 1 || players(always) == 0 -> open;
+    on trigger(2) if global4 -> open;
+  }
+  state open {
+    output open = true;
+    output "light level": num = if global4 then 20 else counter1;
+    output "say \"hi\"\\" = 0 { };
+    timer reset 30 + global0 -> closed { counter1 = duration + counter1; trigger 2; }
+  }
+}
+
+machine clock {
+  state tick {
+    timer rollover duration * 2 -> tick;
+  }
+}
+"#,
+    );
+  }
+
+  #[test]
+  fn globals_using_variables_are_not_printed() {
+    let template: StateMachinePuzzleTemplate<String, String, String> = StateMachinePuzzleTemplate {
+      machines: Vec::new(),
+      variables: vec![
+        VariableExpression::Num(NumberExpression::Seed),
+        VariableExpression::Bool(BoolExpression::Not(Box::new(BoolExpression::Variable(())))),
+      ],
+    };
+    assert!(print(&template).unwrap_err().contains("global1"));
+  }
+}
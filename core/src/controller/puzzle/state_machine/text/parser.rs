@@ -0,0 +1,871 @@
+use crate::controller::puzzle::area::PlayerStateCondition;
+use crate::controller::puzzle::state_machine::edge_action::EdgeAction;
+use crate::controller::puzzle::state_machine::expression::holiday::Holiday;
+use crate::controller::puzzle::state_machine::expression::{BoolExpression, MultiStateVariableExpression, NumberExpression, VariableExpression};
+use crate::controller::puzzle::state_machine::state::{LocalVariableName, StateDefinition, StateMachine, StateVariableName};
+use crate::controller::puzzle::state_machine::text::lexer::{tokenize, Token};
+use crate::controller::puzzle::state_machine::text::ParseError;
+use crate::controller::puzzle::state_machine::timer_transition::{TimerDuration, TimerTransition};
+use crate::controller::puzzle::state_machine::transition::{AreaCount, InputTrigger, Transition};
+use crate::controller::puzzle::state_machine::{StateId, StateMachinePuzzleTemplate};
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+
+type Span = Range<usize>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinaryOperator {
+  Add,
+  And,
+  BitAnd,
+  BitOr,
+  Divide,
+  Equal,
+  GreaterThan,
+  GreaterThanOrEqual,
+  LessThan,
+  LessThanOrEqual,
+  Max,
+  Min,
+  Modulo,
+  Multiply,
+  NotEqual,
+  Or,
+  Subtract,
+  XOr,
+}
+
+/// An expression before its type is known
+enum Expression {
+  Binary(BinaryOperator, Box<Spanned>, Box<Spanned>),
+  Bool(bool),
+  Case(Box<Spanned>, Vec<Spanned>, Box<Spanned>),
+  Holiday(Vec<Holiday>),
+  If(Box<Spanned>, Box<Spanned>, Box<Spanned>),
+  Mask { offset: u8, length: u8 },
+  Not(Box<Spanned>),
+  Number(u32),
+  Permutation { seed: Box<Spanned>, index: Box<Spanned>, length: Box<Spanned> },
+  Seed,
+  Variable(Name),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+  Bool,
+  Num,
+}
+
+/// A variable as written, before checking that it is allowed where it is used
+enum Name {
+  Counter(u8),
+  Duration,
+  Global(u8),
+  Local(u8),
+  Players(PlayerStateCondition),
+}
+
+pub struct Parser<'a> {
+  end: usize,
+  position: usize,
+  tokens: Vec<(Token<'a>, Span)>,
+}
+
+/// A state with the references to other states and outputs still by name
+struct PendingState<InputIdentifier, Area> {
+  definition: StateDefinition<InputIdentifier, Area>,
+  name: Span,
+  next: Vec<(Option<usize>, String, Span)>,
+  outputs: Vec<(String, Span, MultiStateVariableExpression<LocalVariableName>)>,
+}
+
+struct Spanned {
+  expression: Expression,
+  span: Span,
+}
+
+/// A type of variable that can be used in an expression
+trait ParseVariable: Sized {
+  fn from_name(name: Name, span: Span) -> Result<Self, ParseError>;
+}
+
+/// Binary operators from lowest to highest precedence; the flag indicates if the operators can be chained
+const BINARY_OPERATORS: &[(&[(&str, BinaryOperator)], bool)] = &[
+  (&[("||", BinaryOperator::Or)], true),
+  (&[("&&", BinaryOperator::And)], true),
+  (
+    &[
+      ("==", BinaryOperator::Equal),
+      ("!=", BinaryOperator::NotEqual),
+      ("<", BinaryOperator::LessThan),
+      ("<=", BinaryOperator::LessThanOrEqual),
+      (">", BinaryOperator::GreaterThan),
+      (">=", BinaryOperator::GreaterThanOrEqual),
+    ],
+    false,
+  ),
+  (&[("|", BinaryOperator::BitOr)], true),
+  (&[("^", BinaryOperator::XOr)], true),
+  (&[("&", BinaryOperator::BitAnd)], true),
+  (&[("+", BinaryOperator::Add), ("-", BinaryOperator::Subtract)], true),
+  (&[("*", BinaryOperator::Multiply), ("/", BinaryOperator::Divide), ("%", BinaryOperator::Modulo)], true),
+];
+
+impl<'a> Parser<'a> {
+  pub fn new(source: &'a str) -> Result<Self, ParseError> {
+    Ok(Parser { end: source.len(), position: 0, tokens: tokenize(source)? })
+  }
+  pub fn template(mut self) -> Result<StateMachinePuzzleTemplate<String, String, String>, ParseError> {
+    let mut template = StateMachinePuzzleTemplate { machines: Vec::new(), variables: Vec::new() };
+    while self.position < self.tokens.len() {
+      if self.accept_keyword("machine") {
+        template.machines.push(self.machine()?);
+      } else if let Some(Token::Identifier(name)) = self.peek() {
+        let name = *name;
+        let span = self.span();
+        match variable_name(name) {
+          Some(Name::Global(index)) if index as usize == template.variables.len() => {
+            self.position += 1;
+            template.variables.push(self.declaration()?);
+          }
+          Some(Name::Global(_)) => {
+            return Err(ParseError::new(span, format!("Expected global{} to be declared next", template.variables.len())));
+          }
+          _ => return self.unexpected("“machine” or a global variable"),
+        }
+      } else {
+        return self.unexpected("“machine” or a global variable");
+      }
+    }
+    Ok(template)
+  }
+  fn machine(&mut self) -> Result<StateMachine<String, String, String>, ParseError> {
+    self.name()?;
+    self.expect("{")?;
+    let mut counters = None;
+    let mut outputs: Option<Vec<String>> = None;
+    let mut states = Vec::new();
+    let mut state_ids = BTreeMap::new();
+    while !self.accept("}") {
+      let span = self.span();
+      if self.accept_keyword("counters") {
+        if counters.is_some() {
+          return Err(ParseError::new(span, "Counters are already declared for this machine"));
+        }
+        counters = Some(self.small_number()?);
+        self.expect(";")?;
+      } else if self.accept_keyword("outputs") {
+        if outputs.is_some() {
+          return Err(ParseError::new(span, "Outputs are already declared for this machine"));
+        }
+        let mut names = Vec::new();
+        loop {
+          let (name, span) = self.name()?;
+          if names.contains(&name) {
+            return Err(ParseError::new(span, format!("Output “{}” is already declared", name)));
+          }
+          names.push(name);
+          if !self.accept(",") {
+            break;
+          }
+        }
+        self.expect(";")?;
+        outputs = Some(names);
+      } else if self.accept_keyword("state") {
+        let (name, span) = self.name()?;
+        if state_ids.contains_key(&name) {
+          return Err(ParseError::new(span, format!("State “{}” is already defined", name)));
+        }
+        if states.len() > StateId::MAX as usize {
+          return Err(ParseError::new(span, "Too many states in this machine"));
+        }
+        state_ids.insert(name, states.len() as StateId);
+        states.push(self.state(span)?);
+      } else {
+        return self.unexpected("“counters”, “outputs”, “state”, or “}”");
+      }
+    }
+    let outputs = outputs.unwrap_or_default();
+    let states = states
+      .into_iter()
+      .map(|mut state| {
+        for (transition, name, span) in state.next {
+          let next = *state_ids.get(&name).ok_or_else(|| ParseError::new(span, format!("There is no state named “{}”", name)))?;
+          match transition {
+            Some(index) => state.definition.user_transitions[index].next = next,
+            None => match &mut state.definition.timer_transition {
+              TimerTransition::None => (),
+              TimerTransition::Reset { next: target, .. } | TimerTransition::Rollover { next: target, .. } => *target = next,
+            },
+          }
+        }
+        for (name, span, _) in &state.outputs {
+          if !outputs.contains(name) {
+            return Err(ParseError::new(span.clone(), format!("Output “{}” is not declared by this machine", name)));
+          }
+        }
+        for output in &outputs {
+          let index = state
+            .outputs
+            .iter()
+            .position(|(name, _, _)| name == output)
+            .ok_or_else(|| ParseError::new(state.name.clone(), format!("State does not set output “{}”", output)))?;
+          state.definition.outputs.push(state.outputs.swap_remove(index).2);
+        }
+        Ok(state.definition)
+      })
+      .collect::<Result<_, _>>()?;
+    Ok(StateMachine { counters: counters.unwrap_or(0), outputs, states })
+  }
+  fn state(&mut self, name: Span) -> Result<PendingState<String, String>, ParseError> {
+    self.expect("{")?;
+    let mut state = PendingState {
+      definition: StateDefinition {
+        outputs: Vec::new(),
+        user_transitions: Vec::new(),
+        timer_transition: TimerTransition::None,
+        variables: Vec::new(),
+      },
+      name,
+      next: Vec::new(),
+      outputs: Vec::new(),
+    };
+    let mut has_timer = false;
+    while !self.accept("}") {
+      let span = self.span();
+      if self.accept_keyword("output") {
+        let (name, span) = self.name()?;
+        if state.outputs.iter().any(|(existing, _, _)| existing == &name) {
+          return Err(ParseError::new(span, format!("Output “{}” is already set", name)));
+        }
+        let output = self.multi_state_declaration()?;
+        state.outputs.push((name, span, output));
+      } else if self.accept_keyword("on") {
+        let input = if self.accept_keyword("click") {
+          self.expect("(")?;
+          let (source, _) = self.name()?;
+          self.expect(")")?;
+          let player_condition = if self.accept_keyword("when") { self.player_condition()? } else { PlayerStateCondition::Always };
+          InputTrigger::Click { source, player_condition, condition: self.condition()? }
+        } else if self.accept_keyword("count") {
+          self.expect("(")?;
+          let (area, _) = self.name()?;
+          self.expect(")")?;
+          InputTrigger::Count { area, condition: self.condition()? }
+        } else if self.accept_keyword("trigger") {
+          self.expect("(")?;
+          let source = self.small_number()?;
+          self.expect(")")?;
+          InputTrigger::Internal { source, condition: self.condition()? }
+        } else {
+          return self.unexpected("“click”, “count”, or “trigger”");
+        };
+        self.expect("->")?;
+        let (next, next_span) = self.name()?;
+        state.next.push((Some(state.definition.user_transitions.len()), next, next_span));
+        let actions = self.actions()?;
+        state.definition.user_transitions.push(Transition { input, next: 0, actions });
+      } else if self.accept_keyword("timer") {
+        if has_timer {
+          return Err(ParseError::new(span, "State already has a timer"));
+        }
+        has_timer = true;
+        let reset = if self.accept_keyword("reset") {
+          true
+        } else if self.accept_keyword("rollover") {
+          false
+        } else {
+          return self.unexpected("“reset” or “rollover”");
+        };
+        let duration = self.expression()?;
+        self.expect("->")?;
+        let (next, next_span) = self.name()?;
+        state.next.push((None, next, next_span));
+        let actions = self.actions()?;
+        state.definition.timer_transition = if reset {
+          TimerTransition::Reset { duration: number_expression(duration)?, next: 0, actions }
+        } else {
+          TimerTransition::Rollover { duration: number_expression(duration)?, next: 0, actions }
+        };
+      } else {
+        match self.peek() {
+          Some(Token::Identifier(name)) => match variable_name(name) {
+            Some(Name::Local(index)) if index as usize == state.definition.variables.len() => {
+              self.position += 1;
+              let variable = self.declaration()?;
+              state.definition.variables.push(variable);
+            }
+            Some(Name::Local(_)) => {
+              return Err(ParseError::new(span, format!("Expected local{} to be declared next", state.definition.variables.len())));
+            }
+            _ => return self.unexpected("a local variable, “output”, “on”, “timer”, or “}”"),
+          },
+          _ => return self.unexpected("a local variable, “output”, “on”, “timer”, or “}”"),
+        }
+      }
+    }
+    Ok(state)
+  }
+  fn actions<Variable: ParseVariable>(&mut self) -> Result<Vec<EdgeAction<Variable>>, ParseError> {
+    let mut actions = Vec::new();
+    if self.accept(";") {
+      return Ok(actions);
+    }
+    self.expect("{")?;
+    while !self.accept("}") {
+      let span = self.span();
+      if self.accept_keyword("trigger") {
+        let target = self.small_number()?;
+        actions.push(EdgeAction::Trigger { target, condition: self.condition()? });
+      } else {
+        match self.peek() {
+          Some(Token::Identifier(name)) => match variable_name(name) {
+            Some(Name::Counter(counter)) => {
+              self.position += 1;
+              self.expect("=")?;
+              let value = number_expression(self.expression()?)?;
+              actions.push(EdgeAction::Set { counter, condition: self.condition()?, value });
+            }
+            Some(_) => return Err(ParseError::new(span, "Only counters can be changed by a transition")),
+            None => return self.unexpected("a counter, “trigger”, or “}”"),
+          },
+          _ => return self.unexpected("a counter, “trigger”, or “}”"),
+        }
+      }
+      self.expect(";")?;
+    }
+    Ok(actions)
+  }
+  /// Parse an optional `if` clause; if absent, the condition is always true
+  fn condition<Variable: ParseVariable>(&mut self) -> Result<BoolExpression<Variable>, ParseError> {
+    if self.accept_keyword("if") {
+      bool_expression(self.expression()?)
+    } else {
+      Ok(BoolExpression::Constant(true))
+    }
+  }
+  /// Parse the part of a variable declaration after the name: an optional type, the value, and a semicolon
+  fn declaration<Variable: ParseVariable>(&mut self) -> Result<VariableExpression<Variable>, ParseError> {
+    let kind = self.kind()?;
+    self.expect("=")?;
+    let expression = self.expression()?;
+    self.expect(";")?;
+    match kind.or_else(|| expression.kind()) {
+      Some(Kind::Bool) => Ok(VariableExpression::Bool(bool_expression(expression)?)),
+      Some(Kind::Num) => Ok(VariableExpression::Num(number_expression(expression)?)),
+      None => Err(ParseError::new(expression.span, "Cannot determine if this is a true/false value or a number; add “: bool” or “: num”")),
+    }
+  }
+  fn kind(&mut self) -> Result<Option<Kind>, ParseError> {
+    Ok(if self.accept(":") {
+      if self.accept_keyword("bool") {
+        Some(Kind::Bool)
+      } else if self.accept_keyword("num") {
+        Some(Kind::Num)
+      } else {
+        return self.unexpected("“bool” or “num”");
+      }
+    } else {
+      None
+    })
+  }
+  fn multi_state_declaration(&mut self) -> Result<MultiStateVariableExpression<LocalVariableName>, ParseError> {
+    let kind = self.kind()?;
+    self.expect("=")?;
+    let default = self.expression()?;
+    let cases = if self.accept("{") {
+      Some(self.list("}", |parser| {
+        let condition = parser.player_condition()?;
+        parser.expect(":")?;
+        Ok((condition, parser.expression()?))
+      })?)
+    } else {
+      None
+    };
+    self.expect(";")?;
+    let kind = kind.or_else(|| default.kind()).or_else(|| cases.iter().flatten().find_map(|(_, expression)| expression.kind()));
+    Ok(match (kind, cases) {
+      (None, _) => return Err(ParseError::new(default.span, "Cannot determine if this is a true/false value or a number; add “: bool” or “: num”")),
+      (Some(Kind::Bool), None) => MultiStateVariableExpression::Bool(bool_expression(default)?),
+      (Some(Kind::Num), None) => MultiStateVariableExpression::Num(number_expression(default)?),
+      (Some(Kind::Bool), Some(cases)) => MultiStateVariableExpression::MultiBool {
+        default: bool_expression(default)?,
+        expressions: cases.into_iter().map(|(condition, expression)| Ok((condition, bool_expression(expression)?))).collect::<Result<_, _>>()?,
+      },
+      (Some(Kind::Num), Some(cases)) => MultiStateVariableExpression::MultiNum {
+        default: number_expression(default)?,
+        expressions: cases.into_iter().map(|(condition, expression)| Ok((condition, number_expression(expression)?))).collect::<Result<_, _>>()?,
+      },
+    })
+  }
+  fn expression(&mut self) -> Result<Spanned, ParseError> {
+    self.binary(0)
+  }
+  fn binary(&mut self, level: usize) -> Result<Spanned, ParseError> {
+    let Some((operators, chainable)) = BINARY_OPERATORS.get(level) else {
+      return self.unary();
+    };
+    let mut left = self.binary(level + 1)?;
+    while let Some(operator) = operators.iter().find_map(|(symbol, operator)| self.accept(symbol).then_some(*operator)) {
+      let right = self.binary(level + 1)?;
+      let span = left.span.start..right.span.end;
+      left = Spanned { expression: Expression::Binary(operator, Box::new(left), Box::new(right)), span };
+      if !chainable && operators.iter().any(|(symbol, _)| self.peek() == Some(&Token::Punctuation(*symbol))) {
+        return Err(ParseError::new(self.span(), "Comparisons cannot be chained; use parentheses"));
+      }
+    }
+    Ok(left)
+  }
+  fn unary(&mut self) -> Result<Spanned, ParseError> {
+    let start = self.span().start;
+    if self.accept("!") {
+      let inner = self.unary()?;
+      let span = start..inner.span.end;
+      return Ok(Spanned { expression: Expression::Not(Box::new(inner)), span });
+    }
+    self.primary()
+  }
+  fn primary(&mut self) -> Result<Spanned, ParseError> {
+    let start = self.span();
+    let expression = match self.tokens.get(self.position).map(|(token, _)| token.clone()) {
+      Some(Token::Number(value)) => {
+        self.position += 1;
+        Expression::Number(value)
+      }
+      Some(Token::Punctuation("(")) => {
+        self.position += 1;
+        let mut inner = self.expression()?;
+        inner.span.start = start.start;
+        inner.span.end = self.expect(")")?.end;
+        return Ok(inner);
+      }
+      Some(Token::Identifier(word)) => {
+        self.position += 1;
+        match word {
+          "false" => Expression::Bool(false),
+          "true" => Expression::Bool(true),
+          "seed" => Expression::Seed,
+          "duration" => Expression::Variable(Name::Duration),
+          "if" => {
+            let condition = self.expression()?;
+            self.expect_keyword("then")?;
+            let when_true = self.expression()?;
+            self.expect_keyword("else")?;
+            let when_false = self.expression()?;
+            Expression::If(Box::new(condition), Box::new(when_true), Box::new(when_false))
+          }
+          "max" | "min" => {
+            self.expect("(")?;
+            let left = self.expression()?;
+            self.expect(",")?;
+            let right = self.expression()?;
+            self.expect(")")?;
+            Expression::Binary(if word == "max" { BinaryOperator::Max } else { BinaryOperator::Min }, Box::new(left), Box::new(right))
+          }
+          "mask" => {
+            self.expect("(")?;
+            let offset = self.small_number()?;
+            self.expect(",")?;
+            let length = self.small_number()?;
+            self.expect(")")?;
+            Expression::Mask { offset, length }
+          }
+          "permutation" => {
+            self.expect("(")?;
+            let seed = self.expression()?;
+            self.expect(",")?;
+            let index = self.expression()?;
+            self.expect(",")?;
+            let length = self.expression()?;
+            self.expect(")")?;
+            Expression::Permutation { seed: Box::new(seed), index: Box::new(index), length: Box::new(length) }
+          }
+          "case" => {
+            self.expect("(")?;
+            let index = self.expression()?;
+            self.expect(",")?;
+            self.expect("[")?;
+            let cases = self.list("]", |parser| parser.expression())?;
+            self.expect(",")?;
+            let default = self.expression()?;
+            self.expect(")")?;
+            Expression::Case(Box::new(index), cases, Box::new(default))
+          }
+          "holiday" => {
+            self.expect("(")?;
+            Expression::Holiday(self.list(")", |parser| parser.holiday())?)
+          }
+          "players" => {
+            self.expect("(")?;
+            let condition = self.player_condition()?;
+            self.expect(")")?;
+            Expression::Variable(Name::Players(condition))
+          }
+          word => match variable_name(word) {
+            Some(name) => Expression::Variable(name),
+            None => return Err(ParseError::new(start, format!("Unknown variable or function “{}”", word))),
+          },
+        }
+      }
+      _ => return self.unexpected("an expression"),
+    };
+    Ok(Spanned { expression, span: start.start..self.previous_end() })
+  }
+  fn holiday(&mut self) -> Result<Holiday, ParseError> {
+    let mut left = self.holiday_unary()?;
+    while self.accept("&&") {
+      left = Holiday::And(Box::new(left), Box::new(self.holiday_unary()?));
+    }
+    Ok(left)
+  }
+  fn holiday_unary(&mut self) -> Result<Holiday, ParseError> {
+    if self.accept("!") {
+      return Ok(Holiday::Not(Box::new(self.holiday_unary()?)));
+    }
+    if self.accept("(") {
+      let holiday = self.holiday()?;
+      self.expect(")")?;
+      return Ok(holiday);
+    }
+    let span = self.span();
+    let Some(Token::Identifier(word)) = self.peek() else {
+      return self.unexpected("a holiday");
+    };
+    let word = *word;
+    self.position += 1;
+    self.expect("(")?;
+    let holiday = match word {
+      "date" => {
+        let month = self.month()?;
+        self.expect(",")?;
+        let day = self.small_number()?;
+        let weekend_adjust = self.accept(",") && {
+          self.expect_keyword("observed")?;
+          true
+        };
+        self.expect(")")?;
+        Holiday::Date { month, day, weekend_adjust }
+      }
+      "days" => Holiday::DayOfMonth(self.set(|parser| parser.small_number())?),
+      "easter" => {
+        let negative = self.accept("-");
+        let number_span = self.span();
+        let offset = self.small_number()? as i16;
+        self.expect(")")?;
+        Holiday::Easter(
+          (if negative { -offset } else { offset })
+            .try_into()
+            .map_err(|_| ParseError::new(number_span, "Easter offset must be between -128 and 127"))?,
+        )
+      }
+      "months" => Holiday::Month(self.set(|parser| parser.month())?),
+      "nth" | "nth_last" => {
+        let occurrence = self.small_number()?;
+        self.expect(",")?;
+        let day = self.weekday()?;
+        self.expect(",")?;
+        let month = self.month()?;
+        self.expect(")")?;
+        Holiday::WeekDay { day, month, occurrence, ascending: word == "nth" }
+      }
+      "weekdays" => Holiday::DayOfWeek(self.set::<HashSet<_>, _>(|parser| parser.weekday())?),
+      "weeks" => Holiday::IsoWeek(self.set(|parser| parser.small_number())?),
+      _ => return Err(ParseError::new(span, format!("Unknown holiday “{}”", word))),
+    };
+    Ok(holiday)
+  }
+  fn month(&mut self) -> Result<chrono::Month, ParseError> {
+    let span = self.span();
+    let (name, _) = self.name()?;
+    name.parse().map_err(|_| ParseError::new(span, format!("“{}” is not a month", name)))
+  }
+  fn weekday(&mut self) -> Result<chrono::Weekday, ParseError> {
+    let span = self.span();
+    let (name, _) = self.name()?;
+    name.parse().map_err(|_| ParseError::new(span, format!("“{}” is not a day of the week", name)))
+  }
+  fn player_condition(&mut self) -> Result<PlayerStateCondition, ParseError> {
+    let span = self.span();
+    let Some(Token::Identifier(word)) = self.peek() else {
+      return self.unexpected("a player condition");
+    };
+    let word = *word;
+    self.position += 1;
+    if word == "always" {
+      return Ok(PlayerStateCondition::Always);
+    }
+    self.expect("(")?;
+    let condition = match word {
+      "all" => PlayerStateCondition::All(self.list(")", |parser| parser.player_condition())?),
+      "any" => PlayerStateCondition::Any(self.list(")", |parser| parser.player_condition())?),
+      "any_bits" | "no_bits" => {
+        let offset = self.small_number()?;
+        self.expect(",")?;
+        let length = self.small_number()?;
+        self.expect(")")?;
+        if word == "any_bits" {
+          PlayerStateCondition::AnyBits { offset, length }
+        } else {
+          PlayerStateCondition::NoBits { offset, length }
+        }
+      }
+      "bit" | "not_bit" => {
+        let bit = self.small_number()?;
+        self.expect(")")?;
+        if word == "bit" {
+          PlayerStateCondition::HasBit(bit)
+        } else {
+          PlayerStateCondition::NotHasBit(bit)
+        }
+      }
+      _ => return Err(ParseError::new(span, format!("Unknown player condition “{}”", word))),
+    };
+    Ok(condition)
+  }
+  /// Parse a comma-separated list of items up to and including the closing symbol
+  fn list<T>(&mut self, close: &str, mut item: impl FnMut(&mut Self) -> Result<T, ParseError>) -> Result<Vec<T>, ParseError> {
+    let mut items = Vec::new();
+    while !self.accept(close) {
+      items.push(item(self)?);
+      if !self.accept(",") {
+        self.expect(close)?;
+        break;
+      }
+    }
+    Ok(items)
+  }
+  fn set<C: FromIterator<T>, T>(&mut self, item: impl FnMut(&mut Self) -> Result<T, ParseError>) -> Result<C, ParseError> {
+    Ok(self.list(")", item)?.into_iter().collect())
+  }
+  /// Parse a user-provided name, which is either an identifier or a string
+  fn name(&mut self) -> Result<(String, Span), ParseError> {
+    let span = self.span();
+    match self.peek() {
+      Some(Token::Identifier(name)) => {
+        let name = name.to_string();
+        self.position += 1;
+        Ok((name, span))
+      }
+      Some(Token::String(name)) => {
+        let name = name.clone();
+        self.position += 1;
+        Ok((name, span))
+      }
+      _ => self.unexpected("a name"),
+    }
+  }
+  fn small_number(&mut self) -> Result<u8, ParseError> {
+    let span = self.span();
+    match self.peek() {
+      Some(Token::Number(value)) => {
+        let value: u8 = (*value).try_into().map_err(|_| ParseError::new(span, "Number must be between 0 and 255"))?;
+        self.position += 1;
+        Ok(value)
+      }
+      _ => self.unexpected("a number"),
+    }
+  }
+  fn peek(&self) -> Option<&Token<'a>> {
+    self.tokens.get(self.position).map(|(token, _)| token)
+  }
+  fn previous_end(&self) -> usize {
+    self.position.checked_sub(1).and_then(|index| self.tokens.get(index)).map(|(_, span)| span.end).unwrap_or(0)
+  }
+  fn span(&self) -> Span {
+    self.tokens.get(self.position).map(|(_, span)| span.clone()).unwrap_or(self.end..self.end)
+  }
+  fn accept(&mut self, symbol: &str) -> bool {
+    let found = matches!(self.peek(), Some(Token::Punctuation(p)) if *p == symbol);
+    if found {
+      self.position += 1;
+    }
+    found
+  }
+  fn accept_keyword(&mut self, keyword: &str) -> bool {
+    let found = matches!(self.peek(), Some(Token::Identifier(word)) if *word == keyword);
+    if found {
+      self.position += 1;
+    }
+    found
+  }
+  fn expect(&mut self, symbol: &str) -> Result<Span, ParseError> {
+    let span = self.span();
+    if self.accept(symbol) {
+      Ok(span)
+    } else {
+      self.unexpected(&format!("“{}”", symbol))
+    }
+  }
+  fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+    if self.accept_keyword(keyword) {
+      Ok(())
+    } else {
+      self.unexpected(&format!("“{}”", keyword))
+    }
+  }
+  fn unexpected<T>(&self, expected: &str) -> Result<T, ParseError> {
+    Err(ParseError::new(
+      self.span(),
+      match self.peek() {
+        Some(token) => format!("Expected {}, but found {}", expected, token.describe()),
+        None => format!("Expected {}, but reached the end", expected),
+      },
+    ))
+  }
+}
+
+impl Spanned {
+  /// Determine the type of an expression, if it is possible to do so without context
+  fn kind(&self) -> Option<Kind> {
+    match &self.expression {
+      Expression::Binary(operator, _, _) => Some(match operator {
+        BinaryOperator::And
+        | BinaryOperator::Or
+        | BinaryOperator::Equal
+        | BinaryOperator::NotEqual
+        | BinaryOperator::GreaterThan
+        | BinaryOperator::GreaterThanOrEqual
+        | BinaryOperator::LessThan
+        | BinaryOperator::LessThanOrEqual => Kind::Bool,
+        _ => Kind::Num,
+      }),
+      Expression::Bool(_) | Expression::Holiday(_) | Expression::Not(_) => Some(Kind::Bool),
+      Expression::Case(_, _, _) | Expression::Mask { .. } | Expression::Number(_) | Expression::Permutation { .. } | Expression::Seed => {
+        Some(Kind::Num)
+      }
+      Expression::If(_, when_true, when_false) => when_true.kind().or_else(|| when_false.kind()),
+      Expression::Variable(Name::Counter(_) | Name::Duration | Name::Players(_)) => Some(Kind::Num),
+      Expression::Variable(Name::Global(_) | Name::Local(_)) => None,
+    }
+  }
+}
+
+fn bool_expression<Variable: ParseVariable>(expression: Spanned) -> Result<BoolExpression<Variable>, ParseError> {
+  let span = expression.span;
+  Ok(match expression.expression {
+    Expression::Binary(operator, left, right) => match operator {
+      BinaryOperator::And => BoolExpression::And(Box::new(bool_expression(*left)?), Box::new(bool_expression(*right)?)),
+      BinaryOperator::Or => BoolExpression::Or(Box::new(bool_expression(*left)?), Box::new(bool_expression(*right)?)),
+      BinaryOperator::Equal => BoolExpression::Equal(number_expression(*left)?, number_expression(*right)?),
+      BinaryOperator::NotEqual => BoolExpression::NotEqual(number_expression(*left)?, number_expression(*right)?),
+      BinaryOperator::GreaterThan => BoolExpression::GreaterThan(number_expression(*left)?, number_expression(*right)?),
+      BinaryOperator::GreaterThanOrEqual => BoolExpression::GreaterThanOrEqual(number_expression(*left)?, number_expression(*right)?),
+      BinaryOperator::LessThan => BoolExpression::LessThan(number_expression(*left)?, number_expression(*right)?),
+      BinaryOperator::LessThanOrEqual => BoolExpression::LessThanOrEqual(number_expression(*left)?, number_expression(*right)?),
+      _ => return Err(ParseError::new(span, "Expected a true/false value, but this is a number")),
+    },
+    Expression::Bool(value) => BoolExpression::Constant(value),
+    Expression::Holiday(holidays) => BoolExpression::IsHoliday(holidays),
+    Expression::If(condition, when_true, when_false) => {
+      BoolExpression::If(Box::new(bool_expression(*condition)?), Box::new(bool_expression(*when_true)?), Box::new(bool_expression(*when_false)?))
+    }
+    Expression::Not(expression) => BoolExpression::Not(Box::new(bool_expression(*expression)?)),
+    Expression::Variable(name) => BoolExpression::Variable(Variable::from_name(name, span)?),
+    Expression::Case(_, _, _) | Expression::Mask { .. } | Expression::Number(_) | Expression::Permutation { .. } | Expression::Seed => {
+      return Err(ParseError::new(span, "Expected a true/false value, but this is a number"))
+    }
+  })
+}
+
+fn number_expression<Variable: ParseVariable>(expression: Spanned) -> Result<NumberExpression<Variable>, ParseError> {
+  let span = expression.span;
+  Ok(match expression.expression {
+    Expression::Binary(operator, left, right) => {
+      let left = Box::new(number_expression(*left)?);
+      let right = Box::new(number_expression(*right)?);
+      match operator {
+        BinaryOperator::Add => NumberExpression::Add(left, right),
+        BinaryOperator::BitAnd => NumberExpression::And(left, right),
+        BinaryOperator::BitOr => NumberExpression::Or(left, right),
+        BinaryOperator::Divide => NumberExpression::Divide(left, right),
+        BinaryOperator::Max => NumberExpression::Max(left, right),
+        BinaryOperator::Min => NumberExpression::Min(left, right),
+        BinaryOperator::Modulo => NumberExpression::Modulo(left, right),
+        BinaryOperator::Multiply => NumberExpression::Multiply(left, right),
+        BinaryOperator::Subtract => NumberExpression::Subtract(left, right),
+        BinaryOperator::XOr => NumberExpression::XOr(left, right),
+        _ => return Err(ParseError::new(span, "Expected a number, but this is a true/false value")),
+      }
+    }
+    Expression::Case(index, cases, default) => NumberExpression::Case(
+      Box::new(number_expression(*index)?),
+      cases.into_iter().map(number_expression).collect::<Result<_, _>>()?,
+      Box::new(number_expression(*default)?),
+    ),
+    Expression::If(condition, when_true, when_false) => NumberExpression::If(
+      Box::new(bool_expression(*condition)?),
+      Box::new(number_expression(*when_true)?),
+      Box::new(number_expression(*when_false)?),
+    ),
+    Expression::Mask { offset, length } => NumberExpression::Mask { offset, length },
+    Expression::Number(value) => NumberExpression::Constant(value),
+    Expression::Permutation { seed, index, length } => NumberExpression::Permutation {
+      seed: Box::new(number_expression(*seed)?),
+      index: Box::new(number_expression(*index)?),
+      length: Box::new(number_expression(*length)?),
+    },
+    Expression::Seed => NumberExpression::Seed,
+    Expression::Variable(name) => NumberExpression::Variable(Variable::from_name(name, span)?),
+    Expression::Bool(_) | Expression::Holiday(_) | Expression::Not(_) => {
+      return Err(ParseError::new(span, "Expected a number, but this is a true/false value"))
+    }
+  })
+}
+
+/// Interpret an identifier as a variable name
+fn variable_name(word: &str) -> Option<Name> {
+  fn index(word: &str, prefix: &str) -> Option<u8> {
+    let digits = word.strip_prefix(prefix)?;
+    if digits.starts_with(|c: char| c.is_ascii_digit()) && digits.chars().all(|c| c.is_ascii_digit()) {
+      digits.parse().ok()
+    } else {
+      None
+    }
+  }
+  index(word, "counter").map(Name::Counter).or_else(|| index(word, "global").map(Name::Global)).or_else(|| index(word, "local").map(Name::Local))
+}
+
+impl ParseVariable for () {
+  fn from_name(_: Name, span: Span) -> Result<Self, ParseError> {
+    Err(ParseError::new(span, "Global variables cannot use other variables"))
+  }
+}
+
+impl ParseVariable for StateVariableName {
+  fn from_name(name: Name, span: Span) -> Result<Self, ParseError> {
+    match name {
+      Name::Counter(counter) => Ok(StateVariableName::Counter(counter)),
+      Name::Global(global) => Ok(StateVariableName::Global(global)),
+      Name::Local(_) => Err(ParseError::new(span, "Local variables cannot use other local variables")),
+      Name::Duration => Err(ParseError::new(span, "“duration” can only be used in timers")),
+      Name::Players(_) => Err(ParseError::new(span, "“players” can only be used in count transitions")),
+    }
+  }
+}
+
+impl ParseVariable for LocalVariableName {
+  fn from_name(name: Name, span: Span) -> Result<Self, ParseError> {
+    match name {
+      Name::Local(local) => Ok(LocalVariableName::Local(local)),
+      name => Ok(LocalVariableName::Other(StateVariableName::from_name(name, span)?)),
+    }
+  }
+}
+
+impl<Variable: ParseVariable> ParseVariable for AreaCount<Variable> {
+  fn from_name(name: Name, span: Span) -> Result<Self, ParseError> {
+    match name {
+      Name::Players(condition) => Ok(AreaCount::PlayerCount(condition)),
+      name => Ok(AreaCount::Variable(Variable::from_name(name, span)?)),
+    }
+  }
+}
+
+impl<Variable: ParseVariable> ParseVariable for TimerDuration<Variable> {
+  fn from_name(name: Name, span: Span) -> Result<Self, ParseError> {
+    match name {
+      Name::Duration => Ok(TimerDuration::TimerDuration),
+      name => Ok(TimerDuration::Variable(Variable::from_name(name, span)?)),
+    }
+  }
+}
@@ -0,0 +1,484 @@
+use crate::controller::puzzle::area::PlayerStateCondition;
+use crate::controller::puzzle::state_machine::edge_action::EdgeAction;
+use crate::controller::puzzle::state_machine::expression::holiday::Holiday;
+use crate::controller::puzzle::state_machine::expression::{BoolExpression, MultiStateVariableExpression, NumberExpression, VariableExpression};
+use crate::controller::puzzle::state_machine::state::{LocalVariableName, StateVariableName};
+use crate::controller::puzzle::state_machine::text::lexer::is_identifier;
+use crate::controller::puzzle::state_machine::timer_transition::{TimerDuration, TimerTransition};
+use crate::controller::puzzle::state_machine::transition::{AreaCount, InputTrigger};
+use crate::controller::puzzle::state_machine::StateMachinePuzzleTemplate;
+use std::fmt::{Display, Write};
+
+// Operator precedence, from lowest to highest; this must match the parser
+const IF: u8 = 0;
+const OR: u8 = 1;
+const AND: u8 = 2;
+const COMPARE: u8 = 3;
+const BIT_OR: u8 = 4;
+const XOR: u8 = 5;
+const BIT_AND: u8 = 6;
+const ADD: u8 = 7;
+const MULTIPLY: u8 = 8;
+const UNARY: u8 = 9;
+const PRIMARY: u8 = 10;
+
+/// A type of variable that can be written in an expression
+trait PrintVariable {
+  fn write(&self, output: &mut String);
+}
+
+pub fn template<InputIdentifier: Display, OutputIdentifier: Display, Area: Display>(
+  template: &StateMachinePuzzleTemplate<InputIdentifier, OutputIdentifier, Area>,
+) -> String {
+  let mut output = String::new();
+  for (index, variable) in template.variables.iter().enumerate() {
+    write!(output, "global{}", index).unwrap();
+    declaration(&mut output, variable);
+  }
+  for (index, machine) in template.machines.iter().enumerate() {
+    if index > 0 || !template.variables.is_empty() {
+      output.push('\n');
+    }
+    writeln!(output, "machine machine{} {{", index).unwrap();
+    if machine.counters > 0 {
+      writeln!(output, "  counters {};", machine.counters).unwrap();
+    }
+    if !machine.outputs.is_empty() {
+      output.push_str("  outputs ");
+      for (index, name) in machine.outputs.iter().enumerate() {
+        if index > 0 {
+          output.push_str(", ");
+        }
+        identifier(&mut output, name);
+      }
+      output.push_str(";\n");
+    }
+    for (index, state) in machine.states.iter().enumerate() {
+      writeln!(output, "\n  state state{} {{", index).unwrap();
+      for (index, variable) in state.variables.iter().enumerate() {
+        write!(output, "    local{}", index).unwrap();
+        declaration(&mut output, variable);
+      }
+      for (name, value) in machine.outputs.iter().zip(&state.outputs) {
+        output.push_str("    output ");
+        identifier(&mut output, name);
+        multi_state_declaration(&mut output, value);
+      }
+      for transition in &state.user_transitions {
+        output.push_str("    on ");
+        match &transition.input {
+          InputTrigger::Click { source, player_condition, condition } => {
+            output.push_str("click(");
+            identifier(&mut output, source);
+            output.push(')');
+            if player_condition != &PlayerStateCondition::Always {
+              output.push_str(" when ");
+              self::player_condition(&mut output, player_condition);
+            }
+            self::condition(&mut output, condition);
+          }
+          InputTrigger::Count { area, condition } => {
+            output.push_str("count(");
+            identifier(&mut output, area);
+            output.push(')');
+            self::condition(&mut output, condition);
+          }
+          InputTrigger::Internal { source, condition } => {
+            write!(output, "trigger({})", source).unwrap();
+            self::condition(&mut output, condition);
+          }
+        }
+        write!(output, " -> state{}", transition.next).unwrap();
+        actions(&mut output, &transition.actions);
+      }
+      match &state.timer_transition {
+        TimerTransition::None => (),
+        TimerTransition::Reset { duration, next, actions } => {
+          output.push_str("    timer reset ");
+          number_expression(&mut output, duration, IF);
+          write!(output, " -> state{}", next).unwrap();
+          self::actions(&mut output, actions);
+        }
+        TimerTransition::Rollover { duration, next, actions } => {
+          output.push_str("    timer rollover ");
+          number_expression(&mut output, duration, IF);
+          write!(output, " -> state{}", next).unwrap();
+          self::actions(&mut output, actions);
+        }
+      }
+      output.push_str("  }\n");
+    }
+    output.push_str("}\n");
+  }
+  output
+}
+
+fn actions<Variable: PrintVariable>(output: &mut String, actions: &[EdgeAction<Variable>]) {
+  if actions.is_empty() {
+    output.push_str(";\n");
+    return;
+  }
+  output.push_str(" {\n");
+  for action in actions {
+    match action {
+      EdgeAction::Set { counter, condition, value } => {
+        write!(output, "      counter{} = ", counter).unwrap();
+        number_expression(output, value, IF);
+        self::condition(output, condition);
+      }
+      EdgeAction::Trigger { target, condition } => {
+        write!(output, "      trigger {}", target).unwrap();
+        self::condition(output, condition);
+      }
+    }
+    output.push_str(";\n");
+  }
+  output.push_str("    }\n");
+}
+
+/// Write an `if` clause, unless the condition is always true
+fn condition<Variable: PrintVariable>(output: &mut String, condition: &BoolExpression<Variable>) {
+  if !matches!(condition, BoolExpression::Constant(true)) {
+    output.push_str(" if ");
+    bool_expression(output, condition, IF);
+  }
+}
+
+fn declaration<Variable: PrintVariable>(output: &mut String, variable: &VariableExpression<Variable>) {
+  match variable {
+    VariableExpression::Bool(expression) => {
+      output.push_str(if ambiguous_bool(expression) { ": bool = " } else { " = " });
+      bool_expression(output, expression, IF);
+    }
+    VariableExpression::Num(expression) => {
+      output.push_str(if ambiguous_number(expression) { ": num = " } else { " = " });
+      number_expression(output, expression, IF);
+    }
+  }
+  output.push_str(";\n");
+}
+
+fn multi_state_declaration<Variable: PrintVariable>(output: &mut String, variable: &MultiStateVariableExpression<Variable>) {
+  match variable {
+    MultiStateVariableExpression::Bool(expression) => {
+      output.push_str(if ambiguous_bool(expression) { ": bool = " } else { " = " });
+      bool_expression(output, expression, IF);
+    }
+    MultiStateVariableExpression::Num(expression) => {
+      output.push_str(if ambiguous_number(expression) { ": num = " } else { " = " });
+      number_expression(output, expression, IF);
+    }
+    MultiStateVariableExpression::MultiBool { default, expressions } => {
+      output.push_str(if ambiguous_bool(default) { ": bool = " } else { " = " });
+      bool_expression(output, default, IF);
+      output.push_str(" {");
+      for (index, (condition, expression)) in expressions.iter().enumerate() {
+        output.push_str(if index > 0 { ", " } else { " " });
+        player_condition(output, condition);
+        output.push_str(": ");
+        bool_expression(output, expression, IF);
+      }
+      output.push_str(" }");
+    }
+    MultiStateVariableExpression::MultiNum { default, expressions } => {
+      output.push_str(if ambiguous_number(default) { ": num = " } else { " = " });
+      number_expression(output, default, IF);
+      output.push_str(" {");
+      for (index, (condition, expression)) in expressions.iter().enumerate() {
+        output.push_str(if index > 0 { ", " } else { " " });
+        player_condition(output, condition);
+        output.push_str(": ");
+        number_expression(output, expression, IF);
+      }
+      output.push_str(" }");
+    }
+  }
+  output.push_str(";\n");
+}
+
+/// Check if the parser might not be able to tell the type of an expression without an annotation
+fn ambiguous_bool<Variable>(expression: &BoolExpression<Variable>) -> bool {
+  matches!(expression, BoolExpression::If(_, _, _) | BoolExpression::Variable(_))
+}
+
+fn ambiguous_number<Variable>(expression: &NumberExpression<Variable>) -> bool {
+  matches!(expression, NumberExpression::If(_, _, _) | NumberExpression::Variable(_))
+}
+
+fn bool_expression<Variable: PrintVariable>(output: &mut String, expression: &BoolExpression<Variable>, precedence: u8) {
+  let own = match expression {
+    BoolExpression::And(_, _) => AND,
+    BoolExpression::Or(_, _) => OR,
+    BoolExpression::Equal(_, _)
+    | BoolExpression::GreaterThan(_, _)
+    | BoolExpression::GreaterThanOrEqual(_, _)
+    | BoolExpression::LessThan(_, _)
+    | BoolExpression::LessThanOrEqual(_, _)
+    | BoolExpression::NotEqual(_, _) => COMPARE,
+    BoolExpression::If(_, _, _) => IF,
+    BoolExpression::Not(_) => UNARY,
+    BoolExpression::Constant(_) | BoolExpression::IsHoliday(_) | BoolExpression::Variable(_) => PRIMARY,
+  };
+  let parenthesize = own < precedence;
+  if parenthesize {
+    output.push('(');
+  }
+  match expression {
+    BoolExpression::And(left, right) => {
+      bool_expression(output, left, AND);
+      output.push_str(" && ");
+      bool_expression(output, right, AND + 1);
+    }
+    BoolExpression::Constant(value) => write!(output, "{}", value).unwrap(),
+    BoolExpression::Equal(left, right) => comparison(output, left, "==", right),
+    BoolExpression::GreaterThan(left, right) => comparison(output, left, ">", right),
+    BoolExpression::GreaterThanOrEqual(left, right) => comparison(output, left, ">=", right),
+    BoolExpression::If(condition, when_true, when_false) => {
+      output.push_str("if ");
+      bool_expression(output, condition, IF);
+      output.push_str(" then ");
+      bool_expression(output, when_true, IF);
+      output.push_str(" else ");
+      bool_expression(output, when_false, IF);
+    }
+    BoolExpression::IsHoliday(holidays) => {
+      output.push_str("holiday(");
+      for (index, value) in holidays.iter().enumerate() {
+        if index > 0 {
+          output.push_str(", ");
+        }
+        holiday(output, value, false);
+      }
+      output.push(')');
+    }
+    BoolExpression::LessThan(left, right) => comparison(output, left, "<", right),
+    BoolExpression::LessThanOrEqual(left, right) => comparison(output, left, "<=", right),
+    BoolExpression::Not(expression) => {
+      output.push('!');
+      bool_expression(output, expression, UNARY);
+    }
+    BoolExpression::NotEqual(left, right) => comparison(output, left, "!=", right),
+    BoolExpression::Or(left, right) => {
+      bool_expression(output, left, OR);
+      output.push_str(" || ");
+      bool_expression(output, right, OR + 1);
+    }
+    BoolExpression::Variable(name) => name.write(output),
+  }
+  if parenthesize {
+    output.push(')');
+  }
+}
+
+fn comparison<Variable: PrintVariable>(output: &mut String, left: &NumberExpression<Variable>, operator: &str, right: &NumberExpression<Variable>) {
+  number_expression(output, left, COMPARE + 1);
+  write!(output, " {} ", operator).unwrap();
+  number_expression(output, right, COMPARE + 1);
+}
+
+fn number_expression<Variable: PrintVariable>(output: &mut String, expression: &NumberExpression<Variable>, precedence: u8) {
+  let (own, operator) = match expression {
+    NumberExpression::Add(_, _) => (ADD, "+"),
+    NumberExpression::And(_, _) => (BIT_AND, "&"),
+    NumberExpression::Divide(_, _) => (MULTIPLY, "/"),
+    NumberExpression::If(_, _, _) => (IF, ""),
+    NumberExpression::Modulo(_, _) => (MULTIPLY, "%"),
+    NumberExpression::Multiply(_, _) => (MULTIPLY, "*"),
+    NumberExpression::Or(_, _) => (BIT_OR, "|"),
+    NumberExpression::Subtract(_, _) => (ADD, "-"),
+    NumberExpression::XOr(_, _) => (XOR, "^"),
+    _ => (PRIMARY, ""),
+  };
+  let parenthesize = own < precedence;
+  if parenthesize {
+    output.push('(');
+  }
+  match expression {
+    NumberExpression::Add(left, right)
+    | NumberExpression::And(left, right)
+    | NumberExpression::Divide(left, right)
+    | NumberExpression::Modulo(left, right)
+    | NumberExpression::Multiply(left, right)
+    | NumberExpression::Or(left, right)
+    | NumberExpression::Subtract(left, right)
+    | NumberExpression::XOr(left, right) => {
+      number_expression(output, left, own);
+      write!(output, " {} ", operator).unwrap();
+      number_expression(output, right, own + 1);
+    }
+    NumberExpression::Case(index, cases, default) => {
+      output.push_str("case(");
+      number_expression(output, index, IF);
+      output.push_str(", [");
+      for (index, case) in cases.iter().enumerate() {
+        if index > 0 {
+          output.push_str(", ");
+        }
+        number_expression(output, case, IF);
+      }
+      output.push_str("], ");
+      number_expression(output, default, IF);
+      output.push(')');
+    }
+    NumberExpression::Constant(value) => write!(output, "{}", value).unwrap(),
+    NumberExpression::If(condition, when_true, when_false) => {
+      output.push_str("if ");
+      bool_expression(output, condition, IF);
+      output.push_str(" then ");
+      number_expression(output, when_true, IF);
+      output.push_str(" else ");
+      number_expression(output, when_false, IF);
+    }
+    NumberExpression::Mask { offset, length } => write!(output, "mask({}, {})", offset, length).unwrap(),
+    NumberExpression::Max(left, right) | NumberExpression::Min(left, right) => {
+      output.push_str(if matches!(expression, NumberExpression::Max(_, _)) { "max(" } else { "min(" });
+      number_expression(output, left, IF);
+      output.push_str(", ");
+      number_expression(output, right, IF);
+      output.push(')');
+    }
+    NumberExpression::Permutation { seed, index, length } => {
+      output.push_str("permutation(");
+      number_expression(output, seed, IF);
+      output.push_str(", ");
+      number_expression(output, index, IF);
+      output.push_str(", ");
+      number_expression(output, length, IF);
+      output.push(')');
+    }
+    NumberExpression::Seed => output.push_str("seed"),
+    NumberExpression::Variable(name) => name.write(output),
+  }
+  if parenthesize {
+    output.push(')');
+  }
+}
+
+fn holiday(output: &mut String, holiday: &Holiday, nested: bool) {
+  fn list<T>(output: &mut String, name: &str, items: impl IntoIterator<Item = T>, mut item: impl FnMut(&mut String, T)) {
+    output.push_str(name);
+    output.push('(');
+    for (index, value) in items.into_iter().enumerate() {
+      if index > 0 {
+        output.push_str(", ");
+      }
+      item(output, value);
+    }
+    output.push(')');
+  }
+  match holiday {
+    Holiday::And(left, right) => {
+      if nested {
+        output.push('(');
+      }
+      self::holiday(output, left, false);
+      output.push_str(" && ");
+      self::holiday(output, right, true);
+      if nested {
+        output.push(')');
+      }
+    }
+    Holiday::Date { month, day, weekend_adjust } => {
+      write!(output, "date({}, {}{})", month.name(), day, if *weekend_adjust { ", observed" } else { "" }).unwrap()
+    }
+    Holiday::DayOfMonth(days) => list(output, "days", days, |output, day| write!(output, "{}", day).unwrap()),
+    Holiday::DayOfWeek(days) => {
+      let mut days: Vec<_> = days.iter().collect();
+      days.sort_by_key(|day| day.num_days_from_monday());
+      list(output, "weekdays", days, |output, day| write!(output, "{}", day).unwrap())
+    }
+    Holiday::Easter(offset) => write!(output, "easter({})", offset).unwrap(),
+    Holiday::IsoWeek(weeks) => list(output, "weeks", weeks, |output, week| write!(output, "{}", week).unwrap()),
+    Holiday::Month(months) => list(output, "months", months, |output, month| output.push_str(month.name())),
+    Holiday::Not(inner) => {
+      output.push('!');
+      self::holiday(output, inner, true);
+    }
+    Holiday::WeekDay { day, month, occurrence, ascending } => {
+      write!(output, "{}({}, {}, {})", if *ascending { "nth" } else { "nth_last" }, occurrence, day, month.name()).unwrap()
+    }
+  }
+}
+
+fn player_condition(output: &mut String, condition: &PlayerStateCondition) {
+  match condition {
+    PlayerStateCondition::All(conditions) | PlayerStateCondition::Any(conditions) => {
+      output.push_str(if matches!(condition, PlayerStateCondition::All(_)) { "all(" } else { "any(" });
+      for (index, condition) in conditions.iter().enumerate() {
+        if index > 0 {
+          output.push_str(", ");
+        }
+        player_condition(output, condition);
+      }
+      output.push(')');
+    }
+    PlayerStateCondition::Always => output.push_str("always"),
+    PlayerStateCondition::AnyBits { offset, length } => write!(output, "any_bits({}, {})", offset, length).unwrap(),
+    PlayerStateCondition::HasBit(bit) => write!(output, "bit({})", bit).unwrap(),
+    PlayerStateCondition::NoBits { offset, length } => write!(output, "no_bits({}, {})", offset, length).unwrap(),
+    PlayerStateCondition::NotHasBit(bit) => write!(output, "not_bit({})", bit).unwrap(),
+  }
+}
+
+/// Write a name, quoting it if it is not a valid identifier
+fn identifier(output: &mut String, name: &impl Display) {
+  let name = name.to_string();
+  if is_identifier(&name) {
+    output.push_str(&name);
+  } else {
+    output.push('"');
+    for c in name.chars() {
+      if c == '"' || c == '\\' {
+        output.push('\\');
+      }
+      output.push(c);
+    }
+    output.push('"');
+  }
+}
+
+impl PrintVariable for () {
+  fn write(&self, _output: &mut String) {
+    unreachable!("Templates with globals that refer to variables are rejected before printing")
+  }
+}
+
+impl PrintVariable for StateVariableName {
+  fn write(&self, output: &mut String) {
+    match self {
+      StateVariableName::Counter(counter) => write!(output, "counter{}", counter).unwrap(),
+      StateVariableName::Global(global) => write!(output, "global{}", global).unwrap(),
+    }
+  }
+}
+
+impl PrintVariable for LocalVariableName {
+  fn write(&self, output: &mut String) {
+    match self {
+      LocalVariableName::Local(local) => write!(output, "local{}", local).unwrap(),
+      LocalVariableName::Other(name) => name.write(output),
+    }
+  }
+}
+
+impl<Variable: PrintVariable> PrintVariable for AreaCount<Variable> {
+  fn write(&self, output: &mut String) {
+    match self {
+      AreaCount::PlayerCount(condition) => {
+        output.push_str("players(");
+        player_condition(output, condition);
+        output.push(')');
+      }
+      AreaCount::Variable(name) => name.write(output),
+    }
+  }
+}
+
+impl<Variable: PrintVariable> PrintVariable for TimerDuration<Variable> {
+  fn write(&self, output: &mut String) {
+    match self {
+      TimerDuration::TimerDuration => output.push_str("duration"),
+      TimerDuration::Variable(name) => name.write(output),
+    }
+  }
+}
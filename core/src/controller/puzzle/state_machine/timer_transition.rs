@@ -1,5 +1,6 @@
-use crate::controller::puzzle::state_machine::edge_action::EdgeAction;
+use crate::controller::puzzle::state_machine::edge_action::{CompiledEdgeAction, EdgeAction};
 use crate::controller::puzzle::state_machine::error::StateMachineError;
+use crate::controller::puzzle::state_machine::expression::compiled::{CompiledExpression, Constants, FoldableVariable};
 use crate::controller::puzzle::state_machine::state::{LocalData, LocalVariableName, TransitionVariableGenerator};
 use crate::controller::puzzle::Value;
 use chrono::{DateTime, NaiveDate, Utc};
@@ -33,6 +34,22 @@ pub enum TimerTransition<Variable> {
   },
 }
 
+#[derive(Clone, Debug)]
+pub enum CompiledTimerTransition<Variable> {
+  None,
+  Reset { duration: CompiledExpression<Variable>, next: super::StateId, actions: Vec<CompiledEdgeAction<TimerDuration<Variable>>> },
+  Rollover { duration: CompiledExpression<TimerDuration<Variable>>, next: super::StateId, actions: Vec<CompiledEdgeAction<TimerDuration<Variable>>> },
+}
+
+impl<Variable: FoldableVariable> FoldableVariable for TimerDuration<Variable> {
+  fn global(&self) -> Option<u8> {
+    match self {
+      TimerDuration::TimerDuration => None,
+      TimerDuration::Variable(name) => name.global(),
+    }
+  }
+}
+
 impl<Variable, Data: super::expression::ExpressionData<Variable>> super::expression::ExpressionData<TimerDuration<Variable>>
   for TimerDurationData<Data>
 {
@@ -61,7 +78,25 @@ impl TransitionVariableGenerator for TimerDurationDataGenerator {
   }
 }
 
-impl<Variable> TimerTransition<Variable> {
+impl<Variable: FoldableVariable> TimerTransition<Variable> {
+  pub fn compile(&self, constants: &Constants) -> CompiledTimerTransition<Variable> {
+    match self {
+      TimerTransition::None => CompiledTimerTransition::None,
+      TimerTransition::Reset { duration, next, actions } => CompiledTimerTransition::Reset {
+        duration: duration.compile(constants),
+        next: *next,
+        actions: actions.iter().map(|a| a.compile(constants)).collect(),
+      },
+      TimerTransition::Rollover { duration, next, actions } => CompiledTimerTransition::Rollover {
+        duration: duration.compile(constants),
+        next: *next,
+        actions: actions.iter().map(|a| a.compile(constants)).collect(),
+      },
+    }
+  }
+}
+
+impl<Variable> CompiledTimerTransition<Variable> {
   pub fn prepare<Data: super::expression::ExpressionData<Variable>>(
     &self,
     start: DateTime<Utc>,
@@ -69,12 +104,13 @@ impl<Variable> TimerTransition<Variable> {
     data: Data,
   ) -> Result<Option<DateTime<Utc>>, StateMachineError> {
     Ok(match self {
-      TimerTransition::None => None,
-      TimerTransition::Reset { duration, .. } => Some(start + chrono::Duration::seconds(duration.evaluate(&data)? as i64)),
-      TimerTransition::Rollover { duration, .. } => Some(
+      CompiledTimerTransition::None => None,
+      CompiledTimerTransition::Reset { duration, .. } => Some(start + chrono::Duration::seconds(duration.evaluate_number(&data)? as i64)),
+      CompiledTimerTransition::Rollover { duration, .. } => Some(
         start
           + chrono::Duration::seconds(
-            duration.evaluate(&TimerDurationData { data, duration: old.map(|o| (start - o).num_seconds().try_into().unwrap_or(0)).unwrap_or(0) })?
+            duration
+              .evaluate_number(&TimerDurationData { data, duration: old.map(|o| (start - o).num_seconds().try_into().unwrap_or(0)).unwrap_or(0) })?
               as i64,
           ),
       ),
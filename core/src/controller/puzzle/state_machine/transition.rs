@@ -1,7 +1,8 @@
 use crate::controller::puzzle;
 use crate::controller::puzzle::area::{AreaCounter, PlayerStateCondition};
-use crate::controller::puzzle::state_machine::edge_action::EdgeAction;
+use crate::controller::puzzle::state_machine::edge_action::{CompiledEdgeAction, EdgeAction};
 use crate::controller::puzzle::state_machine::error::StateMachineError;
+use crate::controller::puzzle::state_machine::expression::compiled::{CompiledExpression, Constants, FoldableVariable};
 use crate::controller::puzzle::state_machine::state::{LocalData, LocalVariableName};
 use crate::controller::puzzle::state_machine::InternalEventId;
 use crate::controller::puzzle::Value;
@@ -15,6 +16,20 @@ pub enum AreaCount<Variable> {
   Variable(Variable),
 }
 
+/// The condition of a transition with the trigger removed
+#[derive(Clone, Debug)]
+pub enum CompiledCondition<Variable> {
+  Count(CompiledExpression<AreaCount<Variable>>),
+  Local(CompiledExpression<Variable>),
+}
+
+#[derive(Clone, Debug)]
+pub struct CompiledTransition<Variable> {
+  pub condition: CompiledCondition<Variable>,
+  pub next: super::StateId,
+  pub actions: Vec<CompiledEdgeAction<Variable>>,
+}
+
 pub struct AreaCountData<'a, C: AreaCounter, D>(pub &'a C, pub D);
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -49,23 +64,46 @@ impl<'a, Variable, Counter: AreaCounter, Data: super::expression::ExpressionData
   }
 }
 
+impl<Variable: FoldableVariable> FoldableVariable for AreaCount<Variable> {
+  fn global(&self) -> Option<u8> {
+    match self {
+      AreaCount::PlayerCount(_) => None,
+      AreaCount::Variable(name) => name.global(),
+    }
+  }
+}
+
+impl<InputIdentifier, Area, Variable: FoldableVariable> Transition<InputIdentifier, Area, Variable> {
+  pub fn compile(&self, constants: &Constants) -> CompiledTransition<Variable> {
+    CompiledTransition {
+      condition: match &self.input {
+        InputTrigger::Click { condition, .. } | InputTrigger::Internal { condition, .. } => CompiledCondition::Local(condition.compile(constants)),
+        InputTrigger::Count { condition, .. } => CompiledCondition::Count(condition.compile(constants)),
+      },
+      next: self.next,
+      actions: self.actions.iter().map(|a| a.compile(constants)).collect(),
+    }
+  }
+}
+
 impl<InputIdentifier: Eq, Area: Eq> Transition<InputIdentifier, Area, LocalVariableName> {
-  pub fn should_trigger<AreaCount: AreaCounter>(
+  pub fn should_trigger<'a, AreaCount: AreaCounter>(
     &self,
+    compiled: &'a CompiledTransition<LocalVariableName>,
     input: &puzzle::PuzzleInput<InputIdentifier, Area, AreaCount>,
     local_data: &LocalData,
-  ) -> Option<Result<&Self, StateMachineError>> {
-    let keep = match (input, &self.input) {
-      (puzzle::PuzzleInput::Click(name, player_state), InputTrigger::Click { source, player_condition, condition })
+  ) -> Option<Result<&'a CompiledTransition<LocalVariableName>, StateMachineError>> {
+    let keep = match (input, &self.input, &compiled.condition) {
+      (puzzle::PuzzleInput::Click(name, player_state), InputTrigger::Click { source, player_condition, .. }, CompiledCondition::Local(condition))
         if source == name && player_condition.check(*player_state) =>
       {
-        match condition.evaluate(local_data) {
+        match condition.evaluate_bool(local_data) {
           Err(e) => return Some(Err(e)),
           Ok(keep) => Some(keep),
         }
       }
-      (puzzle::PuzzleInput::Area(name, counter), InputTrigger::Count { area, condition }) if area == name => {
-        match condition.evaluate(&AreaCountData(counter, local_data.clone())) {
+      (puzzle::PuzzleInput::Area(name, counter), InputTrigger::Count { area, .. }, CompiledCondition::Count(condition)) if area == name => {
+        match condition.evaluate_bool(&AreaCountData(counter, local_data.clone())) {
           Err(e) => return Some(Err(e)),
           Ok(keep) => Some(keep),
         }
@@ -73,17 +111,22 @@ impl<InputIdentifier: Eq, Area: Eq> Transition<InputIdentifier, Area, LocalVaria
       _ => None,
     }?;
     if keep {
-      Some(Ok(self))
+      Some(Ok(compiled))
     } else {
       None
     }
   }
-  pub fn should_trigger_internal(&self, input: &BTreeSet<InternalEventId>, local_data: &LocalData) -> Option<Result<&Self, StateMachineError>> {
-    if let InputTrigger::Internal { source, condition } = &self.input {
+  pub fn should_trigger_internal<'a>(
+    &self,
+    compiled: &'a CompiledTransition<LocalVariableName>,
+    input: &BTreeSet<InternalEventId>,
+    local_data: &LocalData,
+  ) -> Option<Result<&'a CompiledTransition<LocalVariableName>, StateMachineError>> {
+    if let (InputTrigger::Internal { source, .. }, CompiledCondition::Local(condition)) = (&self.input, &compiled.condition) {
       if input.contains(source) {
-        match condition.evaluate(local_data) {
+        match condition.evaluate_bool(local_data) {
           Err(e) => Some(Err(e)),
-          Ok(true) => Some(Ok(self)),
+          Ok(true) => Some(Ok(compiled)),
           Ok(false) => None,
         }
       } else {
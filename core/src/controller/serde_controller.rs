@@ -81,8 +81,8 @@ where
   type Error = CT::Error;
   type Controller = SerdeController<CT::Controller>;
 
-  fn blank(&self) -> Self::Controller {
-    SerdeController(self.0.blank())
+  fn blank(&self) -> Result<Self::Controller, Self::Error> {
+    Ok(SerdeController(self.0.blank()?))
   }
 
   fn load_json(&self, value: Value) -> Result<Self::Controller, LoadError<serde_json::Error, Self::Error>> {
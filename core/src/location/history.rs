@@ -0,0 +1,50 @@
+use crate::player::PlayerIdentifier;
+use crate::reference_converter::{Converter, Referencer};
+use chrono::{DateTime, Utc};
+
+/// Why a copy of a location's state was saved
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, Eq, PartialEq, Hash, int_enum::IntEnum)]
+#[repr(i16)]
+pub enum SnapshotReason {
+  /// The state the location started with, saved when it first changes
+  Changed = 0,
+  /// Saved regularly while the location is in use and its state is changing
+  Periodic = 1,
+  /// The state just before another snapshot was restored, so the restore can be undone
+  Restore = 2,
+}
+/// A saved copy of a location's state that the owner can restore
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct StateSnapshot {
+  /// The identifier to use when restoring this snapshot
+  pub id: i32,
+  /// The time when the snapshot was saved
+  pub created: DateTime<Utc>,
+  /// Why the snapshot was saved
+  pub reason: SnapshotReason,
+}
+/// A record of a location's state being replaced by a snapshot
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct StateRestore<S: AsRef<str>> {
+  /// The player that restored the snapshot
+  pub principal: PlayerIdentifier<S>,
+  /// The time when the restored snapshot was originally saved
+  pub snapshot: DateTime<Utc>,
+  /// The time when the snapshot was restored
+  pub timestamp: DateTime<Utc>,
+}
+
+impl<S: AsRef<str>> StateRestore<S> {
+  pub fn reference<'a, R: Referencer<S>>(&'a self, referencer: R) -> StateRestore<R::Output<'a>>
+  where
+    <R as Referencer<S>>::Output<'a>: AsRef<str>,
+  {
+    StateRestore { principal: self.principal.reference(referencer), snapshot: self.snapshot, timestamp: self.timestamp }
+  }
+  pub fn convert<C: Converter<S>>(self, conversion: C) -> StateRestore<C::Output>
+  where
+    <C as Converter<S>>::Output: AsRef<str>,
+  {
+    StateRestore { principal: self.principal.convert(conversion), snapshot: self.snapshot, timestamp: self.timestamp }
+  }
+}
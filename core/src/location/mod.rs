@@ -1,6 +1,7 @@
 pub mod change;
 pub mod communication;
 pub mod directory;
+pub mod history;
 pub mod protocol;
 pub mod target;
 
@@ -1,6 +1,7 @@
 use crate::access::{AccessControl, Privilege};
 use crate::avatar::Avatar;
 use crate::location::communication;
+use crate::location::history;
 use crate::player::PlayerIdentifier;
 use crate::reference_converter::{Converter, Referencer};
 use crate::UpdateResult;
@@ -65,6 +66,17 @@ pub enum LocationRequest<S: AsRef<str>, B> {
     from: chrono::DateTime<chrono::Utc>,
     to: chrono::DateTime<chrono::Utc>,
   },
+  /// List the saved copies of the location's state and the times any were restored. Only the owner can do this.
+  StateHistory {
+    id: i32,
+  },
+  /// Replace the location's state with a saved copy. Only the owner can do this.
+  ///
+  /// Everyone in the location is rejoined to the restored state. The current state is saved first, so the restore can be undone.
+  StateRestore {
+    id: i32,
+    snapshot: i32,
+  },
   Internal(i32, B),
 }
 
@@ -117,6 +129,21 @@ pub enum LocationResponse<S: AsRef<str>, B> {
   RequestError {
     id: i32,
   },
+  /// The saved copies of the location's state, newest first, and the times any were restored
+  StateHistory {
+    id: i32,
+    snapshots: Vec<history::StateSnapshot>,
+    restores: Vec<history::StateRestore<S>>,
+  },
+  /// The reason the saved copies of the location's state could not be listed
+  StateHistoryFailure {
+    id: i32,
+    result: UpdateResult,
+  },
+  StateRestore {
+    id: i32,
+    result: UpdateResult,
+  },
   Internal(B),
 }
 
@@ -141,6 +168,8 @@ impl<S: AsRef<str>, B: AsRef<[u8]>> LocationRequest<S, B> {
       LocationRequest::MessageClear { id, from, to } => LocationRequest::MessageClear { id, from, to },
       LocationRequest::MessageSend { body } => LocationRequest::MessageSend { body: body.convert(converter) },
       LocationRequest::MessagesGet { from, to } => LocationRequest::MessagesGet { from, to },
+      LocationRequest::StateHistory { id } => LocationRequest::StateHistory { id },
+      LocationRequest::StateRestore { id, snapshot } => LocationRequest::StateRestore { id, snapshot },
     }
   }
   pub fn reference<'a, R: Referencer<S> + Referencer<B>>(
@@ -168,6 +197,8 @@ impl<S: AsRef<str>, B: AsRef<[u8]>> LocationRequest<S, B> {
       LocationRequest::MessageClear { id, from, to } => LocationRequest::MessageClear { id: *id, from: *from, to: *to },
       LocationRequest::MessageSend { body } => LocationRequest::MessageSend { body: body.reference(reference) },
       LocationRequest::MessagesGet { from, to } => LocationRequest::MessagesGet { from: *from, to: *to },
+      LocationRequest::StateHistory { id } => LocationRequest::StateHistory { id: *id },
+      LocationRequest::StateRestore { id, snapshot } => LocationRequest::StateRestore { id: *id, snapshot: *snapshot },
     }
   }
 }
@@ -203,6 +234,11 @@ impl<S: AsRef<str>, B: AsRef<[u8]>> LocationResponse<S, B> {
       LocationResponse::NameChange { id, result } => LocationResponse::NameChange { id: *id, result: *result },
       LocationResponse::NameChanged { name } => LocationResponse::NameChanged { name: reference.convert(name) },
       LocationResponse::RequestError { id } => LocationResponse::RequestError { id: *id },
+      LocationResponse::StateHistory { id, snapshots, restores } => {
+        LocationResponse::StateHistory { id: *id, snapshots: snapshots.clone(), restores: restores.iter().map(|r| r.reference(reference)).collect() }
+      }
+      LocationResponse::StateHistoryFailure { id, result } => LocationResponse::StateHistoryFailure { id: *id, result: *result },
+      LocationResponse::StateRestore { id, result } => LocationResponse::StateRestore { id: *id, result: *result },
     }
   }
   pub fn convert<C: Converter<S> + Converter<B>>(self, converter: C) -> LocationResponse<<C as Converter<S>>::Output, <C as Converter<B>>::Output>
@@ -232,6 +268,11 @@ impl<S: AsRef<str>, B: AsRef<[u8]>> LocationResponse<S, B> {
       LocationResponse::NameChange { id, result } => LocationResponse::NameChange { id, result },
       LocationResponse::NameChanged { name } => LocationResponse::NameChanged { name: converter.convert(name) },
       LocationResponse::RequestError { id } => LocationResponse::RequestError { id },
+      LocationResponse::StateHistory { id, snapshots, restores } => {
+        LocationResponse::StateHistory { id, snapshots, restores: restores.into_iter().map(|r| r.convert(converter)).collect() }
+      }
+      LocationResponse::StateHistoryFailure { id, result } => LocationResponse::StateHistoryFailure { id, result },
+      LocationResponse::StateRestore { id, result } => LocationResponse::StateRestore { id, result },
     }
   }
 }
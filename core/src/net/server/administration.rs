@@ -1,4 +1,5 @@
 use crate::{access, communication, UpdateResult};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -7,8 +8,26 @@ pub enum AdministrationRequest<S: AsRef<str>> {
   AccountLockChange { name: S, locked: bool },
   /// Check whether an account is locked or not
   AccountLockStatus { name: S },
+  /// Write a copy of the server's database to its backup directory while the server keeps running
+  ///
+  /// This is only available to clients connected through the server's UNIX socket.
+  DatabaseBackup,
   /// Create an invitation for a new player
   Invite,
+  /// List all the invitations that have not been used or expired
+  InvitationList,
+  /// Cancel an invitation so it can no longer be used
+  InvitationRevoke { code: S },
+  /// Replace a player's password with a new temporary one
+  PasswordReset { name: S },
+  /// Allow a player to have a server role
+  RoleGrant { role: ServerRole, name: S },
+  /// Get the players that have each server role
+  RoleList,
+  /// Change whether players have a server role unless listed as an exception; this clears all existing exceptions
+  RoleReset { role: ServerRole, default: access::SimpleAccess },
+  /// Prevent a player from having a server role
+  RoleRevoke { role: ServerRole, name: S },
 }
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum AdministrationResponse<S: AsRef<str>> {
@@ -22,6 +41,10 @@ pub enum AdministrationResponse<S: AsRef<str>> {
     name: S,
     status: access::AccountLockState,
   },
+  /// The result of trying to back up the server's database
+  DatabaseBackup {
+    result: DatabaseBackupResult<S>,
+  },
   /// The invitation created for a new player
   InviteSuccess {
     url: S,
@@ -30,5 +53,70 @@ pub enum AdministrationResponse<S: AsRef<str>> {
   InviteFailure {
     error: communication::InvitationError,
   },
+  /// The invitations that are still available
+  InvitationList {
+    invitations: Vec<Invitation<S>>,
+  },
+  /// The result of trying to revoke an invitation
+  InvitationRevoke {
+    code: S,
+    result: UpdateResult,
+  },
   NotAdministrator,
+  /// The temporary password assigned to a player or none if the password could not be changed
+  PasswordReset {
+    name: S,
+    password: Option<S>,
+  },
+  /// The result of trying to grant or revoke a server role for a player
+  RoleChange {
+    role: ServerRole,
+    name: S,
+    result: UpdateResult,
+  },
+  /// The players that have each server role or none if roles are managed outside of this server
+  RoleList {
+    administrators: Option<access::LocalAccessSetting<S>>,
+    creators: Option<access::LocalAccessSetting<S>>,
+  },
+  /// The result of trying to change the default for a server role
+  RoleReset {
+    role: ServerRole,
+    default: access::SimpleAccess,
+    result: UpdateResult,
+  },
+}
+
+/// The outcome of an online backup of the server's database
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum DatabaseBackupResult<S: AsRef<str>> {
+  /// The backup was written to this file on the server
+  Success(S),
+  /// Backups can only be requested through the server's UNIX socket
+  NotAllowed,
+  /// The server has no backup directory configured or its database cannot be backed up while running
+  NotAvailable,
+  /// The backup could not be written; the details are in the server's log
+  InternalError,
+}
+
+/// Privileges on a server that can be granted to players
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ServerRole {
+  /// Can change server settings and manage other players' accounts
+  Administrator,
+  /// Can create new locations and upload assets
+  Creator,
+}
+
+/// An invitation that a new player can use to create an account
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Invitation<S: AsRef<str>> {
+  /// The secret code in the invitation
+  pub code: S,
+  /// The administrator that created the invitation
+  pub created_by: S,
+  pub created: DateTime<Utc>,
+  /// The time after which the invitation can no longer be used
+  pub expires: DateTime<Utc>,
 }
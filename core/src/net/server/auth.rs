@@ -1,14 +1,57 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+/// A request to log in using a public key previously registered by the player
+///
+/// This is sent to the `/api/client/key` endpoint and the server will respond with a JSON-encoded [PublicKeyChallenge].
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AuthPublicKey<S: AsRef<str>> {
   pub player: S,
   pub fingerprint: S,
 }
-/// Authentication mechanisms that the client and server can use
+/// A challenge the client must sign to prove it has the private key
 ///
-/// A client should perform a `GET` request on a server's `/auth` endpoint to get a JSON-encoded version of this struct detailing which authentication scheme to use
-#[derive(Serialize, Deserialize, Debug)]
+/// The challenge can only be answered once and expires shortly after it is issued. The client should check that the server name matches the
+/// server it is logging in to.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PublicKeyChallenge<S: AsRef<str>> {
+  pub nonce: S,
+  pub server: S,
+}
+/// The answer to a [PublicKeyChallenge]
+///
+/// This is sent to the `/api/client/key/response` endpoint. The signature is over the output of [challenge_message]. If successful, the server
+/// will respond with a JWT.
+#[serde_with::serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PublicKeyChallengeResponse<S: AsRef<str>> {
+  pub player: S,
+  pub fingerprint: S,
+  pub nonce: S,
+  #[serde_as(as = "serde_with::base64::Base64")]
+  pub signature: Vec<u8>,
+}
+/// The players that an authentication mechanism is responsible for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum AuthAccounts<S: AsRef<str>> {
+  /// Any player that is not handled by an earlier mechanism
+  Any,
+  /// Players whose names start with this prefix
+  Prefix(S),
+  /// Players whose names end with `@` followed by this realm
+  Realm(S),
+}
+/// An authentication mechanism available on a server
+///
+/// A client should perform a `GET` request on a server's `/auth/method` endpoint to get a JSON-encoded list of these. The first entry whose accounts match the player's name is the one to use.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuthMethod<S: AsRef<str>> {
+  /// The players that must use this mechanism
+  pub accounts: AuthAccounts<S>,
+  /// How these players authenticate
+  pub scheme: AuthScheme,
+}
+/// Authentication mechanisms that the client and server can use
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AuthScheme {
   /// OpenIdConnect authentication using a remote server. The client should send a request to `/oidc?user=`_user_.
   OpenIdConnect,
@@ -22,16 +65,73 @@ pub struct PasswordRequest<S: AsRef<str>> {
   pub username: S,
   /// The player's raw password; it is the client's responsibility to ensure the channel is encrypted or warn the player
   pub password: S,
+  /// A code from the player's authenticator or an unused recovery code; this is required if the player has enrolled a second factor
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub otp: Option<S>,
+}
+/// The data structure for changing a player's password
+///
+/// This is sent to the `/password/change` endpoint and is only supported if the server stores passwords itself
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordChangeRequest<S: AsRef<str>> {
+  /// The player's login name
+  pub username: S,
+  /// The player's current password
+  pub old_password: S,
+  /// The password the player wishes to use in the future
+  pub new_password: S,
+  /// A code from the player's authenticator or an unused recovery code; this is required if the player has enrolled a second factor
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub otp: Option<S>,
+}
+/// The data structure for creating a new account from an invitation
+///
+/// This is sent to the `/invitation` endpoint. If successful, the server will respond with a JWT as if the player had logged in.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordRegistrationRequest<S: AsRef<str>> {
+  /// The invitation code provided by the server administrator
+  pub invitation: S,
+  /// The login name the player wishes to use
+  pub username: S,
+  /// The player's new password
+  pub password: S,
 }
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct PublicKey {
   pub created: DateTime<Utc>,
+  pub fingerprint: String,
   pub last_used: Option<DateTime<Utc>>,
 }
 
+/// The data a client signs to answer a [PublicKeyChallenge]
+///
+/// Ed25519 and Ed448 keys sign this directly; other keys sign its SHA-256 digest.
+pub fn challenge_message(server: &str, player: &str, fingerprint: &str, nonce: &str) -> Vec<u8> {
+  format!("spadina-key-login\0{}\0{}\0{}\0{}", server, player, fingerprint, nonce).into_bytes()
+}
+
 pub fn compute_fingerprint(der: &[u8]) -> String {
   use sha3::Digest;
   let mut fingerprint = sha3::Sha3_256::new();
   fingerprint.update(der);
   hex::encode(fingerprint.finalize())
 }
+
+impl<S: AsRef<str>> AuthAccounts<S> {
+  /// Get the name a player is known by inside this group, if the player belongs to it
+  pub fn local_name<'a>(&self, player: &'a str) -> Option<&'a str> {
+    match self {
+      AuthAccounts::Any => Some(player),
+      AuthAccounts::Prefix(prefix) => player.strip_prefix(prefix.as_ref()).filter(|name| !name.is_empty()),
+      AuthAccounts::Realm(realm) => player.strip_suffix(realm.as_ref()).and_then(|name| name.strip_suffix('@')).filter(|name| !name.is_empty()),
+    }
+  }
+  /// Convert a name inside this group into the name the player is known by on the server
+  pub fn qualified_name(&self, name: &str) -> String {
+    match self {
+      AuthAccounts::Any => name.to_string(),
+      AuthAccounts::Prefix(prefix) => format!("{}{}", prefix.as_ref(), name),
+      AuthAccounts::Realm(realm) => format!("{}@{}", name, realm.as_ref()),
+    }
+  }
+}
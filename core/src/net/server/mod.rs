@@ -23,10 +23,16 @@ pub const CAPABILITY_HEADER: &str = "X-Spadina-Capability";
 
 pub const CLIENT_KEY_PATH: &str = "/api/client/key";
 
+pub const CLIENT_KEY_RESPONSE_PATH: &str = "/api/client/key/response";
+
 pub const CLIENT_V1_PATH: &str = "/api/client/v1";
 
+pub const INVITATION_PATH: &str = "/api/auth/invitation";
+
 pub const PASSWORD_AUTH_PATH: &str = "/api/auth/password";
 
+pub const PASSWORD_CHANGE_PATH: &str = "/api/auth/password/change";
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum ClientRequest<S: AsRef<str> + Eq + std::hash::Hash + Ord, B> {
   Activity {
@@ -159,27 +165,36 @@ pub enum ClientRequest<S: AsRef<str> + Eq + std::hash::Hash + Ord, B> {
     id: u32,
     ban: access::BannedPeer<S>,
   },
+  /// Get a copy of everything the server stores about this player
+  ///
+  /// This includes their settings, bookmarks, calendar subscriptions, direct messages, public keys, and the locations they own.
+  PlayerDataExport {
+    id: u32,
+  },
   /// Try to get the online status and location of another player
   PlayerOnlineCheck {
     id: u32,
     player: PlayerIdentifier<S>,
   },
-  /// Erases a player from the server on next restart
+  /// Erases a player from the server
   ///
-  /// All of their realms and chats will be deleted. This does *not* prevent them from logging in again. They must also be removed from the authentication provider.
+  /// All of their realms and chats will be deleted immediately and any servers they have chatted with are asked to delete their copies of those chats. This does *not* prevent them from logging in again. They must also be removed from the authentication provider.
   ///
-  /// Only administrators can perform this command.
+  /// Players can erase themselves; only administrators can erase other players.
   PlayerReset {
     id: u32,
     player: S,
   },
 
-  /// Adds a new public key for this player to login with. If the name is already used, this key will replace it.
+  /// Adds a new public key for this player to login with. If the name or the key is already used, the request is rejected as redundant.
   PublicKeyAdd {
     id: u32,
+    name: S,
     der: B,
   },
   /// Removes a public key for this player to login with.
+  ///
+  /// Any tokens issued using this key are revoked and, if the current session logged in with it, the session is disconnected.
   PublicKeyDelete {
     id: u32,
     name: S,
@@ -190,6 +205,33 @@ pub enum ClientRequest<S: AsRef<str> + Eq + std::hash::Hash + Ord, B> {
   },
   /// List the names of all the keys a player can log in with
   PublicKeyList,
+  /// Check a code from the player's authenticator to activate the second factor started by [ClientRequest::SecondFactorEnrol]
+  ///
+  /// If successful, the server responds with a new set of recovery codes.
+  SecondFactorConfirm {
+    id: u32,
+    code: S,
+  },
+  /// Remove the player's second factor, so only a password is required to log in
+  ///
+  /// The code must be a current code from the player's authenticator or one of their recovery codes.
+  SecondFactorDisable {
+    id: u32,
+    code: S,
+  },
+  /// Start enrolling a time-based one-time password as a second factor for logging in
+  ///
+  /// The second factor is not required until it is confirmed. Enrolling again before confirming replaces the secret.
+  SecondFactorEnrol {
+    id: u32,
+  },
+  /// Replace the player's recovery codes with a new set
+  ///
+  /// The code must be a current code from the player's authenticator or one of their recovery codes.
+  SecondFactorRecoveryReset {
+    id: u32,
+    code: S,
+  },
   /// List the realms that we can access.
   LocationsList {
     id: u32,
@@ -343,6 +385,11 @@ pub enum ClientResponse<S: AsRef<str> + Eq + std::hash::Hash + Ord, B> {
     id: u32,
     result: UpdateResult,
   },
+  /// Everything the server stores about this player as a JSON document, or nothing if it could not be assembled
+  PlayerData {
+    id: u32,
+    data: Option<B>,
+  },
   /// The result of trying to reset a player's information
   PlayerReset {
     id: u32,
@@ -357,6 +404,22 @@ pub enum ClientResponse<S: AsRef<str> + Eq + std::hash::Hash + Ord, B> {
     id: u32,
     result: UpdateResult,
   },
+  /// The secret for a second factor being enrolled; the URL can be shown as a QR code for an authenticator application
+  SecondFactorEnrolment {
+    id: u32,
+    secret: S,
+    url: S,
+  },
+  /// Single-use codes that can be used instead of a code from the authenticator if the player loses it
+  SecondFactorRecoveryCodes {
+    id: u32,
+    codes: Vec<S>,
+  },
+  /// Whether changing the player's second factor was successful
+  SecondFactorUpdate {
+    id: u32,
+    result: UpdateResult,
+  },
   LocationVisibility {
     id: u32,
     result: UpdateResult,
@@ -1,3 +1,5 @@
+pub mod navigation;
+pub mod room_positions;
 pub mod room_world;
 
 use crate::reference_converter::{Converter, Referencer};
@@ -0,0 +1,108 @@
+use crate::realm::room_world::{Edge, Tile, World};
+use crate::realm::{Direction, Point};
+
+/// This is the time in ms it takes to walk one tile
+pub const STEP_TIME: u32 = 500;
+// The time in ms it takes to warp in/out
+pub const WARP_TIME: u32 = 800;
+// The time in ms it takes to touch/interact with an item
+pub const TOUCH_TIME: u32 = 1000;
+pub const ROTATE_TIME: u32 = 300;
+// The time in ms a player is befuddled after walking into a closed gate or touching nothing
+pub const CONFUSED_TIME: u32 = 700;
+
+/// The result of a player attempting to take a single step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+  /// The player cannot move in that direction
+  Blocked,
+  /// The player tried to walk into a gate that is currently closed for them
+  Gated,
+  /// The player crosses an edge into another room and will be facing the provided direction
+  Cross(Point, Direction),
+  /// The player walks to an adjacent tile in the same room
+  Walk(Point),
+}
+
+enum Access {
+  Blocked,
+  Gated,
+  Open,
+}
+
+fn access<Area, InputIdentifier, OutputIdentifier, Setting: AsRef<str> + Ord>(
+  world: &World<Area, InputIdentifier, OutputIdentifier, Setting>,
+  position: &Point,
+  gate: impl Fn(&OutputIdentifier) -> bool,
+) -> Access {
+  let Some(room) = world.rooms.get(position.platform as usize) else {
+    return Access::Blocked;
+  };
+  if !room.contains(position.x, position.y) {
+    return Access::Blocked;
+  }
+  match room.tile(position.x, position.y) {
+    None | Some(Tile::Area(..)) => Access::Open,
+    Some(Tile::Solid(_)) | Some(Tile::Input(..)) => Access::Blocked,
+    Some(Tile::Gated(_, output)) => {
+      if gate(output) {
+        Access::Open
+      } else {
+        Access::Gated
+      }
+    }
+  }
+}
+
+/// Find the tile a player is facing, if it is in the same room
+pub fn facing<'a, Area, InputIdentifier, OutputIdentifier, Setting: AsRef<str> + Ord>(
+  world: &'a World<Area, InputIdentifier, OutputIdentifier, Setting>,
+  position: &Point,
+  direction: Direction,
+) -> Option<&'a Tile<Area, InputIdentifier, OutputIdentifier, Setting>> {
+  let target = position.neighbour(direction)?;
+  world.rooms.get(target.platform as usize)?.tile(target.x, target.y)
+}
+
+/// Determine if a player can occupy a position
+///
+/// The `gate` function determines if a gated tile is open for this player
+pub fn is_passable<Area, InputIdentifier, OutputIdentifier, Setting: AsRef<str> + Ord>(
+  world: &World<Area, InputIdentifier, OutputIdentifier, Setting>,
+  position: &Point,
+  gate: impl Fn(&OutputIdentifier) -> bool,
+) -> bool {
+  matches!(access(world, position, gate), Access::Open)
+}
+
+/// Determine where a player will end up if they take one step in the direction provided
+///
+/// Walking off the side of a room is only possible if the room has an edge connected to another room at that point.
+pub fn step<Area, InputIdentifier, OutputIdentifier, Setting: AsRef<str> + Ord>(
+  world: &World<Area, InputIdentifier, OutputIdentifier, Setting>,
+  position: &Point,
+  direction: Direction,
+  gate: impl Fn(&OutputIdentifier) -> bool,
+) -> Step {
+  let Some(room) = world.rooms.get(position.platform as usize) else {
+    return Step::Blocked;
+  };
+  let (target, crossing) = match position.neighbour(direction).filter(|target| room.contains(target.x, target.y)) {
+    Some(target) => (target, None),
+    None => {
+      let Some((target_room, target_edge)) = Edge::exit(&room.size, position.x, position.y, direction).and_then(|edge| room.edge.get(&edge)) else {
+        return Step::Blocked;
+      };
+      let Some(room) = world.rooms.get(*target_room as usize) else {
+        return Step::Blocked;
+      };
+      (target_edge.entry(*target_room as u32, &room.size), Some(target_edge.inward()))
+    }
+  };
+  match (access(world, &target, gate), crossing) {
+    (Access::Blocked, _) => Step::Blocked,
+    (Access::Gated, _) => Step::Gated,
+    (Access::Open, None) => Step::Walk(target),
+    (Access::Open, Some(direction)) => Step::Cross(target, direction),
+  }
+}
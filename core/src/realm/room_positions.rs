@@ -0,0 +1,527 @@
+use crate::avatar::Effect;
+use crate::controller::puzzle::area::{PlayerStateCondition, PlayerStateModification};
+use crate::controller::puzzle::{MultiStateValue, PlayerPositions, PositionEvent, Value};
+use crate::controller::{ControllerOutput, PlayerKind};
+use crate::player::PlayerIdentifier;
+use crate::realm::navigation;
+use crate::realm::navigation::{Step, CONFUSED_TIME, ROTATE_TIME, STEP_TIME, TOUCH_TIME, WARP_TIME};
+use crate::realm::room_world::{Tile, World};
+use crate::realm::{
+  Action, CharacterAnimation, CharacterMotion, Direction, PlayerState, PlayerStates, Point, PropertyKey, PropertyValue, RealmRequest, RealmResponse,
+};
+use crate::reference_converter::AsArc;
+use crate::UpdateResult;
+use chrono::{DateTime, Utc};
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Tracks players and their state bits as they move around the rooms of a room world
+pub struct RoomPositions<Area, InputIdentifier, OutputIdentifier, Setting: AsRef<str> + Ord> {
+  changed: bool,
+  events: Vec<PositionEvent<InputIdentifier, Area>>,
+  outputs: BTreeMap<OutputIdentifier, MultiStateValue>,
+  players: BTreeMap<u32, RoomPlayer>,
+  random: SmallRng,
+  world: Arc<World<Area, InputIdentifier, OutputIdentifier, Setting>>,
+}
+
+#[derive(Debug)]
+pub enum RoomPositionError {
+  NoArea,
+  NoSpawn,
+  UnknownPlayer(u32),
+}
+
+struct RoomPlayer {
+  /// The positions the player's queued motion will take them to and when they get there
+  arrivals: VecDeque<(DateTime<Utc>, Point)>,
+  /// The time when the player will have finished all their queued motion
+  available: DateTime<Utc>,
+  direction: Direction,
+  motion: Vec<CharacterMotion<Point, Arc<str>>>,
+  /// The position the player has actually reached, which decides what area they are counted in; empty until they have entered
+  occupied: Option<Point>,
+  player: PlayerIdentifier<Arc<str>>,
+  /// The position the player will be in once their queued motion is finished
+  position: Point,
+  state: u32,
+}
+
+fn gate_open<OutputIdentifier: Ord>(outputs: &BTreeMap<OutputIdentifier, MultiStateValue>, output: &OutputIdentifier, state: u32) -> bool {
+  outputs.get(output).map(|value| bool::from(value.for_player(state))).unwrap_or(false)
+}
+
+fn millis(duration: u32) -> chrono::Duration {
+  chrono::Duration::milliseconds(duration.into())
+}
+
+impl RoomPlayer {
+  /// Queue a move to a new position that completes at the provided time
+  fn arrive(&mut self, end: DateTime<Utc>, position: Point) {
+    self.arrivals.push_back((end, position));
+    self.position = position;
+  }
+  /// Queue a motion that takes the provided time, returning when it starts and ends
+  fn queue(&mut self, now: DateTime<Utc>, duration: u32) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = self.available.max(now);
+    self.available = start + millis(duration);
+    (start, self.available)
+  }
+}
+
+impl<Area: Clone + Eq, InputIdentifier, OutputIdentifier: Ord, Setting: AsRef<str> + Ord>
+  RoomPositions<Area, InputIdentifier, OutputIdentifier, Setting>
+{
+  /// Find the area, if any, a player is counted in
+  fn area_of<'a>(&'a self, player: &RoomPlayer) -> Option<&'a Area> {
+    player.occupied.as_ref().and_then(|position| self.world.area_at(position))
+  }
+  /// Drop a player's position and state so they no longer occupy the room, returning whether the player was present
+  fn depart(&mut self, player_id: u32) -> bool {
+    match self.players.remove(&player_id) {
+      Some(player) => {
+        if let Some(area) = self.area_of(&player).cloned() {
+          self.events.push(PositionEvent::Area(area));
+        }
+        self.changed = true;
+        true
+      }
+      None => false,
+    }
+  }
+  fn spawn_point(&mut self) -> Option<Point> {
+    let room = self.world.rooms.get(0)?;
+    let candidates: Vec<_> = (0..room.size.0 as u32)
+      .flat_map(|x| (0..room.size.1 as u32).map(move |y| Point { platform: 0, x, y }))
+      .filter(|position| navigation::is_passable(&self.world, position, |output| gate_open(&self.outputs, output, 0)))
+      .collect();
+    candidates.choose(&mut self.random).copied()
+  }
+}
+
+impl<Area, InputIdentifier, OutputIdentifier, Setting> PlayerPositions for RoomPositions<Area, InputIdentifier, OutputIdentifier, Setting>
+where
+  Area: Clone + Eq + Send + Sync + 'static,
+  InputIdentifier: Clone + Display + Send + Sync + 'static,
+  OutputIdentifier: Clone + Ord + Display + Send + Sync + 'static,
+  Setting: AsRef<str> + Ord + Send + Sync + 'static,
+{
+  type Area = Area;
+  type Error = RoomPositionError;
+  type InputIdentifier = InputIdentifier;
+  type OutputIdentifier = OutputIdentifier;
+  type Request = RealmRequest<String>;
+  type Response = RealmResponse<Arc<str>>;
+  type Template = Arc<World<Area, InputIdentifier, OutputIdentifier, Setting>>;
+
+  fn create(template: &Self::Template, _now: DateTime<Utc>, seed: u32) -> Self {
+    RoomPositions {
+      changed: false,
+      events: Vec::new(),
+      outputs: BTreeMap::new(),
+      players: BTreeMap::new(),
+      random: SmallRng::seed_from_u64(seed as u64),
+      world: template.clone(),
+    }
+  }
+
+  fn add(
+    &mut self,
+    player_id: u32,
+    player: PlayerIdentifier<&str>,
+    now: DateTime<Utc>,
+  ) -> Result<Vec<ControllerOutput<Self::Response>>, Self::Error> {
+    let position = self.spawn_point().ok_or(RoomPositionError::NoSpawn)?;
+    let available = now + millis(WARP_TIME);
+    self.players.insert(
+      player_id,
+      RoomPlayer {
+        arrivals: VecDeque::from([(available, position)]),
+        available,
+        direction: Direction::YPos,
+        motion: vec![CharacterMotion::Enter { to: position, end: available }],
+        occupied: None,
+        player: player.convert(AsArc::<str>::default()),
+        position,
+        state: 0,
+      },
+    );
+    self.changed = true;
+    Ok(Vec::new())
+  }
+
+  fn area_states(&self, area: &Self::Area) -> Vec<u32> {
+    self.players.values().filter(|player| self.area_of(player) == Some(area)).map(|player| player.state).collect()
+  }
+
+  fn events(&mut self) -> Vec<PositionEvent<Self::InputIdentifier, Self::Area>> {
+    std::mem::take(&mut self.events)
+  }
+
+  fn leave(&mut self, player_id: u32, _now: DateTime<Utc>) -> Result<Vec<ControllerOutput<Self::Response>>, Self::Error> {
+    if self.depart(player_id) {
+      Ok(Vec::new())
+    } else {
+      Err(RoomPositionError::UnknownPlayer(player_id))
+    }
+  }
+
+  fn modify(&mut self, area: &Self::Area, condition: &PlayerStateCondition, modification: &PlayerStateModification) {
+    let mut modified = false;
+    for player in self.players.values_mut() {
+      if player.occupied.as_ref().and_then(|position| self.world.area_at(position)) == Some(area) && condition.check(player.state) {
+        let state = modification.apply(player.state);
+        if state != player.state {
+          player.state = state;
+          modified = true;
+        }
+      }
+    }
+    if modified {
+      self.events.push(PositionEvent::Area(area.clone()));
+      self.changed = true;
+    }
+  }
+
+  fn next_timer(&self) -> Option<Duration> {
+    let now = Utc::now();
+    self
+      .players
+      .values()
+      .flat_map(|player| player.arrivals.front())
+      .map(|(time, _)| *time)
+      .min()
+      .map(|time| (time - now).to_std().unwrap_or(Duration::ZERO))
+  }
+
+  fn perform(
+    &mut self,
+    player_id: u32,
+    _player_kind: PlayerKind,
+    request: Self::Request,
+    now: DateTime<Utc>,
+  ) -> Result<Vec<ControllerOutput<Self::Response>>, Self::Error> {
+    match request {
+      // Settings are part of the realm asset, so they cannot be changed in a running realm
+      RealmRequest::ChangeSetting { id, .. } => {
+        Ok(vec![ControllerOutput::Response { player: player_id, response: RealmResponse::SettingChange { id, result: UpdateResult::NotAllowed } }])
+      }
+      RealmRequest::Perform(actions) => {
+        let player = self.players.get_mut(&player_id).ok_or(RoomPositionError::UnknownPlayer(player_id))?;
+        let state = player.state;
+        for action in actions {
+          match action {
+            Action::Emote { animation, duration } => {
+              let (start, _) = player.queue(now, duration);
+              player.motion.push(CharacterMotion::DirectedEmote {
+                start,
+                animation: animation.convert(AsArc::<str>::default()),
+                direction: player.direction,
+                at: player.position,
+              });
+            }
+            Action::Interaction { target } => {
+              let input = navigation::facing(&self.world, &player.position, player.direction).and_then(|tile| match tile {
+                Tile::Input(_, input) if input.to_string() == target => Some(input),
+                _ => None,
+              });
+              let (animation, duration) = match input {
+                Some(input) => {
+                  self.events.push(PositionEvent::Click(input.clone(), state));
+                  (CharacterAnimation::Touch, TOUCH_TIME)
+                }
+                None => (CharacterAnimation::Confused, CONFUSED_TIME),
+              };
+              let (start, end) = player.queue(now, duration);
+              player.motion.push(CharacterMotion::Interaction { start, end, animation, at: player.position });
+            }
+            Action::Move { length } => {
+              for _ in 0..length {
+                match navigation::step(&self.world, &player.position, player.direction, |output| gate_open(&self.outputs, output, state)) {
+                  Step::Blocked => break,
+                  Step::Gated => {
+                    let (start, end) = player.queue(now, CONFUSED_TIME);
+                    player.motion.push(CharacterMotion::Interaction { start, end, animation: CharacterAnimation::Confused, at: player.position });
+                    break;
+                  }
+                  Step::Cross(next, direction) => {
+                    let (start, end) = player.queue(now, WARP_TIME);
+                    player.motion.push(CharacterMotion::Leave { from: player.position, start });
+                    player.motion.push(CharacterMotion::Enter { to: next, end });
+                    player.direction = direction;
+                    player.arrive(end, next);
+                  }
+                  Step::Walk(next) => {
+                    let (start, end) = player.queue(now, STEP_TIME);
+                    player.motion.push(CharacterMotion::Move { from: player.position, to: next, start, end, animation: CharacterAnimation::Walk });
+                    player.arrive(end, next);
+                  }
+                }
+              }
+            }
+            Action::Rotate { direction } => {
+              let (start, end) = player.queue(now, ROTATE_TIME);
+              player.motion.push(CharacterMotion::Rotate { at: player.position, start, end, direction });
+              player.direction = direction;
+            }
+          }
+        }
+        self.changed = true;
+        Ok(Vec::new())
+      }
+    }
+  }
+
+  fn remove(&mut self, area: &Self::Area, condition: &PlayerStateCondition) -> Vec<u32> {
+    let removed: Vec<_> =
+      self.players.iter().filter(|(_, player)| self.area_of(player) == Some(area) && condition.check(player.state)).map(|(&id, _)| id).collect();
+    for &id in &removed {
+      self.depart(id);
+    }
+    removed
+  }
+
+  fn set_states<'a>(&mut self, outputs: Box<dyn Iterator<Item = (&'a Self::OutputIdentifier, &'a MultiStateValue)> + 'a>) -> Result<(), Self::Error> {
+    self.outputs = outputs.map(|(id, value)| (id.clone(), value.clone())).collect();
+    self.changed = true;
+    Ok(())
+  }
+
+  fn transfer(&mut self, source: &Self::Area, condition: &PlayerStateCondition, target: &Self::Area, now: DateTime<Utc>) -> Result<(), Self::Error> {
+    let targets = self.world.area_points(target);
+    if targets.is_empty() {
+      return Err(RoomPositionError::NoArea);
+    }
+    for player in self.players.values_mut() {
+      if player.occupied.as_ref().and_then(|position| self.world.area_at(position)) == Some(source) && condition.check(player.state) {
+        let Some(&position) = targets.choose(&mut self.random) else {
+          continue;
+        };
+        let (start, end) = player.queue(now, WARP_TIME);
+        player.motion.push(CharacterMotion::Leave { from: player.position, start });
+        player.motion.push(CharacterMotion::Enter { to: position, end });
+        player.arrive(end, position);
+        self.changed = true;
+      }
+    }
+    Ok(())
+  }
+
+  fn update(&mut self, now: DateTime<Utc>) -> Result<Vec<ControllerOutput<Self::Response>>, Self::Error> {
+    // Players only count as being in an area once they have arrived there; clients already have the motion, so this needs no new state
+    for player in self.players.values_mut() {
+      while let Some(&(time, position)) = player.arrivals.front() {
+        if time > now {
+          break;
+        }
+        player.arrivals.pop_front();
+        let old_area = player.occupied.as_ref().and_then(|position| self.world.area_at(position));
+        let new_area = self.world.area_at(&position);
+        if old_area != new_area {
+          self.events.extend(old_area.into_iter().chain(new_area).cloned().map(PositionEvent::Area));
+        }
+        player.occupied = Some(position);
+      }
+    }
+    if !std::mem::take(&mut self.changed) {
+      return Ok(Vec::new());
+    }
+    let player_states: PlayerStates<Arc<str>> = self
+      .players
+      .values_mut()
+      .map(|player| {
+        (
+          player.player.clone(),
+          PlayerState {
+            effect: Effect::Normal,
+            final_direction: player.direction,
+            final_position: player.position,
+            motion: std::mem::take(&mut player.motion),
+          },
+        )
+      })
+      .collect();
+    Ok(
+      self
+        .players
+        .iter()
+        .map(|(&id, player)| ControllerOutput::Response {
+          player: id,
+          response: RealmResponse::UpdateState {
+            time: now,
+            player: player_states.clone(),
+            state: self
+              .outputs
+              .iter()
+              .map(|(output, value)| match value.for_player(player.state) {
+                Value::Bool(value) => (PropertyKey::BoolSink(Arc::from(output.to_string())), PropertyValue::Bool(value)),
+                Value::Num(value) => (PropertyKey::NumSink(Arc::from(output.to_string())), PropertyValue::Num(value)),
+              })
+              .collect(),
+          },
+        })
+        .collect(),
+    )
+  }
+}
+
+impl Display for RoomPositionError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RoomPositionError::NoArea => f.write_str("Target area has no tiles"),
+      RoomPositionError::NoSpawn => f.write_str("No open tiles in the first room to place player"),
+      RoomPositionError::UnknownPlayer(id) => {
+        f.write_str("Unknown player #")?;
+        Display::fmt(id, f)
+      }
+    }
+  }
+}
+
+impl Error for RoomPositionError {}
+
+#[cfg(test)]
+mod tests {
+  use super::RoomPositions;
+  use crate::controller::puzzle::area::{PlayerStateCondition, PlayerStateModification};
+  use crate::controller::puzzle::state_machine::StateMachinePuzzleTemplate;
+  use crate::controller::puzzle::{PlayerPositions, PositionEvent};
+  use crate::player::PlayerIdentifier;
+  use crate::realm::room_world::{Room, Tile, World};
+  use crate::scene::value::{GlobalValue, LocalDiscreteValue};
+  use crate::scene::Color;
+  use chrono::{DateTime, Duration, Utc};
+  use std::collections::BTreeMap;
+  use std::sync::Arc;
+
+  const FLOOR: Color = Color::Rgb(0, 0, 0);
+
+  fn room(size: (u16, u16), area: u16) -> Room<u16, u16, u16, String> {
+    Room {
+      size,
+      background: GlobalValue::Fixed(FLOOR),
+      tiles: (0..size.0)
+        .flat_map(|x| (0..size.1).map(move |y| ((x, y), Tile::Area(LocalDiscreteValue::Global(GlobalValue::Fixed(FLOOR)), area))))
+        .collect(),
+      edge: BTreeMap::new(),
+    }
+  }
+
+  /// Create positions for a world where players always enter in area 0 and area 1 is a separate room
+  fn positions() -> RoomPositions<u16, u16, u16, String> {
+    let world = World {
+      actions: Vec::new(),
+      rooms: vec![room((1, 1), 0), room((2, 1), 1)],
+      puzzle: StateMachinePuzzleTemplate { machines: Vec::new(), variables: Vec::new() },
+      settings: BTreeMap::new(),
+    };
+    RoomPositions::create(&Arc::new(world), Utc::now(), 0)
+  }
+
+  fn has_area_event(events: &[PositionEvent<u16, u16>], area: u16) -> bool {
+    events.iter().any(|event| matches!(event, PositionEvent::Area(a) if *a == area))
+  }
+
+  /// Add players to area 0 and wait for them to arrive; the first player gets state bit 0 set
+  fn populate(positions: &mut RoomPositions<u16, u16, u16, String>, now: DateTime<Utc>) -> DateTime<Utc> {
+    positions.add(1, PlayerIdentifier::Local("alice"), now).unwrap();
+    let now = now + Duration::seconds(1);
+    positions.update(now).unwrap();
+    positions.modify(&0, &PlayerStateCondition::Always, &PlayerStateModification::SetBit(0));
+    positions.add(2, PlayerIdentifier::Local("bob"), now).unwrap();
+    let now = now + Duration::seconds(1);
+    positions.update(now).unwrap();
+    positions.events();
+    now
+  }
+
+  #[test]
+  fn players_count_once_they_arrive() {
+    let mut positions = positions();
+    let now = Utc::now();
+    positions.add(1, PlayerIdentifier::Local("alice"), now).unwrap();
+    assert!(positions.next_timer().is_some());
+    positions.update(now).unwrap();
+    assert!(positions.area_states(&0).is_empty());
+    assert!(positions.events().is_empty());
+
+    positions.update(now + Duration::seconds(1)).unwrap();
+    assert_eq!(positions.area_states(&0), vec![0]);
+    assert!(has_area_event(&positions.events(), 0));
+    assert_eq!(positions.next_timer(), None);
+
+    positions.leave(1, now).unwrap();
+    assert!(positions.area_states(&0).is_empty());
+    assert!(has_area_event(&positions.events(), 0));
+  }
+
+  #[test]
+  fn next_timer_is_earliest_arrival() {
+    let mut positions = positions();
+    let now = Utc::now();
+    positions.add(1, PlayerIdentifier::Local("alice"), now + Duration::seconds(60)).unwrap();
+    positions.add(2, PlayerIdentifier::Local("bob"), now).unwrap();
+    let timer = positions.next_timer().unwrap();
+    assert!(timer <= std::time::Duration::from_secs(1), "Timer is for later player: {:?}", timer);
+  }
+
+  #[test]
+  fn remove_matches_condition() {
+    let mut positions = positions();
+    populate(&mut positions, Utc::now());
+    let mut states = positions.area_states(&0);
+    states.sort();
+    assert_eq!(states, vec![0, 1]);
+
+    assert!(positions.remove(&1, &PlayerStateCondition::Always).is_empty());
+    assert_eq!(positions.remove(&0, &PlayerStateCondition::HasBit(0)), vec![1]);
+    assert_eq!(positions.area_states(&0), vec![0]);
+    assert!(has_area_event(&positions.events(), 0));
+    assert!(positions.leave(1, Utc::now()).is_err());
+  }
+
+  #[test]
+  fn transfer_matches_condition() {
+    let mut positions = positions();
+    let now = populate(&mut positions, Utc::now());
+    positions.transfer(&0, &PlayerStateCondition::NotHasBit(0), &1, now).unwrap();
+    // Players are still in the source area until they have warped to the target
+    assert_eq!(positions.area_states(&0).len(), 2);
+    assert!(positions.area_states(&1).is_empty());
+
+    positions.update(now + Duration::seconds(1)).unwrap();
+    assert_eq!(positions.area_states(&0), vec![1]);
+    assert_eq!(positions.area_states(&1), vec![0]);
+    let events = positions.events();
+    assert!(has_area_event(&events, 0));
+    assert!(has_area_event(&events, 1));
+  }
+
+  #[test]
+  fn transfer_to_missing_area_fails() {
+    let mut positions = positions();
+    let now = populate(&mut positions, Utc::now());
+    assert!(positions.transfer(&0, &PlayerStateCondition::Always, &7, now).is_err());
+    assert_eq!(positions.area_states(&0).len(), 2);
+  }
+
+  #[test]
+  fn modify_matches_condition() {
+    let mut positions = positions();
+    populate(&mut positions, Utc::now());
+    positions.modify(&0, &PlayerStateCondition::NotHasBit(0), &PlayerStateModification::SetBit(3));
+    let mut states = positions.area_states(&0);
+    states.sort();
+    assert_eq!(states, vec![1, 8]);
+    assert!(has_area_event(&positions.events(), 0));
+
+    positions.modify(&1, &PlayerStateCondition::Always, &PlayerStateModification::ClearAll);
+    assert!(positions.events().is_empty());
+  }
+}
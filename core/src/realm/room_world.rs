@@ -1,14 +1,19 @@
 use crate::asset::extraction::ExtractChildren;
-use crate::controller::puzzle::state_machine::StateMachinePuzzleTemplate;
-use crate::realm::RealmSettings;
+use crate::controller::puzzle::action::{Action, LinkTarget};
+use crate::controller::puzzle::state_machine::{StateMachinePuzzleTemplate, StateMachinePuzzleTemplateSource};
+use crate::location::target::{AbsoluteTarget, UnresolvedTarget};
+use crate::location::Descriptor;
+use crate::realm::{Direction, Point, RealmSetting, RealmSettings};
+use crate::reference_converter::{AsArc, AsReference};
 use crate::scene::value::{GlobalValue, LocalDiscreteValue};
 use crate::scene::Color;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
+use std::sync::Arc;
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Room<Area, InputIdentifier, OutputIdentifier, Setting> {
   pub size: (u16, u16),
   pub background: GlobalValue<Color, OutputIdentifier, Setting>,
@@ -16,7 +21,7 @@ pub struct Room<Area, InputIdentifier, OutputIdentifier, Setting> {
   pub edge: BTreeMap<Edge, (u8, Edge)>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Tile<Area, InputIdentifier, OutputIdentifier, Setting> {
   Solid(LocalDiscreteValue<Color, OutputIdentifier, Setting>),
   Gated(LocalDiscreteValue<Color, OutputIdentifier, Setting>, OutputIdentifier),
@@ -32,28 +37,157 @@ pub enum Edge {
   YM(u16),
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct World<Area, InputIdentifier, OutputIdentifier, Setting: AsRef<str> + Ord> {
+  /// Actions performed on the players when a puzzle output becomes true
+  #[serde(default)]
+  pub actions: Vec<(OutputIdentifier, Action<Area, Setting>)>,
   pub rooms: Vec<Room<Area, InputIdentifier, OutputIdentifier, Setting>>,
   pub puzzle: StateMachinePuzzleTemplate<InputIdentifier, OutputIdentifier, Area>,
   pub settings: RealmSettings<Setting>,
 }
 
 impl Edge {
+  /// Find the edge, if any, that a player would cross by walking out of a room from a position
+  pub fn exit(size: &(u16, u16), x: u32, y: u32, direction: Direction) -> Option<Edge> {
+    let x = u16::try_from(x).ok()?;
+    let y = u16::try_from(y).ok()?;
+    let edge = match direction {
+      Direction::XNeg if x == 0 => Edge::X0(y),
+      Direction::XPos if x.checked_add(1) == Some(size.0) => Edge::XM(y),
+      Direction::YNeg if y == 0 => Edge::Y0(x),
+      Direction::YPos if y.checked_add(1) == Some(size.1) => Edge::YM(x),
+      _ => return None,
+    };
+    if edge.in_bounds(size) {
+      Some(edge)
+    } else {
+      None
+    }
+  }
+  /// The position just inside a room where a player arrives when crossing this edge
+  pub fn entry(&self, platform: u32, size: &(u16, u16)) -> Point {
+    match *self {
+      Edge::X0(y) => Point { platform, x: 0, y: y as u32 },
+      Edge::XM(y) => Point { platform, x: size.0.saturating_sub(1) as u32, y: y as u32 },
+      Edge::Y0(x) => Point { platform, x: x as u32, y: 0 },
+      Edge::YM(x) => Point { platform, x: x as u32, y: size.1.saturating_sub(1) as u32 },
+    }
+  }
   pub fn in_bounds(&self, size: &(u16, u16)) -> bool {
     match self {
       &Edge::X0(v) | &Edge::XM(v) => v < size.1,
       &Edge::Y0(v) | &Edge::YM(v) => v < size.0,
     }
   }
+  /// The direction a player faces after entering a room through this edge
+  pub fn inward(&self) -> Direction {
+    match self {
+      Edge::X0(_) => Direction::XPos,
+      Edge::XM(_) => Direction::XNeg,
+      Edge::Y0(_) => Direction::YPos,
+      Edge::YM(_) => Direction::YNeg,
+    }
+  }
+}
+
+impl<Area, InputIdentifier, OutputIdentifier, Setting> Room<Area, InputIdentifier, OutputIdentifier, Setting> {
+  /// Check if a position is inside the room
+  pub fn contains(&self, x: u32, y: u32) -> bool {
+    x < self.size.0 as u32 && y < self.size.1 as u32
+  }
+  /// Get the tile at a position; positions without a tile are open floor
+  pub fn tile(&self, x: u32, y: u32) -> Option<&Tile<Area, InputIdentifier, OutputIdentifier, Setting>> {
+    self.tiles.get(&(u16::try_from(x).ok()?, u16::try_from(y).ok()?))
+  }
 }
 
-impl<S: AsRef<str> + Ord, Area, InputIdentifier, OutputIdentifier, Setting: AsRef<str> + Ord> ExtractChildren<S>
-  for World<Area, InputIdentifier, OutputIdentifier, Setting>
+impl<Area, InputIdentifier, OutputIdentifier, Setting: AsRef<str> + Ord> World<Area, InputIdentifier, OutputIdentifier, Setting> {
+  /// Find the area, if any, that a position is in
+  pub fn area_at(&self, position: &Point) -> Option<&Area> {
+    match self.rooms.get(position.platform as usize)?.tile(position.x, position.y)? {
+      Tile::Area(_, area) => Some(area),
+      _ => None,
+    }
+  }
+  /// Find all the positions that are part of an area
+  pub fn area_points(&self, area: &Area) -> Vec<Point>
+  where
+    Area: Eq,
+  {
+    self
+      .rooms
+      .iter()
+      .enumerate()
+      .flat_map(|(index, room)| {
+        room.tiles.iter().filter_map(move |(&(x, y), tile)| match tile {
+          Tile::Area(_, a) if a == area => Some(Point { platform: index as u32, x: x as u32, y: y as u32 }),
+          _ => None,
+        })
+      })
+      .collect()
+  }
+  /// Replace the settings that actions link to with the realms they point at, so the actions can be performed without the settings
+  pub fn resolve_actions(&self) -> Vec<(OutputIdentifier, Action<Area, UnresolvedTarget<Arc<str>>>)>
+  where
+    Area: Clone,
+    OutputIdentifier: Clone,
+  {
+    self
+      .actions
+      .iter()
+      .map(|(output, action)| {
+        let action = match action {
+          Action::Link { from, matches, target } => Action::Link {
+            from: from.clone(),
+            matches: matches.clone(),
+            target: match target {
+              LinkTarget::Next => LinkTarget::Next,
+              LinkTarget::Home => LinkTarget::Home,
+              LinkTarget::Setting(setting) => LinkTarget::Setting(match self.settings.get(setting) {
+                Some(RealmSetting::Realm(target)) => target.reference(AsReference::<str>::default()).convert(AsArc::<str>::default()),
+                _ => UnresolvedTarget::NoWhere,
+              }),
+            },
+          },
+          Action::Move { from, to, matches } => Action::Move { from: from.clone(), to: to.clone(), matches: matches.clone() },
+          Action::Mark { location, matches, modification } => {
+            Action::Mark { location: location.clone(), matches: matches.clone(), modification: modification.clone() }
+          }
+        };
+        (output.clone(), action)
+      })
+      .collect()
+  }
+}
+
+impl<Area: Send + 'static, InputIdentifier: Send + 'static, OutputIdentifier: Send + 'static, Setting: AsRef<str> + Ord + Send + 'static>
+  StateMachinePuzzleTemplateSource for World<Area, InputIdentifier, OutputIdentifier, Setting>
 {
-  fn extract_children(&self, _assets: &mut BTreeSet<S>) {}
+  type InputIdentifier = InputIdentifier;
+  type OutputIdentifier = OutputIdentifier;
+  type Area = Area;
+
+  fn template(&self) -> &StateMachinePuzzleTemplate<Self::InputIdentifier, Self::OutputIdentifier, Self::Area> {
+    &self.puzzle
+  }
+}
+
+impl<S: AsRef<str> + Ord + Clone, Area, InputIdentifier, OutputIdentifier> ExtractChildren<S> for World<Area, InputIdentifier, OutputIdentifier, S> {
+  fn extract_children(&self, assets: &mut BTreeSet<S>) {
+    // Realms linked from settings are referenced by asset, so they must be available for those links to work
+    for setting in self.settings.values() {
+      match setting {
+        RealmSetting::Realm(UnresolvedTarget::Personal { asset })
+        | RealmSetting::Realm(UnresolvedTarget::Absolute(AbsoluteTarget { descriptor: Descriptor::Asset(asset), .. })) => {
+          assets.insert(asset.clone());
+        }
+        _ => (),
+      }
+    }
+  }
 }
-impl<Area: Ord + Display, InputIdentifier: Ord + Display, OutputIdentifier: Ord + Display, Setting: Display + Ord + AsRef<str>>
+impl<Area: Ord + Display, InputIdentifier: Ord + Display, OutputIdentifier: Ord + Display, Setting: Ord + AsRef<str>>
   World<Area, InputIdentifier, OutputIdentifier, Setting>
 {
   pub fn validate(&self) -> Result<(), Cow<'static, str>> {
@@ -76,25 +210,45 @@ impl<Area: Ord + Display, InputIdentifier: Ord + Display, OutputIdentifier: Ord
             index, target, target_edge, target_room.size.0, target_room.size.1
           )));
         }
-        for ((x, y), tile) in &room.tiles {
-          if *x > room.size.0 || *y > room.size.1 {
-            return Err(Cow::Owned(format!("Room {} has tile at ({}, {}) which is out of bounds ({}, {})", index, x, y, room.size.0, room.size.1)));
+      }
+      for ((x, y), tile) in &room.tiles {
+        if *x >= room.size.0 || *y >= room.size.1 {
+          return Err(Cow::Owned(format!("Room {} has tile at ({}, {}) which is out of bounds ({}, {})", index, x, y, room.size.0, room.size.1)));
+        }
+        match tile {
+          Tile::Solid(c) => c.validate(&mut outputs, &mut settings)?,
+          Tile::Gated(c, o) => {
+            c.validate(&mut outputs, &mut settings)?;
+            outputs.insert(o);
           }
-          match tile {
-            Tile::Solid(c) => c.validate(&mut outputs, &mut settings)?,
-            Tile::Gated(c, o) => {
-              c.validate(&mut outputs, &mut settings)?;
-              outputs.insert(o);
-            }
-            Tile::Input(c, i) => {
-              c.validate(&mut outputs, &mut settings)?;
-              inputs.insert(i);
-            }
-            Tile::Area(c, a) => {
-              c.validate(&mut outputs, &mut settings)?;
-              areas.insert(a);
+          Tile::Input(c, i) => {
+            c.validate(&mut outputs, &mut settings)?;
+            inputs.insert(i);
+          }
+          Tile::Area(c, a) => {
+            c.validate(&mut outputs, &mut settings)?;
+            areas.insert(a);
+          }
+        }
+      }
+    }
+    for (output, action) in &self.actions {
+      outputs.insert(output);
+      let used_areas = match action {
+        Action::Link { from, target, .. } => {
+          if let LinkTarget::Setting(setting) = target {
+            if !matches!(self.settings.get(setting), Some(RealmSetting::Realm(_))) {
+              return Err(Cow::Owned(format!("Action for {} links to setting {}, which is not a realm", output, setting.as_ref())));
             }
           }
+          vec![from]
+        }
+        Action::Move { from, to, .. } => vec![from, to],
+        Action::Mark { location, .. } => vec![location],
+      };
+      for area in used_areas {
+        if !areas.contains(area) {
+          return Err(Cow::Owned(format!("Action for {} uses area {}, but this does not exist in the world", output, area)));
         }
       }
     }
@@ -107,3 +261,130 @@ impl<Area: Ord + Display, InputIdentifier: Ord + Display, OutputIdentifier: Ord
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{Edge, Room, Tile, World};
+  use crate::controller::puzzle::action::{Action, LinkTarget};
+  use crate::controller::puzzle::area::PlayerStateCondition;
+  use crate::controller::puzzle::state_machine::text::parse;
+  use crate::realm::RealmSetting;
+  use crate::scene::value::{GlobalValue, LocalDiscreteValue};
+  use crate::scene::Color;
+  use std::collections::BTreeMap;
+
+  const FLOOR: Color = Color::Rgb(0, 0, 0);
+
+  fn tile_area(name: &str) -> Tile<String, String, String, String> {
+    Tile::Area(LocalDiscreteValue::Global(GlobalValue::Fixed(FLOOR)), name.to_string())
+  }
+
+  /// Create a world with an entrance room that leads to a room containing a hall, which the puzzle counts
+  fn world() -> World<String, String, String, String> {
+    World {
+      actions: Vec::new(),
+      rooms: vec![
+        Room { size: (2, 2), background: GlobalValue::Fixed(FLOOR), tiles: BTreeMap::new(), edge: BTreeMap::from([(Edge::XM(1), (1, Edge::X0(0)))]) },
+        Room { size: (3, 1), background: GlobalValue::Fixed(FLOOR), tiles: BTreeMap::from([((2, 0), tile_area("hall"))]), edge: BTreeMap::new() },
+      ],
+      puzzle: parse(
+        r#"
+machine hall {
+  outputs occupied;
+
+  state empty {
+    output occupied = false;
+    on count(hall) if players(always) > 0 -> full;
+  }
+  state full {
+    output occupied = true;
+    on count(hall) if players(always) == 0 -> empty;
+  }
+}
+"#,
+      )
+      .unwrap(),
+      settings: BTreeMap::new(),
+    }
+  }
+
+  fn assert_invalid(world: &World<String, String, String, String>, message: &str) {
+    match world.validate() {
+      Ok(()) => panic!("World is valid, but expected: {}", message),
+      Err(e) => assert!(e.contains(message), "Expected error containing {:?}, but got {:?}", message, e),
+    }
+  }
+
+  #[test]
+  fn valid() {
+    world().validate().unwrap();
+  }
+
+  #[test]
+  fn tile_out_of_bounds() {
+    let mut world = world();
+    world.rooms[1].tiles.insert((3, 0), tile_area("hall"));
+    assert_invalid(&world, "tile at (3, 0)");
+    let mut world = self::world();
+    world.rooms[1].tiles.insert((0, 1), tile_area("hall"));
+    assert_invalid(&world, "tile at (0, 1)");
+  }
+
+  #[test]
+  fn edge_out_of_bounds() {
+    let mut world = world();
+    world.rooms[0].edge.insert(Edge::YM(2), (1, Edge::X0(0)));
+    assert_invalid(&world, "source is out of bounds");
+  }
+
+  #[test]
+  fn edge_to_missing_room() {
+    let mut world = world();
+    world.rooms[0].edge.insert(Edge::Y0(0), (2, Edge::X0(0)));
+    assert_invalid(&world, "room 2, which is not present");
+  }
+
+  #[test]
+  fn edge_target_out_of_bounds() {
+    let mut world = world();
+    world.rooms[0].edge.insert(Edge::Y0(0), (1, Edge::X0(1)));
+    assert_invalid(&world, "target (X0(1)) is out of bounds");
+  }
+
+  #[test]
+  fn puzzle_area_missing() {
+    let mut world = world();
+    world.rooms[1].tiles.clear();
+    assert_invalid(&world, "expects area hall");
+  }
+
+  #[test]
+  fn setting_missing() {
+    let mut world = world();
+    world.rooms[1].background = GlobalValue::Setting("sky".to_string());
+    assert_invalid(&world, "Setting sky is used but not defined");
+    world.settings.insert("sky".to_string(), RealmSetting::Color(FLOOR));
+    world.validate().unwrap();
+  }
+
+  #[test]
+  fn action_area_missing() {
+    let mut world = world();
+    world
+      .actions
+      .push(("occupied".to_string(), Action::Move { from: "hall".to_string(), to: "yard".to_string(), matches: PlayerStateCondition::Always }));
+    assert_invalid(&world, "uses area yard");
+  }
+
+  #[test]
+  fn action_link_not_realm() {
+    let mut world = world();
+    world.actions.push((
+      "occupied".to_string(),
+      Action::Link { from: "hall".to_string(), matches: PlayerStateCondition::Always, target: LinkTarget::Setting("next".to_string()) },
+    ));
+    assert_invalid(&world, "links to setting next");
+    world.settings.insert("next".to_string(), RealmSetting::Bool(true));
+    assert_invalid(&world, "links to setting next");
+  }
+}
@@ -0,0 +1,4 @@
+CREATE TABLE token_generation (
+    name text PRIMARY KEY NOT NULL,
+    generation int NOT NULL DEFAULT 0
+);
@@ -0,0 +1,2 @@
+DROP INDEX public_key_label;
+ALTER TABLE public_key DROP COLUMN label;
@@ -0,0 +1,3 @@
+ALTER TABLE public_key ADD COLUMN label text NOT NULL DEFAULT '';
+UPDATE public_key SET label = fingerprint;
+CREATE UNIQUE INDEX public_key_label ON public_key (player, label);
@@ -0,0 +1,2 @@
+DROP INDEX location_kind;
+ALTER TABLE location DROP COLUMN kind;
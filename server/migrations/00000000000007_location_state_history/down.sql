@@ -0,0 +1,2 @@
+DROP TABLE location_state_restore;
+DROP TABLE location_state_history;
@@ -0,0 +1,21 @@
+CREATE TABLE location_state_history (
+    id integer PRIMARY KEY NOT NULL,
+    location int NOT NULL,
+    state blob NOT NULL,
+    reason smallint NOT NULL,
+    created timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT location_state_history_location_id FOREIGN KEY (location) REFERENCES location (id)
+);
+
+CREATE INDEX location_state_history_by_timestamp ON location_state_history (location, created);
+
+CREATE TABLE location_state_restore (
+    id integer PRIMARY KEY NOT NULL,
+    location int NOT NULL,
+    principal blob NOT NULL,
+    snapshot timestamp NOT NULL,
+    created timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT location_state_restore_location_id FOREIGN KEY (location) REFERENCES location (id)
+);
+
+CREATE INDEX location_state_restore_by_timestamp ON location_state_restore (location, created);
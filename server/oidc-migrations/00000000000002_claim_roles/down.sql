@@ -0,0 +1,2 @@
+ALTER TABLE auth_oidc DROP COLUMN creator;
+ALTER TABLE auth_oidc DROP COLUMN admin;
@@ -0,0 +1,2 @@
+ALTER TABLE auth_oidc ADD COLUMN admin boolean NOT NULL DEFAULT false;
+ALTER TABLE auth_oidc ADD COLUMN creator boolean NOT NULL DEFAULT false;
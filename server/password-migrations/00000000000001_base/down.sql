@@ -0,0 +1,2 @@
+DROP TABLE auth_password_invitation;
+DROP TABLE auth_password;
@@ -0,0 +1,10 @@
+CREATE TABLE auth_password (
+    name text NOT NULL,
+    hash text NOT NULL,
+    locked boolean NOT NULL DEFAULT false,
+    PRIMARY KEY (name)
+);
+CREATE TABLE auth_password_invitation (
+    code text NOT NULL,
+    PRIMARY KEY (code)
+);
@@ -0,0 +1,4 @@
+CREATE TABLE auth_password_invitation (
+    code text NOT NULL,
+    PRIMARY KEY (code)
+);
@@ -0,0 +1,17 @@
+DROP VIEW player_size;
+
+DROP TABLE calendar_cache;
+DROP TABLE public_key;
+DROP TABLE bookmark;
+DROP TABLE server_setting;
+DROP TABLE location_calendar_subscription;
+DROP TABLE remote_calendar_subscription;
+DROP TABLE location_announcement;
+DROP TABLE location_chat;
+DROP TABLE location;
+DROP TABLE local_player_chat;
+DROP TABLE local_player_last_read;
+DROP TABLE remote_player_chat;
+DROP TABLE remote_player_last_read;
+DROP TABLE player;
+DROP TABLE announcement;
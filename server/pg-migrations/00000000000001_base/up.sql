@@ -0,0 +1,165 @@
+CREATE TABLE announcement (
+    id serial PRIMARY KEY,
+    title text NOT NULL,
+    body text NOT NULL,
+    "when" bytea NOT NULL,
+    location bytea NOT NULL,
+    "public" boolean NOT NULL
+);
+
+CREATE TABLE player (
+    id serial PRIMARY KEY,
+    name text NOT NULL,
+    avatar bytea NOT NULL,
+    message_acl bytea NOT NULL,
+    online_acl bytea NOT NULL,
+    default_location_acl bytea NOT NULL,
+    reset boolean NOT NULL DEFAULT FALSE,
+    calendar_id bytea NOT NULL,
+    last_login timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    created timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE INDEX player_name ON player(name);
+CREATE INDEX player_calendar_id ON player(calendar_id);
+
+CREATE TABLE local_player_chat (
+    sender int NOT NULL,
+    recipient int NOT NULL,
+    created timestamp NOT NULL,
+    body bytea NOT NULL,
+    PRIMARY KEY (sender, recipient, created),
+    CONSTRAINT local_playerchat_sender_player_id FOREIGN KEY (sender) REFERENCES player (id),
+    CONSTRAINT local_playerchat_recipient_player_id FOREIGN KEY (recipient) REFERENCES player (id)
+);
+
+CREATE INDEX localplayerchat_recipient ON local_player_chat(recipient);
+CREATE INDEX localplayerchat_by_timestamp ON local_player_chat (sender, recipient, created);
+
+CREATE TABLE local_player_last_read (
+    sender int NOT NULL,
+    recipient int NOT NULL,
+    "when" timestamp NOT NULL,
+    PRIMARY KEY (sender, recipient),
+    CONSTRAINT local_playerlastread_sender_player_id FOREIGN KEY (sender) REFERENCES player (id),
+    CONSTRAINT local_playerlastread_recipient_player_id FOREIGN KEY (recipient) REFERENCES player (id)
+);
+
+CREATE TABLE remote_player_chat (
+    player int NOT NULL,
+    inbound boolean NOT NULL,
+    remote_player text NOT NULL,
+    remote_server text NOT NULL,
+    created timestamp NOT NULL,
+    body bytea NOT NULL,
+    PRIMARY KEY (remote_player, remote_server, created, player, inbound),
+    CONSTRAINT remote_playerchat_player_id FOREIGN KEY (player) REFERENCES player (id)
+);
+
+CREATE INDEX remoteplayerchat_by_timestamp ON remote_player_chat (player, remote_server, remote_player, created);
+
+CREATE TABLE remote_player_last_read (
+    player int NOT NULL,
+    remote_player text NOT NULL,
+    remote_server text NOT NULL,
+    "when" timestamp NOT NULL,
+    PRIMARY KEY (player, remote_player, remote_server),
+    CONSTRAINT remote_playerlastread_player_id FOREIGN KEY (player) REFERENCES player (id)
+);
+
+CREATE TABLE location (
+    id serial PRIMARY KEY,
+    name text NOT NULL,
+    owner int NOT NULL,
+    descriptor bytea NOT NULL,
+    state bytea NOT NULL,
+    acl bytea NOT NULL,
+    visibility smallint NOT NULL,
+    visibility_changed timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    created timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    updated_at timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    CONSTRAINT location_player_id FOREIGN KEY (owner) REFERENCES player (id),
+    CONSTRAINT location_only_per_player UNIQUE (owner, descriptor)
+);
+
+CREATE INDEX location_descriptor ON location(descriptor);
+CREATE INDEX location_visibility ON location(visibility);
+
+CREATE TABLE location_chat (
+    location int NOT NULL,
+    principal bytea NOT NULL,
+    created timestamp NOT NULL,
+    body bytea NOT NULL,
+    PRIMARY KEY (principal, created, location),
+    CONSTRAINT locationchat_location_id FOREIGN KEY (location) REFERENCES location (id)
+);
+
+CREATE INDEX locationchat_by_timestamp ON location_chat (location, created);
+
+CREATE TABLE location_announcement (
+    id serial PRIMARY KEY,
+    location int NOT NULL,
+    title text NOT NULL,
+    body text NOT NULL,
+    "when" bytea NOT NULL,
+    "public" boolean NOT NULL,
+    expires timestamp NOT NULL,
+    CONSTRAINT locationannouncement_location_id FOREIGN KEY (location) REFERENCES location (id)
+);
+
+CREATE TABLE location_calendar_subscription (
+    location int NOT NULL,
+    player int NOT NULL,
+    PRIMARY KEY (location, player),
+    CONSTRAINT location_calendar_subscription_player_id FOREIGN KEY (player) REFERENCES player (id),
+    CONSTRAINT location_calendar_subscription_location_id FOREIGN KEY (location) REFERENCES location (id)
+);
+
+CREATE TABLE remote_calendar_subscription (
+    owner text NOT NULL,
+    server text NOT NULL,
+    descriptor bytea NOT NULL,
+    player int NOT NULL,
+    PRIMARY KEY (server, owner, descriptor, player),
+    CONSTRAINT remote_calendar_subscription_player_id FOREIGN KEY (player) REFERENCES player (id)
+);
+
+CREATE TABLE server_setting (
+    category varchar(1) NOT NULL,
+    data bytea NOT NULL,
+    PRIMARY KEY (category)
+);
+
+CREATE TABLE bookmark (
+    player int NOT NULL,
+    value bytea NOT NULL,
+    PRIMARY KEY(player, value),
+    CONSTRAINT bookmark_player_id FOREIGN KEY (player) REFERENCES player (id)
+);
+
+CREATE TABLE public_key (
+    player int NOT NULL,
+    fingerprint text NOT NULL,
+    key bytea NOT NULL,
+    last_used timestamp,
+    created timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    PRIMARY KEY (player, fingerprint),
+    CONSTRAINT public_key_player_id FOREIGN KEY (player) REFERENCES player (id)
+);
+
+CREATE TABLE calendar_cache (
+    player int NOT NULL,
+    server text NOT NULL,
+    calendar_entries bytea NOT NULL,
+    last_used timestamp,
+    last_requested timestamp,
+    last_updated timestamp,
+    created timestamp NOT NULL,
+    PRIMARY KEY (player, server),
+    CONSTRAINT calendar_cache_player_id FOREIGN KEY (player) REFERENCES player (id)
+);
+
+CREATE VIEW player_size AS
+  SELECT SUM(length(location.state) + length(location.acl)) AS total, player.name AS player
+  FROM player JOIN location ON Player.id = location.owner
+  GROUP BY player.name;
@@ -0,0 +1,8 @@
+CREATE TABLE invitation (
+    code text PRIMARY KEY NOT NULL,
+    created_by text NOT NULL,
+    created timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    expires timestamp NOT NULL
+);
+
+CREATE INDEX invitation_by_expires ON invitation(expires);
@@ -0,0 +1,2 @@
+DROP TABLE second_factor_recovery;
+DROP TABLE second_factor;
@@ -0,0 +1,12 @@
+CREATE TABLE second_factor (
+    name text PRIMARY KEY NOT NULL,
+    secret text NOT NULL,
+    confirmed boolean NOT NULL DEFAULT FALSE,
+    created timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE TABLE second_factor_recovery (
+    name text NOT NULL,
+    code text NOT NULL,
+    PRIMARY KEY (name, code)
+);
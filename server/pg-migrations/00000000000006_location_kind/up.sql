@@ -0,0 +1,2 @@
+ALTER TABLE location ADD COLUMN kind bytea NOT NULL DEFAULT '\x';
+CREATE INDEX location_kind ON location(kind);
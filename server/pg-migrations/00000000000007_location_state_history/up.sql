@@ -0,0 +1,21 @@
+CREATE TABLE location_state_history (
+    id serial PRIMARY KEY,
+    location int NOT NULL,
+    state bytea NOT NULL,
+    reason smallint NOT NULL,
+    created timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    CONSTRAINT location_state_history_location_id FOREIGN KEY (location) REFERENCES location (id)
+);
+
+CREATE INDEX location_state_history_by_timestamp ON location_state_history (location, created);
+
+CREATE TABLE location_state_restore (
+    id serial PRIMARY KEY,
+    location int NOT NULL,
+    principal bytea NOT NULL,
+    snapshot timestamp NOT NULL,
+    created timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+    CONSTRAINT location_state_restore_location_id FOREIGN KEY (location) REFERENCES location (id)
+);
+
+CREATE INDEX location_state_restore_by_timestamp ON location_state_restore (location, created);
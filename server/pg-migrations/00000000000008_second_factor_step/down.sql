@@ -0,0 +1 @@
+ALTER TABLE second_factor DROP COLUMN last_step;
@@ -0,0 +1 @@
+ALTER TABLE second_factor ADD COLUMN last_step bigint;
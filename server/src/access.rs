@@ -1,3 +1,4 @@
+use crate::accounts::second_factor::SecondFactors;
 use crate::accounts::ServerAccounts;
 use crate::database::persisted::{PersistedGlobal, PersistedWatch, Persistence};
 use crate::database::setting::Setting;
@@ -9,6 +10,7 @@ use spadina_core::access::{AccessSetting, BannedPeer, SimpleAccess};
 use spadina_core::communication::Announcement;
 use spadina_core::player::PlayerIdentifier;
 use std::collections::HashSet;
+use std::error::Error;
 use std::sync::Arc;
 use tokio::sync::broadcast;
 
@@ -20,7 +22,8 @@ pub(crate) struct AccessManagement {
   #[allow(dead_code)]
   death_rx: broadcast::Receiver<()>,
   death_tx: broadcast::Sender<()>,
-  pub jwt_key: jwt::KeyPair,
+  pub jwt_key: jwt::SigningKeys,
+  pub second_factors: SecondFactors,
   pub server_name: Arc<str>,
 }
 
@@ -32,13 +35,21 @@ pub(crate) struct BannedPeers;
 pub(crate) struct ServerAccess;
 
 impl AccessManagement {
-  pub fn new(accounts: ServerAccounts, database: &Database, server_name: Arc<str>) -> QueryResult<Arc<Self>> {
+  pub fn new(
+    accounts: ServerAccounts,
+    signing: jwt::SigningConfiguration,
+    database: &Database,
+    server_name: Arc<str>,
+  ) -> Result<Arc<Self>, Box<dyn Error + Send + Sync>> {
     eprintln!("Setting up access management");
     let access = PersistedGlobal::new(database.clone(), ServerAccess, &crate::metrics::SETTING)?;
     eprintln!("Setting up announcements");
     let announcements = PersistedWatch::new(database.clone(), ServerAnnouncements)?;
     eprintln!("Setting up peers bans");
     let banned_peers = PersistedGlobal::new(database.clone(), BannedPeers, &crate::metrics::SETTING)?;
+    eprintln!("Loading signing keys");
+    let jwt_key = jwt::SigningKeys::new(signing, database)?;
+    let second_factors = SecondFactors::new(database, server_name.clone());
     eprintln!("Setting up exit handler");
     let (ctrl_c, death_rx) = broadcast::channel(1);
     let death_tx = ctrl_c.clone();
@@ -48,7 +59,7 @@ impl AccessManagement {
       ctrl_c.send(()).expect("Failed to notify of shutdown.");
     });
     eprintln!("Access management configured");
-    Ok(Arc::new(Self { access, announcements, accounts, banned_peers, death_rx, death_tx, jwt_key: Default::default(), server_name }))
+    Ok(Arc::new(Self { access, announcements, accounts, banned_peers, death_rx, death_tx, jwt_key, second_factors, server_name }))
   }
   pub async fn check_access(&self, location: &'static str, player: &PlayerIdentifier<impl AsRef<str>>) -> bool {
     self.access.read(location, |acl| acl.check(player, &self.server_name)).await == SimpleAccess::Allow
@@ -0,0 +1,413 @@
+use crate::accounts::db_policy::DatabaseBackedPolicy;
+use crate::accounts::ldap::LightweightDirectory;
+use crate::accounts::login::{Login, LoginRequest, LoginResponse, Registration, ServerLogin};
+use crate::accounts::policy::{Policy, PolicyRequest};
+use crate::accounts::AuthResult;
+use http::{Method, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{http, Request};
+use spadina_core::access::{AccountLockState, LocalAccessSetting};
+use spadina_core::net::server::administration::ServerRole;
+use spadina_core::net::server::auth::{AuthAccounts, AuthMethod, AuthScheme};
+use spadina_core::net::server::{PASSWORD_AUTH_PATH, PASSWORD_CHANGE_PATH};
+use spadina_core::net::OIDC_AUTH_START_PATH;
+use spadina_core::UpdateResult;
+use std::error::Error;
+use std::future::Future;
+
+/// A login mechanism that can be combined with others
+pub enum AccountsBackend {
+  Login(ServerLogin),
+  LDAP(LightweightDirectory),
+}
+
+/// Multiple login mechanisms on one server, each responsible for a different group of players
+///
+/// A player belongs to the first back-end whose accounts match their name. The back-end only sees the player's name with the prefix or realm
+/// removed. Back-ends other than LDAP share the server's own administration and creation policy.
+pub struct CompositeAccounts {
+  backends: Vec<(AuthAccounts<String>, AccountsBackend)>,
+  policy: DatabaseBackedPolicy,
+}
+
+impl CompositeAccounts {
+  pub fn new(backends: Vec<(AuthAccounts<String>, AccountsBackend)>, policy: DatabaseBackedPolicy) -> Result<Self, Box<dyn Error + Send + Sync>> {
+    if backends.is_empty() {
+      return Err("Composite authentication requires at least one back-end".into());
+    }
+    if backends.iter().filter(|(_, backend)| backend.scheme() == AuthScheme::OpenIdConnect).count() > 1 {
+      return Err("Composite authentication can only have one OpenID Connect back-end".into());
+    }
+    Ok(CompositeAccounts { backends, policy })
+  }
+  /// Find the back-end responsible for a player and the name the back-end knows that player by
+  fn find<'a, 'b>(&'a self, player: &'b str) -> Option<(&'a AuthAccounts<String>, &'b str, &'a AccountsBackend)> {
+    self.backends.iter().find_map(|(accounts, backend)| accounts.local_name(player).map(|name| (accounts, name, backend)))
+  }
+  /// Describe the authentication schemes available for each group of players
+  pub fn methods(&self) -> Vec<AuthMethod<String>> {
+    self.backends.iter().map(|(accounts, backend)| AuthMethod { accounts: accounts.clone(), scheme: backend.scheme() }).collect()
+  }
+  /// Synchronise every LDAP back-end, returning the qualified names of players whose accounts have been disabled
+  pub async fn sync(&self) -> Vec<String> {
+    let mut disabled = Vec::new();
+    for (accounts, backend) in &self.backends {
+      if let AccountsBackend::LDAP(l) = backend {
+        match l.sync().await {
+          Ok(players) => disabled.extend(players.into_iter().map(|player| accounts.qualified_name(&player))),
+          Err(e) => eprintln!("Failed to synchronise with LDAP: {:?}", e),
+        }
+      }
+    }
+    disabled
+  }
+}
+
+fn qualify(accounts: &AuthAccounts<String>, result: AuthResult) -> AuthResult {
+  match result {
+    AuthResult::SendToken(name) => AuthResult::SendToken(accounts.qualified_name(&name)),
+    AuthResult::RedirectToken(name) => AuthResult::RedirectToken(accounts.qualified_name(&name)),
+    AuthResult::Revoke(name, page) => AuthResult::Revoke(accounts.qualified_name(&name), page),
+    result => result,
+  }
+}
+
+fn rebuild(parts: &http::request::Parts, uri: http::Uri, body: Bytes) -> Request<Full<Bytes>> {
+  let mut request = Request::new(Full::new(body));
+  *request.method_mut() = parts.method.clone();
+  *request.uri_mut() = uri;
+  *request.headers_mut() = parts.headers.clone();
+  request
+}
+
+impl Login for AccountsBackend {
+  fn administration_request(&self, request: LoginRequest) -> impl Future<Output = LoginResponse> + Send {
+    async move {
+      match self {
+        AccountsBackend::Login(l) => l.administration_request(request).await,
+        AccountsBackend::LDAP(l) => l.administration_request(request).await,
+      }
+    }
+  }
+
+  fn can_register(&self) -> bool {
+    match self {
+      AccountsBackend::Login(l) => l.can_register(),
+      AccountsBackend::LDAP(l) => l.can_register(),
+    }
+  }
+
+  fn http_handle(&self, req: Request<Full<Bytes>>) -> impl Future<Output = AuthResult> + Send {
+    async move {
+      match self {
+        AccountsBackend::Login(l) => l.http_handle(req).await,
+        AccountsBackend::LDAP(l) => l.http_handle(req).await,
+      }
+    }
+  }
+
+  fn normalize_username(&self, player: String) -> impl Future<Output = Result<String, ()>> + Send {
+    async move {
+      match self {
+        AccountsBackend::Login(l) => l.normalize_username(player).await,
+        AccountsBackend::LDAP(l) => l.normalize_username(player).await,
+      }
+    }
+  }
+
+  fn scheme(&self) -> AuthScheme {
+    match self {
+      AccountsBackend::Login(l) => l.scheme(),
+      AccountsBackend::LDAP(l) => l.scheme(),
+    }
+  }
+}
+
+impl Login for CompositeAccounts {
+  fn administration_request(&self, request: LoginRequest) -> impl Future<Output = LoginResponse> + Send {
+    async move {
+      match request {
+        LoginRequest::DeleteAccount(player) => match self.find(player) {
+          Some((_, name, backend)) => backend.administration_request(LoginRequest::DeleteAccount(name)).await,
+          None => LoginResponse::DeleteAccount(None),
+        },
+        LoginRequest::LockAccount(player, locked) => match self.find(player) {
+          Some((_, name, backend)) => backend.administration_request(LoginRequest::LockAccount(name, locked)).await,
+          None => LoginResponse::LockAccount(None),
+        },
+        LoginRequest::LockStatus(player) => match self.find(player) {
+          Some((_, name, backend)) => backend.administration_request(LoginRequest::LockStatus(name)).await,
+          None => LoginResponse::LockStatus(AccountLockState::Unknown),
+        },
+        LoginRequest::Register { username, password } => match self.find(&username) {
+          Some((accounts, name, backend)) => {
+            match backend.administration_request(LoginRequest::Register { username: name.to_string(), password }).await {
+              LoginResponse::Register(Registration::Success(name)) => LoginResponse::Register(Registration::Success(accounts.qualified_name(&name))),
+              response => response,
+            }
+          }
+          None => LoginResponse::Register(Registration::InvalidName),
+        },
+        LoginRequest::ResetPassword(player) => match self.find(player) {
+          Some((_, name, backend)) => backend.administration_request(LoginRequest::ResetPassword(name)).await,
+          None => LoginResponse::ResetPassword(None),
+        },
+      }
+    }
+  }
+
+  fn can_register(&self) -> bool {
+    self.backends.iter().any(|(_, backend)| backend.can_register())
+  }
+
+  fn http_handle(&self, req: Request<Full<Bytes>>) -> impl Future<Output = AuthResult> + Send {
+    async move {
+      let (parts, body) = req.into_parts();
+      let body = match body.collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(e) => match e {},
+      };
+      match (&parts.method, parts.uri.path()) {
+        // These requests carry the player's name in the body, so it must be rewritten for the back-end that owns the player
+        (&Method::POST, PASSWORD_AUTH_PATH | PASSWORD_CHANGE_PATH) => {
+          let mut request = match serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&body) {
+            Ok(request) => request,
+            Err(e) => return AuthResult::Page(Response::builder().status(StatusCode::BAD_REQUEST).body(e.to_string().into())),
+          };
+          let Some((accounts, name, backend)) =
+            request.get("username").and_then(|username| username.as_str()).and_then(|username| self.find(username))
+          else {
+            return AuthResult::Page(Response::builder().status(StatusCode::UNAUTHORIZED).body("Invalid username or password".into()));
+          };
+          let name = name.to_string();
+          request.insert("username".to_string(), serde_json::Value::String(name));
+          match serde_json::to_vec(&request) {
+            Ok(body) => qualify(accounts, backend.http_handle(rebuild(&parts, parts.uri.clone(), body.into())).await),
+            Err(e) => {
+              eprintln!("Failed to rewrite authentication request: {}", e);
+              AuthResult::Failure
+            }
+          }
+        }
+        (&Method::GET, OIDC_AUTH_START_PATH) => {
+          let Some(player) =
+            parts.uri.query().map(|q| form_urlencoded::parse(q.as_bytes()).filter(|(n, _)| n == "player").map(|(_, value)| value).next()).flatten()
+          else {
+            return AuthResult::Failure;
+          };
+          let Some((accounts, name, backend)) = self.find(&player) else {
+            return AuthResult::Failure;
+          };
+          let query = form_urlencoded::Serializer::new(String::new()).append_pair("player", name).finish();
+          match http::Uri::builder().path_and_query(format!("{}?{}", OIDC_AUTH_START_PATH, query)).build() {
+            Ok(uri) => qualify(accounts, backend.http_handle(rebuild(&parts, uri, body)).await),
+            Err(e) => {
+              eprintln!("Failed to rewrite OpenID Connect request: {}", e);
+              AuthResult::Failure
+            }
+          }
+        }
+        _ => {
+          for (accounts, backend) in &self.backends {
+            match backend.http_handle(rebuild(&parts, parts.uri.clone(), body.clone())).await {
+              AuthResult::NotHandled => (),
+              result => return qualify(accounts, result),
+            }
+          }
+          AuthResult::NotHandled
+        }
+      }
+    }
+  }
+
+  fn normalize_username(&self, player: String) -> impl Future<Output = Result<String, ()>> + Send {
+    async move {
+      match self.find(&player) {
+        Some((accounts, name, backend)) => backend.normalize_username(name.to_string()).await.map(|name| accounts.qualified_name(&name)),
+        None => Err(()),
+      }
+    }
+  }
+
+  fn scheme(&self) -> AuthScheme {
+    self.backends.first().map(|(_, backend)| backend.scheme()).unwrap_or(AuthScheme::Password)
+  }
+}
+
+impl Policy for CompositeAccounts {
+  fn can_create(&self, player: &str) -> impl Future<Output = bool> + Send {
+    async move {
+      match self.find(player) {
+        Some((_, name, AccountsBackend::Login(l))) => self.policy.can_create(player).await || l.has_role(name, ServerRole::Creator).await,
+        Some((_, name, AccountsBackend::LDAP(l))) => l.can_create(name).await,
+        None => false,
+      }
+    }
+  }
+
+  fn is_administrator(&self, player: &str) -> impl Future<Output = bool> + Send {
+    async move {
+      match self.find(player) {
+        Some((_, name, AccountsBackend::Login(l))) => self.policy.is_administrator(player).await || l.has_role(name, ServerRole::Administrator).await,
+        Some((_, name, AccountsBackend::LDAP(l))) => l.is_administrator(name).await,
+        None => false,
+      }
+    }
+  }
+
+  fn read_role(&self, role: ServerRole) -> impl Future<Output = Option<LocalAccessSetting<String>>> + Send {
+    self.policy.read_role(role)
+  }
+
+  fn request(&self, request: PolicyRequest) -> impl Future<Output = UpdateResult> + Send {
+    async move {
+      let backend = match &request {
+        PolicyRequest::AddAdmin(player)
+        | PolicyRequest::AddCreator(player)
+        | PolicyRequest::RemoveAdmin(player)
+        | PolicyRequest::RemoveCreator(player) => match self.find(player) {
+          Some((_, _, backend)) => Some(backend),
+          None => return UpdateResult::NotAllowed,
+        },
+        PolicyRequest::SetCreator(_) | PolicyRequest::SetAdmin(_) => None,
+      };
+      match backend {
+        Some(AccountsBackend::LDAP(l)) => l.request(request).await,
+        _ => self.policy.request(request).await,
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{qualify, AccountsBackend, CompositeAccounts};
+  use crate::accounts::db_policy::DatabaseBackedPolicy;
+  use crate::accounts::login::password::fixed_password::FixedPasswords;
+  use crate::accounts::login::password::ServerPassword;
+  use crate::accounts::login::{Login, ServerLogin};
+  use crate::accounts::AuthResult;
+  use crate::database::configuration::DatabaseConfiguration;
+  use crate::database::Database;
+  use http::{Method, Request, StatusCode};
+  use http_body_util::Full;
+  use spadina_core::net::server::auth::AuthAccounts;
+  use spadina_core::net::server::{PASSWORD_AUTH_PATH, PASSWORD_CHANGE_PATH};
+  use std::path::PathBuf;
+
+  fn fixed(name: &str, password: &str) -> AccountsBackend {
+    AccountsBackend::Login(ServerLogin::Password(ServerPassword::FixedPassword(FixedPasswords::from_iter([(
+      name.to_string(),
+      password.to_string(),
+    )]))))
+  }
+
+  /// Create accounts where `guest-` players, `@example.com` players, and everyone else each have their own password store
+  fn accounts(name: &str) -> (CompositeAccounts, Database, PathBuf) {
+    let db_file = std::env::temp_dir().join(format!("spadina-test-composite-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&db_file);
+    let database = Database::new(&DatabaseConfiguration::default(), db_file.clone());
+    let accounts = CompositeAccounts::new(
+      vec![
+        (AuthAccounts::Prefix("guest-".to_string()), fixed("alice", "a")),
+        (AuthAccounts::Realm("example.com".to_string()), fixed("bob", "b")),
+        (AuthAccounts::Any, fixed("carol", "c")),
+      ],
+      DatabaseBackedPolicy::new(&database).unwrap(),
+    )
+    .unwrap();
+    (accounts, database, db_file)
+  }
+
+  fn cleanup(accounts: CompositeAccounts, database: Database, db_file: PathBuf) {
+    drop(accounts);
+    drop(database);
+    let _ = std::fs::remove_file(db_file);
+  }
+
+  async fn post(accounts: &CompositeAccounts, path: &str, body: serde_json::Value) -> AuthResult {
+    let request = Request::builder().method(Method::POST).uri(path).body(Full::from(serde_json::to_vec(&body).unwrap())).unwrap();
+    accounts.http_handle(request).await
+  }
+
+  fn status(result: AuthResult) -> StatusCode {
+    match result {
+      AuthResult::Page(Ok(response)) => response.status(),
+      _ => panic!("Expected a page"),
+    }
+  }
+
+  fn token(result: AuthResult) -> String {
+    match result {
+      AuthResult::SendToken(name) => name,
+      _ => panic!("Expected a token"),
+    }
+  }
+
+  #[test]
+  fn find_by_prefix_and_realm() {
+    let (accounts, database, db_file) = accounts("find");
+    assert!(matches!(accounts.find("guest-alice"), Some((AuthAccounts::Prefix(_), "alice", _))));
+    assert!(matches!(accounts.find("bob@example.com"), Some((AuthAccounts::Realm(_), "bob", _))));
+    assert!(matches!(accounts.find("carol"), Some((AuthAccounts::Any, "carol", _))));
+    // Names that only match the group's pattern with nothing left over belong to a later group
+    assert!(matches!(accounts.find("guest-"), Some((AuthAccounts::Any, "guest-", _))));
+    assert!(matches!(accounts.find("@example.com"), Some((AuthAccounts::Any, "@example.com", _))));
+    assert!(matches!(accounts.find("bob@example.org"), Some((AuthAccounts::Any, "bob@example.org", _))));
+    cleanup(accounts, database, db_file);
+  }
+
+  #[test]
+  fn qualify_names() {
+    let prefix = AuthAccounts::Prefix("guest-".to_string());
+    let realm = AuthAccounts::Realm("example.com".to_string());
+    assert_eq!(token(qualify(&prefix, AuthResult::SendToken("alice".to_string()))), "guest-alice");
+    assert_eq!(token(qualify(&AuthAccounts::Any, AuthResult::SendToken("carol".to_string()))), "carol");
+    assert!(matches!(qualify(&realm, AuthResult::RedirectToken("bob".to_string())), AuthResult::RedirectToken(name) if name == "bob@example.com"));
+    assert!(
+      matches!(qualify(&realm, AuthResult::Revoke("bob".to_string(), Ok(Default::default()))), AuthResult::Revoke(name, _) if name == "bob@example.com")
+    );
+    assert!(matches!(qualify(&prefix, AuthResult::Failure), AuthResult::Failure));
+    assert!(matches!(qualify(&prefix, AuthResult::NotHandled), AuthResult::NotHandled));
+  }
+
+  #[tokio::test]
+  async fn password_login_uses_local_name() {
+    let (accounts, database, db_file) = accounts("login");
+    assert_eq!(token(post(&accounts, PASSWORD_AUTH_PATH, serde_json::json!({ "username": "guest-alice", "password": "a" })).await), "guest-alice");
+    assert_eq!(
+      token(post(&accounts, PASSWORD_AUTH_PATH, serde_json::json!({ "username": "bob@example.com", "password": "b" })).await),
+      "bob@example.com"
+    );
+    assert_eq!(token(post(&accounts, PASSWORD_AUTH_PATH, serde_json::json!({ "username": "carol", "password": "c" })).await), "carol");
+    // The password belongs to a player in a different group
+    assert_eq!(
+      status(post(&accounts, PASSWORD_AUTH_PATH, serde_json::json!({ "username": "guest-bob", "password": "b" })).await),
+      StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(status(post(&accounts, PASSWORD_AUTH_PATH, serde_json::json!({ "password": "a" })).await), StatusCode::UNAUTHORIZED);
+    cleanup(accounts, database, db_file);
+  }
+
+  #[tokio::test]
+  async fn password_change_goes_to_owning_back_end() {
+    let (accounts, database, db_file) = accounts("change");
+    // Fixed passwords cannot be changed, so the back-end only answers this way if it could parse the rewritten request
+    assert_eq!(
+      status(post(&accounts, PASSWORD_CHANGE_PATH, serde_json::json!({ "username": "guest-alice", "old_password": "a", "new_password": "z" })).await),
+      StatusCode::FORBIDDEN
+    );
+    assert_eq!(status(post(&accounts, PASSWORD_CHANGE_PATH, serde_json::json!({ "username": 5 })).await), StatusCode::UNAUTHORIZED);
+    cleanup(accounts, database, db_file);
+  }
+
+  #[tokio::test]
+  async fn normalize_qualifies_names() {
+    let (accounts, database, db_file) = accounts("normalize");
+    assert_eq!(accounts.normalize_username("guest-alice".to_string()).await, Ok("guest-alice".to_string()));
+    assert_eq!(accounts.normalize_username("bob@example.com".to_string()).await, Ok("bob@example.com".to_string()));
+    assert_eq!(accounts.normalize_username("guest-bob".to_string()).await, Err(()));
+    cleanup(accounts, database, db_file);
+  }
+}
@@ -1,22 +1,28 @@
+use crate::accounts::composite::{AccountsBackend, CompositeAccounts};
 use crate::accounts::db_policy::DatabaseBackedPolicy;
+use crate::accounts::invitation::Invitations;
 use crate::accounts::ldap::{LightweightDirectory, LightweightDirectoryConfiguration};
 use crate::accounts::login::openid::configuration::{OIConnectConfiguration, OpenIdRegistration};
 use crate::accounts::login::openid::db_oidc::DatabaseOpenIdConnect;
 use crate::accounts::login::openid::ServerOpenIdConnect;
 use crate::accounts::login::password::db_otp::DatabaseOneTimePasswords;
+use crate::accounts::login::password::db_password::DatabasePasswords;
 use crate::accounts::login::password::uru::UruDatabase;
 use crate::accounts::login::password::ServerPassword;
 use crate::accounts::login::ServerLogin;
 use crate::accounts::ServerAccounts;
 use crate::database::connect::DatabaseBackend;
 use crate::database::Database;
+use spadina_core::net::server::auth::AuthAccounts;
 use std::collections::BTreeMap;
 use std::error::Error;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum AccountsConfiguration {
+  Composite(Vec<CompositeConfiguration>),
   DatabaseOTPs(String),
+  DatabasePasswords(String),
   LDAP(LightweightDirectoryConfiguration),
   OpenIdConnect { connection: String, providers: Vec<OIConnectConfiguration>, registration: Option<OpenIdRegistration> },
   OTPs(BTreeMap<String, String>),
@@ -25,41 +31,58 @@ pub enum AccountsConfiguration {
   Uru(String),
 }
 
+/// A login mechanism inside a composite configuration and the players it is responsible for
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct CompositeConfiguration {
+  accounts: AuthAccounts<String>,
+  authentication: AccountsConfiguration,
+}
+
 impl AccountsConfiguration {
   /// Parse the configuration string provided to the server into an authentication provider, if possible
   pub async fn load(self, server_name: &str, main_database: &Database) -> Result<ServerAccounts, Box<dyn Error + Send + Sync>> {
     Ok(match self {
-      AccountsConfiguration::DatabaseOTPs(connection) => ServerAccounts::Login(
-        ServerLogin::Password(ServerPassword::DatabaseOneTimePassword(DatabaseOneTimePasswords::try_from(DatabaseBackend::try_connect(
-          connection,
-        )?)?)),
-        DatabaseBackedPolicy::new(main_database)?,
-      ),
-      AccountsConfiguration::LDAP(c) => ServerAccounts::LDAP(LightweightDirectory::new(c).await?),
+      AccountsConfiguration::Composite(members) => {
+        let mut backends = Vec::new();
+        for member in members {
+          backends.push((member.accounts, member.authentication.load_backend(server_name).await?));
+        }
+        ServerAccounts::Composite(
+          CompositeAccounts::new(backends, DatabaseBackedPolicy::new(main_database)?)?,
+          Invitations::new(main_database, server_name),
+        )
+      }
+      configuration => match configuration.load_backend(server_name).await? {
+        AccountsBackend::Login(login) => {
+          ServerAccounts::Login(login, DatabaseBackedPolicy::new(main_database)?, Invitations::new(main_database, server_name))
+        }
+        AccountsBackend::LDAP(directory) => ServerAccounts::LDAP(directory),
+      },
+    })
+  }
+  async fn load_backend(self, server_name: &str) -> Result<AccountsBackend, Box<dyn Error + Send + Sync>> {
+    let login = match self {
+      AccountsConfiguration::Composite(_) => return Err("Composite authentication cannot contain another composite".into()),
+      AccountsConfiguration::DatabaseOTPs(connection) => {
+        ServerLogin::Password(ServerPassword::DatabaseOneTimePassword(DatabaseOneTimePasswords::try_from(DatabaseBackend::try_connect(connection)?)?))
+      }
+      AccountsConfiguration::DatabasePasswords(connection) => {
+        ServerLogin::Password(ServerPassword::Database(DatabasePasswords::new(DatabaseBackend::try_connect(connection)?)?))
+      }
+      AccountsConfiguration::LDAP(c) => return Ok(AccountsBackend::LDAP(LightweightDirectory::new(c).await?)),
       AccountsConfiguration::OpenIdConnect { connection, providers, registration } => {
         let mut clients = BTreeMap::new();
         for provider in providers {
           let (issuer, client) = provider.create_oidc_client(server_name).await?;
           clients.insert(issuer, client);
         }
-        ServerAccounts::Login(
-          ServerLogin::OpenID(ServerOpenIdConnect::Database(DatabaseOpenIdConnect::new(DatabaseBackend::try_connect(connection)?, clients)?.into())),
-          DatabaseBackedPolicy::new(main_database)?,
-        )
+        ServerLogin::OpenID(ServerOpenIdConnect::Database(DatabaseOpenIdConnect::new(DatabaseBackend::try_connect(connection)?, clients)?.into()))
       }
-      AccountsConfiguration::OTPs(users) => {
-        ServerAccounts::Login(ServerLogin::Password(ServerPassword::FixedOneTimePassword(users.into())), DatabaseBackedPolicy::new(main_database)?)
-      }
-      AccountsConfiguration::Passwords(users) => {
-        ServerAccounts::Login(ServerLogin::Password(ServerPassword::FixedPassword(users.into())), DatabaseBackedPolicy::new(main_database)?)
-      }
-      AccountsConfiguration::PhpBB(connection) => ServerAccounts::Login(
-        ServerLogin::Password(ServerPassword::PhpBB(DatabaseBackend::try_connect(connection)?.into())),
-        DatabaseBackedPolicy::new(main_database)?,
-      ),
-      AccountsConfiguration::Uru(connection) => {
-        ServerAccounts::Login(ServerLogin::Password(ServerPassword::Uru(UruDatabase::new(connection)?)), DatabaseBackedPolicy::new(main_database)?)
-      }
-    })
+      AccountsConfiguration::OTPs(users) => ServerLogin::Password(ServerPassword::FixedOneTimePassword(users.into())),
+      AccountsConfiguration::Passwords(users) => ServerLogin::Password(ServerPassword::FixedPassword(users.into())),
+      AccountsConfiguration::PhpBB(connection) => ServerLogin::Password(ServerPassword::PhpBB(DatabaseBackend::try_connect(connection)?.into())),
+      AccountsConfiguration::Uru(connection) => ServerLogin::Password(ServerPassword::Uru(UruDatabase::new(connection)?)),
+    };
+    Ok(AccountsBackend::Login(login))
   }
 }
@@ -6,5 +6,7 @@ diesel::table! {
         subject -> Text,
         locked -> Bool,
         issuer -> Text,
+        admin -> Bool,
+        creator -> Bool,
     }
 }
@@ -5,13 +5,14 @@ use crate::database::Database;
 use crate::metrics::SettingLabel;
 use diesel::result::QueryResult;
 use spadina_core::access::{LocalAccessSetting, SimpleAccess};
+use spadina_core::net::server::administration::ServerRole;
 use spadina_core::UpdateResult;
 use std::future::Future;
 
 #[derive(Debug, Copy, Clone)]
-struct CreateAssets;
+pub(crate) struct CreateAssets;
 #[derive(Debug, Copy, Clone)]
-struct ServerAdministration;
+pub(crate) struct ServerAdministration;
 
 pub struct DatabaseBackedPolicy {
   admin: PersistedGlobal<'static, ServerAdministration, SettingLabel>,
@@ -33,7 +34,16 @@ impl Policy for DatabaseBackedPolicy {
   }
 
   fn is_administrator(&self, player: &str) -> impl Future<Output = bool> + Send {
-    async move { self.creating.read("check_is_admin", |acl| acl.check(player) == SimpleAccess::Allow).await }
+    async move { self.admin.read("check_is_admin", |acl| acl.check(player) == SimpleAccess::Allow).await }
+  }
+
+  fn read_role(&self, role: ServerRole) -> impl Future<Output = Option<LocalAccessSetting<String>>> + Send {
+    async move {
+      Some(match role {
+        ServerRole::Administrator => self.admin.read("read_role", |acl| acl.clone()).await,
+        ServerRole::Creator => self.creating.read("read_role", |acl| acl.clone()).await,
+      })
+    }
   }
 
   fn request(&self, request: PolicyRequest) -> impl Future<Output = UpdateResult> + Send {
@@ -43,8 +53,8 @@ impl Policy for DatabaseBackedPolicy {
         PolicyRequest::AddCreator(player) => self.creating.write("request", |acl| Some(acl.allow(player))).await,
         PolicyRequest::RemoveAdmin(player) => self.admin.write("request", |acl| Some(acl.deny(player))).await,
         PolicyRequest::RemoveCreator(player) => self.creating.write("request", |acl| Some(acl.deny(player))).await,
-        PolicyRequest::SetCreator(default) => self.admin.write("request", |acl| Some(acl.reset(default))).await,
-        PolicyRequest::SetAdmin(default) => self.creating.write("request", |acl| Some(acl.reset(default))).await,
+        PolicyRequest::SetCreator(default) => self.creating.write("request", |acl| Some(acl.reset(default))).await,
+        PolicyRequest::SetAdmin(default) => self.admin.write("request", |acl| Some(acl.reset(default))).await,
       }
     }
   }
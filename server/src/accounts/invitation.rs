@@ -0,0 +1,166 @@
+use crate::accounts::login::{Login, LoginRequest, LoginResponse, Registration};
+use crate::accounts::AuthResult;
+use crate::database::Database;
+use chrono::{Duration, Utc};
+use http::{Method, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::{http, Request};
+use rand::distributions::{Alphanumeric, DistString};
+use spadina_core::communication::InvitationError;
+use spadina_core::net::server::administration::Invitation;
+use spadina_core::net::server::auth::PasswordRegistrationRequest;
+use spadina_core::net::server::INVITATION_PATH;
+use spadina_core::UpdateResult;
+
+/// Single-use invitations, stored in the main database, that allow new players to create accounts
+pub struct Invitations {
+  database: Database,
+  server_name: String,
+}
+
+impl Invitations {
+  pub fn new(database: &Database, server_name: &str) -> Self {
+    Invitations { database: database.clone(), server_name: server_name.to_string() }
+  }
+  /// Create a new invitation and return the URL a player can use to redeem it
+  pub fn create(&self, created_by: &str) -> Result<String, InvitationError> {
+    let code = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+    match self.database.invitation_create(&code, created_by, Utc::now() + Duration::days(7)) {
+      Ok(1) => Ok(format!("https://{}{}?code={}", &self.server_name, INVITATION_PATH, code)),
+      Ok(_) => Err(InvitationError::InternalError),
+      Err(e) => {
+        eprintln!("Failed to create invitation: {}", e);
+        Err(InvitationError::InternalError)
+      }
+    }
+  }
+  pub fn list(&self) -> Vec<Invitation<String>> {
+    match self.database.invitation_list() {
+      Ok(invitations) => invitations,
+      Err(e) => {
+        eprintln!("Failed to list invitations: {}", e);
+        Vec::new()
+      }
+    }
+  }
+  pub fn revoke(&self, code: &str) -> UpdateResult {
+    match self.database.invitation_rm(code) {
+      Ok(0) => UpdateResult::Redundant,
+      Ok(_) => UpdateResult::Success,
+      Err(e) => {
+        eprintln!("Failed to revoke invitation {}: {}", code, e);
+        UpdateResult::InternalError
+      }
+    }
+  }
+  /// Handle the registration page, creating accounts in the login method provided
+  ///
+  /// Browsers are given a form to fill out; clients that submit JSON get a token as if they had logged in
+  pub async fn http_handle(&self, login: &impl Login, req: Request<Full<Bytes>>) -> AuthResult {
+    match (req.method(), req.uri().path()) {
+      (&Method::GET, INVITATION_PATH) => {
+        let Some(code) =
+          req.uri().query().map(|q| form_urlencoded::parse(q.as_bytes()).filter(|(n, _)| n == "code").map(|(_, value)| value).next()).flatten()
+        else {
+          return page(true, StatusCode::BAD_REQUEST, "No invitation was provided.");
+        };
+        match self.database.invitation_check(&code) {
+          Ok(true) => {
+            AuthResult::Page(Response::builder().header(CONTENT_TYPE, "text/html; charset=utf-8").body(crate::html::create_invitation(&code).into()))
+          }
+          Ok(false) => page(true, StatusCode::FORBIDDEN, "This invitation is not valid or has already been used."),
+          Err(e) => {
+            eprintln!("Failed to check invitation: {}", e);
+            AuthResult::Failure
+          }
+        }
+      }
+      (&Method::POST, INVITATION_PATH) => {
+        let is_form = req
+          .headers()
+          .get(CONTENT_TYPE)
+          .map(|value| value.to_str().ok())
+          .flatten()
+          .map(|value| value.starts_with("application/x-www-form-urlencoded"))
+          .unwrap_or(false);
+        let request = if is_form {
+          match req.into_body().collect().await {
+            Err(e) => {
+              eprintln!("Failed to aggregate body: {}", e);
+              return AuthResult::Failure;
+            }
+            Ok(body) => match serde_urlencoded::from_bytes::<PasswordRegistrationRequest<String>>(&body.to_bytes()) {
+              Err(e) => return page(true, StatusCode::BAD_REQUEST, &e.to_string()),
+              Ok(request) => request,
+            },
+          }
+        } else {
+          match crate::http_server::aggregate::<PasswordRegistrationRequest<String>>(req).await {
+            Err(response) => return AuthResult::Page(response),
+            Ok(request) => request,
+          }
+        };
+        match self.register(login, request).await {
+          None => page(is_form, StatusCode::FORBIDDEN, "This invitation is not valid or has already been used."),
+          Some(Registration::Success(username)) if !is_form => AuthResult::SendToken(username),
+          Some(Registration::Success(username)) => page(
+            true,
+            StatusCode::OK,
+            &format!("The account {} has been created. Use a Spadina client to log in to {}.", username, &self.server_name),
+          ),
+          Some(Registration::InternalError) => AuthResult::Failure,
+          Some(Registration::InvalidName) => page(is_form, StatusCode::BAD_REQUEST, "That player name is not allowed."),
+          Some(Registration::NameTaken) => page(is_form, StatusCode::CONFLICT, "That player name is already in use."),
+          Some(Registration::NotSupported) => page(is_form, StatusCode::FORBIDDEN, "Accounts cannot be created on this server."),
+        }
+      }
+      _ => AuthResult::NotHandled,
+    }
+  }
+  /// Consume an invitation and create the account, returning none if the invitation is not usable
+  ///
+  /// If the account cannot be created, the invitation is put back so the player can try again
+  async fn register(&self, login: &impl Login, request: PasswordRegistrationRequest<String>) -> Option<Registration> {
+    if !login.can_register() {
+      return Some(Registration::NotSupported);
+    }
+    // The invitation is checked before the name, so that only someone holding an invitation can find out which names are in use
+    let invitation = match self.database.invitation_redeem(&request.invitation) {
+      Ok(invitation) => invitation?,
+      Err(e) => {
+        eprintln!("Failed to redeem invitation: {}", e);
+        return Some(Registration::InternalError);
+      }
+    };
+    let username = request.username.clone();
+    let registration = if login.normalize_username(request.username.clone()).await.is_ok() {
+      Registration::NameTaken
+    } else {
+      match login.administration_request(LoginRequest::Register { username: request.username, password: request.password }).await {
+        LoginResponse::Register(registration) => registration,
+        _ => Registration::InternalError,
+      }
+    };
+    match &registration {
+      Registration::Success(name) => {
+        eprintln!("Invitation from {} used to create account {}", &invitation.created_by, name);
+      }
+      _ => {
+        if let Err(e) = self.database.invitation_restore(&invitation) {
+          eprintln!("Failed to restore invitation after registration of {} failed: {}", username, e);
+        }
+      }
+    }
+    Some(registration)
+  }
+}
+
+fn page(html: bool, status: StatusCode, message: &str) -> AuthResult {
+  AuthResult::Page(if html {
+    Response::builder().status(status).header(CONTENT_TYPE, "text/html; charset=utf-8").body(crate::html::create_invitation_result(message).into())
+  } else {
+    Response::builder().status(status).body(message.to_string().into())
+  })
+}
@@ -1,15 +1,28 @@
-use crate::accounts::login::{Login, LoginRequest, LoginResponse};
+use crate::accounts::login::{Login, LoginRequest, LoginResponse, Registration};
 use crate::accounts::policy::{Policy, PolicyRequest};
 use crate::accounts::AuthResult;
 use crate::http_server::aggregate;
 use bb8::{Pool, PooledConnection, RunError};
-use hyper::{body::Incoming, Method, Request, Response, StatusCode};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use ldap3::adapters::{Adapter, EntriesOnly, PagedResults};
 use ldap3::{LdapError, LdapResult, SearchEntry, SearchResult};
 use serde::{Deserialize, Serialize};
+use spadina_core::access::{AccountLockState, LocalAccessSetting};
+use spadina_core::net::server::administration::ServerRole;
 use spadina_core::net::server::auth::{AuthScheme, PasswordRequest};
-use spadina_core::net::server::AUTH_METHOD_PATH;
+use spadina_core::net::server::PASSWORD_AUTH_PATH;
 use spadina_core::UpdateResult;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
+use std::sync::RwLock;
+
+/// Matches accounts disabled in Active Directory or locked by OpenLDAP's password policy or 389 Directory Server
+const DISABLED_QUERY: &str = "(|(userAccountControl:1.2.840.113556.1.4.803:=2)(pwdAccountLockedTime=*)(nsAccountLock=TRUE))";
+const DISABLED_ATTRS: [&str; 3] = ["userAccountControl", "pwdAccountLockedTime", "nsAccountLock"];
+/// The number of entries to request at a time when listing accounts, which must be below the directory's size limit
+const PAGE_SIZE: i32 = 500;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct LightweightDirectoryConfiguration {
@@ -30,9 +43,18 @@ pub struct LightweightDirectory {
   connection: Pool<LDAPConnectionManager<String>>,
   create_query: Option<String>,
   search_base: String,
+  snapshot: RwLock<Option<DirectorySnapshot>>,
   user_query: String,
 }
 
+/// The directory's role membership and disabled accounts, as of the last synchronisation
+#[derive(Default)]
+struct DirectorySnapshot {
+  administrators: HashSet<String>,
+  creators: HashSet<String>,
+  disabled: HashSet<String>,
+}
+
 struct LDAPConnectionManager<T: AsRef<str>>(T);
 impl<T: AsRef<str> + Sized + Sync + Send + 'static> bb8::ManageConnection for LDAPConnectionManager<T> {
   type Connection = ldap3::Ldap;
@@ -65,6 +87,7 @@ impl LightweightDirectory {
       bind_pw: configuration.bind_pw,
       create_query: configuration.create_query,
       search_base: configuration.search_base,
+      snapshot: RwLock::new(None),
       user_query: configuration.user_query,
     })
   }
@@ -77,6 +100,7 @@ impl LightweightDirectory {
     connection.simple_bind(username, password).await.and_then(|result| result.success())?;
     Ok(())
   }
+  /// Find the account matching a query, unless it has been disabled
   async fn query_username(
     &self,
     username: &str,
@@ -84,47 +108,137 @@ impl LightweightDirectory {
   ) -> Result<(Option<String>, PooledConnection<LDAPConnectionManager<String>>), RunError<LdapError>> {
     let mut connection = self.connection.get().await?;
     self.bind(&mut connection, &self.bind_dn, &self.bind_pw).await?;
+    let mut attrs = vec!["cn", "dn", &self.account_attr];
+    attrs.extend(DISABLED_ATTRS);
     let result = connection
-      .search(
-        &self.search_base,
-        ldap3::Scope::Subtree,
-        &format!("(&({}={})({}))", &self.account_attr, ldap3::ldap_escape(username), query),
-        vec!["cn", "dn", &self.account_attr],
-      )
+      .search(&self.search_base, ldap3::Scope::Subtree, &format!("(&({}={})({}))", &self.account_attr, ldap3::ldap_escape(username), query), attrs)
       .await
       .map(|SearchResult(results, _)| {
         results
           .into_iter()
           .next()
-          .map(|entry| SearchEntry::construct(entry).attrs.remove(&self.account_attr))
+          .map(|entry| SearchEntry::construct(entry).attrs)
+          .filter(|attrs| !account_disabled(attrs))
+          .map(|mut attrs| attrs.remove(&self.account_attr))
           .flatten()
           .map(|attr| attr.into_iter().next())
           .flatten()
       })?;
     Ok((result, connection))
   }
+  /// Check a role, using the last synchronised state of the directory if there is one
+  async fn check_role(&self, player: &str, query: Option<&String>, role: ServerRole) -> bool {
+    let Some(query) = query else {
+      return true;
+    };
+    let cached = self.snapshot.read().unwrap().as_ref().map(|snapshot| {
+      !snapshot.disabled.contains(player)
+        && match role {
+          ServerRole::Administrator => snapshot.administrators.contains(player),
+          ServerRole::Creator => snapshot.creators.contains(player),
+        }
+    });
+    if let Some(granted) = cached {
+      return granted;
+    }
+    match self.query_username(player, query).await {
+      Err(e) => {
+        eprintln!("Failed to access LDAP: {:?}", e);
+        false
+      }
+      Ok((None, _)) => false,
+      Ok((Some(_), _)) => true,
+    }
+  }
+  /// List the accounts matching a query, using paged results so large directories are not truncated by the server's size limit
+  async fn search_accounts(&self, connection: &mut ldap3::Ldap, query: &str) -> Result<HashSet<String>, LdapError> {
+    let adapters: Vec<Box<dyn Adapter<_, _>>> = vec![Box::new(EntriesOnly::new()), Box::new(PagedResults::new(PAGE_SIZE))];
+    let mut search =
+      connection.streaming_search_with(adapters, &self.search_base, ldap3::Scope::Subtree, query, vec![self.account_attr.as_str()]).await?;
+    let mut accounts = HashSet::new();
+    while let Some(entry) = search.next().await? {
+      accounts.extend(SearchEntry::construct(entry).attrs.remove(&self.account_attr).and_then(|attr| attr.into_iter().next()));
+    }
+    search.finish().await.success()?;
+    Ok(accounts)
+  }
+  /// Refresh the role membership and disabled accounts from the directory
+  ///
+  /// This returns the players whose accounts have been disabled since the last synchronisation. On the first synchronisation, every disabled
+  /// account is returned, since they may have been disabled while the server was not running.
+  pub async fn sync(&self) -> Result<Vec<String>, RunError<LdapError>> {
+    let mut connection = self.connection.get().await?;
+    self.bind(&mut connection, &self.bind_dn, &self.bind_pw).await?;
+    let mut snapshot = DirectorySnapshot::default();
+    if let Some(admin_query) = &self.admin_query {
+      snapshot.administrators = self.search_accounts(&mut connection, &format!("(&({})({}))", &self.user_query, admin_query)).await?;
+    }
+    if let Some(create_query) = &self.create_query {
+      snapshot.creators = self.search_accounts(&mut connection, &format!("(&({})({}))", &self.user_query, create_query)).await?;
+    }
+    snapshot.disabled = self.search_accounts(&mut connection, &format!("(&({}){})", &self.user_query, DISABLED_QUERY)).await?;
+    let mut current = self.snapshot.write().unwrap();
+    let newly_disabled = newly_disabled(current.as_ref(), &snapshot.disabled);
+    *current = Some(snapshot);
+    Ok(newly_disabled)
+  }
+}
+
+/// Find the accounts that are disabled now, but were not at the previous synchronisation
+fn newly_disabled(previous: Option<&DirectorySnapshot>, disabled: &HashSet<String>) -> Vec<String> {
+  match previous {
+    Some(previous) => disabled.difference(&previous.disabled).cloned().collect(),
+    None => disabled.iter().cloned().collect(),
+  }
+}
+
+/// Check the standard attributes directory servers use to mark an account as disabled or locked
+fn account_disabled(attrs: &HashMap<String, Vec<String>>) -> bool {
+  attrs.iter().any(|(name, values)| {
+    if name.eq_ignore_ascii_case("userAccountControl") {
+      values.iter().any(|value| value.parse::<u32>().map(|flags| flags & 0x2 != 0).unwrap_or(false))
+    } else if name.eq_ignore_ascii_case("pwdAccountLockedTime") {
+      !values.is_empty()
+    } else if name.eq_ignore_ascii_case("nsAccountLock") {
+      values.iter().any(|value| value.eq_ignore_ascii_case("true"))
+    } else {
+      false
+    }
+  })
 }
 impl Login for LightweightDirectory {
   fn administration_request(&self, request: LoginRequest) -> impl Future<Output = LoginResponse> + Send {
     async move {
       match request {
+        // The directory is managed outside the server and the server keeps no account records of its own, so there is nothing to remove
+        LoginRequest::DeleteAccount(_) => LoginResponse::DeleteAccount(None),
         LoginRequest::LockAccount(_, _) => LoginResponse::LockAccount(None),
-        LoginRequest::Invite => LoginResponse::Invite(None),
+        LoginRequest::LockStatus(player) => LoginResponse::LockStatus(match self.snapshot.read().unwrap().as_ref() {
+          None => AccountLockState::Unknown,
+          Some(snapshot) if snapshot.disabled.contains(player) => AccountLockState::PermanentlyLocked,
+          Some(_) => AccountLockState::PermanentlyUnlocked,
+        }),
+        LoginRequest::Register { .. } => LoginResponse::Register(Registration::NotSupported),
+        LoginRequest::ResetPassword(_) => LoginResponse::ResetPassword(None),
       }
     }
   }
 
-  fn http_handle(&self, req: Request<Incoming>) -> impl Future<Output = AuthResult> + Send {
+  fn can_register(&self) -> bool {
+    false
+  }
+
+  fn http_handle(&self, req: Request<Full<Bytes>>) -> impl Future<Output = AuthResult> + Send {
     async move {
       match (req.method(), req.uri().path()) {
-        (&Method::GET, AUTH_METHOD_PATH) => match aggregate::<PasswordRequest<String>>(req).await {
+        (&Method::POST, PASSWORD_AUTH_PATH) => match aggregate::<PasswordRequest<String>>(req).await {
           Err(response) => AuthResult::Page(response),
           Ok(request) => match self.query_username(&request.username, &self.user_query).await {
             Err(e) => {
               eprintln!("Failed to access LDAP: {:?}", e);
               return AuthResult::Page(Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default()));
             }
-            Ok((None, _)) => AuthResult::Page(Response::builder().status(StatusCode::FORBIDDEN).body("Unknown username.".into())),
+            Ok((None, _)) => AuthResult::Page(Response::builder().status(StatusCode::FORBIDDEN).body("Unknown username or disabled account.".into())),
             Ok((Some(username), mut connection)) => match connection.simple_bind(&username, &request.password).await {
               Ok(LdapResult { rc, .. }) => {
                 if rc == 0 {
@@ -160,38 +274,69 @@ impl Login for LightweightDirectory {
 }
 impl Policy for LightweightDirectory {
   fn can_create(&self, player: &str) -> impl Future<Output = bool> + Send {
-    async move {
-      match self.create_query.as_ref() {
-        None => true,
-        Some(create_query) => match self.query_username(&player, create_query).await {
-          Err(e) => {
-            eprintln!("Failed to access LDAP: {:?}", e);
-            false
-          }
-          Ok((None, _)) => false,
-          Ok((Some(_), _)) => true,
-        },
-      }
-    }
+    self.check_role(player, self.create_query.as_ref(), ServerRole::Creator)
   }
 
   fn is_administrator(&self, player: &str) -> impl Future<Output = bool> + Send {
-    async move {
-      match self.admin_query.as_ref() {
-        None => true,
-        Some(admin_query) => match self.query_username(&player, admin_query).await {
-          Err(e) => {
-            eprintln!("Failed to access LDAP: {:?}", e);
-            false
-          }
-          Ok((None, _)) => false,
-          Ok((Some(_), _)) => true,
-        },
-      }
-    }
+    self.check_role(player, self.admin_query.as_ref(), ServerRole::Administrator)
+  }
+
+  fn read_role(&self, _role: ServerRole) -> impl Future<Output = Option<LocalAccessSetting<String>>> + Send {
+    async move { None }
   }
 
   fn request(&self, _request: PolicyRequest) -> impl Future<Output = UpdateResult> + Send {
     async move { UpdateResult::NotAllowed }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{account_disabled, newly_disabled, DirectorySnapshot};
+  use std::collections::{HashMap, HashSet};
+
+  fn attrs(name: &str, values: &[&str]) -> HashMap<String, Vec<String>> {
+    HashMap::from([(name.to_string(), values.iter().map(|value| value.to_string()).collect())])
+  }
+  fn names(names: &[&str]) -> HashSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+  }
+  fn sorted(mut names: Vec<String>) -> Vec<String> {
+    names.sort();
+    names
+  }
+
+  #[test]
+  fn active_directory_disabled_flag() {
+    assert!(account_disabled(&attrs("userAccountControl", &["514"])));
+    assert!(account_disabled(&attrs("useraccountcontrol", &["2"])));
+    assert!(!account_disabled(&attrs("userAccountControl", &["512"])));
+    assert!(!account_disabled(&attrs("userAccountControl", &["garbage"])));
+  }
+  #[test]
+  fn openldap_locked_time() {
+    assert!(account_disabled(&attrs("pwdAccountLockedTime", &["20240101000000Z"])));
+    assert!(!account_disabled(&attrs("pwdAccountLockedTime", &[])));
+  }
+  #[test]
+  fn directory_server_lock() {
+    assert!(account_disabled(&attrs("nsAccountLock", &["TRUE"])));
+    assert!(account_disabled(&attrs("nsaccountlock", &["true"])));
+    assert!(!account_disabled(&attrs("nsAccountLock", &["false"])));
+  }
+  #[test]
+  fn unrelated_attributes() {
+    assert!(!account_disabled(&HashMap::new()));
+    assert!(!account_disabled(&attrs("cn", &["TRUE"])));
+  }
+  #[test]
+  fn first_sync_disables_everyone() {
+    assert_eq!(sorted(newly_disabled(None, &names(&["alice", "bob"]))), vec!["alice", "bob"]);
+  }
+  #[test]
+  fn later_sync_only_reports_changes() {
+    let previous = DirectorySnapshot { disabled: names(&["alice"]), ..Default::default() };
+    assert_eq!(sorted(newly_disabled(Some(&previous), &names(&["alice", "bob"]))), vec!["bob"]);
+    assert!(newly_disabled(Some(&previous), &names(&[])).is_empty());
+  }
+}
@@ -4,30 +4,66 @@ pub mod password;
 use crate::accounts::login::openid::ServerOpenIdConnect;
 use crate::accounts::login::password::ServerPassword;
 use crate::accounts::AuthResult;
-use hyper::{body::Incoming, Request};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::Request;
+use spadina_core::access::AccountLockState;
+use spadina_core::net::server::administration::ServerRole;
 use spadina_core::net::server::auth::AuthScheme;
 use std::future::Future;
 
 pub trait Login: Send + Sync {
   fn administration_request(&self, request: LoginRequest) -> impl Future<Output = LoginResponse> + Send;
-  fn http_handle(&self, req: Request<Incoming>) -> impl Future<Output = AuthResult> + Send;
+  /// Whether this login method can create new accounts for players that have redeemed an invitation
+  fn can_register(&self) -> bool;
+  fn http_handle(&self, req: Request<Full<Bytes>>) -> impl Future<Output = AuthResult> + Send;
   fn normalize_username(&self, player: String) -> impl Future<Output = Result<String, ()>> + Send;
   fn scheme(&self) -> AuthScheme;
 }
 
 pub enum LoginRequest<'a> {
+  /// Remove a player's account from the login store, if the server manages it
+  DeleteAccount(&'a str),
   LockAccount(&'a str, bool),
-  Invite,
+  /// Check whether a player's account is locked
+  LockStatus(&'a str),
+  Register {
+    username: String,
+    password: String,
+  },
+  ResetPassword(&'a str),
 }
 
 pub enum LoginResponse {
+  DeleteAccount(Option<bool>),
   LockAccount(Option<bool>),
-  Invite(Option<String>),
+  LockStatus(AccountLockState),
+  Register(Registration),
+  ResetPassword(Option<String>),
+}
+
+/// The outcome of a player trying to create an account using an invitation
+pub enum Registration {
+  InternalError,
+  InvalidName,
+  NameTaken,
+  NotSupported,
+  /// The account was created with the normalized username provided
+  Success(String),
 }
 pub enum ServerLogin {
   Password(ServerPassword),
   OpenID(ServerOpenIdConnect),
 }
+impl ServerLogin {
+  /// Check whether the login mechanism grants a player a role, in addition to any the server's policy grants
+  pub async fn has_role(&self, player: &str, role: ServerRole) -> bool {
+    match self {
+      ServerLogin::Password(_) => false,
+      ServerLogin::OpenID(l) => l.has_role(player, role).await,
+    }
+  }
+}
 impl Login for ServerLogin {
   fn administration_request(&self, request: LoginRequest) -> impl Future<Output = LoginResponse> + Send {
     async move {
@@ -38,7 +74,14 @@ impl Login for ServerLogin {
     }
   }
 
-  fn http_handle(&self, req: Request<Incoming>) -> impl Future<Output = AuthResult> + Send {
+  fn can_register(&self) -> bool {
+    match self {
+      ServerLogin::Password(l) => l.can_register(),
+      ServerLogin::OpenID(l) => l.can_register(),
+    }
+  }
+
+  fn http_handle(&self, req: Request<Full<Bytes>>) -> impl Future<Output = AuthResult> + Send {
     async move {
       match self {
         ServerLogin::Password(l) => l.http_handle(req).await,
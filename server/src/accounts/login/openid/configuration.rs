@@ -7,6 +7,37 @@ pub struct OIConnectConfiguration {
   provider: OIConnectEndpoint,
   client_id: String,
   client_secret: String,
+  /// Players whose claims match this rule are administrators
+  #[serde(default)]
+  admin_claim: Option<ClaimRule>,
+  /// Players whose claims match this rule can create assets
+  #[serde(default)]
+  create_claim: Option<ClaimRule>,
+  /// Players whose claims do not match this rule have their accounts locked
+  #[serde(default)]
+  required_claim: Option<ClaimRule>,
+}
+/// A test on a claim in the identity token provided when a player logs in
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ClaimRule {
+  claim: String,
+  #[serde(flatten)]
+  test: ClaimTest,
+}
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimTest {
+  /// The claim is a list that contains this value
+  Contains(String),
+  /// The claim is this value
+  Equals(String),
+}
+/// The claim rules for a provider, which are checked every time a player logs in
+#[derive(Debug, Default)]
+pub struct ClaimRules {
+  pub admin: Option<ClaimRule>,
+  pub create: Option<ClaimRule>,
+  pub required: Option<ClaimRule>,
 }
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
@@ -52,7 +83,23 @@ impl OIConnectConfiguration {
       openidconnect::RedirectUrl::new(format!("https://{}/{}", server_name, OIDC_AUTH_RETURN_PATH))
         .map_err(|e| format!("Failed to create OpenID callback URL: {}", e))?,
     );
-    Ok((issuer, OpenIdClient { name, client }))
+    Ok((
+      issuer,
+      OpenIdClient { name, client, rules: ClaimRules { admin: self.admin_claim, create: self.create_claim, required: self.required_claim } },
+    ))
+  }
+}
+
+impl ClaimRule {
+  pub fn matches(&self, claims: &serde_json::Map<String, serde_json::Value>) -> bool {
+    match (claims.get(&self.claim), &self.test) {
+      (Some(serde_json::Value::Array(values)), ClaimTest::Contains(expected)) => values.iter().any(|value| value.as_str() == Some(expected.as_str())),
+      (Some(serde_json::Value::String(value)), ClaimTest::Contains(expected)) => value.split_whitespace().any(|value| value == expected),
+      (Some(serde_json::Value::String(value)), ClaimTest::Equals(expected)) => value == expected,
+      (Some(serde_json::Value::Bool(value)), ClaimTest::Equals(expected)) => value.to_string() == *expected,
+      (Some(serde_json::Value::Number(value)), ClaimTest::Equals(expected)) => value.to_string() == *expected,
+      _ => false,
+    }
   }
 }
 
@@ -73,3 +120,66 @@ impl OIConnectEndpoint {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{ClaimRule, ClaimTest};
+  use serde_json::json;
+
+  fn matches(test: ClaimTest, claim: serde_json::Value) -> bool {
+    let rule = ClaimRule { claim: "groups".to_string(), test };
+    let claims = match json!({ "groups": claim, "sub": "1234" }) {
+      serde_json::Value::Object(claims) => claims,
+      _ => unreachable!(),
+    };
+    rule.matches(&claims)
+  }
+
+  #[test]
+  fn contains() {
+    let cases = [
+      (json!(["players", "admins"]), true),
+      (json!(["players"]), false),
+      (json!(["admins-old"]), false),
+      (json!([1, true, "admins"]), true),
+      (json!([]), false),
+      (json!("players admins"), true),
+      (json!("admins"), true),
+      (json!("players,admins"), false),
+      (json!(true), false),
+      (json!(5), false),
+      (json!({ "admins": true }), false),
+      (json!(null), false),
+    ];
+    for (claim, expected) in cases {
+      assert_eq!(matches(ClaimTest::Contains("admins".to_string()), claim.clone()), expected, "contains admins in {}", claim);
+    }
+  }
+
+  #[test]
+  fn equals() {
+    let cases = [
+      ("admins", json!("admins"), true),
+      ("admins", json!("Admins"), false),
+      ("admins", json!("players admins"), false),
+      ("admins", json!(["admins"]), false),
+      ("true", json!(true), true),
+      ("true", json!(false), false),
+      ("false", json!(false), true),
+      ("42", json!(42), true),
+      ("42", json!(42.5), false),
+      ("42.5", json!(42.5), true),
+      ("42", json!("42"), true),
+      ("null", json!(null), false),
+    ];
+    for (expected_value, claim, expected) in cases {
+      assert_eq!(matches(ClaimTest::Equals(expected_value.to_string()), claim.clone()), expected, "{} equals {}", claim, expected_value);
+    }
+  }
+
+  #[test]
+  fn missing_claim() {
+    let rule = ClaimRule { claim: "groups".to_string(), test: ClaimTest::Equals("admins".to_string()) };
+    assert!(!rule.matches(&serde_json::Map::new()));
+  }
+}
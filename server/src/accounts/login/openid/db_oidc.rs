@@ -1,10 +1,14 @@
 use crate::accounts::db_auth::schema_oidc::auth_oidc::dsl as auth_oidc_schema;
 use crate::accounts::db_auth::OIDC_MIGRATIONS;
+use crate::accounts::login::openid::configuration::ClaimRules;
 use crate::accounts::login::openid::OpenIdConnectProvider;
 use crate::accounts::AuthResult;
 use crate::database::connect::DatabaseBackend;
 use diesel::prelude::*;
+use hyper::{Response, StatusCode};
 use openidconnect::core::CoreClient;
+use spadina_core::access::AccountLockState;
+use spadina_core::net::server::administration::ServerRole;
 use std::collections::BTreeMap;
 use std::error::Error;
 use std::future::Future;
@@ -12,6 +16,7 @@ use std::future::Future;
 pub struct OpenIdClient {
   pub name: String,
   pub client: CoreClient,
+  pub rules: ClaimRules,
 }
 
 pub struct DatabaseOpenIdConnect {
@@ -42,6 +47,174 @@ impl DatabaseOpenIdConnect {
     }
     Ok(DatabaseOpenIdConnect { pool, clients })
   }
+  /// Remove an account, returning false if the account does not exist
+  fn delete(&self, username: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let count = match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::delete(auth_oidc_schema::auth_oidc.filter(auth_oidc_schema::name.eq(username))).execute(&mut db_connection)?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::delete(auth_oidc_schema::auth_oidc.filter(auth_oidc_schema::name.eq(username))).execute(&mut db_connection)?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::delete(auth_oidc_schema::auth_oidc.filter(auth_oidc_schema::name.eq(username))).execute(&mut db_connection)?
+      }
+    };
+    Ok(count > 0)
+  }
+  /// Get the issuer for an account, if the account exists, is not locked, and belongs to the subject
+  fn get_issuer(&self, username: &str, subject: &str) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    Ok(match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_oidc_schema::auth_oidc
+          .select(auth_oidc_schema::issuer)
+          .filter(auth_oidc_schema::name.eq(username).and(auth_oidc_schema::subject.eq(subject)).and(auth_oidc_schema::locked.eq(false)))
+          .get_result::<String>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_oidc_schema::auth_oidc
+          .select(auth_oidc_schema::issuer)
+          .filter(auth_oidc_schema::name.eq(username).and(auth_oidc_schema::subject.eq(subject)).and(auth_oidc_schema::locked.eq(false)))
+          .get_result::<String>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_oidc_schema::auth_oidc
+          .select(auth_oidc_schema::issuer)
+          .filter(auth_oidc_schema::name.eq(username).and(auth_oidc_schema::subject.eq(subject)).and(auth_oidc_schema::locked.eq(false)))
+          .get_result::<String>(&mut db_connection)
+          .optional()?
+      }
+    })
+  }
+  /// Check whether the claims provided at the player's last login granted a role
+  fn get_role(&self, username: &str, role: ServerRole) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let roles = match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_oidc_schema::auth_oidc
+          .select((auth_oidc_schema::admin, auth_oidc_schema::creator))
+          .filter(auth_oidc_schema::name.eq(username).and(auth_oidc_schema::locked.eq(false)))
+          .get_result::<(bool, bool)>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_oidc_schema::auth_oidc
+          .select((auth_oidc_schema::admin, auth_oidc_schema::creator))
+          .filter(auth_oidc_schema::name.eq(username).and(auth_oidc_schema::locked.eq(false)))
+          .get_result::<(bool, bool)>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_oidc_schema::auth_oidc
+          .select((auth_oidc_schema::admin, auth_oidc_schema::creator))
+          .filter(auth_oidc_schema::name.eq(username).and(auth_oidc_schema::locked.eq(false)))
+          .get_result::<(bool, bool)>(&mut db_connection)
+          .optional()?
+      }
+    };
+    Ok(match (roles, role) {
+      (Some((admin, _)), ServerRole::Administrator) => admin,
+      (Some((_, creator)), ServerRole::Creator) => creator,
+      (None, _) => false,
+    })
+  }
+  /// Check whether an account is locked, returning none if the account does not exist
+  fn get_locked(&self, username: &str) -> Result<Option<bool>, Box<dyn Error + Send + Sync>> {
+    Ok(match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_oidc_schema::auth_oidc
+          .select(auth_oidc_schema::locked)
+          .filter(auth_oidc_schema::name.eq(username))
+          .get_result::<bool>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_oidc_schema::auth_oidc
+          .select(auth_oidc_schema::locked)
+          .filter(auth_oidc_schema::name.eq(username))
+          .get_result::<bool>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_oidc_schema::auth_oidc
+          .select(auth_oidc_schema::locked)
+          .filter(auth_oidc_schema::name.eq(username))
+          .get_result::<bool>(&mut db_connection)
+          .optional()?
+      }
+    })
+  }
+  fn set_locked(&self, username: &str, locked: bool) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let count = match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(auth_oidc_schema::auth_oidc.filter(auth_oidc_schema::name.eq(username).and(auth_oidc_schema::locked.ne(locked))))
+          .set(auth_oidc_schema::locked.eq(locked))
+          .execute(&mut db_connection)?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(auth_oidc_schema::auth_oidc.filter(auth_oidc_schema::name.eq(username).and(auth_oidc_schema::locked.ne(locked))))
+          .set(auth_oidc_schema::locked.eq(locked))
+          .execute(&mut db_connection)?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(auth_oidc_schema::auth_oidc.filter(auth_oidc_schema::name.eq(username).and(auth_oidc_schema::locked.ne(locked))))
+          .set(auth_oidc_schema::locked.eq(locked))
+          .execute(&mut db_connection)?
+      }
+    };
+    Ok(count > 0)
+  }
+  fn set_roles(&self, username: &str, admin: bool, creator: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(auth_oidc_schema::auth_oidc.filter(auth_oidc_schema::name.eq(username)))
+          .set((auth_oidc_schema::admin.eq(admin), auth_oidc_schema::creator.eq(creator)))
+          .execute(&mut db_connection)?;
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(auth_oidc_schema::auth_oidc.filter(auth_oidc_schema::name.eq(username)))
+          .set((auth_oidc_schema::admin.eq(admin), auth_oidc_schema::creator.eq(creator)))
+          .execute(&mut db_connection)?;
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(auth_oidc_schema::auth_oidc.filter(auth_oidc_schema::name.eq(username)))
+          .set((auth_oidc_schema::admin.eq(admin), auth_oidc_schema::creator.eq(creator)))
+          .execute(&mut db_connection)?;
+      }
+    }
+    Ok(())
+  }
 }
 impl OpenIdConnectProvider for DatabaseOpenIdConnect {
   type Callback = String;
@@ -92,49 +265,96 @@ impl OpenIdConnectProvider for DatabaseOpenIdConnect {
     self.client_for(&*request)
   }
 
+  fn delete_account(&self, username: &str) -> impl Future<Output = Option<bool>> + Send {
+    async move {
+      match self.delete(username) {
+        Ok(deleted) => Some(deleted),
+        Err(e) => {
+          eprintln!("Failed to delete OpenID account for {}: {}", username, e);
+          None
+        }
+      }
+    }
+  }
+
   fn start_login(&self, player: String) -> impl Future<Output = Self::Callback> + Send {
     async move { player }
   }
 
-  fn finish_login(&self, callback: Self::Callback, subject: &str) -> impl Future<Output = AuthResult> + Send {
+  fn finish_login(
+    &self,
+    callback: Self::Callback,
+    subject: &str,
+    claims: &serde_json::Map<String, serde_json::Value>,
+  ) -> impl Future<Output = AuthResult> + Send {
     async move {
-      let result = match &self.pool {
-        DatabaseBackend::SQLite(pool) => pool.get().map(|mut db_connection| {
-          auth_oidc_schema::auth_oidc
-            .select(diesel::dsl::count_star())
-            .filter(auth_oidc_schema::name.eq(&callback).and(auth_oidc_schema::subject.eq(subject)).and(auth_oidc_schema::locked.eq(false)))
-            .get_result::<i64>(&mut db_connection)
-        }),
-        #[cfg(feature = "postgres")]
-        DatabaseBackend::PostgreSQL(pool) => pool.get().map(|mut db_connection| {
-          auth_oidc_schema::auth_oidc
-            .select(diesel::dsl::count_star())
-            .filter(auth_oidc_schema::name.eq(&callback).and(auth_oidc_schema::subject.eq(subject)).and(auth_oidc_schema::locked.eq(false)))
-            .get_result::<i64>(&mut db_connection)
-        }),
-        #[cfg(feature = "mysql")]
-        DatabaseBackend::MySql(pool) => pool.get().map(|mut db_connection| {
-          auth_oidc_schema::auth_oidc
-            .select(diesel::dsl::count_star())
-            .filter(auth_oidc_schema::name.eq(&callback).and(auth_oidc_schema::subject.eq(subject)).and(auth_oidc_schema::locked.eq(false)))
-            .get_result::<i64>(&mut db_connection)
-        }),
+      let client = match self.get_issuer(&callback, subject) {
+        Ok(issuer) => match issuer.and_then(|issuer| self.clients.get(&issuer)) {
+          Some(client) => client,
+          None => return AuthResult::Failure,
+        },
+        Err(e) => {
+          eprintln!("Failed to fetch OpenID information for {}: {}", &callback, e);
+          return AuthResult::Failure;
+        }
       };
-      match result {
-        Ok(Ok(count)) => {
-          if count > 0 {
-            AuthResult::RedirectToken(callback)
-          } else {
-            AuthResult::Failure
-          }
+      // The account is not locked in the database, so the player can log in again as soon as the identity provider grants the claims
+      if client.rules.required.as_ref().map(|rule| !rule.matches(claims)).unwrap_or(false) {
+        if let Err(e) = self.set_roles(&callback, false, false) {
+          eprintln!("Failed to remove OpenID roles for {}: {}", &callback, e);
         }
-        Ok(Err(e)) => {
-          eprintln!("Failed to fetch OpenId information for {}: {}", &callback, e);
+        return AuthResult::Revoke(
+          callback,
+          Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body("Your account is not permitted to use this server. Contact the server administrator.".into()),
+        );
+      }
+      let admin = client.rules.admin.as_ref().map(|rule| rule.matches(claims)).unwrap_or(false);
+      let creator = client.rules.create.as_ref().map(|rule| rule.matches(claims)).unwrap_or(false);
+      match self.set_roles(&callback, admin, creator) {
+        Ok(()) => AuthResult::RedirectToken(callback),
+        Err(e) => {
+          eprintln!("Failed to update OpenID roles for {}: {}", &callback, e);
           AuthResult::Failure
         }
+      }
+    }
+  }
+
+  fn has_role(&self, username: &str, role: ServerRole) -> impl Future<Output = bool> + Send {
+    async move {
+      match self.get_role(username, role) {
+        Ok(granted) => granted,
         Err(e) => {
-          eprintln!("Failed to get connection to fetch OpenID information for {}: {}", &callback, e);
-          AuthResult::Failure
+          eprintln!("Failed to fetch OpenID roles for {}: {}", username, e);
+          false
+        }
+      }
+    }
+  }
+
+  fn lock_account(&self, username: &str, locked: bool) -> impl Future<Output = Option<bool>> + Send {
+    async move {
+      match self.set_locked(username, locked) {
+        Ok(changed) => Some(changed),
+        Err(e) => {
+          eprintln!("Failed to set lock on OpenID account for {}: {}", username, e);
+          None
+        }
+      }
+    }
+  }
+
+  fn lock_status(&self, username: &str) -> impl Future<Output = AccountLockState> + Send {
+    async move {
+      match self.get_locked(username) {
+        Ok(Some(true)) => AccountLockState::Locked,
+        Ok(Some(false)) => AccountLockState::Unlocked,
+        Ok(None) => AccountLockState::Unknown,
+        Err(e) => {
+          eprintln!("Failed to get lock on OpenID account for {}: {}", username, e);
+          AccountLockState::Unknown
         }
       }
     }
@@ -1,13 +1,17 @@
 use crate::accounts::login::openid::db_oidc::DatabaseOpenIdConnect;
-use crate::accounts::login::{Login, LoginRequest, LoginResponse};
+use crate::accounts::login::{Login, LoginRequest, LoginResponse, Registration};
 use crate::accounts::AuthResult;
 use chrono::{DateTime, Duration, Utc};
 use http::{Method, Response};
+use http_body_util::Full;
+use hyper::body::Bytes;
 use hyper::header::LOCATION;
-use hyper::{body::Incoming, http, Request};
-use openidconnect::core::{CoreAuthenticationFlow, CoreClient};
+use hyper::{http, Request};
+use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreIdToken};
 use openidconnect::{AuthorizationCode, TokenResponse};
 use openidconnect::{CsrfToken, Nonce, PkceCodeChallenge, PkceCodeVerifier, Scope};
+use spadina_core::access::AccountLockState;
+use spadina_core::net::server::administration::ServerRole;
 use spadina_core::net::server::auth::AuthScheme;
 use spadina_core::net::OIDC_AUTH_START_PATH;
 use std::collections::BTreeMap;
@@ -24,8 +28,21 @@ pub trait OpenIdConnectProvider: Send + Sync {
   /// Check if the username and password provided are valid
   fn client_for(&self, username: &str) -> impl Future<Output = Option<&CoreClient>> + Send;
   fn client_for_active<'a>(&'a self, request: &Self::Callback) -> impl Future<Output = Option<&'a CoreClient>> + Send;
+  /// Remove a player's account, returning false if the account does not exist
+  fn delete_account(&self, username: &str) -> impl Future<Output = Option<bool>> + Send;
   fn start_login(&self, player: String) -> impl Future<Output = Self::Callback> + Send;
-  fn finish_login(&self, callback: Self::Callback, subject: &str) -> impl Future<Output = AuthResult> + Send;
+  /// Complete a login using the verified subject and all the claims from the identity token
+  fn finish_login(
+    &self,
+    callback: Self::Callback,
+    subject: &str,
+    claims: &serde_json::Map<String, serde_json::Value>,
+  ) -> impl Future<Output = AuthResult> + Send;
+  /// Check whether a player was granted a role by their claims the last time they logged in
+  fn has_role(&self, username: &str, role: ServerRole) -> impl Future<Output = bool> + Send;
+  fn lock_account(&self, username: &str, locked: bool) -> impl Future<Output = Option<bool>> + Send;
+  /// Check whether a player's account is locked
+  fn lock_status(&self, username: &str) -> impl Future<Output = AccountLockState> + Send;
 }
 
 pub struct OpenIdConnect<Provider: OpenIdConnectProvider> {
@@ -48,14 +65,54 @@ impl<T: OpenIdConnectProvider> From<T> for OpenIdConnect<T> {
   }
 }
 
+impl<T: OpenIdConnectProvider> OpenIdConnect<T> {
+  pub async fn has_role(&self, player: &str, role: ServerRole) -> bool {
+    self.provider.has_role(player, role).await
+  }
+}
+
+impl ServerOpenIdConnect {
+  pub async fn has_role(&self, player: &str, role: ServerRole) -> bool {
+    match self {
+      ServerOpenIdConnect::Database(o) => o.has_role(player, role).await,
+    }
+  }
+}
+
+/// Get all the claims in an identity token, including ones the OpenID Connect library does not know about
+///
+/// The token must already have been verified.
+fn raw_claims(token: &CoreIdToken) -> serde_json::Map<String, serde_json::Value> {
+  use base64::Engine;
+  token
+    .to_string()
+    .split('.')
+    .nth(1)
+    .and_then(|payload| base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload).ok())
+    .and_then(|payload| serde_json::from_slice(&payload).ok())
+    .unwrap_or_default()
+}
+
 impl<T: OpenIdConnectProvider> Login for OpenIdConnect<T> {
   fn administration_request(&self, request: LoginRequest) -> impl Future<Output = LoginResponse> + Send {
-    async move { todo!() }
+    async move {
+      match request {
+        LoginRequest::DeleteAccount(player) => LoginResponse::DeleteAccount(self.provider.delete_account(player).await),
+        LoginRequest::LockAccount(player, locked) => LoginResponse::LockAccount(self.provider.lock_account(player, locked).await),
+        LoginRequest::LockStatus(player) => LoginResponse::LockStatus(self.provider.lock_status(player).await),
+        LoginRequest::Register { .. } => LoginResponse::Register(Registration::NotSupported),
+        LoginRequest::ResetPassword(_) => LoginResponse::ResetPassword(None),
+      }
+    }
+  }
+
+  fn can_register(&self) -> bool {
+    false
   }
 
-  fn http_handle(&self, req: Request<Incoming>) -> impl Future<Output = AuthResult> + Send {
+  fn http_handle(&self, req: Request<Full<Bytes>>) -> impl Future<Output = AuthResult> + Send {
     async move {
-      fn query_parameter<'a>(name: &str, req: &'a Request<Incoming>) -> Option<std::borrow::Cow<'a, str>> {
+      fn query_parameter<'a>(name: &str, req: &'a Request<Full<Bytes>>) -> Option<std::borrow::Cow<'a, str>> {
         req.uri().query().map(|q| form_urlencoded::parse(q.as_bytes()).filter(|(n, _)| n == name).map(|(_, value)| value).next()).flatten()
       }
       match (req.method(), req.uri().path()) {
@@ -114,14 +171,15 @@ impl<T: OpenIdConnectProvider> Login for OpenIdConnect<T> {
             Ok(auth) => auth,
             Err(e) => return AuthResult::Page(Response::builder().status(http::StatusCode::BAD_REQUEST).body(e.to_string().into())),
           };
-          match auth.id_token().map(|id| id.claims(&client.id_token_verifier(), &nonce)) {
-                Some(Ok(id_token)) =>
-                   self.provider.finish_login(callback, &*id_token.subject()) .await,
-                Some(Err(e)) =>
-                    AuthResult::Page(Response::builder().status(http::StatusCode::FORBIDDEN).body(format!("Failed to validate OpenId Connect claim: {:?}", e).into())),
-                None =>
-                    AuthResult::Page(Response::builder().status(http::StatusCode::BAD_REQUEST).body("The Spadina server has be connected to a non-OpenID Connect-enable OAuth server. Contact your server administrator. If you are the server administrator, choose a different OpenID server or adjust it to enable OpenID Connect.".into()))
-            }
+          match auth.id_token() {
+            Some(id_token) => match id_token.claims(&client.id_token_verifier(), &nonce) {
+              Ok(claims) => self.provider.finish_login(callback, claims.subject(), &raw_claims(id_token)).await,
+              Err(e) => AuthResult::Page(
+                Response::builder().status(http::StatusCode::FORBIDDEN).body(format!("Failed to validate OpenId Connect claim: {:?}", e).into()),
+              ),
+            },
+            None => AuthResult::Page(Response::builder().status(http::StatusCode::BAD_REQUEST).body("The Spadina server has be connected to a non-OpenID Connect-enable OAuth server. Contact your server administrator. If you are the server administrator, choose a different OpenID server or adjust it to enable OpenID Connect.".into())),
+          }
         }
 
         _ => AuthResult::NotHandled,
@@ -153,7 +211,13 @@ impl Login for ServerOpenIdConnect {
     }
   }
 
-  fn http_handle(&self, req: Request<Incoming>) -> impl Future<Output = AuthResult> + Send {
+  fn can_register(&self) -> bool {
+    match self {
+      ServerOpenIdConnect::Database(o) => o.can_register(),
+    }
+  }
+
+  fn http_handle(&self, req: Request<Full<Bytes>>) -> impl Future<Output = AuthResult> + Send {
     async move {
       match self {
         ServerOpenIdConnect::Database(o) => o.http_handle(req).await,
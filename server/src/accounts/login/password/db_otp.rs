@@ -3,6 +3,7 @@ use crate::accounts::db_auth::OTP_MIGRATIONS;
 use crate::accounts::login::password::otp::OneTimePasswordStore;
 use crate::database::connect::DatabaseBackend;
 use diesel::prelude::*;
+use spadina_core::access::AccountLockState;
 use std::error::Error;
 use std::future::Future;
 
@@ -76,6 +77,43 @@ impl OneTimePasswordStore for DatabaseOneTimePasswords {
     }
   }
 
+  fn lock_status(&self, username: &str) -> impl Future<Output = AccountLockState> + Send {
+    async move {
+      let result = match &self.0 {
+        DatabaseBackend::SQLite(pool) => {
+          let Ok(mut db_connection) = pool.get() else {
+            return AccountLockState::Unknown;
+          };
+          auth_otp_schema::auth_otp.select(auth_otp_schema::locked).filter(auth_otp_schema::name.eq(username)).load::<bool>(&mut db_connection)
+        }
+        #[cfg(feature = "postgres")]
+        DatabaseBackend::PostgreSQL(pool) => {
+          let Ok(mut db_connection) = pool.get() else {
+            return AccountLockState::Unknown;
+          };
+          auth_otp_schema::auth_otp.select(auth_otp_schema::locked).filter(auth_otp_schema::name.eq(username)).load::<bool>(&mut db_connection)
+        }
+        #[cfg(feature = "mysql")]
+        DatabaseBackend::MySql(pool) => {
+          let Ok(mut db_connection) = pool.get() else {
+            return AccountLockState::Unknown;
+          };
+          auth_otp_schema::auth_otp.select(auth_otp_schema::locked).filter(auth_otp_schema::name.eq(username)).load::<bool>(&mut db_connection)
+        }
+      };
+
+      match result {
+        Ok(locks) if locks.is_empty() => AccountLockState::Unknown,
+        Ok(locks) if locks.into_iter().all(|locked| locked) => AccountLockState::Locked,
+        Ok(_) => AccountLockState::Unlocked,
+        Err(e) => {
+          eprintln!("Failed to get locks on OTPs for {}: {}", username, e);
+          AccountLockState::Unknown
+        }
+      }
+    }
+  }
+
   fn secret(&self, username: &str) -> impl Future<Output = Vec<String>> + Send {
     async move {
       let result = match &self.0 {
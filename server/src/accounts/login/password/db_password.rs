@@ -0,0 +1,500 @@
+use crate::accounts::db_auth::schema_password::auth_password::dsl as auth_password_schema;
+use crate::accounts::db_auth::PASSWORD_MIGRATIONS;
+use crate::accounts::login::password::Password;
+use crate::accounts::login::Registration;
+use crate::database::connect::DatabaseBackend;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use diesel::prelude::*;
+use rand::distributions::{Alphanumeric, DistString};
+use spadina_core::access::AccountLockState;
+use std::error::Error;
+use std::future::Future;
+
+/// Passwords stored as salted hashes in a database managed by this server
+///
+/// New passwords are hashed using Argon2, but existing Argon2 or scrypt hashes in PHC format can be used
+pub struct DatabasePasswords {
+  pool: DatabaseBackend,
+}
+
+impl DatabasePasswords {
+  pub fn new(pool: DatabaseBackend) -> Result<Self, Box<dyn Error + Send + Sync>> {
+    match &pool {
+      DatabaseBackend::SQLite(pool) => {
+        use diesel_migrations::MigrationHarness;
+        let mut db_connection = pool.get()?;
+        db_connection.run_pending_migrations(PASSWORD_MIGRATIONS)?;
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        use diesel_migrations::MigrationHarness;
+        let mut db_connection = pool.get()?;
+        db_connection.run_pending_migrations(PASSWORD_MIGRATIONS)?;
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        use diesel_migrations::MigrationHarness;
+        let mut db_connection = pool.get()?;
+        db_connection.run_pending_migrations(PASSWORD_MIGRATIONS)?;
+      }
+    }
+    Ok(DatabasePasswords { pool })
+  }
+  fn create_account(&self, username: &str, hash: &str) -> Result<Registration, Box<dyn Error + Send + Sync>> {
+    Ok(match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+          let existing = auth_password_schema::auth_password
+            .select(diesel::dsl::count_star())
+            .filter(auth_password_schema::name.eq(username))
+            .get_result::<i64>(db_connection)?;
+          if existing > 0 {
+            return Ok(Registration::NameTaken);
+          }
+          diesel::insert_into(auth_password_schema::auth_password)
+            .values((auth_password_schema::name.eq(username), auth_password_schema::hash.eq(hash)))
+            .execute(db_connection)?;
+          Ok(Registration::Success(username.to_string()))
+        })?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+          let existing = auth_password_schema::auth_password
+            .select(diesel::dsl::count_star())
+            .filter(auth_password_schema::name.eq(username))
+            .get_result::<i64>(db_connection)?;
+          if existing > 0 {
+            return Ok(Registration::NameTaken);
+          }
+          diesel::insert_into(auth_password_schema::auth_password)
+            .values((auth_password_schema::name.eq(username), auth_password_schema::hash.eq(hash)))
+            .execute(db_connection)?;
+          Ok(Registration::Success(username.to_string()))
+        })?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+          let existing = auth_password_schema::auth_password
+            .select(diesel::dsl::count_star())
+            .filter(auth_password_schema::name.eq(username))
+            .get_result::<i64>(db_connection)?;
+          if existing > 0 {
+            return Ok(Registration::NameTaken);
+          }
+          diesel::insert_into(auth_password_schema::auth_password)
+            .values((auth_password_schema::name.eq(username), auth_password_schema::hash.eq(hash)))
+            .execute(db_connection)?;
+          Ok(Registration::Success(username.to_string()))
+        })?
+      }
+    })
+  }
+  fn delete_hash(&self, username: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let count = match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::delete(auth_password_schema::auth_password.filter(auth_password_schema::name.eq(username))).execute(&mut db_connection)?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::delete(auth_password_schema::auth_password.filter(auth_password_schema::name.eq(username))).execute(&mut db_connection)?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::delete(auth_password_schema::auth_password.filter(auth_password_schema::name.eq(username))).execute(&mut db_connection)?
+      }
+    };
+    Ok(count > 0)
+  }
+  /// Get the password hash for an account, if it exists and is not locked
+  fn get_hash(&self, username: &str) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    Ok(match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_password_schema::auth_password
+          .select(auth_password_schema::hash)
+          .filter(auth_password_schema::name.eq(username).and(auth_password_schema::locked.eq(false)))
+          .get_result::<String>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_password_schema::auth_password
+          .select(auth_password_schema::hash)
+          .filter(auth_password_schema::name.eq(username).and(auth_password_schema::locked.eq(false)))
+          .get_result::<String>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_password_schema::auth_password
+          .select(auth_password_schema::hash)
+          .filter(auth_password_schema::name.eq(username).and(auth_password_schema::locked.eq(false)))
+          .get_result::<String>(&mut db_connection)
+          .optional()?
+      }
+    })
+  }
+  fn get_locked(&self, username: &str) -> Result<Option<bool>, Box<dyn Error + Send + Sync>> {
+    Ok(match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_password_schema::auth_password
+          .select(auth_password_schema::locked)
+          .filter(auth_password_schema::name.eq(username))
+          .get_result::<bool>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_password_schema::auth_password
+          .select(auth_password_schema::locked)
+          .filter(auth_password_schema::name.eq(username))
+          .get_result::<bool>(&mut db_connection)
+          .optional()?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        auth_password_schema::auth_password
+          .select(auth_password_schema::locked)
+          .filter(auth_password_schema::name.eq(username))
+          .get_result::<bool>(&mut db_connection)
+          .optional()?
+      }
+    })
+  }
+  fn set_hash(&self, username: &str, hash: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let count = match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(auth_password_schema::auth_password.filter(auth_password_schema::name.eq(username)))
+          .set(auth_password_schema::hash.eq(hash))
+          .execute(&mut db_connection)?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(auth_password_schema::auth_password.filter(auth_password_schema::name.eq(username)))
+          .set(auth_password_schema::hash.eq(hash))
+          .execute(&mut db_connection)?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(auth_password_schema::auth_password.filter(auth_password_schema::name.eq(username)))
+          .set(auth_password_schema::hash.eq(hash))
+          .execute(&mut db_connection)?
+      }
+    };
+    Ok(count > 0)
+  }
+  /// Change the lock on an account, returning false if the account does not exist or is already in that state
+  fn set_locked(&self, username: &str, locked: bool) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let count = match &self.pool {
+      DatabaseBackend::SQLite(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(
+          auth_password_schema::auth_password.filter(auth_password_schema::name.eq(username).and(auth_password_schema::locked.ne(locked))),
+        )
+        .set(auth_password_schema::locked.eq(locked))
+        .execute(&mut db_connection)?
+      }
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::PostgreSQL(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(
+          auth_password_schema::auth_password.filter(auth_password_schema::name.eq(username).and(auth_password_schema::locked.ne(locked))),
+        )
+        .set(auth_password_schema::locked.eq(locked))
+        .execute(&mut db_connection)?
+      }
+      #[cfg(feature = "mysql")]
+      DatabaseBackend::MySql(pool) => {
+        let mut db_connection = pool.get()?;
+        diesel::update(
+          auth_password_schema::auth_password.filter(auth_password_schema::name.eq(username).and(auth_password_schema::locked.ne(locked))),
+        )
+        .set(auth_password_schema::locked.eq(locked))
+        .execute(&mut db_connection)?
+      }
+    };
+    Ok(count > 0)
+  }
+}
+
+lazy_static::lazy_static! {
+  /// A hash of a random password to check against when an account does not exist, so the check takes as long as it would for a real account
+  static ref DUMMY_HASH: String = {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+    let password = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+    Argon2::default().hash_password(password.as_bytes(), &salt).expect("Failed to hash dummy password").to_string()
+  };
+}
+
+/// Hash a password with Argon2 on a blocking thread, since hashing is deliberately slow
+async fn hash_password(password: String) -> Result<String, String> {
+  tokio::task::spawn_blocking(move || {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+    Argon2::default().hash_password(password.as_bytes(), &salt).map(|hash| hash.to_string()).map_err(|e| e.to_string())
+  })
+  .await
+  .map_err(|e| e.to_string())?
+}
+
+/// Check a password against an account's hash on a blocking thread
+///
+/// If there is no hash, the password is checked against a dummy hash and rejected, so the response time does not reveal which accounts exist.
+async fn check_password(hash: Option<String>, password: String) -> bool {
+  tokio::task::spawn_blocking(move || match hash {
+    Some(hash) => verify_password(&hash, &password),
+    None => {
+      verify_password(&DUMMY_HASH, &password);
+      false
+    }
+  })
+  .await
+  .unwrap_or(false)
+}
+
+fn verify_password(hash: &str, password: &str) -> bool {
+  match PasswordHash::new(hash) {
+    Ok(hash) => hash.verify_password(&[&Argon2::default() as &dyn PasswordVerifier, &scrypt::Scrypt], password).is_ok(),
+    Err(e) => {
+      eprintln!("Failed to parse stored password hash: {}", e);
+      false
+    }
+  }
+}
+
+impl Password for DatabasePasswords {
+  fn change_password(&self, username: String, old_password: String, new_password: String) -> impl Future<Output = Option<bool>> + Send {
+    async move {
+      let hash = match self.get_hash(&username) {
+        Ok(hash) => hash,
+        Err(e) => {
+          eprintln!("Failed to check password for {}: {}", &username, e);
+          return None;
+        }
+      };
+      if !check_password(hash, old_password).await {
+        return Some(false);
+      }
+      match hash_password(new_password).await.and_then(|hash| self.set_hash(&username, &hash).map_err(|e| e.to_string())) {
+        Ok(changed) => Some(changed),
+        Err(e) => {
+          eprintln!("Failed to change password for {}: {}", &username, e);
+          None
+        }
+      }
+    }
+  }
+
+  fn check_and_normalize(&self, username: String) -> impl Future<Output = Option<String>> + Send {
+    async move {
+      match self.get_hash(&username) {
+        Ok(None) => None,
+        Ok(Some(_)) => Some(username),
+        Err(e) => {
+          eprintln!("Failed to check password for {}: {}", &username, e);
+          None
+        }
+      }
+    }
+  }
+
+  fn accepts_registration(&self) -> bool {
+    true
+  }
+
+  fn delete_account(&self, username: &str) -> impl Future<Output = Option<bool>> + Send {
+    async move {
+      match self.delete_hash(username) {
+        Ok(deleted) => Some(deleted),
+        Err(e) => {
+          eprintln!("Failed to delete password for {}: {}", username, e);
+          None
+        }
+      }
+    }
+  }
+
+  fn lock_account(&self, username: &str, locked: bool) -> impl Future<Output = Option<bool>> + Send {
+    async move {
+      match self.set_locked(username, locked) {
+        Ok(changed) => Some(changed),
+        Err(e) => {
+          eprintln!("Failed to set lock on password for {}: {}", username, e);
+          None
+        }
+      }
+    }
+  }
+
+  fn lock_status(&self, username: &str) -> impl Future<Output = AccountLockState> + Send {
+    async move {
+      match self.get_locked(username) {
+        Ok(Some(true)) => AccountLockState::Locked,
+        Ok(Some(false)) => AccountLockState::Unlocked,
+        Ok(None) => AccountLockState::Unknown,
+        Err(e) => {
+          eprintln!("Failed to get lock on password for {}: {}", username, e);
+          AccountLockState::Unknown
+        }
+      }
+    }
+  }
+
+  fn register(&self, username: String, password: String) -> impl Future<Output = Registration> + Send {
+    async move {
+      if username.is_empty() || username.len() > 64 || username.contains(|c: char| c.is_whitespace() || c.is_control() || c == '@') {
+        return Registration::InvalidName;
+      }
+      let hash = match hash_password(password).await {
+        Ok(hash) => hash,
+        Err(e) => {
+          eprintln!("Failed to hash password for {}: {}", &username, e);
+          return Registration::InternalError;
+        }
+      };
+      match self.create_account(&username, &hash) {
+        Ok(registration) => registration,
+        Err(e) => {
+          eprintln!("Failed to register {}: {}", &username, e);
+          Registration::InternalError
+        }
+      }
+    }
+  }
+
+  fn reset_password(&self, username: &str) -> impl Future<Output = Option<String>> + Send {
+    async move {
+      let password = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+      match hash_password(password.clone()).await.and_then(|hash| self.set_hash(username, &hash).map_err(|e| e.to_string())) {
+        Ok(true) => Some(password),
+        Ok(false) => None,
+        Err(e) => {
+          eprintln!("Failed to reset password for {}: {}", username, e);
+          None
+        }
+      }
+    }
+  }
+
+  fn validate(&self, username: String, password: String) -> impl Future<Output = Option<String>> + Send {
+    async move {
+      match self.get_hash(&username) {
+        Ok(hash) => {
+          if check_password(hash, password).await {
+            Some(username)
+          } else {
+            None
+          }
+        }
+        Err(e) => {
+          eprintln!("Failed to check password for {}: {}", &username, e);
+          None
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{check_password, hash_password, DatabasePasswords};
+  use crate::accounts::login::password::Password;
+  use crate::accounts::login::Registration;
+  use crate::database::connect::DatabaseBackend;
+  use argon2::password_hash::{PasswordHasher, SaltString};
+  use std::path::PathBuf;
+
+  /// Create password storage backed by a fresh SQLite database, returning the database's file so it can be removed
+  fn passwords(name: &str) -> (DatabasePasswords, PathBuf) {
+    let db_file = std::env::temp_dir().join(format!("spadina-test-passwords-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&db_file);
+    let pool = DatabaseBackend::try_connect(format!("sqlite://{}", db_file.display())).unwrap();
+    (DatabasePasswords::new(pool).unwrap(), db_file)
+  }
+
+  fn cleanup(passwords: DatabasePasswords, db_file: PathBuf) {
+    drop(passwords);
+    let _ = std::fs::remove_file(db_file);
+  }
+
+  async fn register(passwords: &DatabasePasswords, username: &str, password: &str) -> Registration {
+    passwords.register(username.to_string(), password.to_string()).await
+  }
+
+  async fn validate(passwords: &DatabasePasswords, username: &str, password: &str) -> bool {
+    passwords.validate(username.to_string(), password.to_string()).await.is_some()
+  }
+
+  #[tokio::test]
+  async fn hashes_are_salted_argon2() {
+    let first = hash_password("hunter2".to_string()).await.unwrap();
+    let second = hash_password("hunter2".to_string()).await.unwrap();
+    assert!(first.starts_with("$argon2"));
+    assert_ne!(first, second);
+    assert!(check_password(Some(first.clone()), "hunter2".to_string()).await);
+    assert!(!check_password(Some(first), "hunter3".to_string()).await);
+    assert!(!check_password(None, "hunter2".to_string()).await);
+  }
+
+  #[tokio::test]
+  async fn scrypt_hashes_are_accepted() {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+    let hash =
+      scrypt::Scrypt.hash_password_customized(b"hunter2", None, None, scrypt::Params::new(10, 8, 1, 32).unwrap(), &salt).unwrap().to_string();
+    assert!(check_password(Some(hash.clone()), "hunter2".to_string()).await);
+    assert!(!check_password(Some(hash), "hunter3".to_string()).await);
+  }
+
+  #[tokio::test]
+  async fn register_then_validate() {
+    let (passwords, db_file) = passwords("register");
+    assert!(matches!(register(&passwords, "tester", "hunter2").await, Registration::Success(name) if name == "tester"));
+    assert!(matches!(register(&passwords, "tester", "other").await, Registration::NameTaken));
+    assert!(matches!(register(&passwords, "bad name", "hunter2").await, Registration::InvalidName));
+    assert!(matches!(register(&passwords, "tester@elsewhere", "hunter2").await, Registration::InvalidName));
+    assert!(validate(&passwords, "tester", "hunter2").await);
+    assert!(!validate(&passwords, "tester", "other").await);
+    assert!(!validate(&passwords, "nobody", "hunter2").await);
+    cleanup(passwords, db_file);
+  }
+
+  #[tokio::test]
+  async fn reset_replaces_password() {
+    let (passwords, db_file) = passwords("reset");
+    register(&passwords, "tester", "hunter2").await;
+    let password = passwords.reset_password("tester").await.expect("Password was not reset");
+    assert!(validate(&passwords, "tester", &password).await);
+    assert!(!validate(&passwords, "tester", "hunter2").await);
+    assert_eq!(passwords.reset_password("nobody").await, None);
+    cleanup(passwords, db_file);
+  }
+
+  #[tokio::test]
+  async fn change_requires_old_password() {
+    let (passwords, db_file) = passwords("change");
+    register(&passwords, "tester", "hunter2").await;
+    assert_eq!(passwords.change_password("tester".to_string(), "wrong".to_string(), "hunter3".to_string()).await, Some(false));
+    assert!(validate(&passwords, "tester", "hunter2").await);
+    assert_eq!(passwords.change_password("tester".to_string(), "hunter2".to_string(), "hunter3".to_string()).await, Some(true));
+    assert!(validate(&passwords, "tester", "hunter3").await);
+    assert!(!validate(&passwords, "tester", "hunter2").await);
+    assert_eq!(passwords.change_password("nobody".to_string(), "hunter2".to_string(), "hunter3".to_string()).await, Some(false));
+    cleanup(passwords, db_file);
+  }
+}
@@ -1,4 +1,5 @@
 use crate::accounts::login::password::otp::OneTimePasswordStore;
+use spadina_core::access::AccountLockState;
 use std::collections::BTreeMap;
 use std::future::Future;
 
@@ -18,6 +19,10 @@ impl OneTimePasswordStore for FixedOneTimePassword {
     async move { None }
   }
 
+  fn lock_status(&self, _username: &str) -> impl Future<Output = AccountLockState> + Send {
+    async move { AccountLockState::PermanentlyUnlocked }
+  }
+
   fn secret(&self, username: &str) -> impl Future<Output = Vec<String>> + Send {
     async move { self.0.get(username).cloned().into_iter().collect() }
   }
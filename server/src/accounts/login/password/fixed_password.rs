@@ -1,4 +1,6 @@
 use crate::accounts::login::password::Password;
+use crate::accounts::login::Registration;
+use spadina_core::access::AccountLockState;
 use std::collections::BTreeMap;
 use std::future::Future;
 
@@ -14,6 +16,14 @@ impl FromIterator<(String, String)> for FixedPasswords {
   }
 }
 impl Password for FixedPasswords {
+  fn accepts_registration(&self) -> bool {
+    false
+  }
+
+  fn change_password(&self, _username: String, _old_password: String, _new_password: String) -> impl Future<Output = Option<bool>> + Send {
+    async move { None }
+  }
+
   fn check_and_normalize(&self, username: String) -> impl Future<Output = Option<String>> + Send {
     async move {
       if self.0.contains_key(&username) {
@@ -24,10 +34,26 @@ impl Password for FixedPasswords {
     }
   }
 
+  fn delete_account(&self, _username: &str) -> impl Future<Output = Option<bool>> + Send {
+    async move { None }
+  }
+
   fn lock_account(&self, _username: &str, _locked: bool) -> impl Future<Output = Option<bool>> + Send {
     async move { None }
   }
 
+  fn lock_status(&self, _username: &str) -> impl Future<Output = AccountLockState> + Send {
+    async move { AccountLockState::PermanentlyUnlocked }
+  }
+
+  fn register(&self, _username: String, _password: String) -> impl Future<Output = Registration> + Send {
+    async move { Registration::NotSupported }
+  }
+
+  fn reset_password(&self, _username: &str) -> impl Future<Output = Option<String>> + Send {
+    async move { None }
+  }
+
   fn validate(&self, username: String, password: String) -> impl Future<Output = Option<String>> + Send {
     async move {
       if self.0.get(&username).map(|p| p == &password).unwrap_or(false) {
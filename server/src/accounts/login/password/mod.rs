@@ -1,19 +1,24 @@
 pub mod db_otp;
+pub mod db_password;
 pub mod fixed_otp;
 pub mod fixed_password;
 pub mod otp;
 pub mod php_bb;
 pub mod uru;
 
-use crate::accounts::login::{Login, LoginRequest, LoginResponse};
+use crate::accounts::login::{Login, LoginRequest, LoginResponse, Registration};
 use crate::accounts::AuthResult;
 use http::{Method, Response, StatusCode};
-use hyper::{body::Incoming, http, Request};
-use spadina_core::net::server::auth::{AuthScheme, PasswordRequest};
-use spadina_core::net::server::PASSWORD_AUTH_PATH;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{http, Request};
+use spadina_core::access::AccountLockState;
+use spadina_core::net::server::auth::{AuthScheme, PasswordChangeRequest, PasswordRequest};
+use spadina_core::net::server::{PASSWORD_AUTH_PATH, PASSWORD_CHANGE_PATH};
 use std::future::Future;
 
 pub enum ServerPassword {
+  Database(db_password::DatabasePasswords),
   DatabaseOneTimePassword(db_otp::DatabaseOneTimePasswords),
   FixedOneTimePassword(fixed_otp::FixedOneTimePassword),
   FixedPassword(fixed_password::FixedPasswords),
@@ -22,8 +27,24 @@ pub enum ServerPassword {
 }
 
 pub trait Password: Send + Sync {
+  /// Whether new accounts can be created in this password store
+  fn accepts_registration(&self) -> bool;
+  /// Change a player's password, if the old password is correct
+  ///
+  /// Returns none if passwords cannot be changed or false if the old password was incorrect
+  fn change_password(&self, username: String, old_password: String, new_password: String) -> impl Future<Output = Option<bool>> + Send;
   fn check_and_normalize(&self, username: String) -> impl Future<Output = Option<String>> + Send;
+  /// Remove a player's account
+  ///
+  /// Returns none if accounts cannot be deleted or false if the account does not exist
+  fn delete_account(&self, username: &str) -> impl Future<Output = Option<bool>> + Send;
   fn lock_account(&self, username: &str, locked: bool) -> impl Future<Output = Option<bool>> + Send;
+  /// Check whether a player's account is locked
+  fn lock_status(&self, username: &str) -> impl Future<Output = AccountLockState> + Send;
+  /// Create a new account for a player that has redeemed an invitation
+  fn register(&self, username: String, password: String) -> impl Future<Output = Registration> + Send;
+  /// Replace a player's password with a randomly generated one, which is returned
+  fn reset_password(&self, username: &str) -> impl Future<Output = Option<String>> + Send;
   fn validate(&self, username: String, password: String) -> impl Future<Output = Option<String>> + Send;
 }
 
@@ -34,13 +55,20 @@ where
   fn administration_request(&self, request: LoginRequest) -> impl Future<Output = LoginResponse> + Send {
     async move {
       match request {
+        LoginRequest::DeleteAccount(account) => LoginResponse::DeleteAccount(self.delete_account(account).await),
         LoginRequest::LockAccount(account, locked) => LoginResponse::LockAccount(self.lock_account(account, locked).await),
-        LoginRequest::Invite => LoginResponse::Invite(None),
+        LoginRequest::LockStatus(account) => LoginResponse::LockStatus(self.lock_status(account).await),
+        LoginRequest::Register { username, password } => LoginResponse::Register(self.register(username, password).await),
+        LoginRequest::ResetPassword(account) => LoginResponse::ResetPassword(self.reset_password(account).await),
       }
     }
   }
 
-  fn http_handle(&self, req: Request<Incoming>) -> impl Future<Output = AuthResult> + Send {
+  fn can_register(&self) -> bool {
+    self.accepts_registration()
+  }
+
+  fn http_handle(&self, req: Request<Full<Bytes>>) -> impl Future<Output = AuthResult> + Send {
     async move {
       match (req.method(), req.uri().path()) {
         (&Method::POST, PASSWORD_AUTH_PATH) => match crate::http_server::aggregate::<PasswordRequest<String>>(req).await {
@@ -50,6 +78,14 @@ where
             None => AuthResult::Page(Response::builder().status(StatusCode::UNAUTHORIZED).body("Invalid username or password".into())),
           },
         },
+        (&Method::POST, PASSWORD_CHANGE_PATH) => match crate::http_server::aggregate::<PasswordChangeRequest<String>>(req).await {
+          Err(response) => AuthResult::Page(response),
+          Ok(request) => match self.change_password(request.username, request.old_password, request.new_password).await {
+            Some(true) => AuthResult::Page(Response::builder().status(StatusCode::OK).body("Password changed".into())),
+            Some(false) => AuthResult::Page(Response::builder().status(StatusCode::UNAUTHORIZED).body("Invalid username or password".into())),
+            None => AuthResult::Page(Response::builder().status(StatusCode::FORBIDDEN).body("Passwords cannot be changed on this server".into())),
+          },
+        },
         _ => AuthResult::NotHandled,
       }
     }
@@ -65,9 +101,34 @@ where
 }
 
 impl Password for ServerPassword {
+  fn accepts_registration(&self) -> bool {
+    match self {
+      ServerPassword::Database(p) => p.accepts_registration(),
+      ServerPassword::DatabaseOneTimePassword(p) => p.accepts_registration(),
+      ServerPassword::FixedOneTimePassword(p) => p.accepts_registration(),
+      ServerPassword::FixedPassword(p) => p.accepts_registration(),
+      ServerPassword::PhpBB(p) => p.accepts_registration(),
+      ServerPassword::Uru(p) => p.accepts_registration(),
+    }
+  }
+
+  fn change_password(&self, username: String, old_password: String, new_password: String) -> impl Future<Output = Option<bool>> + Send {
+    async move {
+      match self {
+        ServerPassword::Database(p) => p.change_password(username, old_password, new_password).await,
+        ServerPassword::DatabaseOneTimePassword(p) => p.change_password(username, old_password, new_password).await,
+        ServerPassword::FixedOneTimePassword(p) => p.change_password(username, old_password, new_password).await,
+        ServerPassword::FixedPassword(p) => p.change_password(username, old_password, new_password).await,
+        ServerPassword::PhpBB(p) => p.change_password(username, old_password, new_password).await,
+        ServerPassword::Uru(p) => p.change_password(username, old_password, new_password).await,
+      }
+    }
+  }
+
   fn check_and_normalize(&self, username: String) -> impl Future<Output = Option<String>> + Send {
     async move {
       match self {
+        ServerPassword::Database(p) => p.check_and_normalize(username).await,
         ServerPassword::DatabaseOneTimePassword(p) => p.check_and_normalize(username).await,
         ServerPassword::FixedOneTimePassword(p) => p.check_and_normalize(username).await,
         ServerPassword::FixedPassword(p) => p.check_and_normalize(username).await,
@@ -77,9 +138,23 @@ impl Password for ServerPassword {
     }
   }
 
+  fn delete_account(&self, username: &str) -> impl Future<Output = Option<bool>> + Send {
+    async move {
+      match self {
+        ServerPassword::Database(p) => p.delete_account(username).await,
+        ServerPassword::DatabaseOneTimePassword(p) => p.delete_account(username).await,
+        ServerPassword::FixedOneTimePassword(p) => p.delete_account(username).await,
+        ServerPassword::FixedPassword(p) => p.delete_account(username).await,
+        ServerPassword::PhpBB(p) => p.delete_account(username).await,
+        ServerPassword::Uru(p) => p.delete_account(username).await,
+      }
+    }
+  }
+
   fn lock_account(&self, username: &str, locked: bool) -> impl Future<Output = Option<bool>> + Send {
     async move {
       match self {
+        ServerPassword::Database(p) => p.lock_account(username, locked).await,
         ServerPassword::DatabaseOneTimePassword(p) => p.lock_account(username, locked).await,
         ServerPassword::FixedOneTimePassword(p) => p.lock_account(username, locked).await,
         ServerPassword::FixedPassword(p) => p.lock_account(username, locked).await,
@@ -89,9 +164,49 @@ impl Password for ServerPassword {
     }
   }
 
+  fn lock_status(&self, username: &str) -> impl Future<Output = AccountLockState> + Send {
+    async move {
+      match self {
+        ServerPassword::Database(p) => p.lock_status(username).await,
+        ServerPassword::DatabaseOneTimePassword(p) => p.lock_status(username).await,
+        ServerPassword::FixedOneTimePassword(p) => p.lock_status(username).await,
+        ServerPassword::FixedPassword(p) => p.lock_status(username).await,
+        ServerPassword::PhpBB(p) => p.lock_status(username).await,
+        ServerPassword::Uru(p) => p.lock_status(username).await,
+      }
+    }
+  }
+
+  fn register(&self, username: String, password: String) -> impl Future<Output = Registration> + Send {
+    async move {
+      match self {
+        ServerPassword::Database(p) => p.register(username, password).await,
+        ServerPassword::DatabaseOneTimePassword(p) => p.register(username, password).await,
+        ServerPassword::FixedOneTimePassword(p) => p.register(username, password).await,
+        ServerPassword::FixedPassword(p) => p.register(username, password).await,
+        ServerPassword::PhpBB(p) => p.register(username, password).await,
+        ServerPassword::Uru(p) => p.register(username, password).await,
+      }
+    }
+  }
+
+  fn reset_password(&self, username: &str) -> impl Future<Output = Option<String>> + Send {
+    async move {
+      match self {
+        ServerPassword::Database(p) => p.reset_password(username).await,
+        ServerPassword::DatabaseOneTimePassword(p) => p.reset_password(username).await,
+        ServerPassword::FixedOneTimePassword(p) => p.reset_password(username).await,
+        ServerPassword::FixedPassword(p) => p.reset_password(username).await,
+        ServerPassword::PhpBB(p) => p.reset_password(username).await,
+        ServerPassword::Uru(p) => p.reset_password(username).await,
+      }
+    }
+  }
+
   fn validate(&self, username: String, password: String) -> impl Future<Output = Option<String>> + Send {
     async move {
       match self {
+        ServerPassword::Database(p) => p.validate(username, password).await,
         ServerPassword::DatabaseOneTimePassword(p) => p.validate(username, password).await,
         ServerPassword::FixedOneTimePassword(p) => p.validate(username, password).await,
         ServerPassword::FixedPassword(p) => p.validate(username, password).await,
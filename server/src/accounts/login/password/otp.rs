@@ -1,15 +1,26 @@
 use crate::accounts::login::password::Password;
+use crate::accounts::login::Registration;
 use otpauth::TOTP;
+use spadina_core::access::AccountLockState;
 use std::future::Future;
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub trait OneTimePasswordStore: Send + Sync {
   fn lock_account(&self, username: &str, locked: bool) -> impl Future<Output = Option<bool>> + Send;
+  fn lock_status(&self, username: &str) -> impl Future<Output = AccountLockState> + Send;
   fn secret(&self, username: &str) -> impl Future<Output = Vec<String>> + Send;
 }
 
 impl<T: OneTimePasswordStore> Password for T {
+  fn accepts_registration(&self) -> bool {
+    false
+  }
+
+  fn change_password(&self, _username: String, _old_password: String, _new_password: String) -> impl Future<Output = Option<bool>> + Send {
+    async move { None }
+  }
+
   fn check_and_normalize(&self, username: String) -> impl Future<Output = Option<String>> + Send {
     async move {
       if self.secret(&username).await.is_empty() {
@@ -20,10 +31,26 @@ impl<T: OneTimePasswordStore> Password for T {
     }
   }
 
+  fn delete_account(&self, _username: &str) -> impl Future<Output = Option<bool>> + Send {
+    async move { None }
+  }
+
   fn lock_account(&self, username: &str, locked: bool) -> impl Future<Output = Option<bool>> + Send {
     OneTimePasswordStore::lock_account(self, username, locked)
   }
 
+  fn lock_status(&self, username: &str) -> impl Future<Output = AccountLockState> + Send {
+    OneTimePasswordStore::lock_status(self, username)
+  }
+
+  fn register(&self, _username: String, _password: String) -> impl Future<Output = Registration> + Send {
+    async move { Registration::NotSupported }
+  }
+
+  fn reset_password(&self, _username: &str) -> impl Future<Output = Option<String>> + Send {
+    async move { None }
+  }
+
   fn validate(self: &Self, username: String, password: String) -> impl Future<Output = Option<String>> + Send {
     async move {
       let Ok(code) = u32::from_str(&password) else { return None };
@@ -1,7 +1,9 @@
 use crate::accounts::login::password::Password;
+use crate::accounts::login::Registration;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool};
 use sha1::Digest;
+use spadina_core::access::AccountLockState;
 use std::error::Error;
 use std::future::Future;
 
@@ -19,6 +21,14 @@ impl UruDatabase {
   }
 }
 impl Password for UruDatabase {
+  fn accepts_registration(&self) -> bool {
+    false
+  }
+
+  fn change_password(&self, _username: String, _old_password: String, _new_password: String) -> impl Future<Output = Option<bool>> + Send {
+    async move { None }
+  }
+
   fn check_and_normalize(&self, username: String) -> impl Future<Output = Option<String>> + Send {
     async move {
       match self.0.get() {
@@ -44,10 +54,26 @@ impl Password for UruDatabase {
     }
   }
 
+  fn delete_account(&self, _username: &str) -> impl Future<Output = Option<bool>> + Send {
+    async move { None }
+  }
+
   fn lock_account(&self, _username: &str, _locked: bool) -> impl Future<Output = Option<bool>> + Send {
     async move { None }
   }
 
+  fn lock_status(&self, _username: &str) -> impl Future<Output = AccountLockState> + Send {
+    async move { AccountLockState::PermanentlyUnlocked }
+  }
+
+  fn register(&self, _username: String, _password: String) -> impl Future<Output = Registration> + Send {
+    async move { Registration::NotSupported }
+  }
+
+  fn reset_password(&self, _username: &str) -> impl Future<Output = Option<String>> + Send {
+    async move { None }
+  }
+
   fn validate(&self, username: String, password: String) -> impl Future<Output = Option<String>> + Send {
     async move {
       match self.0.get() {
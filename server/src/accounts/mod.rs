@@ -1,18 +1,26 @@
+pub mod composite;
 pub mod configuration;
 pub mod db_auth;
 pub mod db_policy;
+pub mod invitation;
 pub mod ldap;
 pub mod login;
 pub mod policy;
+pub mod second_factor;
 
+use crate::accounts::composite::CompositeAccounts;
 use crate::accounts::db_policy::DatabaseBackedPolicy;
+use crate::accounts::invitation::Invitations;
 use crate::accounts::ldap::LightweightDirectory;
 use crate::accounts::login::{Login, LoginRequest, LoginResponse, ServerLogin};
 use crate::accounts::policy::{Policy, PolicyRequest};
 use http_body_util::Full;
 use hyper::body::Bytes;
-use hyper::{body::Incoming, http, Request};
-use spadina_core::net::server::auth::AuthScheme;
+use hyper::{http, Request};
+use spadina_core::access::LocalAccessSetting;
+use spadina_core::net::server::administration::ServerRole;
+use spadina_core::net::server::auth::{AuthAccounts, AuthMethod, AuthScheme};
+use spadina_core::net::server::INVITATION_PATH;
 use spadina_core::UpdateResult;
 use std::future::Future;
 
@@ -25,30 +33,84 @@ pub enum AuthResult {
   RedirectToken(String),
   /// Send an arbitrary HTTP response to the client
   Page(Result<http::Response<Full<Bytes>>, http::Error>),
+  /// The user should be denied access and any existing tokens and sessions for them revoked, then the response sent to the client
+  Revoke(String, Result<http::Response<Full<Bytes>>, http::Error>),
   /// The URL requested is not handled by this authentication provider
   NotHandled,
 }
 
 pub enum ServerAccounts {
-  Login(ServerLogin, DatabaseBackedPolicy),
+  Login(ServerLogin, DatabaseBackedPolicy, Invitations),
   LDAP(LightweightDirectory),
+  Composite(CompositeAccounts, Invitations),
+}
+
+impl ServerAccounts {
+  /// The invitations for new players, if this server manages its own accounts
+  pub fn invitations(&self) -> Option<&Invitations> {
+    match self {
+      ServerAccounts::Login(_, _, i) => Some(i),
+      ServerAccounts::LDAP(_) => None,
+      ServerAccounts::Composite(_, i) => Some(i),
+    }
+  }
+  /// Describe the authentication schemes players can use
+  pub fn methods(&self) -> Vec<AuthMethod<String>> {
+    match self {
+      ServerAccounts::Composite(c, _) => c.methods(),
+      _ => vec![AuthMethod { accounts: AuthAccounts::Any, scheme: self.scheme() }],
+    }
+  }
+  /// Refresh any account information cached from an external directory, returning the players whose accounts have since been disabled
+  pub async fn sync(&self) -> Vec<String> {
+    match self {
+      ServerAccounts::Login(_, _, _) => Vec::new(),
+      ServerAccounts::LDAP(l) => l.sync().await.unwrap_or_else(|e| {
+        eprintln!("Failed to synchronise with LDAP: {:?}", e);
+        Vec::new()
+      }),
+      ServerAccounts::Composite(c, _) => c.sync().await,
+    }
+  }
 }
 
 impl Login for ServerAccounts {
   fn administration_request(&self, request: LoginRequest) -> impl Future<Output = LoginResponse> + Send {
     async move {
       match self {
-        ServerAccounts::Login(l, _) => l.administration_request(request).await,
+        ServerAccounts::Login(l, _, _) => l.administration_request(request).await,
         ServerAccounts::LDAP(l) => l.administration_request(request).await,
+        ServerAccounts::Composite(c, _) => c.administration_request(request).await,
       }
     }
   }
 
-  fn http_handle(self: &Self, req: Request<Incoming>) -> impl Future<Output = AuthResult> + Send {
+  fn can_register(&self) -> bool {
+    match self {
+      ServerAccounts::Login(l, _, _) => l.can_register(),
+      ServerAccounts::LDAP(l) => l.can_register(),
+      ServerAccounts::Composite(c, _) => c.can_register(),
+    }
+  }
+
+  fn http_handle(self: &Self, req: Request<Full<Bytes>>) -> impl Future<Output = AuthResult> + Send {
     async move {
       match self {
-        ServerAccounts::Login(l, _) => l.http_handle(req).await,
+        ServerAccounts::Login(l, _, i) => {
+          if req.uri().path() == INVITATION_PATH {
+            i.http_handle(l, req).await
+          } else {
+            l.http_handle(req).await
+          }
+        }
         ServerAccounts::LDAP(l) => l.http_handle(req).await,
+        ServerAccounts::Composite(c, i) => {
+          if req.uri().path() == INVITATION_PATH {
+            i.http_handle(c, req).await
+          } else {
+            c.http_handle(req).await
+          }
+        }
       }
     }
   }
@@ -56,16 +118,18 @@ impl Login for ServerAccounts {
   fn normalize_username(&self, player: String) -> impl Future<Output = Result<String, ()>> + Send {
     async move {
       match self {
-        ServerAccounts::Login(l, _) => l.normalize_username(player).await,
+        ServerAccounts::Login(l, _, _) => l.normalize_username(player).await,
         ServerAccounts::LDAP(l) => l.normalize_username(player).await,
+        ServerAccounts::Composite(c, _) => c.normalize_username(player).await,
       }
     }
   }
 
   fn scheme(&self) -> AuthScheme {
     match self {
-      ServerAccounts::Login(l, _) => l.scheme(),
+      ServerAccounts::Login(l, _, _) => l.scheme(),
       ServerAccounts::LDAP(l) => l.scheme(),
+      ServerAccounts::Composite(c, _) => c.scheme(),
     }
   }
 }
@@ -74,8 +138,9 @@ impl Policy for ServerAccounts {
   fn can_create(&self, player: &str) -> impl Future<Output = bool> + Send {
     async move {
       match self {
-        ServerAccounts::Login(_, p) => p.can_create(player).await,
+        ServerAccounts::Login(l, p, _) => p.can_create(player).await || l.has_role(player, ServerRole::Creator).await,
         ServerAccounts::LDAP(p) => p.can_create(player).await,
+        ServerAccounts::Composite(c, _) => c.can_create(player).await,
       }
     }
   }
@@ -83,8 +148,19 @@ impl Policy for ServerAccounts {
   fn is_administrator(&self, player: &str) -> impl Future<Output = bool> + Send {
     async move {
       match self {
-        ServerAccounts::Login(_, p) => p.is_administrator(player).await,
+        ServerAccounts::Login(l, p, _) => p.is_administrator(player).await || l.has_role(player, ServerRole::Administrator).await,
         ServerAccounts::LDAP(p) => p.is_administrator(player).await,
+        ServerAccounts::Composite(c, _) => c.is_administrator(player).await,
+      }
+    }
+  }
+
+  fn read_role(&self, role: ServerRole) -> impl Future<Output = Option<LocalAccessSetting<String>>> + Send {
+    async move {
+      match self {
+        ServerAccounts::Login(_, p, _) => p.read_role(role).await,
+        ServerAccounts::LDAP(p) => p.read_role(role).await,
+        ServerAccounts::Composite(c, _) => c.read_role(role).await,
       }
     }
   }
@@ -92,8 +168,9 @@ impl Policy for ServerAccounts {
   fn request(&self, request: PolicyRequest) -> impl Future<Output = UpdateResult> + Send {
     async move {
       match self {
-        ServerAccounts::Login(_, p) => p.request(request).await,
+        ServerAccounts::Login(_, p, _) => p.request(request).await,
         ServerAccounts::LDAP(p) => p.request(request).await,
+        ServerAccounts::Composite(c, _) => c.request(request).await,
       }
     }
   }
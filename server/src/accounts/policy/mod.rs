@@ -1,10 +1,13 @@
-use spadina_core::access::SimpleAccess;
+use spadina_core::access::{LocalAccessSetting, SimpleAccess};
+use spadina_core::net::server::administration::ServerRole;
 use spadina_core::UpdateResult;
 use std::future::Future;
 
 pub trait Policy: Send + Sync {
   fn can_create(&self, player: &str) -> impl Future<Output = bool> + Send;
   fn is_administrator(&self, player: &str) -> impl Future<Output = bool> + Send;
+  /// Get the players that have a role, if the role is managed by this server
+  fn read_role(&self, role: ServerRole) -> impl Future<Output = Option<LocalAccessSetting<String>>> + Send;
   fn request(&self, request: PolicyRequest) -> impl Future<Output = UpdateResult> + Send;
 }
 
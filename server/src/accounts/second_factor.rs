@@ -0,0 +1,260 @@
+use crate::database::Database;
+use diesel::QueryResult;
+use otpauth::TOTP;
+use rand::distributions::{Alphanumeric, DistString};
+use sha3::Digest;
+use spadina_core::UpdateResult;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The number of wrong codes a player can enter before all codes are refused for a while
+const MAX_FAILURES: u32 = 5;
+/// How long codes are refused after too many wrong ones, counted from the last wrong code
+const LOCKOUT: Duration = Duration::from_secs(300);
+/// The number of seconds each one-time password is valid for
+const PERIOD: u64 = 30;
+
+/// Time-based one-time passwords that players can require in addition to their password
+///
+/// This works with any password back-end, since the code is only checked after the back-end has accepted the password. Players that lose
+/// their authenticator can use one of their recovery codes instead, each of which can only be used once. Since codes are short, a player that
+/// enters too many wrong codes has all codes refused until they stop trying for a while.
+pub struct SecondFactors {
+  database: Database,
+  /// The number of wrong codes entered for each player and when the last one was entered
+  failures: Mutex<BTreeMap<String, (u32, Instant)>>,
+  server_name: Arc<str>,
+}
+
+impl SecondFactors {
+  pub fn new(database: &Database, server_name: Arc<str>) -> Self {
+    SecondFactors { database: database.clone(), failures: Default::default(), server_name }
+  }
+  /// Check the second factor for a player whose password has already been accepted
+  ///
+  /// Players that have not enrolled a second factor are always accepted.
+  pub fn check(&self, player: &str, otp: Option<&str>) -> QueryResult<bool> {
+    let Some(secret) = self.database.second_factor_secret(player, true)? else {
+      return Ok(true);
+    };
+    let Some(otp) = otp else {
+      return Ok(false);
+    };
+    self.check_code(player, &secret, otp)
+  }
+  /// Check a one-time password or recovery code for a player with a confirmed second factor
+  ///
+  /// One-time passwords are only accepted once, so a code that has been seen cannot be replayed while it is still current.
+  fn check_code(&self, player: &str, secret: &str, code: &str) -> QueryResult<bool> {
+    if self.locked_out(player) {
+      return Ok(false);
+    }
+    let accepted = match verify(secret, code) {
+      Some(step) if self.database.second_factor_use(player, step)? => true,
+      _ => self.database.second_factor_recovery_redeem(player, &hash(code))?,
+    };
+    self.record(player, accepted);
+    Ok(accepted)
+  }
+  /// Activate a second factor that is being enrolled, returning the new recovery codes if the code matches the secret
+  pub fn confirm(&self, player: &str, code: &str) -> QueryResult<Option<Vec<String>>> {
+    let Some(secret) = self.database.second_factor_secret(player, false)? else {
+      return Ok(None);
+    };
+    if self.locked_out(player) {
+      return Ok(None);
+    }
+    let accepted = match verify(&secret, code) {
+      Some(step) => self.database.second_factor_use(player, step)?,
+      None => false,
+    };
+    self.record(player, accepted);
+    if !accepted {
+      return Ok(None);
+    }
+    let codes = recovery_codes();
+    Ok(if self.database.second_factor_confirm(player, &codes.iter().map(|code| hash(code)).collect::<Vec<_>>())? { Some(codes) } else { None })
+  }
+  /// Remove a player's second factor, if the code provided is a current one-time password or a recovery code
+  pub fn disable(&self, player: &str, code: &str) -> QueryResult<UpdateResult> {
+    let Some(secret) = self.database.second_factor_secret(player, true)? else {
+      return Ok(if self.database.second_factor_rm(player)? > 0 { UpdateResult::Success } else { UpdateResult::Redundant });
+    };
+    if !self.check_code(player, &secret, code)? {
+      return Ok(UpdateResult::NotAllowed);
+    }
+    Ok(if self.database.second_factor_rm(player)? > 0 { UpdateResult::Success } else { UpdateResult::Redundant })
+  }
+  /// Start enrolling a new second factor, returning the secret and a provisioning URL for an authenticator
+  ///
+  /// A player who already has a second factor must disable it before enrolling a new one.
+  pub fn enrol(&self, player: &str) -> QueryResult<Option<(String, String)>> {
+    let secret = Alphanumeric.sample_string(&mut rand::thread_rng(), 20);
+    if !self.database.second_factor_enrol(player, &secret)? {
+      return Ok(None);
+    }
+    let totp = TOTP::new(secret);
+    let label = form_urlencoded::byte_serialize(format!("{}:{}", &self.server_name, player).as_bytes()).collect::<String>();
+    let issuer = form_urlencoded::byte_serialize(self.server_name.as_bytes()).collect::<String>();
+    Ok(Some((totp.base32_secret(), totp.to_uri(label.as_str(), issuer.as_str()))))
+  }
+  /// Check if a player has entered too many wrong codes recently
+  fn locked_out(&self, player: &str) -> bool {
+    let mut failures = self.failures.lock().unwrap();
+    match failures.get(player) {
+      Some((count, last)) if last.elapsed() < LOCKOUT => *count >= MAX_FAILURES,
+      Some(_) => {
+        failures.remove(player);
+        false
+      }
+      None => false,
+    }
+  }
+  /// Count a wrong code against a player or clear their count once they get one right
+  fn record(&self, player: &str, accepted: bool) {
+    let mut failures = self.failures.lock().unwrap();
+    if accepted {
+      failures.remove(player);
+    } else {
+      let (count, last) = failures.entry(player.to_string()).or_insert((0, Instant::now()));
+      *count += 1;
+      *last = Instant::now();
+    }
+  }
+  /// Replace a player's recovery codes, if they have a second factor and the code provided is a current one-time password or a recovery code
+  pub fn recovery_reset(&self, player: &str, code: &str) -> QueryResult<Option<Vec<String>>> {
+    let Some(secret) = self.database.second_factor_secret(player, true)? else {
+      return Ok(None);
+    };
+    if !self.check_code(player, &secret, code)? {
+      return Ok(None);
+    }
+    let codes = recovery_codes();
+    Ok(if self.database.second_factor_recovery_reset(player, &codes.iter().map(|code| hash(code)).collect::<Vec<_>>())? { Some(codes) } else { None })
+  }
+}
+
+fn hash(code: &str) -> String {
+  let mut digest = sha3::Sha3_256::new();
+  digest.update(code.trim().as_bytes());
+  base16ct::lower::encode_string(digest.finalize().as_slice())
+}
+
+fn recovery_codes() -> Vec<String> {
+  let mut rng = rand::thread_rng();
+  (0..10).map(|_| Alphanumeric.sample_string(&mut rng, 12)).collect()
+}
+
+/// Check a one-time password against the secret, returning the time step it belongs to
+///
+/// The previous step is also accepted, to allow for a slow player or a clock that is a little behind.
+fn verify(secret: &str, code: &str) -> Option<i64> {
+  let code = u32::from_str(code.trim()).ok()?;
+  let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+  let totp = TOTP::new(secret);
+  let step = timestamp / PERIOD;
+  [step, step.saturating_sub(1)].into_iter().find(|&step| totp.generate(PERIOD, step * PERIOD) == code).map(|step| step as i64)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{SecondFactors, MAX_FAILURES, PERIOD};
+  use crate::database::configuration::DatabaseConfiguration;
+  use crate::database::Database;
+  use otpauth::TOTP;
+  use spadina_core::UpdateResult;
+  use std::path::PathBuf;
+  use std::sync::Arc;
+  use std::time::{SystemTime, UNIX_EPOCH};
+
+  const PLAYER: &str = "tester";
+
+  /// Create second factors backed by a fresh database and enrol the player, returning the database's file so it can be removed
+  fn enrolled(name: &str) -> (SecondFactors, Database, PathBuf) {
+    let db_file = std::env::temp_dir().join(format!("spadina-test-second-factor-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&db_file);
+    let database = Database::new(&DatabaseConfiguration::default(), db_file.clone());
+    let second_factors = SecondFactors::new(&database, Arc::from("localhost"));
+    assert!(second_factors.enrol(PLAYER).unwrap().is_some());
+    (second_factors, database, db_file)
+  }
+
+  /// Generate the current code the player's authenticator would show
+  fn current_code(database: &Database, confirmed: bool) -> String {
+    let secret = database.second_factor_secret(PLAYER, confirmed).unwrap().expect("Player has no second factor");
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    TOTP::new(secret).generate(PERIOD, timestamp).to_string()
+  }
+
+  fn cleanup(second_factors: SecondFactors, database: Database, db_file: PathBuf) {
+    drop(second_factors);
+    drop(database);
+    let _ = std::fs::remove_file(db_file);
+  }
+
+  #[test]
+  fn players_without_second_factor_are_accepted() {
+    let (second_factors, database, db_file) = enrolled("none");
+    // Enrolment is not finished, so the second factor is not required yet
+    assert!(second_factors.check(PLAYER, None).unwrap());
+    assert!(second_factors.check("someone-else", None).unwrap());
+    cleanup(second_factors, database, db_file);
+  }
+
+  #[test]
+  fn confirm_then_check() {
+    let (second_factors, database, db_file) = enrolled("confirm");
+    assert!(second_factors.confirm(PLAYER, "not a code").unwrap().is_none());
+    let code = current_code(&database, false);
+    let recovery = second_factors.confirm(PLAYER, &code).unwrap().expect("Code was not accepted");
+    assert_eq!(recovery.len(), 10);
+    // The code used to confirm cannot be used again
+    assert!(!second_factors.check(PLAYER, Some(&code)).unwrap());
+    assert!(!second_factors.check(PLAYER, None).unwrap());
+    assert!(!second_factors.check(PLAYER, Some("not a code")).unwrap());
+    assert!(second_factors.check(PLAYER, Some(&recovery[0])).unwrap());
+    assert!(!second_factors.check(PLAYER, Some(&recovery[0])).unwrap());
+    assert!(second_factors.check(PLAYER, Some(&recovery[1])).unwrap());
+    cleanup(second_factors, database, db_file);
+  }
+
+  #[test]
+  fn disable_requires_code() {
+    let (second_factors, database, db_file) = enrolled("disable");
+    let recovery = second_factors.confirm(PLAYER, &current_code(&database, false)).unwrap().unwrap();
+    assert_eq!(second_factors.disable(PLAYER, "not a code").unwrap(), UpdateResult::NotAllowed);
+    assert!(!second_factors.check(PLAYER, None).unwrap());
+    assert_eq!(second_factors.disable(PLAYER, &recovery[0]).unwrap(), UpdateResult::Success);
+    assert!(second_factors.check(PLAYER, None).unwrap());
+    assert_eq!(second_factors.disable(PLAYER, "not a code").unwrap(), UpdateResult::Redundant);
+    cleanup(second_factors, database, db_file);
+  }
+
+  #[test]
+  fn recovery_reset_replaces_codes() {
+    let (second_factors, database, db_file) = enrolled("recovery");
+    let old = second_factors.confirm(PLAYER, &current_code(&database, false)).unwrap().unwrap();
+    assert!(second_factors.recovery_reset(PLAYER, "not a code").unwrap().is_none());
+    let new = second_factors.recovery_reset(PLAYER, &old[0]).unwrap().expect("Recovery code was not accepted");
+    assert!(!second_factors.check(PLAYER, Some(&old[1])).unwrap());
+    assert!(second_factors.check(PLAYER, Some(&new[0])).unwrap());
+    cleanup(second_factors, database, db_file);
+  }
+
+  #[test]
+  fn too_many_wrong_codes_lock_out() {
+    let (second_factors, database, db_file) = enrolled("lockout");
+    let recovery = second_factors.confirm(PLAYER, &current_code(&database, false)).unwrap().unwrap();
+    for _ in 0..MAX_FAILURES {
+      assert!(!second_factors.check(PLAYER, Some("not a code")).unwrap());
+    }
+    // Even a good code is refused, and it is not used up
+    assert!(!second_factors.check(PLAYER, Some(&recovery[0])).unwrap());
+    assert!(database.second_factor_recovery_redeem(PLAYER, &super::hash(&recovery[0])).unwrap());
+    // Other players are not affected
+    assert!(second_factors.check("someone-else", None).unwrap());
+    cleanup(second_factors, database, db_file);
+  }
+}
@@ -0,0 +1,170 @@
+use crate::access::{BannedPeers, ServerAccess};
+use crate::accounts::db_policy::{CreateAssets, ServerAdministration};
+use crate::asset_store::ServerAssetStore;
+use crate::database::archive::{ArchiveSettings, DatabaseArchive};
+use crate::database::Database;
+use chrono::{DateTime, Utc};
+use rename::Rename;
+use spadina_core::asset::Asset;
+use spadina_core::asset_store::AssetStore;
+use spadina_core::location::Descriptor;
+use spadina_core::reference_converter::ForPacket;
+use spadina_core::resource::Resource;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::Path;
+
+pub mod rename;
+
+const ASSET_PREFIX: &str = "assets/";
+const DATABASE: &str = "database.json";
+const MANIFEST: &str = "manifest.json";
+const SETTINGS: &str = "settings.json";
+/// The archive format version; this must be changed whenever the contents of the archive change in a way older servers cannot read
+const VERSION: u32 = 1;
+
+/// Identifies the server that wrote an archive
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Manifest {
+  created: DateTime<Utc>,
+  server: String,
+  version: u32,
+}
+
+/// Write everything needed to move this server to another host into a ZIP archive
+///
+/// The archive holds the players, locations, and settings from the database along with every asset the locations and bookmarks refer to
+/// (and their children). Assets that cannot be pulled from the asset store are reported, but do not stop the export.
+pub async fn export(
+  database: &Database,
+  asset_store: &ServerAssetStore,
+  server_name: &str,
+  output: &Path,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+  eprintln!("Reading database");
+  let data = database.archive_export()?;
+  let settings = ArchiveSettings {
+    administration: database.setting_read::<ServerAdministration>()?,
+    announcements: database.announcements_read()?,
+    banned_peers: database.setting_read::<BannedPeers>()?,
+    create_assets: database.setting_read::<CreateAssets>()?,
+    server_access: database.setting_read::<ServerAccess>()?,
+  };
+  warn_second_factors(&data.second_factor_players);
+  let mut assets: Vec<String> = data
+    .locations
+    .iter()
+    .filter_map(|location| match &location.descriptor {
+      Descriptor::Asset(asset) => Some(asset.clone()),
+      _ => None,
+    })
+    .chain(data.players.iter().flat_map(|player| player.bookmarks.iter()).filter_map(|bookmark| match bookmark {
+      Resource::Asset(asset) => Some(asset.clone()),
+      _ => None,
+    }))
+    .collect();
+
+  eprintln!("Writing archive to {}", output.display());
+  let mut zip = zip::ZipWriter::new(std::fs::File::create(output)?);
+  let options = zip::write::SimpleFileOptions::default();
+  zip.start_file(MANIFEST, options)?;
+  serde_json::to_writer(&mut zip, &Manifest { created: Utc::now(), server: server_name.to_string(), version: VERSION })?;
+  zip.start_file(SETTINGS, options)?;
+  serde_json::to_writer(&mut zip, &settings)?;
+  zip.start_file(DATABASE, options)?;
+  serde_json::to_writer(&mut zip, &data)?;
+
+  let mut seen = BTreeSet::new();
+  while let Some(id) = assets.pop() {
+    if !seen.insert(id.clone()) {
+      continue;
+    }
+    match asset_store.pull(&id).await {
+      Ok(asset) => {
+        assets.extend(asset.children.iter().cloned());
+        zip.start_file(format!("{}{}", ASSET_PREFIX, &id), options)?;
+        rmp_serde::encode::write_named(&mut zip, &asset)?;
+      }
+      Err(e) => eprintln!("Failed to export asset {}: {}", &id, e),
+    }
+  }
+  zip.finish()?;
+  eprintln!("Exported {} players, {} locations, and {} assets", data.players.len(), data.locations.len(), seen.len());
+  Ok(())
+}
+
+/// Load an archive written by [export] into an empty database
+///
+/// An archive from a server with a different name is only loaded if `rename` is set, in which case references to the old server name are
+/// rewritten to this server's name.
+pub async fn import(
+  database: &Database,
+  asset_store: &ServerAssetStore,
+  server_name: &str,
+  input: &Path,
+  rename: bool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+  let mut zip = zip::ZipArchive::new(std::fs::File::open(input)?)?;
+  let manifest: Manifest = serde_json::from_reader(zip.by_name(MANIFEST)?)?;
+  if manifest.version != VERSION {
+    return Err(format!("Archive version {} is not supported; this server reads version {}", manifest.version, VERSION).into());
+  }
+  let rename = if manifest.server == server_name {
+    None
+  } else if rename {
+    Some(Rename { new: server_name, old: &manifest.server })
+  } else {
+    return Err(format!("Archive is from {}, but this server is {}; use --rename to import it anyway", &manifest.server, server_name).into());
+  };
+  if !database.archive_is_empty()? {
+    return Err("The database already has players; an archive can only be imported into an empty database".into());
+  }
+  let mut settings: ArchiveSettings = serde_json::from_reader(zip.by_name(SETTINGS)?)?;
+  let mut data: DatabaseArchive = serde_json::from_reader(zip.by_name(DATABASE)?)?;
+  if let Some(rename) = &rename {
+    eprintln!("Renaming {} to {}", rename.old, rename.new);
+    settings.announcements = settings.announcements.into_iter().map(|announcement| rename.announcement(announcement)).collect();
+    settings.server_access = rename.access(settings.server_access);
+    data = rename.archive(data);
+  }
+
+  eprintln!("Loading assets");
+  let mut asset_count = 0;
+  for i in 0..zip.len() {
+    let asset = {
+      let mut file = zip.by_index(i)?;
+      if !file.name().starts_with(ASSET_PREFIX) {
+        continue;
+      }
+      rmp_serde::from_read::<_, Asset<String, Vec<u8>>>(&mut file)?
+    };
+    let id = asset.principal_hash();
+    asset_store.push(&id, &asset.reference(ForPacket)).await;
+    // The asset store does not report failures when storing, so check the asset can be read back before anything refers to it
+    match asset_store.pull(&id).await {
+      Ok(stored) if stored.principal_hash() == id => (),
+      Ok(_) => return Err(format!("Asset {} was changed by the asset store; nothing has been imported into the database", &id).into()),
+      Err(e) => return Err(format!("Asset {} could not be stored ({}); nothing has been imported into the database", &id, e).into()),
+    }
+    asset_count += 1;
+  }
+
+  eprintln!("Loading database");
+  database.archive_import(&data, &settings)?;
+  eprintln!("Imported {} players, {} locations, and {} assets", data.players.len(), data.locations.len(), asset_count);
+  warn_second_factors(&data.second_factor_players);
+  Ok(())
+}
+
+/// Second factors are not carried in archives, so make sure the administrator knows which players have lost theirs
+fn warn_second_factors(players: &[String]) {
+  if !players.is_empty() {
+    eprintln!(
+      "WARNING: Second factors are not included in archives. These {} players will only need their password to log in until they enrol again:",
+      players.len()
+    );
+    for player in players {
+      eprintln!("  {}", player);
+    }
+  }
+}
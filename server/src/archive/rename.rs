@@ -0,0 +1,261 @@
+use crate::database::archive::{ArchiveDirectMessage, ArchiveLastRead, DatabaseArchive};
+use spadina_core::access::{AccessControl, AccessSetting};
+use spadina_core::communication::{Announcement, MessageBody};
+use spadina_core::location::target::{AbsoluteTarget, LocalTarget, UnresolvedTarget};
+use spadina_core::player::PlayerIdentifier;
+use spadina_core::resource::Resource;
+
+/// Rewrites references to a server when its data is loaded onto a server with a different name
+///
+/// References to the old name are changed to the new one. Anything that referred to the new server as a remote server becomes local, since
+/// it now refers to this server.
+pub struct Rename<'a> {
+  pub new: &'a str,
+  pub old: &'a str,
+}
+
+impl Rename<'_> {
+  fn is_self(&self, server: &str) -> bool {
+    server == self.old || server == self.new
+  }
+  pub fn access<S: AsRef<str> + for<'a> From<&'a str>, T: Copy>(&self, setting: AccessSetting<S, T>) -> AccessSetting<S, T> {
+    AccessSetting {
+      default: setting.default,
+      rules: setting
+        .rules
+        .into_iter()
+        .map(|rule| match rule {
+          AccessControl::Player(player, time, access) => AccessControl::Player(self.player(player), time, access),
+          AccessControl::Server(server, time, access) if self.is_self(server.as_ref()) => AccessControl::Local(time, access),
+          rule => rule,
+        })
+        .collect(),
+    }
+  }
+  pub fn announcement<S: AsRef<str> + for<'a> From<&'a str>>(&self, announcement: Announcement<S>) -> Announcement<S> {
+    Announcement { location: self.unresolved_target(announcement.location), ..announcement }
+  }
+  pub fn archive(&self, archive: DatabaseArchive) -> DatabaseArchive {
+    let DatabaseArchive { mut direct_messages, mut last_read, mut locations, mut players, second_factor_players } = archive;
+    for player in &mut players {
+      player.bookmarks = std::mem::take(&mut player.bookmarks).into_iter().map(|bookmark| self.resource(bookmark)).collect();
+      player.default_location_acl = self.access(std::mem::take(&mut player.default_location_acl));
+      player.message_acl = self.access(std::mem::take(&mut player.message_acl));
+      player.online_acl = self.access(std::mem::take(&mut player.online_acl));
+      // Subscriptions and conversations with players on the new server are now local to this server
+      for target in std::mem::take(&mut player.remote_calendar) {
+        if self.is_self(&target.server) {
+          player.calendar.push(LocalTarget { descriptor: target.descriptor, owner: target.owner });
+        } else {
+          player.remote_calendar.push(target);
+        }
+      }
+      for mut message in std::mem::take(&mut player.remote_direct_messages) {
+        message.body = self.message(message.body);
+        if self.is_self(&message.remote_server) {
+          let (sender, recipient) =
+            if message.inbound { (message.remote_player, player.name.clone()) } else { (player.name.clone(), message.remote_player) };
+          direct_messages.push(ArchiveDirectMessage { body: message.body, created: message.created, recipient, sender });
+        } else {
+          player.remote_direct_messages.push(message);
+        }
+      }
+      for read in std::mem::take(&mut player.remote_last_read) {
+        if self.is_self(&read.remote_server) {
+          last_read.push(ArchiveLastRead { recipient: player.name.clone(), sender: read.remote_player, when: read.when });
+        } else {
+          player.remote_last_read.push(read);
+        }
+      }
+    }
+    for message in &mut direct_messages {
+      message.body = self.message(std::mem::replace(&mut message.body, MessageBody::Read));
+    }
+    for location in &mut locations {
+      location.acl = self.access(std::mem::take(&mut location.acl));
+      for message in &mut location.chat {
+        message.body = self.message(std::mem::replace(&mut message.body, MessageBody::Read));
+        message.sender = self.player(std::mem::replace(&mut message.sender, PlayerIdentifier::Local(String::new())));
+      }
+    }
+    DatabaseArchive { direct_messages, last_read, locations, players, second_factor_players }
+  }
+  pub fn message<S: AsRef<str> + for<'a> From<&'a str>>(&self, body: MessageBody<S>) -> MessageBody<S> {
+    match body {
+      MessageBody::Announcement(announcement) => MessageBody::Announcement(self.announcement(announcement)),
+      MessageBody::Resource(resource) => MessageBody::Resource(self.resource(resource)),
+      body => body,
+    }
+  }
+  pub fn player<S: AsRef<str>>(&self, player: PlayerIdentifier<S>) -> PlayerIdentifier<S> {
+    match player {
+      PlayerIdentifier::Remote { server, player } if self.is_self(server.as_ref()) => PlayerIdentifier::Local(player),
+      player => player,
+    }
+  }
+  pub fn resource<S: AsRef<str> + for<'a> From<&'a str>>(&self, resource: Resource<S>) -> Resource<S> {
+    match resource {
+      Resource::Player(player) => Resource::Player(self.player(player)),
+      Resource::Location(target) => Resource::Location(self.unresolved_target(target)),
+      Resource::Login { player, server, token } => Resource::Login { player, server: self.server(server), token },
+      Resource::Server(server) => Resource::Server(self.server(server)),
+      resource => resource,
+    }
+  }
+  pub fn server<S: AsRef<str> + for<'a> From<&'a str>>(&self, server: S) -> S {
+    if server.as_ref() == self.old {
+      S::from(self.new)
+    } else {
+      server
+    }
+  }
+  pub fn target<S: AsRef<str> + for<'a> From<&'a str>>(&self, target: AbsoluteTarget<S>) -> AbsoluteTarget<S> {
+    AbsoluteTarget { server: self.server(target.server), ..target }
+  }
+  pub fn unresolved_target<S: AsRef<str> + for<'a> From<&'a str>>(&self, target: UnresolvedTarget<S>) -> UnresolvedTarget<S> {
+    match target {
+      UnresolvedTarget::Absolute(target) => UnresolvedTarget::Absolute(self.target(target)),
+      target => target,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Rename;
+  use crate::database::archive::{ArchivePlayer, ArchiveRemoteDirectMessage, ArchiveRemoteLastRead, DatabaseArchive};
+  use chrono::Utc;
+  use spadina_core::access::{AccessControl, AccessSetting, SimpleAccess};
+  use spadina_core::avatar::Avatar;
+  use spadina_core::communication::MessageBody;
+  use spadina_core::location::target::{AbsoluteTarget, LocalTarget};
+  use spadina_core::location::Descriptor;
+  use spadina_core::player::PlayerIdentifier;
+  use spadina_core::resource::Resource;
+
+  const RENAME: Rename = Rename { new: "new.example.com", old: "old.example.com" };
+
+  fn remote(server: &str, player: &str) -> PlayerIdentifier<String> {
+    PlayerIdentifier::Remote { server: server.to_string(), player: player.to_string() }
+  }
+
+  fn player(name: &str) -> ArchivePlayer {
+    ArchivePlayer {
+      avatar: Avatar::default_for(name),
+      bookmarks: Vec::new(),
+      calendar: Vec::new(),
+      calendar_id: Vec::new(),
+      created: Utc::now(),
+      default_location_acl: Default::default(),
+      last_login: Utc::now(),
+      message_acl: Default::default(),
+      name: name.to_string(),
+      online_acl: Default::default(),
+      remote_calendar: Vec::new(),
+      remote_direct_messages: Vec::new(),
+      remote_last_read: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn players_on_either_name_become_local() {
+    assert_eq!(RENAME.player(remote("old.example.com", "alice")), PlayerIdentifier::Local("alice".to_string()));
+    assert_eq!(RENAME.player(remote("new.example.com", "bob")), PlayerIdentifier::Local("bob".to_string()));
+    assert_eq!(RENAME.player(remote("other.example.com", "carol")), remote("other.example.com", "carol"));
+    assert_eq!(RENAME.player(PlayerIdentifier::Local("dave".to_string())), PlayerIdentifier::Local("dave".to_string()));
+  }
+
+  #[test]
+  fn servers_renamed() {
+    assert_eq!(RENAME.server("old.example.com".to_string()), "new.example.com");
+    assert_eq!(RENAME.server("new.example.com".to_string()), "new.example.com");
+    assert_eq!(RENAME.server("other.example.com".to_string()), "other.example.com");
+    assert_eq!(RENAME.resource(Resource::Server("old.example.com".to_string())), Resource::Server("new.example.com".to_string()));
+    assert_eq!(RENAME.resource(Resource::Player(remote("new.example.com", "alice"))), Resource::Player(PlayerIdentifier::Local("alice".to_string())));
+  }
+
+  #[test]
+  fn access_rules_for_either_name_become_local() {
+    let setting = RENAME.access(AccessSetting::<String, SimpleAccess> {
+      default: SimpleAccess::Deny,
+      rules: vec![
+        AccessControl::Server("old.example.com".to_string(), None, SimpleAccess::Allow),
+        AccessControl::Server("new.example.com".to_string(), None, SimpleAccess::Allow),
+        AccessControl::Server("other.example.com".to_string(), None, SimpleAccess::Allow),
+        AccessControl::Player(remote("old.example.com", "alice"), None, SimpleAccess::Deny),
+        AccessControl::Player(remote("other.example.com", "bob"), None, SimpleAccess::Deny),
+      ],
+    });
+    assert_eq!(
+      setting.rules,
+      vec![
+        AccessControl::Local(None, SimpleAccess::Allow),
+        AccessControl::Local(None, SimpleAccess::Allow),
+        AccessControl::Server("other.example.com".to_string(), None, SimpleAccess::Allow),
+        AccessControl::Player(PlayerIdentifier::Local("alice".to_string()), None, SimpleAccess::Deny),
+        AccessControl::Player(remote("other.example.com", "bob"), None, SimpleAccess::Deny),
+      ]
+    );
+  }
+
+  #[test]
+  fn conversations_with_either_name_become_local() {
+    let now = Utc::now();
+    let mut alice = player("alice");
+    alice.remote_calendar = ["old.example.com", "new.example.com", "other.example.com"]
+      .into_iter()
+      .map(|server| AbsoluteTarget { descriptor: Descriptor::Asset("realm".to_string()), owner: "bob".to_string(), server: server.to_string() })
+      .collect();
+    alice.remote_direct_messages = vec![
+      ArchiveRemoteDirectMessage {
+        body: MessageBody::Read,
+        created: now,
+        inbound: true,
+        remote_player: "bob".to_string(),
+        remote_server: "new.example.com".to_string(),
+      },
+      ArchiveRemoteDirectMessage {
+        body: MessageBody::Read,
+        created: now,
+        inbound: false,
+        remote_player: "carol".to_string(),
+        remote_server: "old.example.com".to_string(),
+      },
+      ArchiveRemoteDirectMessage {
+        body: MessageBody::Read,
+        created: now,
+        inbound: true,
+        remote_player: "dave".to_string(),
+        remote_server: "other.example.com".to_string(),
+      },
+    ];
+    alice.remote_last_read = vec![
+      ArchiveRemoteLastRead { remote_player: "bob".to_string(), remote_server: "new.example.com".to_string(), when: now },
+      ArchiveRemoteLastRead { remote_player: "dave".to_string(), remote_server: "other.example.com".to_string(), when: now },
+    ];
+    let archive = RENAME.archive(DatabaseArchive {
+      direct_messages: Vec::new(),
+      last_read: Vec::new(),
+      locations: Vec::new(),
+      players: vec![alice],
+      second_factor_players: Vec::new(),
+    });
+
+    let alice = &archive.players[0];
+    assert_eq!(
+      alice.calendar,
+      vec![
+        LocalTarget { descriptor: Descriptor::Asset("realm".to_string()), owner: "bob".to_string() },
+        LocalTarget { descriptor: Descriptor::Asset("realm".to_string()), owner: "bob".to_string() }
+      ]
+    );
+    assert_eq!(alice.remote_calendar.iter().map(|target| target.server.as_str()).collect::<Vec<_>>(), vec!["other.example.com"]);
+    assert_eq!(
+      archive.direct_messages.iter().map(|message| (message.sender.as_str(), message.recipient.as_str())).collect::<Vec<_>>(),
+      vec![("bob", "alice"), ("alice", "carol")]
+    );
+    assert_eq!(alice.remote_direct_messages.iter().map(|message| message.remote_player.as_str()).collect::<Vec<_>>(), vec!["dave"]);
+    assert_eq!(archive.last_read.iter().map(|read| (read.sender.as_str(), read.recipient.as_str())).collect::<Vec<_>>(), vec![("bob", "alice")]);
+    assert_eq!(alice.remote_last_read.iter().map(|read| read.remote_player.as_str()).collect::<Vec<_>>(), vec!["dave"]);
+  }
+}
@@ -36,7 +36,7 @@ pub type AssetManager = mpsc::Sender<AssetRequest>;
 #[derive(Clone)]
 pub enum RealmTemplate {
   Found(GenericControllerTemplate),
-  NotFound(Arc<str>),
+  NotFound(Vec<Arc<str>>),
   MissingCapabilities(Vec<Arc<str>>),
   Invalid,
 }
@@ -163,11 +163,11 @@ async fn pull_realm(id: Arc<str>, directory: Directory) -> Option<RealmTemplate>
     Ok(realm) => match realm.await {
       Ok(realm) => realm,
       Err(_) => {
-        return Some(RealmTemplate::NotFound(id));
+        return Some(RealmTemplate::NotFound(vec![id]));
       }
     },
     Err(()) => {
-      return Some(RealmTemplate::NotFound(id));
+      return Some(RealmTemplate::NotFound(vec![id]));
     }
   };
   let children = match stream::iter(realm.children.iter().cloned().map(Ok))
@@ -187,12 +187,16 @@ async fn pull_realm(id: Arc<str>, directory: Directory) -> Option<RealmTemplate>
     .await
   {
     Ok(v) => v,
-    Err(missing) => return Some(RealmTemplate::NotFound(Arc::from(missing))),
+    Err(missing) => return Some(RealmTemplate::NotFound(vec![Arc::from(missing)])),
   };
+  // No optional capabilities are supported yet, so any the realm or its children declare are missing
+  if !realm.capabilities.is_empty() {
+    return Some(RealmTemplate::MissingCapabilities(realm.capabilities.clone()));
+  }
   let Ok(realm) = realm.deserialize_inner::<AllSupportedAssets<Arc<str>>>() else { return Some(RealmTemplate::Invalid) };
   Some(match realm.create_realm_template(&children) {
     Ok(template) => RealmTemplate::Found(template),
-    Err(Some(capabilities)) => RealmTemplate::MissingCapabilities(capabilities),
+    Err(Some(missing)) => RealmTemplate::NotFound(missing),
     Err(None) => RealmTemplate::Invalid,
   })
 }
@@ -348,6 +348,9 @@ pub fn start_hosting(
               from,
               to,
             }),
+            // Hosted locations keep their state on the host's client, so there is no history to restore
+            LocationRequest::StateHistory { id } => Some(LocationResponse::StateHistoryFailure { id, result: UpdateResult::NotAllowed }),
+            LocationRequest::StateRestore { id, .. } => Some(LocationResponse::StateRestore { id, result: UpdateResult::NotAllowed }),
             LocationRequest::Internal(request_id, request) => {
               if let Some((player, handle)) = player.as_ref().map(|p| players.get_key_value(p)).flatten() {
                 if client_tx
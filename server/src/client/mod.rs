@@ -1,4 +1,5 @@
-use crate::accounts::policy::Policy;
+use crate::accounts::login::{Login, LoginRequest, LoginResponse};
+use crate::accounts::policy::{Policy, PolicyRequest};
 use crate::aggregating_map::AggregatingMap;
 use crate::database::location_scope::{LocationListScope, LocationScope};
 use crate::database::persisted::PersistedLocal;
@@ -16,14 +17,14 @@ use chrono::{Duration, Utc};
 use diesel::QueryResult;
 use futures::StreamExt;
 use futures::{FutureExt, Stream};
-use spadina_core::access::{AccessSetting, BulkLocationSelector, OnlineAccess};
+use spadina_core::access::{AccessSetting, AccountLockState, BulkLocationSelector, OnlineAccess};
 use spadina_core::communication::DirectMessage;
 use spadina_core::location::change::{LocationChangeRequest, LocationChangeResponse};
 use spadina_core::location::directory::Activity;
 use spadina_core::location::target::{AbsoluteTarget, LocalTarget, UnresolvedTarget};
 use spadina_core::location::DescriptorKind;
 use spadina_core::net::mixed_connection::MixedConnection;
-use spadina_core::net::server::administration::{AdministrationRequest, AdministrationResponse};
+use spadina_core::net::server::administration::{AdministrationRequest, AdministrationResponse, DatabaseBackupResult, ServerRole};
 use spadina_core::net::server::{AssetError, ClientRequest, ClientResponse};
 use spadina_core::player::{OnlineState, PlayerIdentifier};
 use spadina_core::reference_converter::{AsArc, AsReference, AsShared, AsSingle, ForPacket};
@@ -50,6 +51,8 @@ pub struct Client {
   db_id: i32,
   default_location_acl: PersistedLocal<PlayerDefaultLocationAccess>,
   idle_timer: idle_timer::IdleTimer,
+  /// The fingerprint of the public key the current connection logged in with, if any
+  key: Option<String>,
   message_acl: PersistedLocal<PlayerMessageAccess>,
   name: Arc<str>,
   online_acl: PersistedLocal<PlayerOnlineAccess>,
@@ -70,7 +73,7 @@ impl SocketEntity for Client {
   type ExternalRequest = ClientRequest<String, Vec<u8>>;
 
   fn establish(claim: Self::Claim, connection: WebSocketStream<MixedConnection>, directory: Directory) -> impl Future<Output = Result<(), ()>> {
-    async move { directory.register_player(Arc::from(claim.name), connection).await }
+    async move { directory.register_player(Arc::from(claim.name), Some((claim.generation, claim.key)), connection).await }
   }
 
   fn new(name: Arc<str>, database: &Database) -> QueryResult<Self> {
@@ -82,6 +85,7 @@ impl SocketEntity for Client {
       db_id,
       default_location_acl: PersistedLocal::new(database.clone(), PlayerDefaultLocationAccess(db_id))?,
       idle_timer: Default::default(),
+      key: None,
       message_acl: PersistedLocal::new(database.clone(), PlayerMessageAccess(db_id))?,
       name,
       online_acl: PersistedLocal::new(database.clone(), PlayerOnlineAccess(db_id))?,
@@ -136,7 +140,10 @@ impl SocketEntity for Client {
         });
         vec![]
       }
-      Incoming::Directory(PlayerRequest::Connect(connection)) => vec![Outgoing::Connect(connection)],
+      Incoming::Directory(PlayerRequest::Connect(connection, key)) => {
+        self.key = key;
+        vec![Outgoing::Connect(connection)]
+      }
       Incoming::Directory(PlayerRequest::DirectMessage(player, body, timestamp)) => vec![Outgoing::Send(
         ClientResponse::<_, &[u8]>::DirectMessage {
           player: player.reference(AsReference::<str>::default()),
@@ -144,6 +151,10 @@ impl SocketEntity for Client {
         }
         .into(),
       )],
+      Incoming::Directory(PlayerRequest::Disconnect) => {
+        self.current_location = location::Location::NoWhere;
+        vec![Outgoing::Break]
+      }
       Incoming::External(ClientRequest::Activity { id, player }) => {
         let result = match player.localize(&directory.access_management.server_name) {
           PlayerIdentifier::Local(player) => directory.check_host_activity(SharedRef::Single(player)).await,
@@ -165,6 +176,7 @@ impl SocketEntity for Client {
         vec![output]
       }
       Incoming::External(ClientRequest::Administration { id, request }) => {
+        let database = database.clone();
         let directory = directory.clone();
         let player_name = self.name.clone();
         let task = Outgoing::SideTask(
@@ -174,9 +186,102 @@ impl SocketEntity for Client {
                 id,
                 response: if is_superuser || directory.access_management.accounts.is_administrator(&player_name).await {
                   match request {
-                    AdministrationRequest::AccountLockChange { .. } => todo!(),
-                    AdministrationRequest::AccountLockStatus { .. } => todo!(),
-                    AdministrationRequest::Invite => todo!(),
+                    AdministrationRequest::AccountLockChange { name, locked } => {
+                      let result = match directory.access_management.accounts.administration_request(LoginRequest::LockAccount(&name, locked)).await {
+                        LoginResponse::LockAccount(Some(true)) => UpdateResult::Success,
+                        LoginResponse::LockAccount(Some(false)) => UpdateResult::Redundant,
+                        _ => UpdateResult::NotAllowed,
+                      };
+                      if locked && result != UpdateResult::NotAllowed {
+                        if let Err(e) = database.token_generation_increment(&name) {
+                          eprintln!("Failed to revoke tokens for {}: {}", &name, e);
+                        }
+                        directory.disconnect_player(Arc::from(name.as_str())).await;
+                      }
+                      AdministrationResponse::AccountLockChange { name, result }
+                    }
+                    AdministrationRequest::AccountLockStatus { name } => {
+                      let status = match directory.access_management.accounts.administration_request(LoginRequest::LockStatus(&name)).await {
+                        LoginResponse::LockStatus(status) => status,
+                        _ => AccountLockState::Unknown,
+                      };
+                      AdministrationResponse::AccountLockStatus { name, status }
+                    }
+                    AdministrationRequest::DatabaseBackup => AdministrationResponse::DatabaseBackup {
+                      result: if is_superuser {
+                        let database = database.clone();
+                        match tokio::task::spawn_blocking(move || database.backup_create()).await {
+                          Ok(result) => result,
+                          Err(e) => {
+                            eprintln!("Failed to run database backup: {}", e);
+                            DatabaseBackupResult::InternalError
+                          }
+                        }
+                      } else {
+                        DatabaseBackupResult::NotAllowed
+                      },
+                    },
+                    AdministrationRequest::Invite => match directory.access_management.accounts.invitations() {
+                      Some(invitations) if directory.access_management.accounts.can_register() => match invitations.create(&player_name) {
+                        Ok(url) => AdministrationResponse::InviteSuccess { url },
+                        Err(error) => AdministrationResponse::InviteFailure { error },
+                      },
+                      _ => AdministrationResponse::InviteFailure { error: communication::InvitationError::Closed },
+                    },
+                    AdministrationRequest::InvitationList => AdministrationResponse::InvitationList {
+                      invitations: directory.access_management.accounts.invitations().map(|invitations| invitations.list()).unwrap_or_default(),
+                    },
+                    AdministrationRequest::InvitationRevoke { code } => {
+                      let result = match directory.access_management.accounts.invitations() {
+                        Some(invitations) => invitations.revoke(&code),
+                        None => UpdateResult::NotAllowed,
+                      };
+                      AdministrationResponse::InvitationRevoke { code, result }
+                    }
+                    AdministrationRequest::PasswordReset { name } => {
+                      let password = match directory.access_management.accounts.administration_request(LoginRequest::ResetPassword(&name)).await {
+                        LoginResponse::ResetPassword(password) => password,
+                        _ => None,
+                      };
+                      AdministrationResponse::PasswordReset { name, password }
+                    }
+                    AdministrationRequest::RoleGrant { role, name } => {
+                      let result = directory
+                        .access_management
+                        .accounts
+                        .request(match role {
+                          ServerRole::Administrator => PolicyRequest::AddAdmin(name.clone()),
+                          ServerRole::Creator => PolicyRequest::AddCreator(name.clone()),
+                        })
+                        .await;
+                      AdministrationResponse::RoleChange { role, name, result }
+                    }
+                    AdministrationRequest::RoleList => AdministrationResponse::RoleList {
+                      administrators: directory.access_management.accounts.read_role(ServerRole::Administrator).await,
+                      creators: directory.access_management.accounts.read_role(ServerRole::Creator).await,
+                    },
+                    AdministrationRequest::RoleReset { role, default } => {
+                      let result = directory
+                        .access_management
+                        .accounts
+                        .request(match role {
+                          ServerRole::Administrator => PolicyRequest::SetAdmin(default),
+                          ServerRole::Creator => PolicyRequest::SetCreator(default),
+                        })
+                        .await;
+                      AdministrationResponse::RoleReset { role, default, result }
+                    }
+                    AdministrationRequest::RoleRevoke { role, name } => {
+                      let result = directory
+                        .access_management
+                        .accounts
+                        .request(match role {
+                          ServerRole::Administrator => PolicyRequest::RemoveAdmin(name.clone()),
+                          ServerRole::Creator => PolicyRequest::RemoveCreator(name.clone()),
+                        })
+                        .await;
+                      AdministrationResponse::RoleChange { role, name, result }
+                    }
                   }
                 } else {
                   AdministrationResponse::<String>::NotAdministrator
@@ -723,25 +828,67 @@ impl SocketEntity for Client {
           online::watch_online(id, directory.check_online_on_peer(self.name.clone(), server, player).await)
         }
       },
+      Incoming::External(ClientRequest::PlayerDataExport { id }) => {
+        let database = database.clone();
+        let db_id = self.db_id;
+        let player_name = self.name.clone();
+        let task = Outgoing::SideTask(
+          async move {
+            let data = match tokio::task::spawn_blocking(move || database.player_data_export(db_id)).await {
+              Ok(Ok(data)) => match serde_json::to_vec(&data) {
+                Ok(data) => Some(data),
+                Err(e) => {
+                  eprintln!("Failed to serialize data for player {}: {}", &player_name, e);
+                  None
+                }
+              },
+              Ok(Err(e)) => {
+                eprintln!("Failed to export data for player {}: {}", &player_name, e);
+                None
+              }
+              Err(e) => {
+                eprintln!("Failed to run data export for player {}: {}", &player_name, e);
+                None
+              }
+            };
+            vec![Outgoing::Send(ClientResponse::<String, Vec<u8>>::PlayerData { id, data }.into())]
+          }
+          .into_stream()
+          .boxed(),
+        );
+        vec![task]
+      }
       Incoming::External(ClientRequest::PlayerReset { id, player }) => {
-        let result = if is_superuser || directory.access_management.accounts.is_administrator(&self.name).await {
-          match database.player_reset(&player) {
+        let result = if player.as_str() == &*self.name || is_superuser || directory.access_management.accounts.is_administrator(&self.name).await {
+          // Running locations would otherwise keep writing state and history for rows that are about to be deleted
+          directory.close_locations(Arc::from(player.as_str())).await;
+          match database.player_delete(&player) {
             Err(e) => {
               eprintln!("Failed to delete player {}: {}", &player, e);
               UpdateResult::InternalError
             }
-            Ok(_) => UpdateResult::Success,
+            Ok(servers) => {
+              if let LoginResponse::DeleteAccount(None) =
+                directory.access_management.accounts.administration_request(LoginRequest::DeleteAccount(&player)).await
+              {
+                eprintln!("Account for {} cannot be removed from the login back-end", &player);
+              }
+              directory.player_deleted(Arc::from(player), servers).await;
+              UpdateResult::Success
+            }
           }
         } else {
           UpdateResult::NotAllowed
         };
         vec![Outgoing::Send(ClientResponse::<String, Vec<u8>>::PlayerReset { id, result }.into())]
       }
-      Incoming::External(ClientRequest::PublicKeyAdd { id, der }) => {
+      Incoming::External(ClientRequest::PublicKeyAdd { id, name, der }) => {
         let result = match openssl::pkey::PKey::public_key_from_der(&der) {
           Err(_) => UpdateResult::NotAllowed,
-          Ok(_) => match database.public_key_add(self.db_id, &der) {
-            Ok(_) => UpdateResult::Success,
+          Ok(_) if name.trim().is_empty() || name.len() > 100 => UpdateResult::NotAllowed,
+          Ok(_) => match database.public_key_add(self.db_id, name.trim(), &der) {
+            Ok(true) => UpdateResult::Success,
+            Ok(false) => UpdateResult::Redundant,
             Err(e) => {
               eprintln!("Failed to add public key: {}", e);
               UpdateResult::InternalError
@@ -756,7 +903,13 @@ impl SocketEntity for Client {
             eprintln!("Failed to delete public key: {}", e);
             UpdateResult::InternalError
           }
-          Ok(_) => UpdateResult::Success,
+          Ok(fingerprint) => {
+            // Tokens issued for the key are refused when they are next used, but a session that logged in with it would otherwise stay connected
+            if fingerprint.is_some() && fingerprint == self.key {
+              directory.disconnect_player(self.name.clone()).await;
+            }
+            UpdateResult::Success
+          }
         };
         vec![Outgoing::Send(ClientResponse::<String, Vec<u8>>::PublicKeyUpdate { id, result }.into())]
       }
@@ -766,7 +919,13 @@ impl SocketEntity for Client {
             eprintln!("Failed to delete all public keys: {}", e);
             UpdateResult::InternalError
           }
-          Ok(_) => UpdateResult::Success,
+          Ok(0) => UpdateResult::Success,
+          Ok(_) => {
+            if self.key.is_some() {
+              directory.disconnect_player(self.name.clone()).await;
+            }
+            UpdateResult::Success
+          }
         };
         vec![Outgoing::Send(ClientResponse::<String, Vec<u8>>::PublicKeyUpdate { id, result }.into())]
       }
@@ -779,6 +938,49 @@ impl SocketEntity for Client {
         }
         .into(),
       )],
+      Incoming::External(ClientRequest::SecondFactorConfirm { id, code }) => {
+        let response = match directory.access_management.second_factors.confirm(&self.name, &code) {
+          Ok(Some(codes)) => ClientResponse::<String, Vec<u8>>::SecondFactorRecoveryCodes { id, codes },
+          Ok(None) => ClientResponse::<String, Vec<u8>>::SecondFactorUpdate { id, result: UpdateResult::NotAllowed },
+          Err(e) => {
+            eprintln!("Failed to confirm second factor for {}: {}", &self.name, e);
+            ClientResponse::<String, Vec<u8>>::SecondFactorUpdate { id, result: UpdateResult::InternalError }
+          }
+        };
+        vec![Outgoing::Send(response.into())]
+      }
+      Incoming::External(ClientRequest::SecondFactorDisable { id, code }) => {
+        let result = match directory.access_management.second_factors.disable(&self.name, &code) {
+          Ok(result) => result,
+          Err(e) => {
+            eprintln!("Failed to disable second factor for {}: {}", &self.name, e);
+            UpdateResult::InternalError
+          }
+        };
+        vec![Outgoing::Send(ClientResponse::<String, Vec<u8>>::SecondFactorUpdate { id, result }.into())]
+      }
+      Incoming::External(ClientRequest::SecondFactorEnrol { id }) => {
+        let response = match directory.access_management.second_factors.enrol(&self.name) {
+          Ok(Some((secret, url))) => ClientResponse::<String, Vec<u8>>::SecondFactorEnrolment { id, secret, url },
+          Ok(None) => ClientResponse::<String, Vec<u8>>::SecondFactorUpdate { id, result: UpdateResult::NotAllowed },
+          Err(e) => {
+            eprintln!("Failed to enrol second factor for {}: {}", &self.name, e);
+            ClientResponse::<String, Vec<u8>>::SecondFactorUpdate { id, result: UpdateResult::InternalError }
+          }
+        };
+        vec![Outgoing::Send(response.into())]
+      }
+      Incoming::External(ClientRequest::SecondFactorRecoveryReset { id, code }) => {
+        let response = match directory.access_management.second_factors.recovery_reset(&self.name, &code) {
+          Ok(Some(codes)) => ClientResponse::<String, Vec<u8>>::SecondFactorRecoveryCodes { id, codes },
+          Ok(None) => ClientResponse::<String, Vec<u8>>::SecondFactorUpdate { id, result: UpdateResult::NotAllowed },
+          Err(e) => {
+            eprintln!("Failed to reset recovery codes for {}: {}", &self.name, e);
+            ClientResponse::<String, Vec<u8>>::SecondFactorUpdate { id, result: UpdateResult::InternalError }
+          }
+        };
+        vec![Outgoing::Send(response.into())]
+      }
       Incoming::External(ClientRequest::LocationsList { id, source, timeout }) => {
         let timeout = Duration::seconds(timeout.clamp(5, 60) as i64);
         let recipient = incremental_search::SearchRequest(id);
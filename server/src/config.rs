@@ -1,5 +1,7 @@
 use crate::accounts::configuration::AccountsConfiguration;
 use crate::asset_store::AssetStoreConfiguration;
+use crate::database::configuration::DatabaseConfiguration;
+use crate::http_server::jwt::SigningConfiguration;
 use std::path::PathBuf;
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -8,25 +10,108 @@ pub(crate) struct ServerConfiguration {
   pub authentication: AccountsConfiguration,
   pub bind_address: Option<String>,
   pub certificate: Option<PathBuf>,
+  #[serde(default)]
+  pub database: DatabaseConfiguration,
+  /// The URL of the main database from before the `[database]` section existed; it is moved into `database.url` when the configuration is loaded
+  #[serde(default, skip_serializing)]
+  database_url: Option<String>,
   pub name: String,
+  #[serde(default)]
+  pub signing: SigningConfiguration,
   pub unix_socket: Option<String>,
 }
 
+/// What the server should do once its configuration is loaded
+pub(crate) enum ServerCommand {
+  /// Write the server's players, locations, settings, and assets to an archive
+  Export { output: PathBuf },
+  /// Load an archive written by another server into an empty database
+  Import { input: PathBuf, rename: bool },
+  /// Run the server normally
+  Serve,
+}
+
+#[derive(Debug)]
+enum Subcommand {
+  Export,
+  Import,
+  Serve,
+}
+
+impl std::str::FromStr for Subcommand {
+  type Err = ();
+  fn from_str(src: &str) -> Result<Subcommand, ()> {
+    match src {
+      "export" => Ok(Subcommand::Export),
+      "import" => Ok(Subcommand::Import),
+      "serve" => Ok(Subcommand::Serve),
+      _ => Err(()),
+    }
+  }
+}
+
 impl ServerConfiguration {
-  pub fn load() -> (Self, PathBuf) {
+  pub fn load() -> (Self, PathBuf, ServerCommand) {
     let mut configuration_file: String = "spadina.config".into();
+    let mut subcommand = Subcommand::Serve;
+    let mut args = vec![];
     {
       let mut ap = argparse::ArgumentParser::new();
       ap.set_description("Spadina Server");
       ap.refer(&mut configuration_file).add_option(&["-c", "--config"], argparse::Store, "Set the configuration JSON file");
+      ap.refer(&mut subcommand).add_argument("command", argparse::Store, "Command to run: serve (the default), export, or import");
+      ap.refer(&mut args).add_argument("arguments", argparse::List, "Arguments for command");
+      ap.stop_on_first_argument(true);
       ap.parse_args_or_exit();
     }
+    args.insert(0, format!("subcommand {:?}", subcommand));
+    let command = match subcommand {
+      Subcommand::Export => {
+        let mut output = String::new();
+        {
+          let mut ap = argparse::ArgumentParser::new();
+          ap.set_description("Writes the server's data to an archive that can be imported on another host");
+          ap.refer(&mut output).add_option(&["-o", "--output"], argparse::Store, "The archive file to write").required();
+          if let Err(x) = ap.parse(args, &mut std::io::stdout(), &mut std::io::stderr()) {
+            std::process::exit(x);
+          }
+        }
+        ServerCommand::Export { output: PathBuf::from(output) }
+      }
+      Subcommand::Import => {
+        let mut input = String::new();
+        let mut rename = false;
+        {
+          let mut ap = argparse::ArgumentParser::new();
+          ap.set_description("Loads an archive written by another server into an empty database");
+          ap.refer(&mut input).add_option(&["-i", "--input"], argparse::Store, "The archive file to read").required();
+          ap.refer(&mut rename).add_option(
+            &["-r", "--rename"],
+            argparse::StoreTrue,
+            "Allow importing an archive from a server with a different name, rewriting references to the old name",
+          );
+          if let Err(x) = ap.parse(args, &mut std::io::stdout(), &mut std::io::stderr()) {
+            std::process::exit(x);
+          }
+        }
+        ServerCommand::Import { input: PathBuf::from(input), rename }
+      }
+      Subcommand::Serve => ServerCommand::Serve,
+    };
     let mut configuration_file = PathBuf::try_from(configuration_file).expect("Invalid configuration path");
     let mut config: ServerConfiguration = toml::from_str(&std::fs::read_to_string(&configuration_file).expect("Cannot open configuration file"))
       .expect("Cannot parse configuration file.");
     let name = spadina_core::net::parse_server_name(&config.name).expect("Invalid server name. Must be a valid DNS name.");
     config.name = name;
+    if let Some(url) = config.database_url.take() {
+      if config.database.url.is_some() {
+        eprintln!("Both database_url and database.url are set; database_url is ignored");
+      } else {
+        eprintln!("The database_url setting is deprecated; use url in the [database] section instead");
+        config.database.url = Some(url);
+      }
+    }
     configuration_file.set_extension("db");
-    (config, configuration_file)
+    (config, configuration_file, command)
   }
 }
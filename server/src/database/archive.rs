@@ -0,0 +1,133 @@
+use chrono::{DateTime, Utc};
+use spadina_core::access::{AccessSetting, BannedPeer, LocalAccessSetting, OnlineAccess, Privilege, SimpleAccess};
+use spadina_core::avatar::Avatar;
+use spadina_core::communication;
+use spadina_core::communication::MessageBody;
+use spadina_core::location::communication::Announcement;
+use spadina_core::location::directory::Visibility;
+use spadina_core::location::target::{AbsoluteTarget, LocalTarget};
+use spadina_core::location::Descriptor;
+use spadina_core::net::server::auth::PublicKey;
+use spadina_core::player::PlayerIdentifier;
+use spadina_core::resource::Resource;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+/// The contents of the database tables that are carried between servers
+///
+/// Records refer to players and locations by name rather than by database identifier, so they can be loaded into a database that already has
+/// its own identifiers. Login credentials (public keys and second factors) and caches are deliberately excluded; the players who lose a
+/// second factor are listed so they can be warned.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct DatabaseArchive {
+  pub direct_messages: Vec<ArchiveDirectMessage>,
+  pub last_read: Vec<ArchiveLastRead>,
+  pub locations: Vec<ArchiveLocation>,
+  pub players: Vec<ArchivePlayer>,
+  #[serde(default)]
+  pub second_factor_players: Vec<String>,
+}
+
+/// The server-wide settings carried in an archive
+///
+/// Signing keys are not included, so all existing tokens are invalid on the new server.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchiveSettings {
+  pub administration: LocalAccessSetting<String>,
+  pub announcements: Vec<communication::Announcement<Arc<str>>>,
+  pub banned_peers: HashSet<BannedPeer<String>>,
+  pub create_assets: LocalAccessSetting<String>,
+  pub server_access: AccessSetting<Arc<str>, SimpleAccess>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchiveDirectMessage {
+  pub body: MessageBody<String>,
+  pub created: DateTime<Utc>,
+  pub recipient: String,
+  pub sender: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchiveLastRead {
+  pub recipient: String,
+  pub sender: String,
+  pub when: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchiveLocation {
+  pub acl: AccessSetting<String, Privilege>,
+  pub announcements: Vec<Announcement<String>>,
+  pub chat: Vec<ArchiveLocationMessage>,
+  pub created: DateTime<Utc>,
+  pub descriptor: Descriptor<String>,
+  pub name: String,
+  pub owner: String,
+  pub state: serde_json::Value,
+  pub updated_at: DateTime<Utc>,
+  pub visibility: Visibility,
+  pub visibility_changed: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchiveLocationMessage {
+  pub body: MessageBody<String>,
+  pub created: DateTime<Utc>,
+  pub sender: PlayerIdentifier<String>,
+}
+
+/// A chat message a player sent in a location owned by someone else
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchiveSentLocationMessage {
+  pub body: MessageBody<String>,
+  pub created: DateTime<Utc>,
+  pub location: LocalTarget<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchivePlayer {
+  pub avatar: Avatar,
+  pub bookmarks: Vec<Resource<String>>,
+  pub calendar: Vec<LocalTarget<String>>,
+  pub calendar_id: Vec<u8>,
+  pub created: DateTime<Utc>,
+  pub default_location_acl: AccessSetting<String, Privilege>,
+  pub last_login: DateTime<Utc>,
+  pub message_acl: AccessSetting<String, SimpleAccess>,
+  pub name: String,
+  pub online_acl: AccessSetting<String, OnlineAccess>,
+  pub remote_calendar: Vec<AbsoluteTarget<String>>,
+  pub remote_direct_messages: Vec<ArchiveRemoteDirectMessage>,
+  pub remote_last_read: Vec<ArchiveRemoteLastRead>,
+}
+
+/// Everything the server stores about a single player, so it can be given to them
+///
+/// Unlike [DatabaseArchive], this includes the player's public keys (but not the keys themselves, only their fingerprints) and only has the
+/// direct messages that player sent or received. Chat the player sent in locations owned by other players is included separately.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PlayerDataArchive {
+  pub direct_messages: Vec<ArchiveDirectMessage>,
+  pub last_read: Vec<ArchiveLastRead>,
+  pub location_messages: Vec<ArchiveSentLocationMessage>,
+  pub locations: Vec<ArchiveLocation>,
+  pub player: ArchivePlayer,
+  pub public_keys: BTreeMap<String, PublicKey>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchiveRemoteDirectMessage {
+  pub body: MessageBody<String>,
+  pub created: DateTime<Utc>,
+  pub inbound: bool,
+  pub remote_player: String,
+  pub remote_server: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchiveRemoteLastRead {
+  pub remote_player: String,
+  pub remote_server: String,
+  pub when: DateTime<Utc>,
+}
@@ -0,0 +1,117 @@
+use chrono::Utc;
+use libsqlite3_sys as ffi;
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How many times a backup step blocked by another connection is retried before the backup is abandoned
+const MAX_BUSY_RETRIES: u32 = 600;
+/// How long to wait before retrying a blocked backup step
+const BUSY_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Writes consistent copies of a SQLite database while the server continues to use it
+///
+/// The copy is made with SQLite's online backup API over separate connections, so it reflects a single point in time. It is written under a
+/// temporary name and only renamed once complete, so a partial backup is never mistaken for a good one. Old backups are never removed; pruning
+/// the backup directory is left to the administrator.
+pub struct Backup {
+  directory: PathBuf,
+  /// Held while a backup is written, so two backups requested in the same second cannot pick the same name
+  lock: Mutex<()>,
+  source: String,
+}
+
+/// A SQLite connection opened outside of the connection pool
+struct RawConnection(*mut ffi::sqlite3);
+
+impl Backup {
+  pub fn new(source: String, directory: PathBuf) -> Self {
+    Backup { directory, lock: Mutex::new(()), source }
+  }
+  /// Write a new backup, returning the file it was written to
+  pub fn write(&self) -> Result<PathBuf, String> {
+    let _guard = self.lock.lock().unwrap();
+    std::fs::create_dir_all(&self.directory).map_err(|e| format!("Cannot create backup directory {}: {}", self.directory.display(), e))?;
+    let timestamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut name = format!("spadina-{}.db", timestamp);
+    let mut suffix = 1;
+    while self.directory.join(&name).exists() {
+      name = format!("spadina-{}-{}.db", timestamp, suffix);
+      suffix += 1;
+    }
+    let destination = self.directory.join(&name);
+    let partial = self.directory.join(format!("{}.partial", name));
+    if let Err(e) = copy(&self.source, &partial) {
+      let _ = std::fs::remove_file(&partial);
+      return Err(e);
+    }
+    std::fs::rename(&partial, &destination).map_err(|e| format!("Cannot rename backup to {}: {}", destination.display(), e))?;
+    Ok(destination)
+  }
+}
+
+impl RawConnection {
+  fn open(path: &str, flags: i32) -> Result<Self, String> {
+    let path = CString::new(path).map_err(|e| e.to_string())?;
+    let mut connection = std::ptr::null_mut();
+    let code = unsafe { ffi::sqlite3_open_v2(path.as_ptr(), &mut connection, flags | ffi::SQLITE_OPEN_URI, std::ptr::null()) };
+    // SQLite may allocate a connection even when opening fails, so it must be closed either way
+    let connection = RawConnection(connection);
+    if code == ffi::SQLITE_OK {
+      Ok(connection)
+    } else {
+      Err(connection.error())
+    }
+  }
+  fn error(&self) -> String {
+    if self.0.is_null() {
+      "Out of memory".to_string()
+    } else {
+      unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.0)) }.to_string_lossy().into_owned()
+    }
+  }
+}
+
+impl Drop for RawConnection {
+  fn drop(&mut self) {
+    unsafe {
+      ffi::sqlite3_close(self.0);
+    }
+  }
+}
+
+fn copy(source: &str, destination: &Path) -> Result<(), String> {
+  let source = RawConnection::open(source, ffi::SQLITE_OPEN_READONLY)?;
+  let destination = RawConnection::open(
+    destination.to_str().ok_or_else(|| format!("Backup path {} is not valid UTF-8", destination.display()))?,
+    ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE,
+  )?;
+  unsafe {
+    let backup = ffi::sqlite3_backup_init(destination.0, c"main".as_ptr(), source.0, c"main".as_ptr());
+    if backup.is_null() {
+      return Err(destination.error());
+    }
+    // Copying every page in one step holds a single read transaction on the source, so the copy is consistent; the step is only retried
+    // if another connection holds a lock that prevents reading
+    let mut attempts = 0;
+    loop {
+      match ffi::sqlite3_backup_step(backup, -1) {
+        ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED if attempts < MAX_BUSY_RETRIES => {
+          attempts += 1;
+          std::thread::sleep(BUSY_RETRY_DELAY)
+        }
+        ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED => {
+          ffi::sqlite3_backup_finish(backup);
+          return Err(format!("Database stayed locked after {} attempts; backup abandoned", attempts + 1));
+        }
+        _ => break,
+      }
+    }
+    if ffi::sqlite3_backup_finish(backup) == ffi::SQLITE_OK {
+      Ok(())
+    } else {
+      Err(destination.error())
+    }
+  }
+}
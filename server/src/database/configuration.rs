@@ -0,0 +1,17 @@
+use std::path::PathBuf;
+
+/// How the server stores its main database
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct DatabaseConfiguration {
+  /// The directory where online backups are written; backups are only available for SQLite databases
+  pub backup_directory: Option<PathBuf>,
+  /// How long, in milliseconds, a SQLite connection waits for another connection to release a lock before failing
+  pub busy_timeout: Option<u32>,
+  /// The maximum number of connections kept open to the database
+  pub pool_size: Option<u32>,
+  /// The URL of the database; if absent, a SQLite database next to the configuration file is used
+  pub url: Option<String>,
+  /// Whether a SQLite database uses write-ahead logging, which allows players to read while another connection is writing
+  pub wal: bool,
+}
@@ -1,5 +1,5 @@
-use diesel::r2d2::{ConnectionManager, Pool};
-use diesel::{ConnectionError, MysqlConnection, PgConnection, SqliteConnection};
+use diesel::r2d2::{ConnectionManager, Error as PoolError, ManageConnection, Pool};
+use diesel::{Connection, ConnectionError, MysqlConnection, PgConnection, SqliteConnection};
 use std::error::Error;
 
 pub enum DatabaseBackend {
@@ -10,6 +10,34 @@ pub enum DatabaseBackend {
   MySql(Pool<ConnectionManager<MysqlConnection>>),
 }
 
+/// A connection to the server's main database
+#[cfg(feature = "postgres")]
+#[derive(diesel::MultiConnection)]
+pub enum MainConnection {
+  SQLite(SqliteConnection),
+  PostgreSQL(PgConnection),
+}
+
+/// A connection to the server's main database
+#[cfg(not(feature = "postgres"))]
+#[derive(diesel::MultiConnection)]
+pub enum MainConnection {
+  SQLite(SqliteConnection),
+}
+
+pub type MainBackend = <MainConnection as Connection>::Backend;
+
+/// Creates connections to the server's main database
+///
+/// The database engine is chosen by the URL rather than by trying each engine in turn, so a PostgreSQL server that is unreachable cannot
+/// be mistaken for a SQLite file.
+#[derive(Debug)]
+pub enum MainConnectionManager {
+  SQLite(String),
+  #[cfg(feature = "postgres")]
+  PostgreSQL(String),
+}
+
 impl DatabaseBackend {
   pub fn try_connect(database_url: String) -> Result<DatabaseBackend, Box<dyn Error + Sync + Send + 'static>> {
     if database_url.starts_with("sqlite://") {
@@ -36,3 +64,55 @@ impl DatabaseBackend {
     }
   }
 }
+
+impl MainConnectionManager {
+  /// Parse a database URL for the main database; only `sqlite://` and `postgresql://` are supported
+  pub fn try_new(database_url: &str) -> Result<Self, ConnectionError> {
+    if database_url.starts_with("sqlite://") {
+      Ok(MainConnectionManager::SQLite(database_url["sqlite:/".len()..].to_string()))
+    } else if database_url.starts_with("postgresql://") {
+      #[cfg(feature = "postgres")]
+      {
+        Ok(MainConnectionManager::PostgreSQL(database_url.to_string()))
+      }
+      #[cfg(not(feature = "postgres"))]
+      Err(ConnectionError::InvalidConnectionUrl("PostgreSQL is not enabled.".to_string()))
+    } else {
+      Err(ConnectionError::InvalidConnectionUrl(format!("Unsupported database URL for main database: {}", database_url)))
+    }
+  }
+}
+
+impl std::fmt::Display for MainConnectionManager {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      MainConnectionManager::SQLite(path) => write!(f, "SQLite database {}", path),
+      // The URL may contain a password, so it is not displayed
+      #[cfg(feature = "postgres")]
+      MainConnectionManager::PostgreSQL(_) => f.write_str("PostgreSQL database"),
+    }
+  }
+}
+
+impl ManageConnection for MainConnectionManager {
+  type Connection = MainConnection;
+  type Error = PoolError;
+
+  fn connect(&self) -> Result<Self::Connection, Self::Error> {
+    match self {
+      MainConnectionManager::SQLite(path) => SqliteConnection::establish(path).map(MainConnection::SQLite),
+      #[cfg(feature = "postgres")]
+      MainConnectionManager::PostgreSQL(url) => PgConnection::establish(url).map(MainConnection::PostgreSQL),
+    }
+    .map_err(PoolError::ConnectionError)
+  }
+
+  fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+    use diesel::connection::SimpleConnection;
+    conn.batch_execute("SELECT 1").map_err(PoolError::QueryError)
+  }
+
+  fn has_broken(&self, _conn: &mut Self::Connection) -> bool {
+    std::thread::panicking()
+  }
+}
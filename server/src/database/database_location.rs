@@ -17,6 +17,7 @@ use spadina_core::controller::{Controller, ControllerInput, ControllerOutput, Co
 use spadina_core::location::change::LocationChangeResponse;
 use spadina_core::location::communication::ChatMessage;
 use spadina_core::location::directory::Visibility;
+use spadina_core::location::history::SnapshotReason;
 use spadina_core::location::protocol::{KickResult, LocationRequest, LocationResponse};
 use spadina_core::location::Descriptor;
 use spadina_core::player::{PlayerIdentifier, SharedPlayerIdentifier};
@@ -26,12 +27,15 @@ use spadina_core::UpdateResult;
 use std::collections::{BTreeMap, HashSet};
 use std::future::Future;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::spawn;
 use tokio::sync::mpsc;
 use tokio::time::sleep;
 use tokio_stream::StreamExt;
 
+/// How often the state of a busy location is saved to its history
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 pub enum Event {
   Add(JoinRequest),
   Ignore,
@@ -69,8 +73,48 @@ impl OutputMapper<SharedPlayerIdentifier> for Player {
   }
 }
 
-async fn run<C: Controller<Input = Vec<u8>, Output = Vec<u8>>>(
-  mut controller: C,
+/// Decides when a copy of a location's state should be kept in its history
+struct StateHistory {
+  initial: Option<Value>,
+  last: Option<Value>,
+  last_time: Instant,
+}
+
+impl StateHistory {
+  fn new(initial: Option<Value>, now: Instant) -> Self {
+    StateHistory { initial, last: None, last_time: now }
+  }
+  /// Find the snapshot, if any, that must be saved before the location's state is replaced by `state`
+  ///
+  /// The state the location was loaded with is saved on the first change; after that, the state is saved at most once per
+  /// [SNAPSHOT_INTERVAL] and only if it differs from the last snapshot, rather than on every change.
+  fn update(&mut self, state: &Value, now: Instant) -> Option<(&Value, SnapshotReason)> {
+    if let Some(initial) = self.initial.take() {
+      if &initial == state {
+        self.initial = Some(initial);
+        None
+      } else {
+        self.last_time = now;
+        Some((self.last.insert(initial), SnapshotReason::Changed))
+      }
+    } else if now.duration_since(self.last_time) >= SNAPSHOT_INTERVAL && self.last.as_ref() != Some(state) {
+      self.last_time = now;
+      Some((self.last.insert(state.clone()), SnapshotReason::Periodic))
+    } else {
+      None
+    }
+  }
+  /// Track a state restored from the history, which is already saved
+  fn restored(&mut self, state: Value, now: Instant) {
+    self.initial = None;
+    self.last = Some(state);
+    self.last_time = now;
+  }
+}
+
+async fn run<CT: ControllerTemplate>(
+  template: CT,
+  mut controller: CT::Controller,
   owner_name: Arc<str>,
   local_server: Arc<str>,
   descriptor: Descriptor<Arc<str>>,
@@ -78,7 +122,11 @@ async fn run<C: Controller<Input = Vec<u8>, Output = Vec<u8>>>(
   db_id: i32,
   database: Database,
   mut waiting: Vec<JoinRequest>,
-) -> QueryResult<()> {
+) -> QueryResult<()>
+where
+  CT::Controller: Controller<Input = Vec<u8>, Output = Vec<u8>>,
+{
+  let mut history = StateHistory::new(controller.to_json().ok(), Instant::now());
   let mut acl = persisted::PersistedLocal::new(database.clone(), LocationAccess(db_id))?;
   let mut announcements = persisted::PersistedLocal::new(database.clone(), LocationAnnouncements(db_id))?;
   let mut location_name = persisted::PersistedLocal::new(database.clone(), LocationName(db_id))?;
@@ -341,6 +389,63 @@ async fn run<C: Controller<Input = Vec<u8>, Output = Vec<u8>>>(
             }
             Ok(messages) => Some(PlayerLocationUpdate::ResponseSingle(LocationResponse::Messages { messages, from, to })),
           },
+          LocationRequest::StateHistory { id } => Some(PlayerLocationUpdate::ResponseSingle(
+            if player.reference(AsReference::<str>::default()) == PlayerIdentifier::Local(owner_name.as_ref()) {
+              match database.location_state_history_list(db_id) {
+                Err(e) => {
+                  eprintln!("Failed to read state history for location {:?} (id={}): {}", &descriptor, db_id, e);
+                  LocationResponse::StateHistoryFailure { id, result: UpdateResult::InternalError }
+                }
+                Ok((snapshots, restores)) => LocationResponse::StateHistory { id, snapshots, restores },
+              }
+            } else {
+              LocationResponse::StateHistoryFailure { id, result: UpdateResult::NotAllowed }
+            },
+          )),
+          LocationRequest::StateRestore { id, snapshot } => Some(PlayerLocationUpdate::ResponseShared(LocationResponse::StateRestore {
+            id,
+            result: if player.reference(AsReference::<str>::default()) == PlayerIdentifier::Local(owner_name.as_ref()) {
+              match (database.location_state_history_read(db_id, snapshot), controller.to_json()) {
+                (Ok(None), _) => UpdateResult::NotAllowed,
+                (Err(e), _) => {
+                  eprintln!("Failed to read state history for location {:?} (id={}): {}", &descriptor, db_id, e);
+                  UpdateResult::InternalError
+                }
+                (_, Err(e)) => {
+                  eprintln!("Failed to serialize location state for {:?} (id={}): {}", &descriptor, db_id, e);
+                  UpdateResult::InternalError
+                }
+                (Ok(Some((state, created))), Ok(current)) => match template.load_json(state.clone()) {
+                  Err(e) => {
+                    eprintln!("Corrupt state in history for location {:?} (id={}, snapshot={}): {:?}", &descriptor, db_id, snapshot, e);
+                    UpdateResult::InternalError
+                  }
+                  Ok(restored) => match database.location_state_restore(db_id, &current, &state, created, &player) {
+                    Err(e) => {
+                      eprintln!("Failed to restore state for location {:?} (id={}): {}", &descriptor, db_id, e);
+                      UpdateResult::InternalError
+                    }
+                    Ok(()) => {
+                      eprintln!("Location {:?} (id={}) restored to snapshot {} from {} by {:?}", &descriptor, db_id, snapshot, created, &player);
+                      controller = restored;
+                      history.restored(state, Instant::now());
+                      // The restored controller has never seen the players that are present, so introduce them
+                      for (_, handle) in players.iter() {
+                        output.extend(controller.process(ControllerInput::Add {
+                          player: handle.principal.reference(AsReference::<str>::default()),
+                          player_kind: handle.kind,
+                          player_id: handle.id,
+                        }));
+                      }
+                      UpdateResult::Success
+                    }
+                  },
+                },
+              }
+            } else {
+              UpdateResult::NotAllowed
+            },
+          })),
           LocationRequest::Internal(request_id, request) => {
             if let Some(handle) = players.get(&player) {
               output.extend(controller.process(ControllerInput::Input {
@@ -423,6 +528,11 @@ async fn run<C: Controller<Input = Vec<u8>, Output = Vec<u8>>>(
           continue;
         }
       };
+      if let Some((snapshot, reason)) = history.update(&json, Instant::now()) {
+        if let Err(e) = database.location_state_snapshot(db_id, snapshot, reason) {
+          eprintln!("Failed to save location state history for {:?} (id={}): {}", &descriptor, db_id, e);
+        }
+      }
       if let Err(e) = database.location_state_write(db_id, json) {
         eprintln!("Failed to write location state for {:?} (id={}): {}", &descriptor, db_id, e);
       }
@@ -456,7 +566,14 @@ fn create_new_location(
   directory: &Directory,
   waiting: Vec<JoinRequest>,
 ) -> Option<impl Future<Output = ()> + Send + Sync + 'static> {
-  let controller = template.blank();
+  let controller = match template.blank() {
+    Ok(controller) => controller,
+    Err(e) => {
+      eprintln!("Failed to create state for new location: {}", e);
+      location_join.into_black_hole(LocationChangeResponse::InternalError);
+      return None;
+    }
+  };
   let state = match controller.to_json() {
     Ok(state) => state,
     Err(e) => {
@@ -469,7 +586,7 @@ fn create_new_location(
     Ok(db_id) => {
       let server_name = directory.access_management.server_name.clone();
       Some(async move {
-        if let Err(e) = run(controller, owner, server_name, descriptor, location_join, db_id, database, waiting).await {
+        if let Err(e) = run(template, controller, owner, server_name, descriptor, location_join, db_id, database, waiting).await {
           eprintln!("Failed to load state for new location (id={}): {}", db_id, e);
         }
       })
@@ -498,7 +615,7 @@ async fn load_location<CT: ControllerTemplate>(
   match template.load_json(state) {
     Ok(controller) => {
       if let Err(e) =
-        run(controller, owner, directory.access_management.server_name.clone(), descriptor, location_join, db_id, database, waiting).await
+        run(template, controller, owner, directory.access_management.server_name.clone(), descriptor, location_join, db_id, database, waiting).await
       {
         eprintln!("Failed to load location (id={}): {}", db_id, e);
       }
@@ -527,7 +644,9 @@ pub fn load(db_id: i32, owner: Arc<str>, descriptor: Descriptor<Arc<str>>, datab
         Ok((template, location_join, waiting)) => (ServerControllerTemplate::Asset(template), location_join, waiting),
         Err(()) => return,
       },
-      Descriptor::Application(a, _) => (ServerControllerTemplate::Application(*a), location_join, Vec::new()),
+      Descriptor::Application(a, _) => {
+        (ServerControllerTemplate::Application(*a, directory.access_management.server_name.clone()), location_join, Vec::new())
+      }
       Descriptor::Unsupported(s, _) => {
         eprintln!("Unsupported location type in database (id={}): {}", db_id, s);
         location_join.into_black_hole(LocationChangeResponse::UnsupportedError);
@@ -615,11 +734,53 @@ async fn find_asset(
 
     RealmTemplate::NotFound(missing) => {
       for join_request in waiting {
-        let _ =
-          join_request.tx.send(PlayerLocationUpdate::ResolveUpdate(LocationChangeResponse::MissingAssetError { assets: vec![missing.clone()] }));
+        let _ = join_request.tx.send(PlayerLocationUpdate::ResolveUpdate(LocationChangeResponse::MissingAssetError { assets: missing.clone() }));
       }
       // Don't black hole to allow a retry
       Err(())
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{StateHistory, SNAPSHOT_INTERVAL};
+  use serde_json::{json, Value};
+  use spadina_core::location::history::SnapshotReason;
+  use std::time::{Duration, Instant};
+
+  fn saved(history: &mut StateHistory, state: &Value, now: Instant) -> Option<(Value, SnapshotReason)> {
+    history.update(state, now).map(|(snapshot, reason)| (snapshot.clone(), reason))
+  }
+
+  #[test]
+  fn initial_state_saved_on_first_change() {
+    let start = Instant::now();
+    let mut history = StateHistory::new(Some(json!(0)), start);
+    assert_eq!(saved(&mut history, &json!(0), start + Duration::from_secs(1)), None);
+    assert_eq!(saved(&mut history, &json!(1), start + Duration::from_secs(2)), Some((json!(0), SnapshotReason::Changed)));
+    assert_eq!(saved(&mut history, &json!(2), start + Duration::from_secs(3)), None);
+  }
+
+  #[test]
+  fn periodic_snapshots_only_when_changed() {
+    let start = Instant::now();
+    let mut history = StateHistory::new(Some(json!(0)), start);
+    assert_eq!(saved(&mut history, &json!(1), start), Some((json!(0), SnapshotReason::Changed)));
+    assert_eq!(saved(&mut history, &json!(2), start + SNAPSHOT_INTERVAL - Duration::from_secs(1)), None);
+    let later = start + SNAPSHOT_INTERVAL;
+    assert_eq!(saved(&mut history, &json!(2), later), Some((json!(2), SnapshotReason::Periodic)));
+    assert_eq!(saved(&mut history, &json!(2), later + SNAPSHOT_INTERVAL), None);
+    assert_eq!(saved(&mut history, &json!(3), later + SNAPSHOT_INTERVAL), Some((json!(3), SnapshotReason::Periodic)));
+  }
+
+  #[test]
+  fn restored_state_not_saved_again() {
+    let start = Instant::now();
+    let mut history = StateHistory::new(Some(json!(0)), start);
+    history.restored(json!(5), start);
+    assert_eq!(saved(&mut history, &json!(5), start), None);
+    assert_eq!(saved(&mut history, &json!(5), start + SNAPSHOT_INTERVAL), None);
+    assert_eq!(saved(&mut history, &json!(6), start + SNAPSHOT_INTERVAL), Some((json!(6), SnapshotReason::Periodic)));
+  }
+}
@@ -17,10 +17,13 @@ use spadina_core::reference_converter::{AsReference, ToClone};
 use spadina_core::shared_ref::SharedRef;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot};
 
 pub enum DatabaseLocationRequest {
   Activity(LocalTarget<SharedRef<str>>, oneshot::Sender<Activity>),
+  /// Stop all the locations owned by a player, replying once they have finished
+  Close(Arc<str>, oneshot::Sender<()>),
   Create(DescriptorKind<SharedRef<str>>, JoinRequest),
   Join(LocalTarget<SharedRef<str>>, JoinRequest),
 }
@@ -56,6 +59,15 @@ pub fn start(auth: &AccessManagement, database: Database, directory: Directory,
           });
           None
         }
+        Event::Resolve(DatabaseLocationRequest::Close(owner, output)) => {
+          let closing: Vec<_> = active.keys().filter(|target| target.owner.as_ref() == owner.as_ref()).cloned().collect();
+          let endpoints: Vec<_> = closing.into_iter().filter_map(|target| active.remove(&target)).collect();
+          tokio::spawn(async move {
+            futures::future::join_all(endpoints.into_iter().map(|endpoint| endpoint.shutdown())).await;
+            let _ = output.send(());
+          });
+          None
+        }
         Event::Resolve(DatabaseLocationRequest::Create(target, join_request)) => {
           let PlayerIdentifier::Local(player) = &join_request.name else {
             let _ = join_request.tx.try_send(PlayerLocationUpdate::ResolveUpdate(LocationChangeResponse::PermissionError));
@@ -112,7 +124,7 @@ pub fn start(auth: &AccessManagement, database: Database, directory: Directory,
                 }
               };
               let endpoint = database_location::create_location(
-                ServerControllerTemplate::Application(application),
+                ServerControllerTemplate::Application(application, directory.access_management.server_name.clone()),
                 player.clone(),
                 Descriptor::Application(application, id),
                 &database,
@@ -1,15 +1,23 @@
+use crate::database::connect::MainBackend;
 use diesel::backend::Backend;
 use diesel::deserialize::FromSql;
 use diesel::deserialize::FromSqlRow;
 use diesel::expression::AsExpression;
+#[cfg(feature = "postgres")]
+use diesel::pg::{Pg, PgValue};
 use diesel::serialize::{IsNull, Output, ToSql};
 use diesel::sql_types::Binary;
 use diesel::sqlite::Sqlite;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+#[cfg(feature = "postgres")]
+use std::io::Write;
 use std::ops::{Deref, DerefMut};
 
+/// A value stored in the database in SQLite's binary JSON format
+///
+/// PostgreSQL stores the same bytes in a `bytea` column, so a database can be moved between engines without converting these values.
 #[derive(Debug, Clone, FromSqlRow, AsExpression, Serialize, Deserialize)]
 #[serde(transparent)]
 #[diesel(sql_type = Binary)]
@@ -52,6 +60,33 @@ impl<T: DeserializeOwned + Debug> FromSql<Binary, Sqlite> for AsJsonb<T> {
     Ok(serde_sqlite_jsonb::from_slice(unsafe { &*slice })?)
   }
 }
+#[cfg(feature = "postgres")]
+impl<T: Serialize + Debug> ToSql<Binary, Pg> for AsJsonb<T> {
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> diesel::serialize::Result {
+    out.write_all(&serde_sqlite_jsonb::to_vec(&self.0)?)?;
+    Ok(IsNull::No)
+  }
+}
+
+#[cfg(feature = "postgres")]
+impl<T: DeserializeOwned + Debug> FromSql<Binary, Pg> for AsJsonb<T> {
+  fn from_sql(bytes: PgValue<'_>) -> diesel::deserialize::Result<Self> {
+    Ok(serde_sqlite_jsonb::from_slice(bytes.as_bytes())?)
+  }
+}
+
+impl<T: Serialize + Debug> ToSql<Binary, MainBackend> for AsJsonb<T> {
+  fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, MainBackend>) -> diesel::serialize::Result {
+    out.set_value((Binary, self));
+    Ok(IsNull::No)
+  }
+}
+
+impl<T: DeserializeOwned + Debug> FromSql<Binary, MainBackend> for AsJsonb<T> {
+  fn from_sql(bytes: <MainBackend as Backend>::RawValue<'_>) -> diesel::deserialize::Result<Self> {
+    bytes.from_sql::<Self, Binary>()
+  }
+}
 impl<T: Default> Default for AsJsonb<T> {
   fn default() -> Self {
     AsJsonb(Default::default())
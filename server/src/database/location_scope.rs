@@ -1,3 +1,4 @@
+use crate::database::connect::MainBackend;
 use crate::database::diesel_serde_jsonb::AsJsonb;
 use crate::database::player_reference::PlayerReference;
 use crate::database::schema;
@@ -5,14 +6,12 @@ use crate::database::schema::location::dsl as location_schema;
 use diesel::expression::{AsExpression, SelectableExpression};
 use diesel::prelude::*;
 use diesel::sql_types::Bool;
-use diesel::sqlite::Sqlite;
 use diesel::BoxableExpression;
 use spadina_core::location::directory::{SearchCriteria, TimeRange, Visibility};
 use spadina_core::location::{Descriptor, DescriptorKind};
 use spadina_core::reference_converter::AsReference;
 use std::fmt::Debug;
 
-diesel::postfix_operator!(NoCaseCollation, " COLLATE UNICODE_NOCASE ", Bool, backend: Sqlite);
 pub struct LocationScope<S: AsRef<str>> {
   pub owner: PlayerReference<S>,
   pub descriptor: Descriptor<S>,
@@ -32,7 +31,7 @@ pub(crate) enum LocationListScope<S: AsRef<str>> {
 }
 
 impl<S: AsRef<str>> LocationScope<S> {
-  pub fn as_expression<'a, T: 'a>(&'a self) -> Box<dyn BoxableExpression<T, Sqlite, SqlType = Bool> + 'a>
+  pub fn as_expression<'a, T: 'a>(&'a self) -> Box<dyn BoxableExpression<T, MainBackend, SqlType = Bool> + 'a>
   where
     location_schema::descriptor: SelectableExpression<T>,
     schema::player::dsl::id: SelectableExpression<T>,
@@ -43,10 +42,11 @@ impl<S: AsRef<str>> LocationScope<S> {
   }
 }
 impl<S: AsRef<str> + Debug> LocationListScope<S> {
-  pub fn as_expression<'a, T: 'a>(&'a self) -> Box<dyn BoxableExpression<T, Sqlite, SqlType = Bool> + 'a>
+  pub fn as_expression<'a, T: 'a>(&'a self) -> Box<dyn BoxableExpression<T, MainBackend, SqlType = Bool> + 'a>
   where
     location_schema::created: SelectableExpression<T>,
     location_schema::descriptor: SelectableExpression<T>,
+    location_schema::kind: SelectableExpression<T>,
     location_schema::name: SelectableExpression<T>,
     location_schema::owner: SelectableExpression<T>,
     location_schema::updated_at: SelectableExpression<T>,
@@ -67,16 +67,12 @@ impl<S: AsRef<str> + Debug> LocationListScope<S> {
         TimeRange::In(start, end) => Box::new(location_schema::created.between(start.naive_utc(), end.naive_utc())),
       },
       LocationListScope::Exact(scope) => scope.as_expression(),
-      LocationListScope::Kind(kind) => match kind {
-        DescriptorKind::Asset(a) => Box::new(location_schema::descriptor.eq(AsJsonb(a.as_ref()))),
-        DescriptorKind::Application(application) => Box::new(super::get_kind_from_descriptor(location_schema::descriptor).eq(AsJsonb(*application))),
-        DescriptorKind::Unsupported(k) => Box::new(super::get_kind_from_descriptor(location_schema::descriptor).eq(AsJsonb(k.as_ref()))),
-      },
+      LocationListScope::Kind(kind) => Box::new(location_schema::kind.eq(AsJsonb(kind.reference(AsReference::<str>::default())))),
       LocationListScope::NameContains(name, case_sensitive) => {
         if *case_sensitive {
           Box::new(location_schema::name.like(name.as_ref()).escape('*'))
         } else {
-          Box::new(NoCaseCollation::new(location_schema::name.like(name.as_ref()).escape('*')))
+          Box::new(super::lower(location_schema::name).like(super::lower(name.as_ref())).escape('*'))
         }
       }
       LocationListScope::Not(scope) => Box::new(diesel::dsl::not(scope.as_expression())),
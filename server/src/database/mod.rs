@@ -1,3 +1,6 @@
+pub mod archive;
+pub mod backup;
+pub mod configuration;
 pub mod connect;
 pub mod database_location;
 pub mod database_location_directory;
@@ -11,17 +14,20 @@ pub mod player_reference;
 pub mod schema;
 pub mod setting;
 
+use crate::access::{BannedPeers, ServerAccess};
+use crate::accounts::db_policy::{CreateAssets, ServerAdministration};
 use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
-use diesel::connection::DefaultLoadingMode;
-use diesel::dsl::{count_star, sql};
+use configuration::DatabaseConfiguration;
+use connect::{MainConnection, MainConnectionManager};
+use diesel::connection::{DefaultLoadingMode, SimpleConnection};
+use diesel::dsl::count_star;
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, CustomizeConnection, Error, Pool};
+use diesel::r2d2::{CustomizeConnection, Error, Pool};
 use diesel::sql_types;
 use diesel::upsert::excluded;
 use diesel_serde_jsonb::AsJsonb;
 use futures::Stream;
 use futures::StreamExt;
-use icu::collator::{AlternateHandling, Collator, CollatorOptions, Strength};
 use player_access::PlayerAccess;
 use player_reference::PlayerReference;
 use serde::Serialize;
@@ -29,14 +35,16 @@ use spadina_core::access::AccessSetting;
 use spadina_core::avatar::Avatar;
 use spadina_core::location::communication::{Announcement, ChatMessage};
 use spadina_core::location::directory::{Activity, DirectoryEntry, Visibility};
+use spadina_core::location::history::{SnapshotReason, StateRestore, StateSnapshot};
 use spadina_core::location::target::{AbsoluteTarget, LocalTarget, UnresolvedTarget};
 use spadina_core::location::Descriptor;
+use spadina_core::net::server::administration::{DatabaseBackupResult, Invitation};
 use spadina_core::net::server::auth::PublicKey;
 use spadina_core::player::PlayerIdentifier;
 use spadina_core::reference_converter::{AsReference, AsSingle};
 use spadina_core::shared_ref::SharedRef;
 use spadina_core::{access, communication};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -44,66 +52,107 @@ use tokio::sync::broadcast;
 use tokio_stream::wrappers::BroadcastStream;
 
 #[derive(Clone)]
-pub(crate) struct Database(DbPool, broadcast::Sender<i32>);
+pub(crate) struct Database(DbPool, broadcast::Sender<i32>, Option<Arc<backup::Backup>>);
 
 pub(crate) struct StaleRemoteCalendar {
   pub(crate) player: String,
   pub(crate) server: String,
 }
 
-pub type DbPool = Pool<ConnectionManager<SqliteConnection>>;
+pub type DbPool = Pool<MainConnectionManager>;
 pub type CalendarCacheEntries<S> = Vec<(LocalTarget<S>, Announcement<S>)>;
 pub type Announcements = Vec<(AbsoluteTarget<SharedRef<str>>, Announcement<String>)>;
 
 pub const MIGRATIONS: diesel_migrations::EmbeddedMigrations = diesel_migrations::embed_migrations!();
+#[cfg(feature = "postgres")]
+pub const PG_MIGRATIONS: diesel_migrations::EmbeddedMigrations = diesel_migrations::embed_migrations!("pg-migrations");
 
-fn create_collator() -> Collator {
-  let mut options = CollatorOptions::new();
-  options.strength = Some(Strength::Primary);
-  options.alternate_handling = Some(AlternateHandling::Shifted);
-  Collator::try_new(&Default::default(), options).expect("Failed to initialize Unicode sorting")
-}
-
-lazy_static::lazy_static! {
-    static ref COLLATOR: Collator = create_collator();
-}
 define_sql_function!(fn get_kind_from_descriptor(descriptor: sql_types::Binary) -> sql_types::Binary);
+// SQLite's built-in version only handles ASCII, so it is replaced on every connection to match PostgreSQL's Unicode-aware one
+define_sql_function!(fn lower(text: sql_types::Text) -> sql_types::Text);
 
 impl Database {
-  pub fn new(db_file: PathBuf) -> Self {
+  pub fn new(configuration: &DatabaseConfiguration, db_file: PathBuf) -> Self {
     #[derive(Debug)]
-    struct Customizer;
-    impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for Customizer {
-      fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), Error> {
-        conn.register_collation("UNICODE_NOCASE", |a, b| COLLATOR.compare(a, b)).map_err(Error::QueryError)?;
-        get_kind_from_descriptor_utils::register_impl(conn, |descriptor: *const [u8]| {
-          let descriptor = unsafe { &*descriptor };
-          match serde_sqlite_jsonb::from_slice::<Descriptor<&str>>(descriptor) {
-            Ok(descriptor) => serde_sqlite_jsonb::to_vec(&descriptor.kind()).unwrap_or_else(|e| {
-              eprintln!("Failed to write descriptor kind in database call: {}", e);
-              vec![0_u8]
-            }),
-            Err(e) => {
-              eprintln!("Corrupt descriptor in database: {}", e);
-              vec![0_u8]
+    struct Customizer {
+      busy_timeout: Option<u32>,
+      wal: bool,
+    }
+    impl CustomizeConnection<MainConnection, diesel::r2d2::Error> for Customizer {
+      fn on_acquire(&self, conn: &mut MainConnection) -> Result<(), Error> {
+        match conn {
+          MainConnection::SQLite(conn) => {
+            if let Some(busy_timeout) = self.busy_timeout {
+              conn.batch_execute(&format!("PRAGMA busy_timeout = {};", busy_timeout)).map_err(Error::QueryError)?;
             }
+            if self.wal {
+              conn.batch_execute("PRAGMA journal_mode = WAL;").map_err(Error::QueryError)?;
+            }
+            // SQLite's LIKE ignores ASCII case by default, but PostgreSQL's does not; case-insensitive searches use lower() on both sides instead
+            conn.batch_execute("PRAGMA case_sensitive_like = ON;").map_err(Error::QueryError)?;
+            lower_utils::register_impl(conn, |text: String| text.to_lowercase()).map_err(Error::QueryError)?;
+            get_kind_from_descriptor_utils::register_impl(conn, |descriptor: *const [u8]| {
+              let descriptor = unsafe { &*descriptor };
+              match serde_sqlite_jsonb::from_slice::<Descriptor<&str>>(descriptor) {
+                Ok(descriptor) => serde_sqlite_jsonb::to_vec(&descriptor.kind()).unwrap_or_else(|e| {
+                  eprintln!("Failed to write descriptor kind in database call: {}", e);
+                  vec![0_u8]
+                }),
+                Err(e) => {
+                  eprintln!("Corrupt descriptor in database: {}", e);
+                  vec![0_u8]
+                }
+              }
+            })
+            .map_err(Error::QueryError)?;
           }
-        })
-        .map_err(Error::QueryError)?;
+          #[cfg(feature = "postgres")]
+          MainConnection::PostgreSQL(_) => (),
+        }
         Ok(())
       }
     }
+    let manager = match configuration.url.as_deref() {
+      Some(database_url) => MainConnectionManager::try_new(database_url).expect("Invalid database URL"),
+      None => MainConnectionManager::SQLite(db_file.display().to_string()),
+    };
+    let backup = configuration.backup_directory.as_ref().and_then(|directory| match &manager {
+      MainConnectionManager::SQLite(path) => Some(Arc::new(backup::Backup::new(path.clone(), directory.clone()))),
+      #[cfg(feature = "postgres")]
+      MainConnectionManager::PostgreSQL(_) => {
+        eprintln!("Online backups are only available for SQLite databases; use the database's own tools instead");
+        None
+      }
+    });
     let pool = {
-      eprintln!("Connecting to database: {}", db_file.display());
-      let manager = ConnectionManager::<SqliteConnection>::new(db_file.display().to_string());
-      Pool::builder().connection_customizer(Box::new(Customizer)).build(manager).expect("Failed to create pool.")
+      eprintln!("Connecting to {}", &manager);
+      let mut builder =
+        Pool::builder().connection_customizer(Box::new(Customizer { busy_timeout: configuration.busy_timeout, wal: configuration.wal }));
+      if let Some(pool_size) = configuration.pool_size {
+        builder = builder.max_size(pool_size);
+      }
+      builder.build(manager).expect("Failed to create pool.")
     };
     use diesel_migrations::MigrationHarness;
     eprintln!("Apply migrations");
     let mut db_connection = pool.get().expect("Failed to connect to database");
-    db_connection.run_pending_migrations(MIGRATIONS).expect("Failed to migrate database to latest schema");
+    match &mut *db_connection {
+      MainConnection::SQLite(db_connection) => {
+        use schema::location::dsl as location_schema;
+        db_connection.run_pending_migrations(MIGRATIONS).expect("Failed to migrate database to latest schema");
+        // Locations created before the kind was stored need it extracted from the descriptor
+        diesel::update(location_schema::location.filter(location_schema::kind.eq(Vec::<u8>::new())))
+          .set(location_schema::kind.eq(get_kind_from_descriptor(location_schema::descriptor)))
+          .execute(db_connection)
+          .expect("Failed to update location kinds");
+      }
+      #[cfg(feature = "postgres")]
+      MainConnection::PostgreSQL(db_connection) => {
+        db_connection.run_pending_migrations(PG_MIGRATIONS).expect("Failed to migrate database to latest schema");
+      }
+    }
     let (tx, _) = broadcast::channel(200);
-    Database(pool, tx)
+    Database(pool, tx, backup)
   }
   pub fn announcements_read(&self) -> QueryResult<Vec<communication::Announcement<Arc<str>>>> {
     use diesel::prelude::*;
@@ -133,24 +182,436 @@ impl Database {
     results
   }
   pub fn announcements_write(&self, announcements: &[communication::Announcement<impl AsRef<str> + Debug + serde::Serialize>]) -> QueryResult<()> {
-    use diesel::prelude::*;
-    use schema::announcement::dsl as announcement_schema;
-    self.0.get().unwrap().transaction::<(), diesel::result::Error, _>(|db_connection| {
-      diesel::delete(announcement_schema::announcement).execute(db_connection)?;
-      for a in announcements {
-        diesel::insert_into(announcement_schema::announcement)
+    self.0.get().unwrap().transaction::<(), diesel::result::Error, _>(|db_connection| write_announcements(db_connection, announcements))
+  }
+  /// Read everything needed to recreate this server's players and locations in another database
+  ///
+  /// Players that are waiting to be deleted are left out, along with anything that belongs to them.
+  pub fn archive_export(&self) -> QueryResult<archive::DatabaseArchive> {
+    use schema::bookmark::dsl as bookmark_schema;
+    use schema::local_player_chat::dsl as local_player_chat_schema;
+    use schema::local_player_last_read::dsl as local_player_last_read_schema;
+    use schema::location::dsl as location_schema;
+    use schema::location_announcement::dsl as location_announcement_schema;
+    use schema::location_calendar_subscription::dsl as calendar_schema;
+    use schema::location_chat::dsl as location_chat_schema;
+    use schema::player::dsl as player_schema;
+    use schema::remote_calendar_subscription::dsl as remote_calendar_schema;
+    use schema::remote_player_chat::dsl as remote_player_chat_schema;
+    use schema::remote_player_last_read::dsl as remote_player_last_read_schema;
+    use schema::second_factor::dsl as second_factor_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      let mut players = player_schema::player
+        .select((
+          player_schema::id,
+          player_schema::name,
+          player_schema::avatar,
+          player_schema::message_acl,
+          player_schema::online_acl,
+          player_schema::default_location_acl,
+          player_schema::calendar_id,
+          player_schema::last_login,
+          player_schema::created,
+        ))
+        .filter(player_schema::reset.eq(false))
+        .load::<(
+          i32,
+          String,
+          AsJsonb<Avatar>,
+          AsJsonb<AccessSetting<String, access::SimpleAccess>>,
+          AsJsonb<AccessSetting<String, access::OnlineAccess>>,
+          AsJsonb<AccessSetting<String, access::Privilege>>,
+          Vec<u8>,
+          NaiveDateTime,
+          NaiveDateTime,
+        )>(db_connection)?
+        .into_iter()
+        .map(|(id, name, avatar, message_acl, online_acl, default_location_acl, calendar_id, last_login, created)| {
+          (
+            id,
+            archive::ArchivePlayer {
+              avatar: avatar.0,
+              bookmarks: Vec::new(),
+              calendar: Vec::new(),
+              calendar_id,
+              created: Utc.from_utc_datetime(&created),
+              default_location_acl: default_location_acl.0,
+              last_login: Utc.from_utc_datetime(&last_login),
+              message_acl: message_acl.0,
+              name,
+              online_acl: online_acl.0,
+              remote_calendar: Vec::new(),
+              remote_direct_messages: Vec::new(),
+              remote_last_read: Vec::new(),
+            },
+          )
+        })
+        .collect::<BTreeMap<_, _>>();
+      for (player, value) in bookmark_schema::bookmark
+        .select((bookmark_schema::player, bookmark_schema::value))
+        .load::<(i32, AsJsonb<spadina_core::resource::Resource<String>>)>(db_connection)?
+      {
+        if let Some(player) = players.get_mut(&player) {
+          player.bookmarks.push(value.0);
+        }
+      }
+      for (player, descriptor, owner) in calendar_schema::location_calendar_subscription
+        .inner_join(location_schema::location.on(calendar_schema::location.eq(location_schema::id)))
+        .inner_join(player_schema::player.on(player_schema::id.eq(location_schema::owner)))
+        .select((calendar_schema::player, location_schema::descriptor, player_schema::name))
+        .filter(player_schema::reset.eq(false))
+        .load::<(i32, AsJsonb<Descriptor<String>>, String)>(db_connection)?
+      {
+        if let Some(player) = players.get_mut(&player) {
+          player.calendar.push(LocalTarget { descriptor: descriptor.0, owner });
+        }
+      }
+      for (player, owner, server, descriptor) in remote_calendar_schema::remote_calendar_subscription
+        .select((remote_calendar_schema::player, remote_calendar_schema::owner, remote_calendar_schema::server, remote_calendar_schema::descriptor))
+        .load::<(i32, String, String, AsJsonb<Descriptor<String>>)>(db_connection)?
+      {
+        if let Some(player) = players.get_mut(&player) {
+          player.remote_calendar.push(AbsoluteTarget { descriptor: descriptor.0, owner, server });
+        }
+      }
+      for (player, inbound, remote_player, remote_server, created, body) in remote_player_chat_schema::remote_player_chat
+        .select((
+          remote_player_chat_schema::player,
+          remote_player_chat_schema::inbound,
+          remote_player_chat_schema::remote_player,
+          remote_player_chat_schema::remote_server,
+          remote_player_chat_schema::created,
+          remote_player_chat_schema::body,
+        ))
+        .load::<(i32, bool, String, String, NaiveDateTime, AsJsonb<communication::MessageBody<String>>)>(db_connection)?
+      {
+        if let Some(player) = players.get_mut(&player) {
+          player.remote_direct_messages.push(archive::ArchiveRemoteDirectMessage {
+            body: body.0,
+            created: Utc.from_utc_datetime(&created),
+            inbound,
+            remote_player,
+            remote_server,
+          });
+        }
+      }
+      for (player, remote_player, remote_server, when) in remote_player_last_read_schema::remote_player_last_read
+        .select((
+          remote_player_last_read_schema::player,
+          remote_player_last_read_schema::remote_player,
+          remote_player_last_read_schema::remote_server,
+          remote_player_last_read_schema::when,
+        ))
+        .load::<(i32, String, String, NaiveDateTime)>(db_connection)?
+      {
+        if let Some(player) = players.get_mut(&player) {
+          player.remote_last_read.push(archive::ArchiveRemoteLastRead { remote_player, remote_server, when: Utc.from_utc_datetime(&when) });
+        }
+      }
+      let mut direct_messages = Vec::new();
+      for (sender, recipient, created, body) in local_player_chat_schema::local_player_chat
+        .select((
+          local_player_chat_schema::sender,
+          local_player_chat_schema::recipient,
+          local_player_chat_schema::created,
+          local_player_chat_schema::body,
+        ))
+        .load::<(i32, i32, NaiveDateTime, AsJsonb<communication::MessageBody<String>>)>(db_connection)?
+      {
+        if let (Some(sender), Some(recipient)) = (players.get(&sender), players.get(&recipient)) {
+          direct_messages.push(archive::ArchiveDirectMessage {
+            body: body.0,
+            created: Utc.from_utc_datetime(&created),
+            recipient: recipient.name.clone(),
+            sender: sender.name.clone(),
+          });
+        }
+      }
+      let mut last_read = Vec::new();
+      for (sender, recipient, when) in local_player_last_read_schema::local_player_last_read
+        .select((local_player_last_read_schema::sender, local_player_last_read_schema::recipient, local_player_last_read_schema::when))
+        .load::<(i32, i32, NaiveDateTime)>(db_connection)?
+      {
+        if let (Some(sender), Some(recipient)) = (players.get(&sender), players.get(&recipient)) {
+          last_read.push(archive::ArchiveLastRead {
+            recipient: recipient.name.clone(),
+            sender: sender.name.clone(),
+            when: Utc.from_utc_datetime(&when),
+          });
+        }
+      }
+      let mut locations = location_schema::location
+        .inner_join(player_schema::player)
+        .select((
+          location_schema::id,
+          player_schema::name,
+          location_schema::name,
+          location_schema::descriptor,
+          location_schema::state,
+          location_schema::acl,
+          location_schema::visibility,
+          location_schema::visibility_changed,
+          location_schema::created,
+          location_schema::updated_at,
+        ))
+        .filter(player_schema::reset.eq(false))
+        .load::<(
+          i32,
+          String,
+          String,
+          AsJsonb<Descriptor<String>>,
+          AsJsonb<serde_json::Value>,
+          AsJsonb<AccessSetting<String, access::Privilege>>,
+          i16,
+          NaiveDateTime,
+          NaiveDateTime,
+          NaiveDateTime,
+        )>(db_connection)?
+        .into_iter()
+        .map(|(id, owner, name, descriptor, state, acl, visibility, visibility_changed, created, updated_at)| {
+          (
+            id,
+            archive::ArchiveLocation {
+              acl: acl.0,
+              announcements: Vec::new(),
+              chat: Vec::new(),
+              created: Utc.from_utc_datetime(&created),
+              descriptor: descriptor.0,
+              name,
+              owner,
+              state: state.0,
+              updated_at: Utc.from_utc_datetime(&updated_at),
+              visibility: Visibility::try_from(visibility).unwrap_or(Visibility::Archived),
+              visibility_changed: Utc.from_utc_datetime(&visibility_changed),
+            },
+          )
+        })
+        .collect::<BTreeMap<_, _>>();
+      for (location, title, body, when, public) in location_announcement_schema::location_announcement
+        .select((
+          location_announcement_schema::location,
+          location_announcement_schema::title,
+          location_announcement_schema::body,
+          location_announcement_schema::when,
+          location_announcement_schema::public,
+        ))
+        .load::<(i32, String, String, AsJsonb<communication::AnnouncementTime>, bool)>(db_connection)?
+      {
+        if let Some(location) = locations.get_mut(&location) {
+          location.announcements.push(Announcement { title, body, when: when.0, public });
+        }
+      }
+      for (location, principal, created, body) in location_chat_schema::location_chat
+        .select((location_chat_schema::location, location_chat_schema::principal, location_chat_schema::created, location_chat_schema::body))
+        .load::<(i32, AsJsonb<PlayerIdentifier<String>>, NaiveDateTime, AsJsonb<communication::MessageBody<String>>)>(db_connection)?
+      {
+        if let Some(location) = locations.get_mut(&location) {
+          location.chat.push(archive::ArchiveLocationMessage { body: body.0, created: Utc.from_utc_datetime(&created), sender: principal.0 });
+        }
+      }
+      let second_factor_players = second_factor_schema::second_factor
+        .select(second_factor_schema::name)
+        .filter(second_factor_schema::confirmed.eq(true))
+        .load::<String>(db_connection)?
+        .into_iter()
+        .filter(|name| players.values().any(|player| &player.name == name))
+        .collect();
+      Ok(archive::DatabaseArchive {
+        direct_messages,
+        last_read,
+        locations: locations.into_values().collect(),
+        players: players.into_values().collect(),
+        second_factor_players,
+      })
+    })
+  }
+  /// Load the players, locations, and server settings from another server's archive
+  ///
+  /// Everything is written in a single transaction, so a failed import leaves the database untouched. Records that refer to players or
+  /// locations missing from the archive are skipped.
+  pub fn archive_import(&self, archive: &archive::DatabaseArchive, settings: &archive::ArchiveSettings) -> QueryResult<()> {
+    use schema::bookmark::dsl as bookmark_schema;
+    use schema::local_player_chat::dsl as local_player_chat_schema;
+    use schema::local_player_last_read::dsl as local_player_last_read_schema;
+    use schema::location::dsl as location_schema;
+    use schema::location_announcement::dsl as location_announcement_schema;
+    use schema::location_calendar_subscription::dsl as calendar_schema;
+    use schema::location_chat::dsl as location_chat_schema;
+    use schema::player::dsl as player_schema;
+    use schema::remote_calendar_subscription::dsl as remote_calendar_schema;
+    use schema::remote_player_chat::dsl as remote_player_chat_schema;
+    use schema::remote_player_last_read::dsl as remote_player_last_read_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      let mut players = HashMap::new();
+      for player in &archive.players {
+        let id = diesel::insert_into(player_schema::player)
+          .values((
+            player_schema::name.eq(&player.name),
+            player_schema::avatar.eq(AsJsonb(&player.avatar)),
+            player_schema::message_acl.eq(AsJsonb(&player.message_acl)),
+            player_schema::online_acl.eq(AsJsonb(&player.online_acl)),
+            player_schema::default_location_acl.eq(AsJsonb(&player.default_location_acl)),
+            player_schema::calendar_id.eq(&player.calendar_id),
+            player_schema::last_login.eq(player.last_login.naive_utc()),
+            player_schema::created.eq(player.created.naive_utc()),
+          ))
+          .returning(player_schema::id)
+          .get_result::<i32>(db_connection)?;
+        players.insert(player.name.as_str(), id);
+        for bookmark in &player.bookmarks {
+          diesel::insert_into(bookmark_schema::bookmark)
+            .values((bookmark_schema::player.eq(id), bookmark_schema::value.eq(AsJsonb(bookmark))))
+            .on_conflict_do_nothing()
+            .execute(db_connection)?;
+        }
+        for target in &player.remote_calendar {
+          diesel::insert_into(remote_calendar_schema::remote_calendar_subscription)
+            .values((
+              remote_calendar_schema::player.eq(id),
+              remote_calendar_schema::descriptor.eq(AsJsonb(&target.descriptor)),
+              remote_calendar_schema::owner.eq(&target.owner),
+              remote_calendar_schema::server.eq(&target.server),
+            ))
+            .on_conflict_do_nothing()
+            .execute(db_connection)?;
+        }
+        for message in &player.remote_direct_messages {
+          diesel::insert_into(remote_player_chat_schema::remote_player_chat)
+            .values((
+              remote_player_chat_schema::player.eq(id),
+              remote_player_chat_schema::inbound.eq(message.inbound),
+              remote_player_chat_schema::remote_player.eq(&message.remote_player),
+              remote_player_chat_schema::remote_server.eq(&message.remote_server),
+              remote_player_chat_schema::created.eq(message.created.naive_utc()),
+              remote_player_chat_schema::body.eq(AsJsonb(&message.body)),
+            ))
+            .on_conflict_do_nothing()
+            .execute(db_connection)?;
+        }
+        for last_read in &player.remote_last_read {
+          diesel::insert_into(remote_player_last_read_schema::remote_player_last_read)
+            .values((
+              remote_player_last_read_schema::player.eq(id),
+              remote_player_last_read_schema::remote_player.eq(&last_read.remote_player),
+              remote_player_last_read_schema::remote_server.eq(&last_read.remote_server),
+              remote_player_last_read_schema::when.eq(last_read.when.naive_utc()),
+            ))
+            .on_conflict_do_nothing()
+            .execute(db_connection)?;
+        }
+      }
+      let mut locations = HashMap::new();
+      for location in &archive.locations {
+        let Some(&owner) = players.get(location.owner.as_str()) else {
+          continue;
+        };
+        let id = diesel::insert_into(location_schema::location)
           .values((
-            announcement_schema::title.eq(a.title.as_ref()),
-            announcement_schema::body.eq(a.body.as_ref()),
-            announcement_schema::when.eq(AsJsonb(&a.when)),
-            announcement_schema::location.eq(AsJsonb(&a.location)),
-            announcement_schema::public.eq(a.public),
+            location_schema::name.eq(&location.name),
+            location_schema::owner.eq(owner),
+            location_schema::descriptor.eq(AsJsonb(&location.descriptor)),
+            location_schema::kind.eq(AsJsonb(location.descriptor.kind())),
+            location_schema::state.eq(AsJsonb(&location.state)),
+            location_schema::acl.eq(AsJsonb(&location.acl)),
+            location_schema::visibility.eq(location.visibility as i16),
+            location_schema::visibility_changed.eq(location.visibility_changed.naive_utc()),
+            location_schema::created.eq(location.created.naive_utc()),
+            location_schema::updated_at.eq(location.updated_at.naive_utc()),
           ))
-          .execute(db_connection)?;
+          .returning(location_schema::id)
+          .get_result::<i32>(db_connection)?;
+        locations.insert((location.owner.as_str(), &location.descriptor), id);
+        for announcement in &location.announcements {
+          diesel::insert_into(location_announcement_schema::location_announcement)
+            .values((
+              location_announcement_schema::location.eq(id),
+              location_announcement_schema::title.eq(&announcement.title),
+              location_announcement_schema::body.eq(&announcement.body),
+              location_announcement_schema::when.eq(AsJsonb(&announcement.when)),
+              location_announcement_schema::expires.eq(announcement.when.expires().naive_utc()),
+              location_announcement_schema::public.eq(announcement.public),
+            ))
+            .execute(db_connection)?;
+        }
+        for message in &location.chat {
+          diesel::insert_into(location_chat_schema::location_chat)
+            .values((
+              location_chat_schema::location.eq(id),
+              location_chat_schema::principal.eq(AsJsonb(&message.sender)),
+              location_chat_schema::created.eq(message.created.naive_utc()),
+              location_chat_schema::body.eq(AsJsonb(&message.body)),
+            ))
+            .on_conflict_do_nothing()
+            .execute(db_connection)?;
+        }
+      }
+      for player in &archive.players {
+        let id = players[player.name.as_str()];
+        for target in &player.calendar {
+          if let Some(&location) = locations.get(&(target.owner.as_str(), &target.descriptor)) {
+            diesel::insert_into(calendar_schema::location_calendar_subscription)
+              .values((calendar_schema::player.eq(id), calendar_schema::location.eq(location)))
+              .on_conflict_do_nothing()
+              .execute(db_connection)?;
+          }
+        }
       }
+      for message in &archive.direct_messages {
+        if let (Some(&sender), Some(&recipient)) = (players.get(message.sender.as_str()), players.get(message.recipient.as_str())) {
+          diesel::insert_into(local_player_chat_schema::local_player_chat)
+            .values((
+              local_player_chat_schema::sender.eq(sender),
+              local_player_chat_schema::recipient.eq(recipient),
+              local_player_chat_schema::created.eq(message.created.naive_utc()),
+              local_player_chat_schema::body.eq(AsJsonb(&message.body)),
+            ))
+            .on_conflict_do_nothing()
+            .execute(db_connection)?;
+        }
+      }
+      for last_read in &archive.last_read {
+        if let (Some(&sender), Some(&recipient)) = (players.get(last_read.sender.as_str()), players.get(last_read.recipient.as_str())) {
+          diesel::insert_into(local_player_last_read_schema::local_player_last_read)
+            .values((
+              local_player_last_read_schema::sender.eq(sender),
+              local_player_last_read_schema::recipient.eq(recipient),
+              local_player_last_read_schema::when.eq(last_read.when.naive_utc()),
+            ))
+            .on_conflict_do_nothing()
+            .execute(db_connection)?;
+        }
+      }
+      write_setting::<ServerAdministration>(db_connection, &settings.administration)?;
+      write_setting::<BannedPeers>(db_connection, &settings.banned_peers)?;
+      write_setting::<CreateAssets>(db_connection, &settings.create_assets)?;
+      write_setting::<ServerAccess>(db_connection, &settings.server_access)?;
+      write_announcements(db_connection, &settings.announcements)?;
       Ok(())
     })
   }
+  /// Check whether the database has any players, so an import does not mix two servers together
+  pub fn archive_is_empty(&self) -> QueryResult<bool> {
+    use schema::player::dsl as player_schema;
+    let mut db_connection = self.0.get().unwrap();
+    Ok(player_schema::player.select(count_star()).get_result::<i64>(&mut db_connection)? == 0)
+  }
+  /// Write a consistent copy of the database to the backup directory while the server keeps running
+  ///
+  /// This blocks until the copy is complete.
+  pub fn backup_create(&self) -> DatabaseBackupResult<String> {
+    match &self.2 {
+      None => DatabaseBackupResult::NotAvailable,
+      Some(backup) => match backup.write() {
+        Ok(path) => DatabaseBackupResult::Success(path.display().to_string()),
+        Err(e) => {
+          eprintln!("Failed to back up database: {}", e);
+          DatabaseBackupResult::InternalError
+        }
+      },
+    }
+  }
   pub fn bookmark_add(
     &self,
     db_id: i32,
@@ -395,7 +856,7 @@ impl Database {
             .select((
               remote_calendar_subscription_schema::player,
               remote_calendar_subscription_schema::server,
-              sql::<sql_types::Binary>("jsonb('[]')"),
+              <AsJsonb<Vec<()>> as AsExpression<sql_types::Binary>>::as_expression(AsJsonb(Vec::new())),
               <Option<NaiveDateTime> as AsExpression<sql_types::Nullable<sql_types::Timestamp>>>::as_expression(None),
               <NaiveDateTime as AsExpression<sql_types::Nullable<sql_types::Timestamp>>>::as_expression(now.naive_utc()),
               <Option<NaiveDateTime> as AsExpression<sql_types::Nullable<sql_types::Timestamp>>>::as_expression(None),
@@ -626,69 +1087,420 @@ impl Database {
       player_schema::player.select(player_schema::last_login).filter(player_schema::id.eq(db_id)).first::<NaiveDateTime>(&mut db_connection)?;
     Ok((stats, Utc.from_utc_datetime(&last_login)))
   }
-  pub fn player_reset(&self, player_name: &str) -> QueryResult<()> {
+  pub fn invitation_check(&self, code: &str) -> QueryResult<bool> {
+    use schema::invitation::dsl as invitation_schema;
     let mut db_connection = self.0.get().unwrap();
-    use schema::player::dsl as player_schema;
-    diesel::update(player_schema::player.filter(player_schema::name.eq(player_name)))
-      .set(player_schema::reset.eq(true))
-      .execute(&mut db_connection)?;
+    diesel::select(diesel::dsl::exists(
+      invitation_schema::invitation.filter(invitation_schema::code.eq(code).and(invitation_schema::expires.gt(Utc::now().naive_utc()))),
+    ))
+    .get_result(&mut db_connection)
+  }
+  pub fn invitation_clean(&self) -> QueryResult<()> {
+    use schema::invitation::dsl as invitation_schema;
+    let mut db_connection = self.0.get().unwrap();
+    diesel::delete(invitation_schema::invitation.filter(invitation_schema::expires.le(Utc::now().naive_utc()))).execute(&mut db_connection)?;
     Ok(())
   }
-  pub fn player_clean(&self) -> QueryResult<()> {
+  pub fn invitation_create(&self, code: &str, created_by: &str, expires: DateTime<Utc>) -> QueryResult<usize> {
+    use schema::invitation::dsl as invitation_schema;
     let mut db_connection = self.0.get().unwrap();
-    db_connection.transaction::<(), diesel::result::Error, _>(|db_connection| {
-      use schema::bookmark::dsl as bookmark_schema;
-      use schema::local_player_chat::dsl as local_player_chat_schema;
-      use schema::location::dsl as location_schema;
-      use schema::location_calendar_subscription::dsl as location_calendar_subscription_schema;
-      use schema::location_chat::dsl as location_chat_schema;
-      use schema::player::dsl as player_schema;
-      use schema::public_key::dsl as public_key_schema;
-      use schema::remote_player_chat::dsl as remote_player_chat_schema;
-      diesel::delete(
-        local_player_chat_schema::local_player_chat.filter(
-          local_player_chat_schema::sender
-            .eq_any(player_schema::player.select(player_schema::id).filter(player_schema::reset))
-            .or(local_player_chat_schema::recipient.eq_any(player_schema::player.select(player_schema::id).filter(player_schema::reset))),
-        ),
-      )
-      .execute(db_connection)?;
-      diesel::delete(
-        location_calendar_subscription_schema::location_calendar_subscription
-          .filter(location_calendar_subscription_schema::player.eq_any(player_schema::player.select(player_schema::id).filter(player_schema::reset))),
-      )
-      .execute(db_connection)?;
-      diesel::delete(
-        remote_player_chat_schema::remote_player_chat
-          .filter(remote_player_chat_schema::player.eq_any(player_schema::player.select(player_schema::id).filter(player_schema::reset))),
-      )
-      .execute(db_connection)?;
-      diesel::delete(
-        location_chat_schema::location_chat.filter(
-          location_chat_schema::location.eq_any(
-            location_schema::location
-              .select(location_schema::id)
-              .filter(location_schema::owner.eq_any(player_schema::player.select(player_schema::id).filter(player_schema::reset))),
-          ),
-        ),
-      )
-      .execute(db_connection)?;
-      diesel::delete(
-        location_schema::location.filter(location_schema::owner.eq_any(player_schema::player.select(player_schema::id).filter(player_schema::reset))),
-      )
-      .execute(db_connection)?;
-      diesel::delete(
-        bookmark_schema::bookmark
-          .filter(bookmark_schema::player.eq_any(player_schema::player.select(player_schema::id).filter(player_schema::reset))),
-      )
-      .execute(db_connection)?;
+    diesel::insert_into(invitation_schema::invitation)
+      .values((invitation_schema::code.eq(code), invitation_schema::created_by.eq(created_by), invitation_schema::expires.eq(expires.naive_utc())))
+      .on_conflict_do_nothing()
+      .execute(&mut db_connection)
+  }
+  pub fn invitation_list(&self) -> QueryResult<Vec<Invitation<String>>> {
+    use schema::invitation::dsl as invitation_schema;
+    let mut db_connection = self.0.get().unwrap();
+    let results = invitation_schema::invitation
+      .select((invitation_schema::code, invitation_schema::created_by, invitation_schema::created, invitation_schema::expires))
+      .filter(invitation_schema::expires.gt(Utc::now().naive_utc()))
+      .order_by(invitation_schema::expires)
+      .load_iter::<(String, String, NaiveDateTime, NaiveDateTime), DefaultLoadingMode>(&mut db_connection)?
+      .map(|r| {
+        r.map(|(code, created_by, created, expires)| Invitation {
+          code,
+          created_by,
+          created: Utc.from_utc_datetime(&created),
+          expires: Utc.from_utc_datetime(&expires),
+        })
+      })
+      .collect();
+    results
+  }
+  pub fn invitation_redeem(&self, code: &str) -> QueryResult<Option<Invitation<String>>> {
+    use schema::invitation::dsl as invitation_schema;
+    let mut db_connection = self.0.get().unwrap();
+    Ok(
       diesel::delete(
-        public_key_schema::public_key
-          .filter(public_key_schema::player.eq_any(player_schema::player.select(player_schema::id).filter(player_schema::reset))),
+        invitation_schema::invitation.filter(invitation_schema::code.eq(code).and(invitation_schema::expires.gt(Utc::now().naive_utc()))),
       )
-      .execute(db_connection)?;
-      diesel::delete(player_schema::player.filter(player_schema::reset)).execute(db_connection)?;
+      .returning((invitation_schema::code, invitation_schema::created_by, invitation_schema::created, invitation_schema::expires))
+      .get_result::<(String, String, NaiveDateTime, NaiveDateTime)>(&mut db_connection)
+      .optional()?
+      .map(|(code, created_by, created, expires)| Invitation {
+        code,
+        created_by,
+        created: Utc.from_utc_datetime(&created),
+        expires: Utc.from_utc_datetime(&expires),
+      }),
+    )
+  }
+  pub fn invitation_restore(&self, invitation: &Invitation<impl AsRef<str>>) -> QueryResult<usize> {
+    use schema::invitation::dsl as invitation_schema;
+    let mut db_connection = self.0.get().unwrap();
+    diesel::insert_into(invitation_schema::invitation)
+      .values((
+        invitation_schema::code.eq(invitation.code.as_ref()),
+        invitation_schema::created_by.eq(invitation.created_by.as_ref()),
+        invitation_schema::created.eq(invitation.created.naive_utc()),
+        invitation_schema::expires.eq(invitation.expires.naive_utc()),
+      ))
+      .on_conflict_do_nothing()
+      .execute(&mut db_connection)
+  }
+  pub fn invitation_rm(&self, code: &str) -> QueryResult<usize> {
+    use schema::invitation::dsl as invitation_schema;
+    let mut db_connection = self.0.get().unwrap();
+    diesel::delete(invitation_schema::invitation.filter(invitation_schema::code.eq(code))).execute(&mut db_connection)
+  }
+  /// Read everything the server stores about a player
+  pub fn player_data_export(&self, db_id: i32) -> QueryResult<archive::PlayerDataArchive> {
+    use schema::bookmark::dsl as bookmark_schema;
+    use schema::local_player_chat::dsl as local_player_chat_schema;
+    use schema::local_player_last_read::dsl as local_player_last_read_schema;
+    use schema::location::dsl as location_schema;
+    use schema::location_announcement::dsl as location_announcement_schema;
+    use schema::location_calendar_subscription::dsl as calendar_schema;
+    use schema::location_chat::dsl as location_chat_schema;
+    use schema::player::dsl as player_schema;
+    use schema::public_key::dsl as public_key_schema;
+    use schema::remote_calendar_subscription::dsl as remote_calendar_schema;
+    use schema::remote_player_chat::dsl as remote_player_chat_schema;
+    use schema::remote_player_last_read::dsl as remote_player_last_read_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      let (name, avatar, message_acl, online_acl, default_location_acl, calendar_id, last_login, created) = player_schema::player
+        .select((
+          player_schema::name,
+          player_schema::avatar,
+          player_schema::message_acl,
+          player_schema::online_acl,
+          player_schema::default_location_acl,
+          player_schema::calendar_id,
+          player_schema::last_login,
+          player_schema::created,
+        ))
+        .filter(player_schema::id.eq(db_id))
+        .first::<(
+          String,
+          AsJsonb<Avatar>,
+          AsJsonb<AccessSetting<String, access::SimpleAccess>>,
+          AsJsonb<AccessSetting<String, access::OnlineAccess>>,
+          AsJsonb<AccessSetting<String, access::Privilege>>,
+          Vec<u8>,
+          NaiveDateTime,
+          NaiveDateTime,
+        )>(db_connection)?;
+      let bookmarks = bookmark_schema::bookmark
+        .select(bookmark_schema::value)
+        .filter(bookmark_schema::player.eq(db_id))
+        .load::<AsJsonb<spadina_core::resource::Resource<String>>>(db_connection)?
+        .into_iter()
+        .map(|bookmark| bookmark.0)
+        .collect();
+      let calendar = calendar_schema::location_calendar_subscription
+        .inner_join(location_schema::location.on(calendar_schema::location.eq(location_schema::id)))
+        .inner_join(player_schema::player.on(player_schema::id.eq(location_schema::owner)))
+        .select((location_schema::descriptor, player_schema::name))
+        .filter(calendar_schema::player.eq(db_id))
+        .load::<(AsJsonb<Descriptor<String>>, String)>(db_connection)?
+        .into_iter()
+        .map(|(descriptor, owner)| LocalTarget { descriptor: descriptor.0, owner })
+        .collect();
+      let remote_calendar = remote_calendar_schema::remote_calendar_subscription
+        .select((remote_calendar_schema::owner, remote_calendar_schema::server, remote_calendar_schema::descriptor))
+        .filter(remote_calendar_schema::player.eq(db_id))
+        .load::<(String, String, AsJsonb<Descriptor<String>>)>(db_connection)?
+        .into_iter()
+        .map(|(owner, server, descriptor)| AbsoluteTarget { descriptor: descriptor.0, owner, server })
+        .collect();
+      let remote_direct_messages = remote_player_chat_schema::remote_player_chat
+        .select((
+          remote_player_chat_schema::inbound,
+          remote_player_chat_schema::remote_player,
+          remote_player_chat_schema::remote_server,
+          remote_player_chat_schema::created,
+          remote_player_chat_schema::body,
+        ))
+        .filter(remote_player_chat_schema::player.eq(db_id))
+        .load::<(bool, String, String, NaiveDateTime, AsJsonb<communication::MessageBody<String>>)>(db_connection)?
+        .into_iter()
+        .map(|(inbound, remote_player, remote_server, created, body)| archive::ArchiveRemoteDirectMessage {
+          body: body.0,
+          created: Utc.from_utc_datetime(&created),
+          inbound,
+          remote_player,
+          remote_server,
+        })
+        .collect();
+      let remote_last_read = remote_player_last_read_schema::remote_player_last_read
+        .select((remote_player_last_read_schema::remote_player, remote_player_last_read_schema::remote_server, remote_player_last_read_schema::when))
+        .filter(remote_player_last_read_schema::player.eq(db_id))
+        .load::<(String, String, NaiveDateTime)>(db_connection)?
+        .into_iter()
+        .map(|(remote_player, remote_server, when)| archive::ArchiveRemoteLastRead {
+          remote_player,
+          remote_server,
+          when: Utc.from_utc_datetime(&when),
+        })
+        .collect();
+
+      let chats = local_player_chat_schema::local_player_chat
+        .select((
+          local_player_chat_schema::sender,
+          local_player_chat_schema::recipient,
+          local_player_chat_schema::created,
+          local_player_chat_schema::body,
+        ))
+        .filter(local_player_chat_schema::sender.eq(db_id).or(local_player_chat_schema::recipient.eq(db_id)))
+        .load::<(i32, i32, NaiveDateTime, AsJsonb<communication::MessageBody<String>>)>(db_connection)?;
+      let reads = local_player_last_read_schema::local_player_last_read
+        .select((local_player_last_read_schema::sender, local_player_last_read_schema::recipient, local_player_last_read_schema::when))
+        .filter(local_player_last_read_schema::sender.eq(db_id).or(local_player_last_read_schema::recipient.eq(db_id)))
+        .load::<(i32, i32, NaiveDateTime)>(db_connection)?;
+      let mut other_players: Vec<i32> = chats
+        .iter()
+        .flat_map(|(sender, recipient, _, _)| [*sender, *recipient])
+        .chain(reads.iter().flat_map(|(sender, recipient, _)| [*sender, *recipient]))
+        .filter(|id| *id != db_id)
+        .collect();
+      other_players.sort();
+      other_players.dedup();
+      let mut names = player_schema::player
+        .select((player_schema::id, player_schema::name))
+        .filter(player_schema::id.eq_any(other_players))
+        .load::<(i32, String)>(db_connection)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+      names.insert(db_id, name.clone());
+      let direct_messages = chats
+        .into_iter()
+        .filter_map(|(sender, recipient, created, body)| {
+          Some(archive::ArchiveDirectMessage {
+            body: body.0,
+            created: Utc.from_utc_datetime(&created),
+            recipient: names.get(&recipient)?.clone(),
+            sender: names.get(&sender)?.clone(),
+          })
+        })
+        .collect();
+      let last_read = reads
+        .into_iter()
+        .filter_map(|(sender, recipient, when)| {
+          Some(archive::ArchiveLastRead {
+            recipient: names.get(&recipient)?.clone(),
+            sender: names.get(&sender)?.clone(),
+            when: Utc.from_utc_datetime(&when),
+          })
+        })
+        .collect();
+
+      let mut locations = location_schema::location
+        .select((
+          location_schema::id,
+          location_schema::name,
+          location_schema::descriptor,
+          location_schema::state,
+          location_schema::acl,
+          location_schema::visibility,
+          location_schema::visibility_changed,
+          location_schema::created,
+          location_schema::updated_at,
+        ))
+        .filter(location_schema::owner.eq(db_id))
+        .load::<(
+          i32,
+          String,
+          AsJsonb<Descriptor<String>>,
+          AsJsonb<serde_json::Value>,
+          AsJsonb<AccessSetting<String, access::Privilege>>,
+          i16,
+          NaiveDateTime,
+          NaiveDateTime,
+          NaiveDateTime,
+        )>(db_connection)?
+        .into_iter()
+        .map(|(id, location_name, descriptor, state, acl, visibility, visibility_changed, created, updated_at)| {
+          (
+            id,
+            archive::ArchiveLocation {
+              acl: acl.0,
+              announcements: Vec::new(),
+              chat: Vec::new(),
+              created: Utc.from_utc_datetime(&created),
+              descriptor: descriptor.0,
+              name: location_name,
+              owner: name.clone(),
+              state: state.0,
+              updated_at: Utc.from_utc_datetime(&updated_at),
+              visibility: Visibility::try_from(visibility).unwrap_or(Visibility::Archived),
+              visibility_changed: Utc.from_utc_datetime(&visibility_changed),
+            },
+          )
+        })
+        .collect::<BTreeMap<_, _>>();
+      for (location, title, body, when, public) in location_announcement_schema::location_announcement
+        .select((
+          location_announcement_schema::location,
+          location_announcement_schema::title,
+          location_announcement_schema::body,
+          location_announcement_schema::when,
+          location_announcement_schema::public,
+        ))
+        .filter(
+          location_announcement_schema::location
+            .eq_any(location_schema::location.select(location_schema::id).filter(location_schema::owner.eq(db_id))),
+        )
+        .load::<(i32, String, String, AsJsonb<communication::AnnouncementTime>, bool)>(db_connection)?
+      {
+        if let Some(location) = locations.get_mut(&location) {
+          location.announcements.push(Announcement { title, body, when: when.0, public });
+        }
+      }
+      for (location, principal, created, body) in location_chat_schema::location_chat
+        .select((location_chat_schema::location, location_chat_schema::principal, location_chat_schema::created, location_chat_schema::body))
+        .filter(location_chat_schema::location.eq_any(location_schema::location.select(location_schema::id).filter(location_schema::owner.eq(db_id))))
+        .load::<(i32, AsJsonb<PlayerIdentifier<String>>, NaiveDateTime, AsJsonb<communication::MessageBody<String>>)>(db_connection)?
+      {
+        if let Some(location) = locations.get_mut(&location) {
+          location.chat.push(archive::ArchiveLocationMessage { body: body.0, created: Utc.from_utc_datetime(&created), sender: principal.0 });
+        }
+      }
+
+      let location_messages = location_chat_schema::location_chat
+        .inner_join(location_schema::location.on(location_chat_schema::location.eq(location_schema::id)))
+        .inner_join(player_schema::player.on(player_schema::id.eq(location_schema::owner)))
+        .select((location_schema::descriptor, player_schema::name, location_chat_schema::created, location_chat_schema::body))
+        .filter(location_chat_schema::principal.eq(AsJsonb(PlayerIdentifier::Local(name.as_str()))).and(location_schema::owner.ne(db_id)))
+        .load::<(AsJsonb<Descriptor<String>>, String, NaiveDateTime, AsJsonb<communication::MessageBody<String>>)>(db_connection)?
+        .into_iter()
+        .map(|(descriptor, owner, created, body)| archive::ArchiveSentLocationMessage {
+          body: body.0,
+          created: Utc.from_utc_datetime(&created),
+          location: LocalTarget { descriptor: descriptor.0, owner },
+        })
+        .collect();
 
+      let public_keys = public_key_schema::public_key
+        .select((public_key_schema::label, public_key_schema::fingerprint, public_key_schema::created, public_key_schema::last_used))
+        .filter(public_key_schema::player.eq(db_id))
+        .load::<(String, String, NaiveDateTime, Option<NaiveDateTime>)>(db_connection)?
+        .into_iter()
+        .map(|(label, fingerprint, created, last_used)| {
+          (
+            label,
+            PublicKey {
+              created: Utc.from_utc_datetime(&created),
+              fingerprint,
+              last_used: last_used.map(|last_used| Utc.from_utc_datetime(&last_used)),
+            },
+          )
+        })
+        .collect();
+
+      Ok(archive::PlayerDataArchive {
+        direct_messages,
+        last_read,
+        location_messages,
+        locations: locations.into_values().collect(),
+        player: archive::ArchivePlayer {
+          avatar: avatar.0,
+          bookmarks,
+          calendar,
+          calendar_id,
+          created: Utc.from_utc_datetime(&created),
+          default_location_acl: default_location_acl.0,
+          last_login: Utc.from_utc_datetime(&last_login),
+          message_acl: message_acl.0,
+          name,
+          online_acl: online_acl.0,
+          remote_calendar,
+          remote_direct_messages,
+          remote_last_read,
+        },
+        public_keys,
+      })
+    })
+  }
+  /// Erase a player and everything that belongs to them
+  ///
+  /// The servers the player has exchanged direct messages with or follows calendars on are returned, so they can be told to erase their copies
+  /// of that data. The player's second factor, token generation, and invitations are also removed. This does *not* remove the player's account
+  /// from the login back-end or prevent the player from logging in again.
+  pub fn player_delete(&self, player_name: &str) -> QueryResult<BTreeSet<String>> {
+    use schema::calendar_cache::dsl as calendar_cache_schema;
+    use schema::invitation::dsl as invitation_schema;
+    use schema::player::dsl as player_schema;
+    use schema::remote_calendar_subscription::dsl as remote_calendar_subscription_schema;
+    use schema::remote_player_chat::dsl as remote_player_chat_schema;
+    use schema::remote_player_last_read::dsl as remote_player_last_read_schema;
+    use schema::second_factor::dsl as second_factor_schema;
+    use schema::second_factor_recovery::dsl as second_factor_recovery_schema;
+    use schema::token_generation::dsl as token_generation_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      diesel::delete(second_factor_recovery_schema::second_factor_recovery.filter(second_factor_recovery_schema::name.eq(player_name)))
+        .execute(db_connection)?;
+      diesel::delete(second_factor_schema::second_factor.filter(second_factor_schema::name.eq(player_name))).execute(db_connection)?;
+      diesel::delete(token_generation_schema::token_generation.filter(token_generation_schema::name.eq(player_name))).execute(db_connection)?;
+      diesel::delete(invitation_schema::invitation.filter(invitation_schema::created_by.eq(player_name))).execute(db_connection)?;
+      let Some(db_id) =
+        player_schema::player.select(player_schema::id).filter(player_schema::name.eq(player_name)).first::<i32>(db_connection).optional()?
+      else {
+        return Ok(BTreeSet::new());
+      };
+      let mut servers = BTreeSet::new();
+      servers.extend(
+        remote_player_chat_schema::remote_player_chat
+          .select(remote_player_chat_schema::remote_server)
+          .filter(remote_player_chat_schema::player.eq(db_id))
+          .distinct()
+          .load::<String>(db_connection)?,
+      );
+      servers.extend(
+        remote_player_last_read_schema::remote_player_last_read
+          .select(remote_player_last_read_schema::remote_server)
+          .filter(remote_player_last_read_schema::player.eq(db_id))
+          .distinct()
+          .load::<String>(db_connection)?,
+      );
+      servers.extend(
+        remote_calendar_subscription_schema::remote_calendar_subscription
+          .select(remote_calendar_subscription_schema::server)
+          .filter(remote_calendar_subscription_schema::player.eq(db_id))
+          .distinct()
+          .load::<String>(db_connection)?,
+      );
+      servers.extend(
+        calendar_cache_schema::calendar_cache
+          .select(calendar_cache_schema::server)
+          .filter(calendar_cache_schema::player.eq(db_id))
+          .distinct()
+          .load::<String>(db_connection)?,
+      );
+      delete_player(db_connection, db_id)?;
+      Ok(servers)
+    })
+  }
+  /// Erase any players that were marked for deletion by older versions of the server
+  pub fn player_clean(&self) -> QueryResult<()> {
+    use schema::player::dsl as player_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<(), diesel::result::Error, _>(|db_connection| {
+      for db_id in player_schema::player.select(player_schema::id).filter(player_schema::reset).load::<i32>(db_connection)? {
+        delete_player(db_connection, db_id)?;
+      }
       Ok(())
     })
   }
@@ -710,16 +1522,62 @@ impl Database {
       .returning((player_schema::id, player_schema::calendar_id))
       .get_result(&mut db_connection)
   }
-  pub fn public_key_add(&self, db_id: i32, der: &[u8]) -> QueryResult<usize> {
+  /// Add a public key, returning false if the player already has a key with the same label or the same key under a different label
+  pub fn public_key_add(&self, db_id: i32, label: &str, der: &[u8]) -> QueryResult<bool> {
     use schema::public_key::dsl as public_key_dsl;
     let fingerprint = spadina_core::net::server::auth::compute_fingerprint(der);
     let mut db_connection = self.0.get().unwrap();
-    diesel::insert_into(public_key_dsl::public_key)
-      .values((public_key_dsl::player.eq(db_id), public_key_dsl::fingerprint.eq(fingerprint), public_key_dsl::key.eq(der)))
-      .on_conflict_do_nothing()
-      .execute(&mut db_connection)
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      if diesel::select(diesel::dsl::exists(
+        public_key_dsl::public_key
+          .filter(public_key_dsl::player.eq(db_id).and(public_key_dsl::label.eq(label).or(public_key_dsl::fingerprint.eq(&fingerprint)))),
+      ))
+      .get_result::<bool>(db_connection)?
+      {
+        return Ok(false);
+      }
+      diesel::insert_into(public_key_dsl::public_key)
+        .values((
+          public_key_dsl::player.eq(db_id),
+          public_key_dsl::fingerprint.eq(&fingerprint),
+          public_key_dsl::key.eq(der),
+          public_key_dsl::label.eq(label),
+        ))
+        .execute(db_connection)?;
+      Ok(true)
+    })
+  }
+  /// Check that a public key is still registered to a player, without counting it as used
+  pub fn public_key_check(&self, player_name: &str, fingerprint: &str) -> QueryResult<bool> {
+    use schema::player::dsl as player_schema;
+    use schema::public_key::dsl as public_key_schema;
+    let mut db_connection = self.0.get().unwrap();
+    diesel::select(diesel::dsl::exists(
+      public_key_schema::public_key.filter(
+        public_key_schema::player
+          .eq(player_schema::player.select(player_schema::id).filter(player_schema::name.eq(player_name)).single_value().assume_not_null())
+          .and(public_key_schema::fingerprint.eq(fingerprint)),
+      ),
+    ))
+    .get_result(&mut db_connection)
   }
+  /// Get a player's public key to check a login
   pub fn public_key_get(&self, player_name: &str, fingerprint: &str) -> QueryResult<Option<Vec<u8>>> {
+    use schema::player::dsl as player_schema;
+    use schema::public_key::dsl as public_key_schema;
+    let mut db_connection = self.0.get().unwrap();
+    public_key_schema::public_key
+      .select(public_key_schema::key)
+      .filter(
+        public_key_schema::player
+          .eq(player_schema::player.select(player_schema::id).filter(player_schema::name.eq(player_name)).single_value().assume_not_null())
+          .and(public_key_schema::fingerprint.eq(fingerprint)),
+      )
+      .first(&mut db_connection)
+      .optional()
+  }
+  /// Record that a public key was used to log in successfully
+  pub fn public_key_used(&self, player_name: &str, fingerprint: &str) -> QueryResult<usize> {
     use schema::player::dsl as player_schema;
     use schema::public_key::dsl as public_key_schema;
     let mut db_connection = self.0.get().unwrap();
@@ -731,38 +1589,188 @@ impl Database {
       ),
     )
     .set(public_key_schema::last_used.eq(Utc::now().naive_utc()))
-    .returning(public_key_schema::key)
-    .get_result(&mut db_connection)
-    .optional()
+    .execute(&mut db_connection)
   }
   pub fn public_key_list(&self, db_id: i32) -> QueryResult<BTreeMap<String, PublicKey>> {
     use schema::public_key::dsl as public_key_schema;
     let mut db_connection = self.0.get().unwrap();
     let results = public_key_schema::public_key
-      .select((public_key_schema::fingerprint, public_key_schema::created, public_key_schema::last_used))
+      .select((public_key_schema::label, public_key_schema::fingerprint, public_key_schema::created, public_key_schema::last_used))
       .filter(public_key_schema::player.eq(&db_id))
-      .load_iter::<(String, NaiveDateTime, Option<NaiveDateTime>), DefaultLoadingMode>(&mut db_connection)?
+      .load_iter::<(String, String, NaiveDateTime, Option<NaiveDateTime>), DefaultLoadingMode>(&mut db_connection)?
       .map(|r| match r {
-        Ok((fingerprint, created, last_used)) => Ok((
-          fingerprint,
-          PublicKey { created: Utc.from_utc_datetime(&created), last_used: last_used.map(|last_used| Utc.from_utc_datetime(&last_used)) },
+        Ok((label, fingerprint, created, last_used)) => Ok((
+          label,
+          PublicKey {
+            created: Utc.from_utc_datetime(&created),
+            fingerprint,
+            last_used: last_used.map(|last_used| Utc.from_utc_datetime(&last_used)),
+          },
         )),
         Err(e) => Err(e),
       })
       .collect();
     results
   }
-  pub fn public_key_rm(&self, db_id: i32, fingerprint: &str) -> QueryResult<usize> {
+  /// Remove a public key, returning the fingerprint of the key removed, if there was one
+  pub fn public_key_rm(&self, db_id: i32, label: &str) -> QueryResult<Option<String>> {
     use schema::public_key::dsl as public_key_schema;
     let mut db_connection = self.0.get().unwrap();
-    diesel::delete(public_key_schema::public_key.filter(public_key_schema::fingerprint.eq(fingerprint).and(public_key_schema::player.eq(db_id))))
-      .execute(&mut db_connection)
+    diesel::delete(public_key_schema::public_key.filter(public_key_schema::label.eq(label).and(public_key_schema::player.eq(db_id))))
+      .returning(public_key_schema::fingerprint)
+      .get_result::<String>(&mut db_connection)
+      .optional()
   }
   pub fn public_key_rm_all(&self, db_id: i32) -> QueryResult<usize> {
     use schema::public_key::dsl as public_key_schema;
     let mut db_connection = self.0.get().unwrap();
     diesel::delete(public_key_schema::public_key.filter(public_key_schema::player.eq(&db_id))).execute(&mut db_connection)
   }
+  pub fn second_factor_confirm(&self, player_name: &str, recovery_codes: &[String]) -> QueryResult<bool> {
+    use schema::second_factor::dsl as second_factor_schema;
+    use schema::second_factor_recovery::dsl as second_factor_recovery_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      let count = diesel::update(
+        second_factor_schema::second_factor.filter(second_factor_schema::name.eq(player_name).and(second_factor_schema::confirmed.eq(false))),
+      )
+      .set(second_factor_schema::confirmed.eq(true))
+      .execute(db_connection)?;
+      if count == 0 {
+        return Ok(false);
+      }
+      diesel::delete(second_factor_recovery_schema::second_factor_recovery.filter(second_factor_recovery_schema::name.eq(player_name)))
+        .execute(db_connection)?;
+      diesel::insert_into(second_factor_recovery_schema::second_factor_recovery)
+        .values(
+          recovery_codes
+            .iter()
+            .map(|code| (second_factor_recovery_schema::name.eq(player_name), second_factor_recovery_schema::code.eq(code)))
+            .collect::<Vec<_>>(),
+        )
+        .execute(db_connection)?;
+      Ok(true)
+    })
+  }
+  pub fn second_factor_enrol(&self, player_name: &str, secret: &str) -> QueryResult<bool> {
+    use schema::second_factor::dsl as second_factor_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      let confirmed = second_factor_schema::second_factor
+        .select(second_factor_schema::confirmed)
+        .filter(second_factor_schema::name.eq(player_name))
+        .get_result::<bool>(db_connection)
+        .optional()?
+        .unwrap_or(false);
+      if confirmed {
+        return Ok(false);
+      }
+      diesel::insert_into(second_factor_schema::second_factor)
+        .values((
+          second_factor_schema::name.eq(player_name),
+          second_factor_schema::secret.eq(secret),
+          second_factor_schema::created.eq(Utc::now().naive_utc()),
+        ))
+        .on_conflict(second_factor_schema::name)
+        .do_update()
+        .set((
+          second_factor_schema::secret.eq(excluded(second_factor_schema::secret)),
+          second_factor_schema::created.eq(excluded(second_factor_schema::created)),
+          second_factor_schema::last_step.eq(None::<i64>),
+        ))
+        .execute(db_connection)?;
+      Ok(true)
+    })
+  }
+  pub fn second_factor_recovery_redeem(&self, player_name: &str, code: &str) -> QueryResult<bool> {
+    use schema::second_factor_recovery::dsl as second_factor_recovery_schema;
+    let mut db_connection = self.0.get().unwrap();
+    let count = diesel::delete(
+      second_factor_recovery_schema::second_factor_recovery
+        .filter(second_factor_recovery_schema::name.eq(player_name).and(second_factor_recovery_schema::code.eq(code))),
+    )
+    .execute(&mut db_connection)?;
+    Ok(count > 0)
+  }
+  pub fn second_factor_recovery_reset(&self, player_name: &str, recovery_codes: &[String]) -> QueryResult<bool> {
+    use schema::second_factor::dsl as second_factor_schema;
+    use schema::second_factor_recovery::dsl as second_factor_recovery_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      let count = second_factor_schema::second_factor
+        .select(diesel::dsl::count_star())
+        .filter(second_factor_schema::name.eq(player_name).and(second_factor_schema::confirmed.eq(true)))
+        .get_result::<i64>(db_connection)?;
+      if count == 0 {
+        return Ok(false);
+      }
+      diesel::delete(second_factor_recovery_schema::second_factor_recovery.filter(second_factor_recovery_schema::name.eq(player_name)))
+        .execute(db_connection)?;
+      diesel::insert_into(second_factor_recovery_schema::second_factor_recovery)
+        .values(
+          recovery_codes
+            .iter()
+            .map(|code| (second_factor_recovery_schema::name.eq(player_name), second_factor_recovery_schema::code.eq(code)))
+            .collect::<Vec<_>>(),
+        )
+        .execute(db_connection)?;
+      Ok(true)
+    })
+  }
+  pub fn second_factor_rm(&self, player_name: &str) -> QueryResult<usize> {
+    use schema::second_factor::dsl as second_factor_schema;
+    use schema::second_factor_recovery::dsl as second_factor_recovery_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      diesel::delete(second_factor_recovery_schema::second_factor_recovery.filter(second_factor_recovery_schema::name.eq(player_name)))
+        .execute(db_connection)?;
+      diesel::delete(second_factor_schema::second_factor.filter(second_factor_schema::name.eq(player_name))).execute(db_connection)
+    })
+  }
+  /// Record the time step of a one-time password that was accepted, returning false if a code from that step or a later one was already used
+  pub fn second_factor_use(&self, player_name: &str, step: i64) -> QueryResult<bool> {
+    use schema::second_factor::dsl as second_factor_schema;
+    let mut db_connection = self.0.get().unwrap();
+    let count = diesel::update(second_factor_schema::second_factor.filter(
+      second_factor_schema::name.eq(player_name).and(second_factor_schema::last_step.is_null().or(second_factor_schema::last_step.lt(step))),
+    ))
+    .set(second_factor_schema::last_step.eq(step))
+    .execute(&mut db_connection)?;
+    Ok(count > 0)
+  }
+  /// Get the secret for a player's second factor, if they have one that is confirmed or is still being enrolled
+  pub fn second_factor_secret(&self, player_name: &str, confirmed: bool) -> QueryResult<Option<String>> {
+    use schema::second_factor::dsl as second_factor_schema;
+    let mut db_connection = self.0.get().unwrap();
+    second_factor_schema::second_factor
+      .select(second_factor_schema::secret)
+      .filter(second_factor_schema::name.eq(player_name).and(second_factor_schema::confirmed.eq(confirmed)))
+      .get_result::<String>(&mut db_connection)
+      .optional()
+  }
+  pub fn token_generation_check(&self, player_name: &str) -> QueryResult<i32> {
+    use schema::token_generation::dsl as token_generation_schema;
+    let mut db_connection = self.0.get().unwrap();
+    Ok(
+      token_generation_schema::token_generation
+        .select(token_generation_schema::generation)
+        .filter(token_generation_schema::name.eq(player_name))
+        .get_result::<i32>(&mut db_connection)
+        .optional()?
+        .unwrap_or(0),
+    )
+  }
+  pub fn token_generation_increment(&self, player_name: &str) -> QueryResult<i32> {
+    use schema::token_generation::dsl as token_generation_schema;
+    let mut db_connection = self.0.get().unwrap();
+    diesel::insert_into(token_generation_schema::token_generation)
+      .values((token_generation_schema::name.eq(player_name), token_generation_schema::generation.eq(1)))
+      .on_conflict(token_generation_schema::name)
+      .do_update()
+      .set(token_generation_schema::generation.eq(token_generation_schema::generation + 1))
+      .returning(token_generation_schema::generation)
+      .get_result(&mut db_connection)
+  }
   pub fn location_acl_read(&self, id: i32) -> QueryResult<AccessSetting<Arc<str>, access::Privilege>> {
     use schema::location::dsl as location_schema;
     let mut db_connection = self.0.get().unwrap();
@@ -876,7 +1884,7 @@ impl Database {
       String,
     );
     let mut db_connection = self.0.get().unwrap();
-    let public_filter: Box<dyn BoxableExpression<_, diesel::sqlite::Sqlite, SqlType = sql_types::Bool>> = match player_secret_id.as_ref() {
+    let public_filter: Box<dyn BoxableExpression<_, connect::MainBackend, SqlType = sql_types::Bool>> = match player_secret_id.as_ref() {
       None => Box::new(location_announcement_schema::public.as_expression()),
       Some(calendar_id) => Box::new(player_schema::calendar_id.eq(calendar_id).or(location_announcement_schema::public).as_expression()),
     };
@@ -1124,6 +2132,7 @@ impl Database {
         location_schema::owner
           .eq(player_schema::player.select(player_schema::id).filter(player_schema::name.eq(owner)).single_value().assume_not_null()),
         location_schema::descriptor.eq(AsJsonb(descriptor)),
+        location_schema::kind.eq(AsJsonb(descriptor.kind())),
         location_schema::state.eq(AsJsonb(state)),
         location_schema::acl.eq(
           player_schema::player.select(player_schema::default_location_acl).filter(player_schema::name.eq(owner)).single_value().assume_not_null(),
@@ -1143,7 +2152,13 @@ impl Database {
       use schema::location_announcement::dsl as announcement_schema;
       use schema::location_calendar_subscription::dsl as calendar_schema;
       use schema::location_chat::dsl as chat_schema;
+      use schema::location_state_history::dsl as location_state_history_schema;
+      use schema::location_state_restore::dsl as location_state_restore_schema;
       diesel::delete(announcement_schema::location_announcement.filter(announcement_schema::location.eq(db_id))).execute(db_connection)?;
+      diesel::delete(location_state_history_schema::location_state_history.filter(location_state_history_schema::location.eq(db_id)))
+        .execute(db_connection)?;
+      diesel::delete(location_state_restore_schema::location_state_restore.filter(location_state_restore_schema::location.eq(db_id)))
+        .execute(db_connection)?;
       diesel::delete(calendar_schema::location_calendar_subscription.filter(calendar_schema::location.eq(db_id))).execute(db_connection)?;
       diesel::delete(chat_schema::location_chat.filter(chat_schema::location.eq(db_id))).execute(db_connection)?;
       diesel::delete(
@@ -1237,6 +2252,138 @@ impl Database {
       .map(|state| state.0)
   }
 
+  /// Delete old copies of location state
+  ///
+  /// Periodic snapshots are kept for two days; snapshots of a location's state before it first changes or before a restore are kept for
+  /// thirty days. The newest snapshot of each location is always kept. The record of restores is kept as long as the location exists.
+  pub fn location_state_history_clean(&self) -> QueryResult<()> {
+    use schema::location_state_history::dsl as location_state_history_schema;
+    let mut db_connection = self.0.get().unwrap();
+    let now = Utc::now();
+    diesel::alias!(schema::location_state_history as newest_history: NewestHistorySchema);
+    // Every location keeps its most recent snapshot, however old, so an idle location can still be rolled back
+    let superseded = || {
+      location_state_history_schema::id.nullable().lt(
+        newest_history
+          .filter(newest_history.field(location_state_history_schema::location).eq(location_state_history_schema::location))
+          .select(diesel::dsl::max(newest_history.field(location_state_history_schema::id)))
+          .single_value(),
+      )
+    };
+    diesel::delete(
+      location_state_history_schema::location_state_history.filter(
+        location_state_history_schema::reason
+          .eq(SnapshotReason::Periodic as i16)
+          .and(location_state_history_schema::created.le((now - Duration::days(2)).naive_utc()))
+          .and(superseded()),
+      ),
+    )
+    .execute(&mut db_connection)?;
+    diesel::delete(
+      location_state_history_schema::location_state_history
+        .filter(location_state_history_schema::created.le((now - Duration::days(30)).naive_utc()).and(superseded())),
+    )
+    .execute(&mut db_connection)?;
+    Ok(())
+  }
+  /// List the saved copies of a location's state, newest first, along with the times any were restored
+  pub fn location_state_history_list(&self, db_id: i32) -> QueryResult<(Vec<StateSnapshot>, Vec<StateRestore<String>>)> {
+    use schema::location_state_history::dsl as location_state_history_schema;
+    use schema::location_state_restore::dsl as location_state_restore_schema;
+    let mut db_connection = self.0.get().unwrap();
+    let snapshots = location_state_history_schema::location_state_history
+      .select((location_state_history_schema::id, location_state_history_schema::created, location_state_history_schema::reason))
+      .filter(location_state_history_schema::location.eq(db_id))
+      .order_by(location_state_history_schema::created.desc())
+      .load::<(i32, NaiveDateTime, i16)>(&mut db_connection)?
+      .into_iter()
+      .map(|(id, created, reason)| StateSnapshot {
+        id,
+        created: Utc.from_utc_datetime(&created),
+        reason: SnapshotReason::try_from(reason).unwrap_or(SnapshotReason::Periodic),
+      })
+      .collect();
+    let restores = location_state_restore_schema::location_state_restore
+      .select((location_state_restore_schema::principal, location_state_restore_schema::snapshot, location_state_restore_schema::created))
+      .filter(location_state_restore_schema::location.eq(db_id))
+      .order_by(location_state_restore_schema::created.desc())
+      .load::<(AsJsonb<PlayerIdentifier<String>>, NaiveDateTime, NaiveDateTime)>(&mut db_connection)?
+      .into_iter()
+      .map(|(principal, snapshot, created)| StateRestore {
+        principal: principal.0,
+        snapshot: Utc.from_utc_datetime(&snapshot),
+        timestamp: Utc.from_utc_datetime(&created),
+      })
+      .collect();
+    Ok((snapshots, restores))
+  }
+  /// Read a saved copy of a location's state and when it was saved, if it belongs to that location
+  pub fn location_state_history_read(&self, db_id: i32, snapshot: i32) -> QueryResult<Option<(serde_json::Value, DateTime<Utc>)>> {
+    use schema::location_state_history::dsl as location_state_history_schema;
+    let mut db_connection = self.0.get().unwrap();
+    Ok(
+      location_state_history_schema::location_state_history
+        .select((location_state_history_schema::state, location_state_history_schema::created))
+        .filter(location_state_history_schema::id.eq(snapshot).and(location_state_history_schema::location.eq(db_id)))
+        .first::<(AsJsonb<serde_json::Value>, NaiveDateTime)>(&mut db_connection)
+        .optional()?
+        .map(|(state, created)| (state.0, Utc.from_utc_datetime(&created))),
+    )
+  }
+  /// Replace a location's state with a saved copy
+  ///
+  /// The current state is saved as a new snapshot first, so the restore can be undone, and the restore is recorded along with the player that
+  /// requested it.
+  pub fn location_state_restore(
+    &self,
+    db_id: i32,
+    current: &serde_json::Value,
+    restored: &serde_json::Value,
+    snapshot: DateTime<Utc>,
+    principal: &PlayerIdentifier<impl AsRef<str> + serde::Serialize + Debug>,
+  ) -> QueryResult<()> {
+    use schema::location::dsl as location_schema;
+    use schema::location_state_history::dsl as location_state_history_schema;
+    use schema::location_state_restore::dsl as location_state_restore_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      let now = Utc::now().naive_utc();
+      diesel::insert_into(location_state_history_schema::location_state_history)
+        .values((
+          location_state_history_schema::location.eq(db_id),
+          location_state_history_schema::state.eq(AsJsonb(current)),
+          location_state_history_schema::reason.eq(SnapshotReason::Restore as i16),
+          location_state_history_schema::created.eq(now),
+        ))
+        .execute(db_connection)?;
+      diesel::update(location_schema::location.filter(location_schema::id.eq(db_id)))
+        .set(location_schema::state.eq(AsJsonb(restored)))
+        .execute(db_connection)?;
+      diesel::insert_into(location_state_restore_schema::location_state_restore)
+        .values((
+          location_state_restore_schema::location.eq(db_id),
+          location_state_restore_schema::principal.eq(AsJsonb(principal)),
+          location_state_restore_schema::snapshot.eq(snapshot.naive_utc()),
+          location_state_restore_schema::created.eq(now),
+        ))
+        .execute(db_connection)?;
+      Ok(())
+    })
+  }
+  /// Save a copy of a location's state so it can be restored later
+  pub fn location_state_snapshot(&self, db_id: i32, state: &serde_json::Value, reason: SnapshotReason) -> QueryResult<()> {
+    use schema::location_state_history::dsl as location_state_history_schema;
+    let mut db_connection = self.0.get().unwrap();
+    diesel::insert_into(location_state_history_schema::location_state_history)
+      .values((
+        location_state_history_schema::location.eq(db_id),
+        location_state_history_schema::state.eq(AsJsonb(state)),
+        location_state_history_schema::reason.eq(reason as i16),
+        location_state_history_schema::created.eq(Utc::now().naive_utc()),
+      ))
+      .execute(&mut db_connection)?;
+    Ok(())
+  }
   pub fn location_state_write(&self, db_id: i32, state: serde_json::Value) -> QueryResult<()> {
     use schema::location::dsl as location_schema;
     let mut db_connection = self.0.get().unwrap();
@@ -1351,6 +2498,30 @@ impl Database {
     }
     Ok(timestamp)
   }
+  /// Erase all conversations with and calendar subscriptions to a player on another server after that server has deleted them
+  pub fn remote_player_delete(&self, remote_player: &str, remote_server: &str) -> QueryResult<()> {
+    use schema::remote_calendar_subscription::dsl as remote_calendar_subscription_schema;
+    use schema::remote_player_chat::dsl as remote_player_chat_schema;
+    use schema::remote_player_last_read::dsl as remote_player_last_read_schema;
+    let mut db_connection = self.0.get().unwrap();
+    db_connection.transaction::<_, diesel::result::Error, _>(|db_connection| {
+      diesel::delete(
+        remote_player_chat_schema::remote_player_chat
+          .filter(remote_player_chat_schema::remote_player.eq(remote_player).and(remote_player_chat_schema::remote_server.eq(remote_server))),
+      )
+      .execute(db_connection)?;
+      diesel::delete(remote_player_last_read_schema::remote_player_last_read.filter(
+        remote_player_last_read_schema::remote_player.eq(remote_player).and(remote_player_last_read_schema::remote_server.eq(remote_server)),
+      ))
+      .execute(db_connection)?;
+      diesel::delete(
+        remote_calendar_subscription_schema::remote_calendar_subscription
+          .filter(remote_calendar_subscription_schema::owner.eq(remote_player).and(remote_calendar_subscription_schema::server.eq(remote_server))),
+      )
+      .execute(db_connection)?;
+      Ok(())
+    })
+  }
   pub(crate) fn player_avatar_read(&self, db_id: i32) -> QueryResult<Avatar> {
     use schema::player::dsl as player_schema;
     let mut db_connection = self.0.get().unwrap();
@@ -1402,15 +2573,236 @@ impl Database {
     )
   }
   pub fn setting_write<T: setting::Setting>(&self, data: &T::Stored) -> QueryResult<()> {
-    use schema::server_setting::dsl as server_setting_schema;
     let mut db_connection = self.0.get().unwrap();
+    write_setting::<T>(&mut db_connection, data)
+  }
+}
 
-    diesel::insert_into(server_setting_schema::server_setting)
-      .values(&(server_setting_schema::category.eq(std::str::from_utf8(&[T::CODE]).unwrap()), server_setting_schema::data.eq(AsJsonb(data))))
-      .on_conflict(server_setting_schema::category)
-      .do_update()
-      .set(server_setting_schema::data.eq(excluded(server_setting_schema::data)))
-      .execute(&mut db_connection)?;
-    Ok(())
+/// Replace all the server announcements
+fn write_announcements(
+  db_connection: &mut MainConnection,
+  announcements: &[communication::Announcement<impl AsRef<str> + Debug + serde::Serialize>],
+) -> QueryResult<()> {
+  use schema::announcement::dsl as announcement_schema;
+  diesel::delete(announcement_schema::announcement).execute(db_connection)?;
+  for a in announcements {
+    diesel::insert_into(announcement_schema::announcement)
+      .values((
+        announcement_schema::title.eq(a.title.as_ref()),
+        announcement_schema::body.eq(a.body.as_ref()),
+        announcement_schema::when.eq(AsJsonb(&a.when)),
+        announcement_schema::location.eq(AsJsonb(&a.location)),
+        announcement_schema::public.eq(a.public),
+      ))
+      .execute(db_connection)?;
+  }
+  Ok(())
+}
+
+/// Store a server setting, replacing any existing value
+fn write_setting<T: setting::Setting>(db_connection: &mut MainConnection, data: &T::Stored) -> QueryResult<()> {
+  use schema::server_setting::dsl as server_setting_schema;
+  diesel::insert_into(server_setting_schema::server_setting)
+    .values(&(server_setting_schema::category.eq(std::str::from_utf8(&[T::CODE]).unwrap()), server_setting_schema::data.eq(AsJsonb(data))))
+    .on_conflict(server_setting_schema::category)
+    .do_update()
+    .set(server_setting_schema::data.eq(excluded(server_setting_schema::data)))
+    .execute(db_connection)?;
+  Ok(())
+}
+
+/// Delete a player and every row that refers to them or their locations
+fn delete_player(db_connection: &mut MainConnection, db_id: i32) -> QueryResult<()> {
+  use schema::bookmark::dsl as bookmark_schema;
+  use schema::calendar_cache::dsl as calendar_cache_schema;
+  use schema::local_player_chat::dsl as local_player_chat_schema;
+  use schema::local_player_last_read::dsl as local_player_last_read_schema;
+  use schema::location::dsl as location_schema;
+  use schema::location_announcement::dsl as location_announcement_schema;
+  use schema::location_calendar_subscription::dsl as location_calendar_subscription_schema;
+  use schema::location_chat::dsl as location_chat_schema;
+  use schema::location_state_history::dsl as location_state_history_schema;
+  use schema::location_state_restore::dsl as location_state_restore_schema;
+  use schema::player::dsl as player_schema;
+  use schema::public_key::dsl as public_key_schema;
+  use schema::remote_calendar_subscription::dsl as remote_calendar_subscription_schema;
+  use schema::remote_player_chat::dsl as remote_player_chat_schema;
+  use schema::remote_player_last_read::dsl as remote_player_last_read_schema;
+  diesel::delete(
+    local_player_chat_schema::local_player_chat.filter(local_player_chat_schema::sender.eq(db_id).or(local_player_chat_schema::recipient.eq(db_id))),
+  )
+  .execute(db_connection)?;
+  diesel::delete(
+    local_player_last_read_schema::local_player_last_read
+      .filter(local_player_last_read_schema::sender.eq(db_id).or(local_player_last_read_schema::recipient.eq(db_id))),
+  )
+  .execute(db_connection)?;
+  diesel::delete(
+    location_calendar_subscription_schema::location_calendar_subscription.filter(
+      location_calendar_subscription_schema::player.eq(db_id).or(
+        location_calendar_subscription_schema::location
+          .eq_any(location_schema::location.select(location_schema::id).filter(location_schema::owner.eq(db_id))),
+      ),
+    ),
+  )
+  .execute(db_connection)?;
+  diesel::delete(remote_calendar_subscription_schema::remote_calendar_subscription.filter(remote_calendar_subscription_schema::player.eq(db_id)))
+    .execute(db_connection)?;
+  diesel::delete(remote_player_chat_schema::remote_player_chat.filter(remote_player_chat_schema::player.eq(db_id))).execute(db_connection)?;
+  diesel::delete(remote_player_last_read_schema::remote_player_last_read.filter(remote_player_last_read_schema::player.eq(db_id)))
+    .execute(db_connection)?;
+  diesel::delete(calendar_cache_schema::calendar_cache.filter(calendar_cache_schema::player.eq(db_id))).execute(db_connection)?;
+  diesel::delete(location_announcement_schema::location_announcement.filter(
+    location_announcement_schema::location.eq_any(location_schema::location.select(location_schema::id).filter(location_schema::owner.eq(db_id))),
+  ))
+  .execute(db_connection)?;
+  diesel::delete(
+    location_chat_schema::location_chat
+      .filter(location_chat_schema::location.eq_any(location_schema::location.select(location_schema::id).filter(location_schema::owner.eq(db_id)))),
+  )
+  .execute(db_connection)?;
+  let name = player_schema::player.select(player_schema::name).filter(player_schema::id.eq(db_id)).first::<String>(db_connection)?;
+  diesel::delete(location_chat_schema::location_chat.filter(location_chat_schema::principal.eq(AsJsonb(PlayerIdentifier::Local(name.as_str())))))
+    .execute(db_connection)?;
+  diesel::delete(location_state_history_schema::location_state_history.filter(
+    location_state_history_schema::location.eq_any(location_schema::location.select(location_schema::id).filter(location_schema::owner.eq(db_id))),
+  ))
+  .execute(db_connection)?;
+  diesel::delete(location_state_restore_schema::location_state_restore.filter(
+    location_state_restore_schema::location.eq_any(location_schema::location.select(location_schema::id).filter(location_schema::owner.eq(db_id))),
+  ))
+  .execute(db_connection)?;
+  diesel::delete(location_schema::location.filter(location_schema::owner.eq(db_id))).execute(db_connection)?;
+  diesel::delete(bookmark_schema::bookmark.filter(bookmark_schema::player.eq(db_id))).execute(db_connection)?;
+  diesel::delete(public_key_schema::public_key.filter(public_key_schema::player.eq(db_id))).execute(db_connection)?;
+  diesel::delete(player_schema::player.filter(player_schema::id.eq(db_id))).execute(db_connection)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::configuration::DatabaseConfiguration;
+  use super::location_scope::LocationListScope;
+  use super::player_reference::PlayerReference;
+  use super::Database;
+  use chrono::{Duration, Utc};
+  use diesel::prelude::*;
+  use spadina_core::communication::MessageBody;
+  use spadina_core::location::history::SnapshotReason;
+  use spadina_core::location::Descriptor;
+  use spadina_core::player::PlayerIdentifier;
+  use std::sync::Arc;
+
+  /// Check that case-insensitive name searches treat non-ASCII letters the same way on every back-end
+  fn check_name_search(database: &Database, owner: &str) {
+    database.player_load(owner).unwrap();
+    database.location_create(&Descriptor::Asset("name-search-test"), owner, "Ünïcode Garden", serde_json::Value::Null).unwrap();
+    let server_name: Arc<str> = Arc::from("localhost");
+    let count = |pattern: &str, case_sensitive: bool| {
+      database
+        .location_list(
+          &server_name,
+          LocationListScope::And(vec![
+            LocationListScope::Owner(PlayerReference::Name(owner)),
+            LocationListScope::NameContains(pattern, case_sensitive),
+          ]),
+        )
+        .unwrap()
+        .len()
+    };
+    assert_eq!(count("%Garden%", true), 1);
+    assert_eq!(count("%GARDEN%", true), 0);
+    assert_eq!(count("%garden%", true), 0);
+    assert_eq!(count("%Ünïcode%", true), 1);
+    assert_eq!(count("%GARDEN%", false), 1);
+    assert_eq!(count("%üNÏCODE%", false), 1);
+    assert_eq!(count("GARDEN%", false), 0);
+    assert_eq!(count("%Lawn%", false), 0);
+  }
+
+  /// Create an empty SQLite database that is removed when the test finishes
+  fn with_sqlite(test: &str, check: impl FnOnce(&Database)) {
+    let db_file = std::env::temp_dir().join(format!("spadina-{}-{}.db", test, std::process::id()));
+    let database = Database::new(&DatabaseConfiguration::default(), db_file.clone());
+    check(&database);
+    drop(database);
+    let _ = std::fs::remove_file(db_file);
+  }
+
+  /// Save a snapshot and make it look like it was taken some time ago
+  fn snapshot_aged(database: &Database, db_id: i32, reason: SnapshotReason, age: Duration) {
+    use super::schema::location_state_history::dsl as location_state_history_schema;
+    database.location_state_snapshot(db_id, &serde_json::Value::Null, reason).unwrap();
+    let mut db_connection = database.0.get().unwrap();
+    let id = location_state_history_schema::location_state_history
+      .select(diesel::dsl::max(location_state_history_schema::id))
+      .get_result::<Option<i32>>(&mut db_connection)
+      .unwrap()
+      .unwrap();
+    diesel::update(location_state_history_schema::location_state_history.filter(location_state_history_schema::id.eq(id)))
+      .set(location_state_history_schema::created.eq((Utc::now() - age).naive_utc()))
+      .execute(&mut db_connection)
+      .unwrap();
+  }
+
+  fn snapshot_reasons(database: &Database, db_id: i32) -> Vec<SnapshotReason> {
+    database.location_state_history_list(db_id).unwrap().0.into_iter().map(|snapshot| snapshot.reason).collect()
+  }
+
+  #[test]
+  fn name_search_sqlite() {
+    with_sqlite("name-search", |database| check_name_search(database, "sqlite-tester"));
+  }
+
+  #[test]
+  fn player_chat_in_other_locations() {
+    with_sqlite("player-chat", |database| {
+      let (chatter, _) = database.player_load("chatter").unwrap();
+      database.player_load("host").unwrap();
+      let location = database.location_create(&Descriptor::Asset("chat"), "host", "Chat", serde_json::Value::Null).unwrap();
+      database.location_chat_write(location, &PlayerIdentifier::Local("chatter"), &MessageBody::Text("hello")).unwrap();
+      database.location_chat_write(location, &PlayerIdentifier::Local("host"), &MessageBody::Text("welcome")).unwrap();
+
+      let export = database.player_data_export(chatter).unwrap();
+      assert_eq!(export.location_messages.len(), 1);
+      assert_eq!(export.location_messages[0].location.owner, "host");
+
+      database.player_delete("chatter").unwrap();
+      let now = Utc::now();
+      let remaining = database.location_messages(location, now - Duration::hours(1), now + Duration::hours(1)).unwrap();
+      assert_eq!(remaining.len(), 1);
+    });
+  }
+
+  #[test]
+  fn state_history_retention() {
+    with_sqlite("state-history", |database| {
+      database.player_load("owner").unwrap();
+      let busy = database.location_create(&Descriptor::Asset("busy"), "owner", "Busy", serde_json::Value::Null).unwrap();
+      let idle = database.location_create(&Descriptor::Asset("idle"), "owner", "Idle", serde_json::Value::Null).unwrap();
+      snapshot_aged(database, busy, SnapshotReason::Changed, Duration::days(40));
+      snapshot_aged(database, busy, SnapshotReason::Restore, Duration::days(10));
+      snapshot_aged(database, busy, SnapshotReason::Periodic, Duration::days(3));
+      snapshot_aged(database, busy, SnapshotReason::Periodic, Duration::days(1));
+      snapshot_aged(database, idle, SnapshotReason::Changed, Duration::days(50));
+      snapshot_aged(database, idle, SnapshotReason::Periodic, Duration::days(40));
+
+      database.location_state_history_clean().unwrap();
+
+      assert_eq!(snapshot_reasons(database, busy), vec![SnapshotReason::Periodic, SnapshotReason::Restore]);
+      assert_eq!(snapshot_reasons(database, idle), vec![SnapshotReason::Periodic]);
+    });
+  }
+
+  /// Runs against the PostgreSQL database in `SPADINA_TEST_POSTGRES_URL`, which the CI workflow provides
+  #[cfg(feature = "postgres")]
+  #[test]
+  fn name_search_postgres() {
+    let Ok(url) = std::env::var("SPADINA_TEST_POSTGRES_URL") else {
+      eprintln!("Skipping PostgreSQL test since SPADINA_TEST_POSTGRES_URL is not set");
+      return;
+    };
+    let database = Database::new(&DatabaseConfiguration { url: Some(url), ..Default::default() }, std::path::PathBuf::new());
+    check_name_search(&database, "postgres-tester");
   }
 }
@@ -1,3 +1,4 @@
+use crate::database::connect::MainBackend;
 use crate::database::schema;
 use diesel::{Column, SelectableExpression};
 use spadina_core::access;
@@ -7,7 +8,7 @@ pub trait PlayerAccess:
   + SelectableExpression<schema::player::table>
   + diesel::expression::ValidGrouping<()>
   + diesel::query_builder::QueryId
-  + diesel::query_builder::QueryFragment<diesel::sqlite::Sqlite>
+  + diesel::query_builder::QueryFragment<MainBackend>
 {
   type Verb: serde::de::DeserializeOwned + serde::Serialize + Copy + Default + std::fmt::Debug + 'static;
 }
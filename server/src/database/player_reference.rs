@@ -1,8 +1,8 @@
+use crate::database::connect::{MainBackend, MainConnection};
 use crate::database::schema::player::dsl as player_schema;
 use diesel::expression::SelectableExpression;
 use diesel::sql_types::Bool;
-use diesel::sqlite::Sqlite;
-use diesel::{BoxableExpression, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl, SqliteConnection};
+use diesel::{BoxableExpression, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
 
 pub enum PlayerReference<S: AsRef<str>> {
   Id(i32),
@@ -10,7 +10,7 @@ pub enum PlayerReference<S: AsRef<str>> {
 }
 
 impl<S: AsRef<str>> PlayerReference<S> {
-  pub fn as_expression<'a, T>(&'a self) -> Box<dyn BoxableExpression<T, Sqlite, SqlType = Bool> + 'a>
+  pub fn as_expression<'a, T>(&'a self) -> Box<dyn BoxableExpression<T, MainBackend, SqlType = Bool> + 'a>
   where
     player_schema::id: SelectableExpression<T>,
     player_schema::name: SelectableExpression<T>,
@@ -21,7 +21,7 @@ impl<S: AsRef<str>> PlayerReference<S> {
       PlayerReference::Name(name) => Box::new(player_schema::name.eq(name.as_ref())),
     }
   }
-  pub fn get_id(self, db_connection: &mut diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<SqliteConnection>>) -> QueryResult<i32> {
+  pub fn get_id(self, db_connection: &mut MainConnection) -> QueryResult<i32> {
     match self {
       PlayerReference::Id(id) => Ok(id),
       PlayerReference::Name(name) => {
@@ -30,6 +30,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    invitation (code) {
+        code -> Text,
+        created_by -> Text,
+        created -> Timestamp,
+        expires -> Timestamp,
+    }
+}
+
 diesel::table! {
     local_player_chat (sender, recipient, created) {
         sender -> Integer,
@@ -59,6 +68,7 @@ diesel::table! {
         visibility_changed -> Timestamp,
         created -> Timestamp,
         updated_at -> Timestamp,
+        kind -> Binary,
     }
 }
 
@@ -90,6 +100,26 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    location_state_history (id) {
+        id -> Integer,
+        location -> Integer,
+        state -> Binary,
+        reason -> SmallInt,
+        created -> Timestamp,
+    }
+}
+
+diesel::table! {
+    location_state_restore (id) {
+        id -> Integer,
+        location -> Integer,
+        principal -> Binary,
+        snapshot -> Timestamp,
+        created -> Timestamp,
+    }
+}
+
 diesel::table! {
     player (id) {
         id -> Integer,
@@ -112,6 +142,7 @@ diesel::table! {
         key -> Binary,
         last_used -> Nullable<Timestamp>,
         created -> Timestamp,
+        label -> Text,
     }
 }
 
@@ -144,6 +175,23 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    second_factor (name) {
+        name -> Text,
+        secret -> Text,
+        confirmed -> Bool,
+        created -> Timestamp,
+        last_step -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    second_factor_recovery (name, code) {
+        name -> Text,
+        code -> Text,
+    }
+}
+
 diesel::table! {
     server_setting (category) {
         category -> Text,
@@ -151,6 +199,13 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    token_generation (name) {
+        name -> Text,
+        generation -> Integer,
+    }
+}
+
 diesel::joinable!(bookmark -> player (player));
 diesel::joinable!(calendar_cache -> player (player));
 diesel::joinable!(location -> player (owner));
@@ -158,6 +213,8 @@ diesel::joinable!(location_announcement -> location (location));
 diesel::joinable!(location_calendar_subscription -> location (location));
 diesel::joinable!(location_calendar_subscription -> player (player));
 diesel::joinable!(location_chat -> location (location));
+diesel::joinable!(location_state_history -> location (location));
+diesel::joinable!(location_state_restore -> location (location));
 diesel::joinable!(public_key -> player (player));
 diesel::joinable!(remote_calendar_subscription -> player (player));
 diesel::joinable!(remote_player_chat -> player (player));
@@ -167,16 +224,22 @@ diesel::allow_tables_to_appear_in_same_query!(
   announcement,
   bookmark,
   calendar_cache,
+  invitation,
   local_player_chat,
   local_player_last_read,
   location,
   location_announcement,
   location_calendar_subscription,
   location_chat,
+  location_state_history,
+  location_state_restore,
   player,
   public_key,
   remote_calendar_subscription,
   remote_player_chat,
   remote_player_last_read,
+  second_factor,
+  second_factor_recovery,
   server_setting,
+  token_generation,
 );
@@ -8,18 +8,20 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use tokio::sync::mpsc::error::TrySendError;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::time::{interval, Duration, Interval};
 use tokio_stream::wrappers::BroadcastStream;
 
 pub struct LocationEndpoint {
   activity: AtomicActivity,
+  finished: oneshot::Receiver<()>,
   join: mpsc::Sender<JoinRequest>,
 }
 
 pub struct LocationJoin {
   activity: AtomicActivity,
   death: BroadcastStream<()>,
+  _finished: oneshot::Sender<()>,
   incoming: mpsc::Receiver<JoinRequest>,
   interval: Interval,
 }
@@ -35,6 +37,12 @@ impl LocationEndpoint {
   pub fn is_closed(&self) -> bool {
     self.join.is_closed()
   }
+  /// Stop the location from accepting new players and wait for it to finish running
+  pub async fn shutdown(self) {
+    let LocationEndpoint { finished, join, .. } = self;
+    std::mem::drop(join);
+    let _ = finished.await;
+  }
   pub fn join(&self, join_request: JoinRequest) -> Result<(), JoinRequest> {
     match self.join.try_send(join_request) {
       Ok(()) => Ok(()),
@@ -49,10 +57,17 @@ impl LocationEndpoint {
 
 pub fn new(death: broadcast::Receiver<()>) -> (LocationEndpoint, LocationJoin) {
   let (join, incoming) = mpsc::channel(100);
+  let (finished_tx, finished) = oneshot::channel();
   let activity = AtomicActivity::default();
   (
-    LocationEndpoint { join, activity: activity.clone() },
-    LocationJoin { incoming, activity, death: BroadcastStream::new(death), interval: interval(Duration::from_millis(900_000)) },
+    LocationEndpoint { join, activity: activity.clone(), finished },
+    LocationJoin {
+      incoming,
+      activity,
+      death: BroadcastStream::new(death),
+      _finished: finished_tx,
+      interval: interval(Duration::from_millis(900_000)),
+    },
   )
 }
 
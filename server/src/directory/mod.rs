@@ -111,6 +111,13 @@ impl Directory {
       Err(input)
     }
   }
+  /// Stop all of a player's locations that are running, waiting until they are no longer using the database
+  pub async fn close_locations(&self, owner: Arc<str>) {
+    let (tx, rx) = oneshot::channel();
+    if self.locations.send(DatabaseLocationRequest::Close(owner, tx)).await.is_ok() {
+      let _ = rx.await;
+    }
+  }
   pub async fn create_location(&self, descriptor_kind: DescriptorKind<SharedRef<str>>, join_request: JoinRequest) {
     if let Err(mpsc::error::SendError(DatabaseLocationRequest::Create(_, join_request))) =
       self.locations.send(DatabaseLocationRequest::Create(descriptor_kind, join_request)).await
@@ -118,6 +125,11 @@ impl Directory {
       let _ = join_request.tx.try_send(PlayerLocationUpdate::ResolveUpdate(LocationChangeResponse::InternalError));
     }
   }
+  /// Forcibly disconnect all of a player's sessions and remove them from any peer servers they are visiting
+  pub async fn disconnect_player(&self, player: Arc<str>) {
+    let _ = self.peers.send(PeerDirectoryRequest::Yank(player.clone())).await;
+    let _ = self.players.send(PlayerDirectoryRequest::Disconnect(player)).await;
+  }
   pub async fn join_host(&self, owner: SharedRef<str>, join_request: JoinRequest) {
     if let Err(mpsc::error::SendError(PlayerDirectoryRequest::Join(_, join_request))) =
       self.players.send(PlayerDirectoryRequest::Join(owner, join_request)).await
@@ -173,6 +185,16 @@ impl Directory {
     self.assets.send(AssetRequest::Upload(asset, output)).await.map_err(|_| AssetError::InternalError)?;
     input.await.map_err(|_| AssetError::InternalError)?
   }
+  /// Disconnect a player that has been deleted and tell the servers they had conversations with to erase them
+  pub async fn player_deleted(&self, player: Arc<str>, servers: impl IntoIterator<Item = String>) {
+    for server in servers {
+      let _ = self
+        .peers
+        .send(PeerDirectoryRequest::Request { server: SharedRef::Single(server), request: PeerRequest::PlayerDeleted(player.clone()) })
+        .await;
+    }
+    self.disconnect_player(player).await;
+  }
   pub async fn refresh_calendars(&self, updates: Vec<StaleRemoteCalendar>) {
     for StaleRemoteCalendar { server, player } in updates {
       let _ =
@@ -188,8 +210,17 @@ impl Directory {
       .try_send(PeerDirectoryRequest::Request { server: SharedRef::Shared(server), request: PeerRequest::Connect(connection) })
       .map_err(|_| ())
   }
-  pub async fn register_player(&self, name: Arc<str>, connection: WebSocketStream<MixedConnection>) -> Result<(), ()> {
-    self.players.send(PlayerDirectoryRequest::Connect(name, connection)).await.map_err(|_| ())
+  /// Attach a connection to a player
+  ///
+  /// Connections authenticated by a token must provide the token's generation and, if it was issued for a public key, that key's fingerprint;
+  /// if the player's tokens have been revoked or the key removed since, the connection is dropped.
+  pub async fn register_player(
+    &self,
+    name: Arc<str>,
+    token: Option<(i32, Option<String>)>,
+    connection: WebSocketStream<MixedConnection>,
+  ) -> Result<(), ()> {
+    self.players.send(PlayerDirectoryRequest::Connect(name, token, connection)).await.map_err(|_| ())
   }
   pub async fn search_on_peer(
     &self,
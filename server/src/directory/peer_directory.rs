@@ -27,12 +27,15 @@ pub enum PeerRequest {
   DirectMessage { sender: SharedRef<str>, recipient: SharedRef<str>, body: MessageBody<String>, status: watch::Sender<DirectMessageStatus> },
   Host(String, JoinRequest),
   Location { player: SharedRef<str>, descriptor: Descriptor<SharedRef<str>>, request: JoinRequest },
+  PlayerDeleted(Arc<str>),
   RefreshCalendar { player: String },
+  Yank(Arc<str>),
 }
 
 pub enum PeerDirectoryRequest {
   Peers(oneshot::Sender<Vec<Arc<str>>>),
   Request { server: SharedRef<str>, request: PeerRequest },
+  Yank(Arc<str>),
 }
 pub type PeerDirectory = mpsc::Sender<PeerDirectoryRequest>;
 
@@ -51,6 +54,11 @@ pub fn start(database: Database, directory: Directory, mut rx: mpsc::Receiver<Pe
             socket_entity::send::<Peer>(Arc::from(server), request, &database, &directory, &mut servers).await;
           }
         }
+        Some(PeerDirectoryRequest::Yank(player)) => {
+          for endpoint in servers.values() {
+            let _ = endpoint.send(PeerRequest::Yank(player.clone())).await;
+          }
+        }
       };
     }
   });
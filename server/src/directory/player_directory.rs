@@ -21,19 +21,22 @@ use tokio_tungstenite::WebSocketStream;
 
 pub enum PlayerRequest {
   Check(PlayerIdentifier<SharedRef<str>>, oneshot::Sender<OnlineState<SharedRef<str>>>),
-  Connect(WebSocketStream<MixedConnection>),
+  /// Attach a connection, along with the fingerprint of the public key it logged in with, if any
+  Connect(WebSocketStream<MixedConnection>, Option<String>),
   DirectMessage(PlayerIdentifier<SharedRef<str>>, MessageBody<String>, DateTime<Utc>),
+  Disconnect,
 }
 pub enum PlayerDirectoryRequest {
   Activity(SharedRef<str>, oneshot::Sender<Activity>),
   Check(PlayerIdentifier<SharedRef<str>>, SharedRef<str>, oneshot::Sender<OnlineState<SharedRef<str>>>),
-  Connect(Arc<str>, WebSocketStream<MixedConnection>),
+  Connect(Arc<str>, Option<(i32, Option<String>)>, WebSocketStream<MixedConnection>),
   DirectMessage {
     recipient: SharedRef<str>,
     sender: PlayerIdentifier<SharedRef<str>>,
     body: MessageBody<String>,
     status: watch::Sender<DirectMessageStatus>,
   },
+  Disconnect(Arc<str>),
   Host(Arc<str>, LocationEndpoint),
   Join(SharedRef<str>, JoinRequest),
 }
@@ -73,8 +76,32 @@ pub fn start(database: Database, directory: Directory, mut rx: mpsc::Receiver<Pl
               });
           }
         },
-        Some(PlayerDirectoryRequest::Connect(player, connection)) => {
-          socket_entity::send::<Client>(player, PlayerRequest::Connect(connection), &database, &directory, &mut players).await;
+        Some(PlayerDirectoryRequest::Connect(player, token, mut connection)) => {
+          let (current, key) = match token {
+            None => (true, None),
+            Some((generation, key)) => match database.token_generation_check(&player).and_then(|current| {
+              Ok(
+                current == generation
+                  && match &key {
+                    None => true,
+                    Some(key) => database.public_key_check(&player, key)?,
+                  },
+              )
+            }) {
+              Ok(current) => (current, key),
+              Err(e) => {
+                eprintln!("Failed to check token for {}: {}", &player, e);
+                (false, None)
+              }
+            },
+          };
+          if current {
+            socket_entity::send::<Client>(player, PlayerRequest::Connect(connection, key), &database, &directory, &mut players).await;
+          } else {
+            tokio::spawn(async move {
+              let _ = connection.close(None).await;
+            });
+          }
         }
         Some(PlayerDirectoryRequest::DirectMessage { recipient, sender, body, status }) => {
           let result = match &sender {
@@ -98,6 +125,11 @@ pub fn start(database: Database, directory: Directory, mut rx: mpsc::Receiver<Pl
             }
           }
         }
+        Some(PlayerDirectoryRequest::Disconnect(player)) => {
+          if let Some(handle) = players.remove(&player) {
+            let _ = handle.send(PlayerRequest::Disconnect).await;
+          }
+        }
         Some(PlayerDirectoryRequest::Host(player, endpoint)) => {
           hosting.insert(player, endpoint);
         }
@@ -37,3 +37,45 @@ pub fn create_main() -> String {
   };
   contents.into_string().unwrap()
 }
+
+pub fn create_invitation(code: &str) -> String {
+  let contents = horrorshow::html! {
+       : horrorshow::helper::doctype::HTML;
+       head {
+           title: "Spadina - Create Account";
+       }
+       body {
+           img(src="/spadina.svg", alt="");
+           form(method="post") {
+              input(type="hidden", name="invitation", value=code);
+              p {
+                 label(for="username") { : "Player name: " }
+                 input(type="text", id="username", name="username", required?=true);
+              }
+              p {
+                 label(for="password") { : "Password: " }
+                 input(type="password", id="password", name="password", required?=true);
+              }
+              input(type="submit", value="Create Account");
+           }
+       }
+  };
+  contents.into_string().unwrap()
+}
+
+pub fn create_invitation_result(message: &str) -> String {
+  let contents = horrorshow::html! {
+       : horrorshow::helper::doctype::HTML;
+       head {
+           title: "Spadina - Create Account";
+       }
+       body {
+           p {
+              img(src="/spadina.svg", alt="");
+              br;
+              : message;
+           }
+       }
+  };
+  contents.into_string().unwrap()
+}
@@ -1,42 +1,265 @@
 use crate::access::AccessManagement;
+use crate::database::setting::Setting;
+use crate::database::Database;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use diesel::QueryResult;
 use http_body_util::Full;
 use hyper::body::Bytes;
 use hyper::header::LOCATION;
 use hyper::{Response, StatusCode};
-use jsonwebtoken::{DecodingKey, EncodingKey};
+use jsonwebtoken::jwk::{
+  AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters, EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm,
+  OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use rand::distributions::{Alphanumeric, DistString};
 use spadina_core::resource::Resource;
+use std::error::Error;
+use std::fmt::{Debug, Formatter};
+use std::sync::RwLock;
 
-pub struct KeyPair {
-  encoding: EncodingKey,
+/// The algorithms that can be used to sign tokens
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum SigningAlgorithm {
+  #[default]
+  EdDSA,
+  ES256,
+  HS256,
+}
+
+/// How tokens issued by this server are signed
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct SigningConfiguration {
+  /// The algorithm used when generating a new key
+  pub algorithm: SigningAlgorithm,
+  /// How long, in hours, a replaced key is still accepted when verifying tokens
+  pub grace_hours: u32,
+  /// How long, in days, a key is used before a replacement is generated; if absent, keys are only replaced when the algorithm changes
+  pub rotate_days: Option<u32>,
+}
+
+/// The signing keys used by this server
+///
+/// The current key signs all new tokens. Keys that have been replaced are kept for a grace period so tokens that were signed by them remain
+/// valid until they expire.
+pub struct SigningKeys {
+  configuration: SigningConfiguration,
+  keys: RwLock<Vec<LoadedKey>>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct JwtKeys;
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct StoredKey {
+  algorithm: SigningAlgorithm,
+  created: DateTime<Utc>,
+  id: String,
+  retired: Option<DateTime<Utc>>,
+  secret: String,
+}
+
+struct LoadedKey {
+  algorithm: Algorithm,
   decoding: DecodingKey,
+  encoding: EncodingKey,
+  id: String,
+  public: Option<Jwk>,
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct PlayerClaim<S: AsRef<str>> {
   pub exp: usize,
+  /// The player's token generation when this token was issued; tokens from an older generation have been revoked
+  pub generation: i32,
+  /// The fingerprint of the public key used to log in, if any; the token is revoked if the key is removed
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub key: Option<String>,
   pub name: S,
 }
 
-impl Default for KeyPair {
+impl<S: AsRef<str>> PlayerClaim<S> {
+  pub fn new(name: S, database: &Database) -> QueryResult<Self> {
+    let generation = database.token_generation_check(name.as_ref())?;
+    Ok(PlayerClaim { exp: expiry_time(3600), generation, key: None, name })
+  }
+}
+
+impl Default for SigningConfiguration {
   fn default() -> Self {
-    let mut jwt_secret = [0; 32];
-    openssl::rand::rand_bytes(&mut jwt_secret).expect("Failed to generate JWT");
-    KeyPair { encoding: EncodingKey::from_secret(&jwt_secret), decoding: DecodingKey::from_secret(&jwt_secret) }
+    SigningConfiguration { algorithm: SigningAlgorithm::default(), grace_hours: 2, rotate_days: None }
   }
 }
+
+impl SigningKeys {
+  pub fn new(configuration: SigningConfiguration, database: &Database) -> Result<Self, Box<dyn Error + Send + Sync>> {
+    let keys = SigningKeys { configuration, keys: RwLock::new(Vec::new()) };
+    keys.rotate(database)?;
+    Ok(keys)
+  }
+  pub fn decode<C: serde::de::DeserializeOwned>(&self, data: &str) -> jsonwebtoken::errors::Result<C> {
+    let header = jsonwebtoken::decode_header(data)?;
+    let keys = self.keys.read().unwrap();
+    let key = keys.iter().find(|key| Some(key.id.as_str()) == header.kid.as_deref()).ok_or(jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+    Ok(jsonwebtoken::decode::<C>(data, &key.decoding, &jsonwebtoken::Validation::new(key.algorithm))?.claims)
+  }
+  pub fn encode<C: serde::Serialize>(&self, claim: &C) -> jsonwebtoken::errors::Result<String> {
+    let keys = self.keys.read().unwrap();
+    let key = keys.first().ok_or(jsonwebtoken::errors::ErrorKind::InvalidKeyFormat)?;
+    let mut header = jsonwebtoken::Header::new(key.algorithm);
+    header.kid = Some(key.id.clone());
+    jsonwebtoken::encode(&header, claim, &key.encoding)
+  }
+  /// The public keys that can be used to verify tokens signed by this server
+  ///
+  /// Keys for symmetric algorithms are never included.
+  pub fn public_keys(&self) -> JwkSet {
+    JwkSet { keys: self.keys.read().unwrap().iter().filter_map(|key| key.public.clone()).collect() }
+  }
+  /// Generate a new key if the current one has expired and discard any keys whose grace period has ended
+  pub fn rotate(&self, database: &Database) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut stored = database.setting_read::<JwtKeys>()?;
+    let now = Utc::now();
+    let grace = Duration::hours(self.configuration.grace_hours as i64);
+    let count = stored.len();
+    stored.retain(|key| key.retired.map(|retired| retired + grace > now).unwrap_or(true));
+    let mut changed = stored.len() != count;
+    let replace = match stored.iter().find(|key| key.retired.is_none()) {
+      None => true,
+      Some(current) => {
+        current.algorithm != self.configuration.algorithm
+          || self.configuration.rotate_days.map(|days| current.created + Duration::days(days as i64) < now).unwrap_or(false)
+      }
+    };
+    if replace {
+      for key in stored.iter_mut() {
+        key.retired.get_or_insert(now);
+      }
+      stored.insert(0, StoredKey::generate(self.configuration.algorithm)?);
+      changed = true;
+    }
+    if changed {
+      database.setting_write::<JwtKeys>(&stored)?;
+    }
+    let loaded = stored.iter().map(|key| key.load()).collect::<Result<Vec<_>, _>>()?;
+    *self.keys.write().unwrap() = loaded;
+    Ok(())
+  }
+}
+
+impl Setting for JwtKeys {
+  const CODE: u8 = b'k';
+  const METRIC: &'static str = "signing_keys";
+  type Stored = Vec<StoredKey>;
+}
+
+impl StoredKey {
+  fn generate(algorithm: SigningAlgorithm) -> Result<Self, openssl::error::ErrorStack> {
+    let secret = match algorithm {
+      SigningAlgorithm::EdDSA => String::from_utf8_lossy(&PKey::generate_ed25519()?.private_key_to_pem_pkcs8()?).into_owned(),
+      SigningAlgorithm::ES256 => {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        String::from_utf8_lossy(&PKey::from_ec_key(EcKey::generate(&group)?)?.private_key_to_pem_pkcs8()?).into_owned()
+      }
+      SigningAlgorithm::HS256 => {
+        let mut secret = [0; 32];
+        openssl::rand::rand_bytes(&mut secret)?;
+        base64::engine::general_purpose::STANDARD.encode(secret)
+      }
+    };
+    Ok(StoredKey { algorithm, created: Utc::now(), id: Alphanumeric.sample_string(&mut rand::thread_rng(), 16), retired: None, secret })
+  }
+  fn load(&self) -> Result<LoadedKey, Box<dyn Error + Send + Sync>> {
+    let (algorithm, encoding, decoding, public) = match self.algorithm {
+      SigningAlgorithm::EdDSA => {
+        let private = PKey::private_key_from_pem(self.secret.as_bytes())?;
+        (
+          Algorithm::EdDSA,
+          EncodingKey::from_ed_pem(self.secret.as_bytes())?,
+          DecodingKey::from_ed_pem(&private.public_key_to_pem()?)?,
+          Some((
+            KeyAlgorithm::EdDSA,
+            AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+              key_type: OctetKeyPairType::OctetKeyPair,
+              curve: EllipticCurve::Ed25519,
+              x: base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(private.raw_public_key()?),
+            }),
+          )),
+        )
+      }
+      SigningAlgorithm::ES256 => {
+        let private = PKey::private_key_from_pem(self.secret.as_bytes())?;
+        let ec_key = private.ec_key()?;
+        let mut context = BigNumContext::new()?;
+        let mut x = BigNum::new()?;
+        let mut y = BigNum::new()?;
+        ec_key.public_key().affine_coordinates_gfp(ec_key.group(), &mut x, &mut y, &mut context)?;
+        (
+          Algorithm::ES256,
+          EncodingKey::from_ec_pem(self.secret.as_bytes())?,
+          DecodingKey::from_ec_pem(&private.public_key_to_pem()?)?,
+          Some((
+            KeyAlgorithm::ES256,
+            AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+              key_type: EllipticCurveKeyType::EC,
+              curve: EllipticCurve::P256,
+              x: base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(x.to_vec_padded(32)?),
+              y: base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(y.to_vec_padded(32)?),
+            }),
+          )),
+        )
+      }
+      SigningAlgorithm::HS256 => {
+        let secret = base64::engine::general_purpose::STANDARD.decode(&self.secret)?;
+        (Algorithm::HS256, EncodingKey::from_secret(&secret), DecodingKey::from_secret(&secret), None)
+      }
+    };
+    Ok(LoadedKey {
+      algorithm,
+      decoding,
+      encoding,
+      id: self.id.clone(),
+      public: public.map(|(key_algorithm, algorithm)| Jwk {
+        common: CommonParameters {
+          public_key_use: Some(PublicKeyUse::Signature),
+          key_algorithm: Some(key_algorithm),
+          key_id: Some(self.id.clone()),
+          ..Default::default()
+        },
+        algorithm,
+      }),
+    })
+  }
+}
+
+impl Debug for StoredKey {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("StoredKey")
+      .field("algorithm", &self.algorithm)
+      .field("created", &self.created)
+      .field("id", &self.id)
+      .field("retired", &self.retired)
+      .finish_non_exhaustive()
+  }
+}
+
 pub fn expiry_time(duration_secs: u64) -> usize {
   (std::time::SystemTime::now() + std::time::Duration::from_secs(duration_secs)).duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as usize
 }
 pub fn decode_jwt<C: serde::de::DeserializeOwned>(data: &str, auth: &AccessManagement) -> Result<C, hyper::http::Result<Response<Full<Bytes>>>> {
-  jsonwebtoken::decode::<C>(data, &auth.jwt_key.decoding, &jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256))
-    .map(|token| token.claims)
-    .map_err(|e| {
-      eprintln!("Failed to decode encryption: {}", e);
-      Response::builder().status(StatusCode::BAD_REQUEST).body("JWT is invalid".into())
-    })
+  auth.jwt_key.decode::<C>(data).map_err(|e| {
+    eprintln!("Failed to decode encryption: {}", e);
+    Response::builder().status(StatusCode::BAD_REQUEST).body("JWT is invalid".into())
+  })
 }
 pub fn encode_jwt<C: serde::Serialize>(claim: &C, auth: &AccessManagement) -> Result<String, jsonwebtoken::errors::Error> {
-  jsonwebtoken::encode(&jsonwebtoken::Header::default(), claim, &auth.jwt_key.encoding)
+  auth.jwt_key.encode(claim)
 }
 pub fn encode_jwt_response<C: serde::Serialize>(claim: &C, auth: &AccessManagement) -> hyper::http::Result<Response<Full<Bytes>>> {
   let token = match encode_jwt(claim, auth) {
@@ -70,3 +293,152 @@ pub fn encode_jwt_redirect<S: serde::Serialize + AsRef<str>>(
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{expiry_time, JwtKeys, PlayerClaim, SigningAlgorithm, SigningConfiguration, SigningKeys, StoredKey};
+  use crate::database::configuration::DatabaseConfiguration;
+  use crate::database::Database;
+  use chrono::{Duration, Utc};
+  use std::path::PathBuf;
+
+  fn temporary_database(name: &str) -> (Database, PathBuf) {
+    let db_file = std::env::temp_dir().join(format!("spadina-test-jwt-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&db_file);
+    (Database::new(&DatabaseConfiguration::default(), db_file.clone()), db_file)
+  }
+
+  fn cleanup(database: Database, db_file: PathBuf) {
+    drop(database);
+    let _ = std::fs::remove_file(db_file);
+  }
+
+  fn configuration(algorithm: SigningAlgorithm, rotate_days: Option<u32>) -> SigningConfiguration {
+    SigningConfiguration { algorithm, grace_hours: 2, rotate_days }
+  }
+
+  fn token(keys: &SigningKeys) -> String {
+    keys.encode(&PlayerClaim { exp: expiry_time(3600), generation: 0, key: None, name: "tester".to_string() }).unwrap()
+  }
+
+  fn accepted(keys: &SigningKeys, token: &str) -> bool {
+    keys.decode::<PlayerClaim<String>>(token).map(|claim| claim.name == "tester").unwrap_or(false)
+  }
+
+  fn stored(database: &Database) -> Vec<StoredKey> {
+    database.setting_read::<JwtKeys>().unwrap()
+  }
+
+  /// Change the times on every stored key as if they had happened some time ago
+  fn age(database: &Database, created: Duration, retired: Duration) {
+    let mut keys = stored(database);
+    for key in keys.iter_mut() {
+      key.created = key.created - created;
+      key.retired = key.retired.map(|time| time - retired);
+    }
+    database.setting_write::<JwtKeys>(&keys).unwrap();
+  }
+
+  #[test]
+  fn keys_are_kept_when_nothing_changes() {
+    let (database, db_file) = temporary_database("keep");
+    let keys = SigningKeys::new(configuration(SigningAlgorithm::EdDSA, Some(30)), &database).unwrap();
+    let token = token(&keys);
+    let id = stored(&database)[0].id.clone();
+    let keys = SigningKeys::new(configuration(SigningAlgorithm::EdDSA, Some(30)), &database).unwrap();
+    assert_eq!(stored(&database).len(), 1);
+    assert_eq!(stored(&database)[0].id, id);
+    assert!(accepted(&keys, &token));
+    cleanup(database, db_file);
+  }
+
+  #[test]
+  fn algorithm_change_rotates() {
+    let (database, db_file) = temporary_database("algorithm");
+    let old_keys = SigningKeys::new(configuration(SigningAlgorithm::EdDSA, None), &database).unwrap();
+    let old_token = token(&old_keys);
+    let keys = SigningKeys::new(configuration(SigningAlgorithm::ES256, None), &database).unwrap();
+    let stored = stored(&database);
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0].algorithm, SigningAlgorithm::ES256);
+    assert!(stored[0].retired.is_none());
+    assert_eq!(stored[1].algorithm, SigningAlgorithm::EdDSA);
+    assert!(stored[1].retired.is_some());
+    assert_eq!(keys.public_keys().keys.len(), 2);
+    let new_token = token(&keys);
+    assert_eq!(jsonwebtoken::decode_header(&new_token).unwrap().kid.as_deref(), Some(stored[0].id.as_str()));
+    assert!(accepted(&keys, &old_token));
+    assert!(accepted(&keys, &new_token));
+    cleanup(database, db_file);
+  }
+
+  #[test]
+  fn age_rotates() {
+    let (database, db_file) = temporary_database("age");
+    SigningKeys::new(configuration(SigningAlgorithm::HS256, Some(30)), &database).unwrap();
+    age(&database, Duration::days(29), Duration::zero());
+    let keys = SigningKeys::new(configuration(SigningAlgorithm::HS256, Some(30)), &database).unwrap();
+    assert_eq!(stored(&database).len(), 1);
+    age(&database, Duration::days(2), Duration::zero());
+    keys.rotate(&database).unwrap();
+    assert_eq!(stored(&database).len(), 2);
+    // Symmetric keys are never published
+    assert!(keys.public_keys().keys.is_empty());
+    cleanup(database, db_file);
+  }
+
+  #[test]
+  fn retired_keys_expire_after_grace() {
+    let (database, db_file) = temporary_database("grace");
+    let old_keys = SigningKeys::new(configuration(SigningAlgorithm::EdDSA, None), &database).unwrap();
+    let old_token = token(&old_keys);
+    let keys = SigningKeys::new(configuration(SigningAlgorithm::ES256, None), &database).unwrap();
+    age(&database, Duration::zero(), Duration::hours(1));
+    keys.rotate(&database).unwrap();
+    assert_eq!(stored(&database).len(), 2);
+    assert!(accepted(&keys, &old_token));
+    age(&database, Duration::zero(), Duration::hours(2));
+    keys.rotate(&database).unwrap();
+    let stored = stored(&database);
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].algorithm, SigningAlgorithm::ES256);
+    assert!(!accepted(&keys, &old_token));
+    assert!(accepted(&keys, &token(&keys)));
+    cleanup(database, db_file);
+  }
+
+  #[test]
+  fn decode_requires_known_key_id() {
+    let (database, db_file) = temporary_database("kid");
+    let (other_database, other_db_file) = temporary_database("kid-other");
+    let keys = SigningKeys::new(configuration(SigningAlgorithm::HS256, None), &database).unwrap();
+    let other_keys = SigningKeys::new(configuration(SigningAlgorithm::HS256, None), &other_database).unwrap();
+    assert!(accepted(&keys, &token(&keys)));
+    assert!(!accepted(&keys, &token(&other_keys)));
+    // A token without a key ID is rejected even if the signature is valid
+    let secret = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &stored(&database)[0].secret).unwrap();
+    let unlabelled = jsonwebtoken::encode(
+      &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+      &PlayerClaim { exp: expiry_time(3600), generation: 0, key: None, name: "tester".to_string() },
+      &jsonwebtoken::EncodingKey::from_secret(&secret),
+    )
+    .unwrap();
+    assert!(!accepted(&keys, &unlabelled));
+    cleanup(database, db_file);
+    cleanup(other_database, other_db_file);
+  }
+
+  #[test]
+  fn retired_keys_stay_until_grace_ends_even_after_restart() {
+    let (database, db_file) = temporary_database("restart");
+    SigningKeys::new(configuration(SigningAlgorithm::EdDSA, None), &database).unwrap();
+    SigningKeys::new(configuration(SigningAlgorithm::ES256, None), &database).unwrap();
+    let retired = stored(&database)[1].retired.unwrap();
+    SigningKeys::new(configuration(SigningAlgorithm::ES256, None), &database).unwrap();
+    let stored = stored(&database);
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[1].retired, Some(retired));
+    assert!(retired <= Utc::now());
+    cleanup(database, db_file);
+  }
+}
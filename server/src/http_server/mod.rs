@@ -14,6 +14,7 @@ use hyper::header::{CONTENT_TYPE, ETAG};
 use hyper::http;
 use hyper::http::{Request, Response, StatusCode};
 use hyper::service::Service;
+use spadina_core::net::server::auth::{PasswordChangeRequest, PasswordRequest};
 use std::sync::Arc;
 
 pub mod calendar;
@@ -24,6 +25,7 @@ pub mod websocket;
 
 #[derive(Clone)]
 pub(crate) struct WebServer {
+  challenges: Arc<public_key_login::Challenges>,
   /// The authentication provider that can determine what users can get a JWT to log in
   database: Database,
   pub directory: Directory,
@@ -34,7 +36,7 @@ impl WebServer {
   pub fn new(directory: Directory, database: Database) -> Self {
     let mut registry = Default::default();
     crate::metrics::register(&mut registry);
-    WebServer { database, directory, registry: Arc::new(registry) }
+    WebServer { challenges: Default::default(), database, directory, registry: Arc::new(registry) }
   }
 }
 
@@ -77,10 +79,10 @@ impl Service<Request<Incoming>> for WebServer {
             Err(()) => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body("".into()),
           }
         }
-        // Describe what authentication scheme is used
+        // Describe what authentication schemes are used
         (&http::Method::GET, spadina_core::net::server::AUTH_METHOD_PATH) => {
-          let scheme = server.directory.access_management.accounts.scheme();
-          match serde_json::to_string(&scheme) {
+          let methods = server.directory.access_management.accounts.methods();
+          match serde_json::to_string(&methods) {
             Err(e) => {
               crate::metrics::BAD_WEB_REQUEST.get_or_create(&()).inc();
               eprintln!("Failed to serialise authentication scheme: {}", e);
@@ -102,29 +104,118 @@ impl Service<Request<Incoming>> for WebServer {
         (&http::Method::GET, spadina_core::net::server::CLIENT_V1_PATH) => open_websocket::<crate::client::Client>(req, &server),
         // Handle a new server connection by upgrading to a web socket
         (&http::Method::GET, peer::net::PATH_FINISH) => open_websocket::<peer::Peer>(req, &server),
-        (&http::Method::POST, spadina_core::net::server::CLIENT_KEY_PATH) => public_key_login::handle(req, &server).await,
+        // Publish the keys used to sign tokens so peers can verify them
+        (&http::Method::GET, peer::net::PATH_KEYS) => match serde_json::to_vec(&server.directory.access_management.jwt_key.public_keys()) {
+          Ok(keys) => Response::builder().status(StatusCode::OK).header(CONTENT_TYPE, "application/json").body(keys.into()),
+          Err(e) => {
+            eprintln!("Failed to serialise signing keys: {}", e);
+            Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default())
+          }
+        },
+        (&http::Method::POST, spadina_core::net::server::CLIENT_KEY_PATH) => public_key_login::challenge(req, &server).await,
+        (&http::Method::POST, spadina_core::net::server::CLIENT_KEY_RESPONSE_PATH) => public_key_login::respond(req, &server).await,
         // Handle a request by a peer server for a connection back
         (&http::Method::POST, peer::net::PATH_START) => peer::handshake::handle(req, &server).await,
-        // For other URLs, see if the authentication mechanism is prepared to deal with them
-        _ => match server.directory.access_management.accounts.http_handle(req).await {
-          AuthResult::Failure => {
-            Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body("Internal server error during authentication".into())
-          }
-          AuthResult::NotHandled => Response::builder().status(StatusCode::NOT_FOUND).body("Not Found".into()),
-          AuthResult::Page(page) => page,
-          AuthResult::SendToken(name) => {
-            jwt::encode_jwt_response(&jwt::PlayerClaim { exp: jwt::expiry_time(3600), name }, &server.directory.access_management)
+        // The password is checked by the authentication mechanism, but the second factor, if the player has one, is checked here
+        (&http::Method::POST, spadina_core::net::server::PASSWORD_AUTH_PATH) => {
+          let (parts, body) = match buffer(req).await {
+            Ok(req) => req.into_parts(),
+            Err(response) => return response,
+          };
+          let body = match body.collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(e) => match e {},
+          };
+          let otp = serde_json::from_slice::<PasswordRequest<String>>(&body).ok().and_then(|request| request.otp);
+          match server.directory.access_management.accounts.http_handle(Request::from_parts(parts, Full::new(body))).await {
+            AuthResult::SendToken(name) => match server.directory.access_management.second_factors.check(&name, otp.as_deref()) {
+              Ok(true) => auth_response(AuthResult::SendToken(name), &server),
+              // This is the same response as a wrong password, so it does not reveal that the password was right
+              Ok(false) => Response::builder().status(StatusCode::UNAUTHORIZED).body("Invalid username or password".into()),
+              Err(e) => {
+                eprintln!("Failed to check second factor for {}: {}", &name, e);
+                Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default())
+              }
+            },
+            result => auth_response(result, &server),
           }
-          AuthResult::RedirectToken(name) => {
-            jwt::encode_jwt_redirect(&jwt::PlayerClaim { exp: jwt::expiry_time(3600), name }, &server.directory.access_management)
+        }
+        // A password change would let someone with only the password lock out the player, so the second factor is checked before the change
+        (&http::Method::POST, spadina_core::net::server::PASSWORD_CHANGE_PATH) => {
+          let (parts, body) = match buffer(req).await {
+            Ok(req) => req.into_parts(),
+            Err(response) => return response,
+          };
+          let body = match body.collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(e) => match e {},
+          };
+          let request = match serde_json::from_slice::<PasswordChangeRequest<String>>(&body) {
+            Ok(request) => request,
+            Err(e) => return Response::builder().status(StatusCode::BAD_REQUEST).body(e.to_string().into()),
+          };
+          // Unknown players are left to the authentication mechanism, which rejects them with the same response
+          if let Ok(name) = server.directory.access_management.accounts.normalize_username(request.username).await {
+            match server.directory.access_management.second_factors.check(&name, request.otp.as_deref()) {
+              Ok(true) => (),
+              Ok(false) => return Response::builder().status(StatusCode::UNAUTHORIZED).body("Invalid username or password".into()),
+              Err(e) => {
+                eprintln!("Failed to check second factor for {}: {}", &name, e);
+                return Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default());
+              }
+            }
           }
-        },
+          auth_response(server.directory.access_management.accounts.http_handle(Request::from_parts(parts, Full::new(body))).await, &server)
+        }
+        // For other URLs, see if the authentication mechanism is prepared to deal with them
+        _ => auth_response(
+          server
+            .directory
+            .access_management
+            .accounts
+            .http_handle(match buffer(req).await {
+              Ok(req) => req,
+              Err(response) => return response,
+            })
+            .await,
+          &server,
+        ),
       }
     }
     .boxed()
   }
 }
 
+fn auth_response(result: AuthResult, server: &WebServer) -> http::Result<Response<Full<Bytes>>> {
+  match result {
+    AuthResult::Failure => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body("Internal server error during authentication".into()),
+    AuthResult::NotHandled => Response::builder().status(StatusCode::NOT_FOUND).body("Not Found".into()),
+    AuthResult::Page(page) => page,
+    AuthResult::Revoke(name, page) => {
+      if let Err(e) = server.database.token_generation_increment(&name) {
+        eprintln!("Failed to revoke tokens for {}: {}", &name, e);
+      }
+      let directory = server.directory.clone();
+      tokio::spawn(async move { directory.disconnect_player(Arc::from(name)).await });
+      page
+    }
+    AuthResult::SendToken(name) => match jwt::PlayerClaim::new(name, &server.database) {
+      Ok(claim) => jwt::encode_jwt_response(&claim, &server.directory.access_management),
+      Err(e) => {
+        eprintln!("Failed to get token generation: {}", e);
+        Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default())
+      }
+    },
+    AuthResult::RedirectToken(name) => match jwt::PlayerClaim::new(name, &server.database) {
+      Ok(claim) => jwt::encode_jwt_redirect(&claim, &server.directory.access_management),
+      Err(e) => {
+        eprintln!("Failed to get token generation: {}", e);
+        Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default())
+      }
+    },
+  }
+}
+
 fn etag_request(content_type: &'static str, contents: &'static [u8], req: Request<Incoming>) -> http::Result<Response<Full<Bytes>>> {
   if req.headers().get("If-None-Match").map(|tag| tag.to_str().ok()).flatten().map(|v| v == git_version::git_version!()).unwrap_or(false) {
     Response::builder().status(StatusCode::NOT_MODIFIED).body(Default::default())
@@ -136,7 +227,22 @@ fn etag_request(content_type: &'static str, contents: &'static [u8], req: Reques
       .body(Bytes::from_static(contents).into())
   }
 }
-pub async fn aggregate<T: serde::de::DeserializeOwned>(req: Request<Incoming>) -> Result<T, http::Result<Response<Full<Bytes>>>> {
+/// Collect the body of a request so it can be inspected more than once
+async fn buffer(req: Request<Incoming>) -> Result<Request<Full<Bytes>>, http::Result<Response<Full<Bytes>>>> {
+  let (parts, body) = req.into_parts();
+  match body.collect().await {
+    Err(e) => {
+      crate::metrics::BAD_WEB_REQUEST.get_or_create(&()).inc();
+      eprintln!("Failed to aggregate body: {}", e);
+      Err(Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(format!("Aggregation failed: {}", e).into()))
+    }
+    Ok(whole_body) => Ok(Request::from_parts(parts, Full::new(whole_body.to_bytes()))),
+  }
+}
+pub async fn aggregate<T: serde::de::DeserializeOwned, B: hyper::body::Body>(req: Request<B>) -> Result<T, http::Result<Response<Full<Bytes>>>>
+where
+  B::Error: std::fmt::Display,
+{
   match req.into_body().collect().await {
     Err(e) => {
       crate::metrics::BAD_WEB_REQUEST.get_or_create(&()).inc();
@@ -4,49 +4,120 @@ use http_body_util::Full;
 use hyper::body::Bytes;
 use hyper::header::CONTENT_TYPE;
 use hyper::{body::Incoming, Request, Response, StatusCode};
-use spadina_core::net::server::auth::AuthPublicKey;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+use rand::distributions::{Alphanumeric, DistString};
+use spadina_core::net::server::auth::{challenge_message, AuthPublicKey, PublicKeyChallenge, PublicKeyChallengeResponse};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-pub async fn handle(req: Request<Incoming>, web_server: &WebServer) -> hyper::http::Result<Response<Full<Bytes>>> {
+const CHALLENGE_LIFETIME: Duration = Duration::from_secs(60);
+const MAX_OUTSTANDING_CHALLENGES: usize = 10_000;
+const MAX_PLAYER_CHALLENGES: usize = 5;
+
+/// Challenges that have been issued to clients, but not yet answered
+#[derive(Default)]
+pub struct Challenges(Mutex<HashMap<String, Challenge>>);
+
+struct Challenge {
+  expires: Instant,
+  fingerprint: String,
+  player: String,
+}
+
+/// Issue a challenge for a player to sign with their private key
+///
+/// A challenge is issued whether or not the key exists, so this does not reveal which keys a player has registered.
+pub async fn challenge(req: Request<Incoming>, web_server: &WebServer) -> hyper::http::Result<Response<Full<Bytes>>> {
   let AuthPublicKey { player, fingerprint } = match aggregate::<AuthPublicKey<String>>(req).await {
     Err(response) => return response,
     Ok(request) => request,
   };
+  let nonce = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+  {
+    let mut challenges = web_server.challenges.0.lock().unwrap();
+    let now = Instant::now();
+    challenges.retain(|_, challenge| challenge.expires > now);
+    // Rather than refuse new challenges, which would let one client block logins for everyone, replace the oldest ones
+    if challenges.values().filter(|challenge| challenge.player == player).count() >= MAX_PLAYER_CHALLENGES {
+      evict_oldest(&mut challenges, |challenge| challenge.player == player);
+    }
+    if challenges.len() >= MAX_OUTSTANDING_CHALLENGES {
+      evict_oldest(&mut challenges, |_| true);
+    }
+    challenges.insert(nonce.clone(), Challenge { expires: now + CHALLENGE_LIFETIME, fingerprint, player });
+  }
+  match serde_json::to_vec(&PublicKeyChallenge { nonce: nonce.as_str(), server: &web_server.directory.access_management.server_name }) {
+    Ok(body) => Response::builder().status(StatusCode::OK).header(CONTENT_TYPE, "application/json").body(body.into()),
+    Err(e) => {
+      eprintln!("Failed to serialise public key challenge: {}", e);
+      Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default())
+    }
+  }
+}
+
+/// Check a signed challenge and issue a token if the key is still registered to the player
+pub async fn respond(req: Request<Incoming>, web_server: &WebServer) -> hyper::http::Result<Response<Full<Bytes>>> {
+  let PublicKeyChallengeResponse { player, fingerprint, nonce, signature } = match aggregate::<PublicKeyChallengeResponse<String>>(req).await {
+    Err(response) => return response,
+    Ok(request) => request,
+  };
+  // Remove the challenge before checking it, so it cannot be used again even if this attempt fails
+  let challenge = web_server.challenges.0.lock().unwrap().remove(&nonce);
+  match challenge {
+    Some(challenge) if challenge.expires > Instant::now() && challenge.player == player && challenge.fingerprint == fingerprint => (),
+    _ => return Response::builder().status(StatusCode::FORBIDDEN).body("Challenge is invalid or has expired".into()),
+  }
+  let message = challenge_message(&web_server.directory.access_management.server_name, &player, &fingerprint, &nonce);
   let player = match web_server.directory.access_management.accounts.normalize_username(player).await {
     Ok(player) => player,
     Err(()) => return Response::builder().status(StatusCode::FORBIDDEN).body("Invalid user name".into()),
   };
   let der = match web_server.database.public_key_get(&player, &fingerprint) {
     Ok(Some(der)) => der,
-    Ok(None) => return Response::builder().status(StatusCode::NOT_FOUND).body(Default::default()),
+    Ok(None) => return Response::builder().status(StatusCode::FORBIDDEN).body("Challenge is invalid or has expired".into()),
     Err(e) => {
       eprintln!("Failed to fetch public keys during authentication: {}", e);
       return Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default());
     }
   };
-  let pkey = match openssl::pkey::PKey::public_key_from_der(der.as_slice()) {
+  let pkey = match PKey::public_key_from_der(der.as_slice()) {
     Ok(pkey) => pkey,
     Err(e) => return Response::builder().status(StatusCode::UNPROCESSABLE_ENTITY).body(format!("Certificate is invalid: {}", e).into()),
   };
-
-  let encrypter = match openssl::encrypt::Encrypter::new(&pkey) {
-    Ok(encrypter) => encrypter,
-    Err(e) => return Response::builder().status(StatusCode::UNPROCESSABLE_ENTITY).body(format!("Failed to build encrypter: {}", e).into()),
-  };
-  let token = match jwt::encode_jwt(&jwt::PlayerClaim { exp: jwt::expiry_time(3600), name: &player }, &web_server.directory.access_management)
-    .map_err(|e| {
-      eprintln!("Failed to encode JWT: {}", e);
-      Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default())
-    }) {
-    Ok(token) => token,
-    Err(e) => return e,
-  };
-  let buffer_len = match encrypter.encrypt_len(token.as_bytes()) {
-    Ok(buffer_len) => buffer_len,
-    Err(e) => return Response::builder().status(StatusCode::UNPROCESSABLE_ENTITY).body(format!("Failed to encrypt: {}", e).into()),
+  match verify(&pkey, &message, &signature) {
+    Ok(true) => (),
+    Ok(false) => return Response::builder().status(StatusCode::FORBIDDEN).body("Signature is invalid".into()),
+    Err(e) => return Response::builder().status(StatusCode::UNPROCESSABLE_ENTITY).body(format!("Failed to check signature: {}", e).into()),
+  }
+  if let Err(e) = web_server.database.public_key_used(&player, &fingerprint) {
+    eprintln!("Failed to record public key use for {}: {}", &player, e);
+  }
+  let mut claim = match jwt::PlayerClaim::new(player.as_str(), &web_server.database) {
+    Ok(claim) => claim,
+    Err(e) => {
+      eprintln!("Failed to get token generation during authentication: {}", e);
+      return Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Default::default());
+    }
   };
-  let mut output = vec![0; buffer_len];
-  match encrypter.encrypt(token.as_bytes(), &mut output) {
-    Ok(_) => Response::builder().status(StatusCode::OK).header(CONTENT_TYPE, "application/octet-stream").body(output.into()),
-    Err(e) => Response::builder().status(StatusCode::UNPROCESSABLE_ENTITY).body(format!("Failed to encrypt: {}", e).into()),
+  claim.key = Some(fingerprint);
+  jwt::encode_jwt_response(&claim, &web_server.directory.access_management)
+}
+
+fn evict_oldest(challenges: &mut HashMap<String, Challenge>, filter: impl Fn(&Challenge) -> bool) {
+  if let Some(nonce) =
+    challenges.iter().filter(|(_, challenge)| filter(challenge)).min_by_key(|(_, challenge)| challenge.expires).map(|(nonce, _)| nonce.clone())
+  {
+    challenges.remove(&nonce);
   }
 }
+
+fn verify(pkey: &PKey<Public>, message: &[u8], signature: &[u8]) -> Result<bool, openssl::error::ErrorStack> {
+  let mut verifier = match pkey.id() {
+    Id::ED25519 | Id::ED448 => Verifier::new_without_digest(pkey)?,
+    _ => Verifier::new(MessageDigest::sha256(), pkey)?,
+  };
+  verifier.verify_oneshot(signature, message)
+}
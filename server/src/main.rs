@@ -14,6 +14,7 @@ use tokio::time::sleep;
 mod access;
 mod accounts;
 mod aggregating_map;
+mod archive;
 mod asset_store;
 mod atomic_activity;
 mod client;
@@ -38,11 +39,18 @@ mod unix_socket;
 
 /// Start the server. This is in a separate function from main because the tokio annotation mangles compile error information
 async fn start() -> Result<(), Box<dyn Error + Send + Sync>> {
-  let (configuration, db_path) = config::ServerConfiguration::load();
+  let (configuration, db_path, command) = config::ServerConfiguration::load();
   let server_name: Arc<str> = Arc::from(parse_server_name(&configuration.name).expect("Invalid server name. It must be a valid DNS name"));
-  let database = database::Database::new(db_path);
+  let database = database::Database::new(&configuration.database, db_path);
   database.player_clean()?;
-  let auth = AccessManagement::new(configuration.authentication.load(&server_name, &database).await?, &database, server_name)?;
+  match command {
+    config::ServerCommand::Export { output } => return archive::export(&database, &configuration.asset_store.load(), &server_name, &output).await,
+    config::ServerCommand::Import { input, rename } => {
+      return archive::import(&database, &configuration.asset_store.load(), &server_name, &input, rename).await
+    }
+    config::ServerCommand::Serve => (),
+  }
+  let auth = AccessManagement::new(configuration.authentication.load(&server_name, &database).await?, configuration.signing, &database, server_name)?;
   let asset_store = configuration.asset_store.load();
   let directory = Directory::new(auth, asset_store, database.clone());
 
@@ -58,24 +66,40 @@ async fn start() -> Result<(), Box<dyn Error + Send + Sync>> {
 fn start_cleaner_task(auth: &AccessManagement, database: database::Database, directory: Directory) {
   let mut death = auth.give_me_death();
   tokio::spawn(async move {
+    // Run immediately on start up, so accounts disabled in an external directory while the server was down are caught
     loop {
-      tokio::select! {
-        biased;
-        _ = death.recv() => break,
-        _ = sleep(Duration::from_secs(600)) => ()
-      }
       if let Err(e) = database.direct_message_clean() {
         eprintln!("Failed to delete old chats: {}", e);
       }
       if let Err(e) = database.location_announcements_clean() {
         eprintln!("Failed to delete old announcements: {}", e);
       }
+      if let Err(e) = database.location_state_history_clean() {
+        eprintln!("Failed to delete old location snapshots: {}", e);
+      }
+      if let Err(e) = database.invitation_clean() {
+        eprintln!("Failed to delete expired invitations: {}", e);
+      }
+      if let Err(e) = directory.access_management.jwt_key.rotate(&database) {
+        eprintln!("Failed to rotate signing keys: {}", e);
+      }
+      for player in directory.access_management.accounts.sync().await {
+        if let Err(e) = database.token_generation_increment(&player) {
+          eprintln!("Failed to revoke tokens for {}: {}", &player, e);
+        }
+        directory.disconnect_player(Arc::from(player)).await;
+      }
       match database.calender_cache_refresh() {
         Ok(updates) => directory.refresh_calendars(updates).await,
         Err(e) => {
           eprintln!("Failed to refresh calendars: {}", e);
         }
       }
+      tokio::select! {
+        biased;
+        _ = death.recv() => break,
+        _ = sleep(Duration::from_secs(600)) => ()
+      }
     }
   });
 }
@@ -106,6 +106,10 @@ pub enum PeerMessage<S: AsRef<str> + Ord + Eq + Hash, B: AsRef<[u8]>> {
     id: u32,
     state: OnlineState<S>,
   },
+  /// A player on the originating server has been deleted, so any conversations with them should be erased
+  PlayerDeleted {
+    player: S,
+  },
   /// Releases control of a player to the originating server
   VisitorRelease {
     player: S,
@@ -179,6 +179,10 @@ impl SocketEntity for Peer {
           VisitorTarget::Location { owner: player.as_ref(), descriptor: descriptor.reference(AsReference::<str>::default()) },
           &mut self.players_on_peer,
         ),
+        PeerRequest::PlayerDeleted(player) => {
+          self.players_on_peer.remove(player.as_ref());
+          vec![Outgoing::Send(PeerMessage::<_, &[u8]>::PlayerDeleted { player: player.as_ref() }.into())]
+        }
         PeerRequest::RefreshCalendar { player } => {
           let locations = database.calender_cache_fetch_locations_by_server(&player, &self.name);
           match locations {
@@ -199,6 +203,10 @@ impl SocketEntity for Peer {
             }
           }
         }
+        PeerRequest::Yank(player) => match self.players_on_peer.remove(player.as_ref()) {
+          Some(_) => vec![Outgoing::Send(PeerMessage::<_, &[u8]>::VisitorYank { player: player.as_ref() }.into())],
+          None => vec![],
+        },
       },
       Incoming::External(message) => match message {
         PeerMessage::AssetRequest { id, asset } => match directory.pull_asset(asset.into(), false).await {
@@ -365,6 +373,13 @@ impl SocketEntity for Peer {
           }
           vec![]
         }
+        PeerMessage::PlayerDeleted { player } => {
+          self.players_from_peer.remove(player.as_str());
+          if let Err(e) = database.remote_player_delete(&player, &self.name) {
+            eprintln!("Failed to erase deleted player {} from {}: {}", &player, &self.name, e);
+          }
+          vec![]
+        }
         PeerMessage::LocationChange { player, response } => {
           let mut output = Vec::new();
           let remove_player = if let Some(state) = self.players_on_peer.get(player.as_str()) {
@@ -1,5 +1,6 @@
 pub const PATH_START: &str = "/api/server/start/v1";
 pub const PATH_FINISH: &str = "/api/server/finish/v1";
+pub const PATH_KEYS: &str = "/.well-known/jwks.json";
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct PeerClaim<S: AsRef<str>> {
   pub exp: usize,
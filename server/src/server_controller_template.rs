@@ -4,35 +4,40 @@ use spadina_core::controller::serde_controller::SerdeController;
 use spadina_core::controller::{Controller, ControllerTemplate, GenericControllerTemplate, GenericError, LoadError, MessagePackDeserializer};
 use spadina_core::location::Application;
 use std::borrow::Cow;
+use std::sync::Arc;
 
 pub enum ServerControllerTemplate {
   Asset(GenericControllerTemplate),
-  Application(Application),
+  Application(Application, Arc<str>),
 }
 
 impl ControllerTemplate for ServerControllerTemplate {
   type Error = GenericError;
   type Controller = Box<dyn Controller<Input = Vec<u8>, Output = Vec<u8>> + Send + Sync + 'static>;
 
-  fn blank(&self) -> Self::Controller {
+  fn blank(&self) -> Result<Self::Controller, Self::Error> {
     match self {
       ServerControllerTemplate::Asset(a) => a.blank(),
-      ServerControllerTemplate::Application(Application::Editor) => Box::new(SerdeController(Editor.blank())),
+      ServerControllerTemplate::Application(Application::Editor, server_name) => {
+        Ok(Box::new(SerdeController(Editor { server_name: server_name.clone() }.blank().map_err(|e| GenericError(Box::new(e)))?)))
+      }
     }
   }
 
   fn load_json(&self, value: Value) -> Result<Self::Controller, LoadError<Error, Self::Error>> {
     match self {
       ServerControllerTemplate::Asset(a) => a.load_json(value),
-      ServerControllerTemplate::Application(Application::Editor) => Ok(Box::new(SerdeController(Editor.load_json(value).map_err(|e| e.boxed())?))),
+      ServerControllerTemplate::Application(Application::Editor, server_name) => {
+        Ok(Box::new(SerdeController(Editor { server_name: server_name.clone() }.load_json(value).map_err(|e| e.boxed())?)))
+      }
     }
   }
 
   fn load_message_pack(&self, de: MessagePackDeserializer) -> Result<Self::Controller, LoadError<rmp_serde::decode::Error, Self::Error>> {
     match self {
       ServerControllerTemplate::Asset(a) => a.load_message_pack(de),
-      ServerControllerTemplate::Application(Application::Editor) => {
-        Ok(Box::new(SerdeController(Editor.load_message_pack(de).map_err(|e| e.boxed())?)))
+      ServerControllerTemplate::Application(Application::Editor, server_name) => {
+        Ok(Box::new(SerdeController(Editor { server_name: server_name.clone() }.load_message_pack(de).map_err(|e| e.boxed())?)))
       }
     }
   }
@@ -40,7 +45,7 @@ impl ControllerTemplate for ServerControllerTemplate {
   fn name(&self, owner: &str) -> Cow<'static, str> {
     match self {
       ServerControllerTemplate::Asset(a) => a.name(owner),
-      ServerControllerTemplate::Application(Application::Editor) => Editor.name(owner),
+      ServerControllerTemplate::Application(Application::Editor, server_name) => Editor { server_name: server_name.clone() }.name(owner),
     }
   }
 }
@@ -31,7 +31,7 @@ pub(crate) fn start(path: String, directory: Directory) {
           .await
           {
             Ok(connection) => {
-              let _ = directory.register_player(Arc::from(player_name), connection);
+              let _ = directory.register_player(Arc::from(player_name), None, connection).await;
             }
             Err(e) => {
               eprintln!("Failed to connect on UNIX socket: {}", e);
@@ -54,7 +54,9 @@ struct LoginStore<'a> {
 }
 impl<'a> Callback for LoginStore<'a> {
   fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
-    match futures::executor::block_on(self.directory.access_management.accounts.normalize_username(request.uri().path().to_string())) {
+    match futures::executor::block_on(
+      self.directory.access_management.accounts.normalize_username(request.uri().path().trim_start_matches('/').to_string()),
+    ) {
       Ok(player_name) => {
         *self.player_name = player_name;
         Ok(response)